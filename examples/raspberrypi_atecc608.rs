@@ -1,12 +1,13 @@
 // $ CROSS_COMPILE=arm-none-linux-gnueabihf- cargo b \
-//   --example raspberrypi_atecc608 --features std \
+//   --example raspberrypi_atecc608 --features std,std-linux \
 //   --target armv7-unknown-linux-gnueabihf
 //
 // $ scp target/armv7-unknown-linux-gnueabihf/debug/examples/raspberrypi_atecc608 \
 //   pi@${PI_IP_ADDR}:/home/pi/
 //
 // $ ssh pi@${PI_IP_ADDR} "RUST_LOG=info ./raspberrypi_atecc608"
-use at_cryptoauth::memory::{Size, Slot, Zone};
+use at_cryptoauth::linux::LinuxI2c;
+use at_cryptoauth::memory::{LockToken, Size, Slot};
 use at_cryptoauth::tngtls::{AES_KEY, AUTH_PRIVATE_KEY, SIGN_PRIVATE_KEY, USER_PRIVATE_KEY1};
 use at_cryptoauth::{AtCaClient, Block};
 use core::fmt::Debug;
@@ -65,7 +66,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let mut i2c = I2cdev::new(I2C_PATH)?;
     i2c.set_slave_address(ATECC608_ADDR)?;
-    let mut atca = AtCaClient::new(i2c, Delay);
+    let mut atca = AtCaClient::new(LinuxI2c::new(i2c), Delay);
 
     // Imitate flash-time procedure.
     let revision = atca.info().map_err(|e| format!("{}", e))?;
@@ -82,11 +83,11 @@ fn main() -> Result<(), Box<dyn Error>> {
         .map(|response| info!("Lock bytes {:02x?}", response.as_ref()))
         .map_err(|e| format!("{}", e))?;
     atca.memory()
-        .is_locked(Zone::Config)
+        .is_config_locked()
         .map(|response| info!("Config zone is locked: {}", response))
         .map_err(|e| format!("{}", e))?;
     atca.memory()
-        .is_locked(Zone::Data)
+        .is_data_locked()
         .map(|response| info!("Data zone is locked: {}", response))
         .map_err(|e| format!("{}", e))?;
     for key_id in Slot::keys() {
@@ -116,7 +117,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Sign
     let _ = atca.random().map_err(|e| format!("{}", e))?;
     let public_key = atca
-        .generate_pubkey(USER_PRIVATE_KEY1)
+        .public_key(USER_PRIVATE_KEY1)
         .map_err(|e| format!("{}", e))?;
     let _signature_01 = atca
         .signer(USER_PRIVATE_KEY1)
@@ -147,7 +148,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     // requires the Data zone to be locked.
     for key_id in Slot::keys() {
         if key_id.is_certificate() {
-            match atca.memory().pubkey(key_id) {
+            match atca.memory().read_pubkey(key_id) {
                 Ok(cert) => info!("{:?}, {:?}", key_id, &cert.as_ref()[..0x10]),
                 Err(e) => info!("{:?}, {}", key_id, e),
             }
@@ -155,7 +156,8 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     // Enforce TrustAndGo device config and lock config zone
-    atca.tng().map_err(|e| format!("{}", e))?;
+    let token = LockToken::i_understand_this_is_irreversible();
+    atca.tng(Some(token)).map_err(|e| format!("{}", e))?;
 
     // Leave data zone unloced.
     // Write AES key to AES_KEY slot
@@ -211,7 +213,7 @@ where
     test_private_key.as_mut().copy_from_slice(&TEST_PRIVATE_KEY);
     atca.write_private_key(key_id, &test_private_key)
         .map_err(|e| format!("{}", e))?;
-    let pub_key = atca.generate_pubkey(key_id).map_err(|e| format!("{}", e))?;
+    let pub_key = atca.public_key(key_id).map_err(|e| format!("{}", e))?;
     assert_eq!(TEST_PUBLIC_KEY, pub_key.as_ref());
     Ok(())
 }
@@ -227,7 +229,7 @@ where
     D: DelayNs,
 {
     let result = atca
-        .create_private_key(key_id)
+        .generate_key(key_id)
         .map_err(|e| info!("{:?}, {}", key_id, e));
 
     if let Ok(pub_key) = result {
@@ -264,7 +266,7 @@ where
     atca.write_private_key(key_id, &openssl_private_key)
         .map_err(|e| format!("{}", e))?;
 
-    let pub_key = atca.generate_pubkey(key_id).map_err(|e| format!("{}", e))?;
+    let pub_key = atca.public_key(key_id).map_err(|e| format!("{}", e))?;
     info!(
         "Generated public key for {:?}, {:02x?}",
         key_id,
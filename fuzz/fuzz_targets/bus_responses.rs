@@ -0,0 +1,45 @@
+// Whatever a glitching bus returns, the client must fail cleanly.
+//
+// $ cargo +nightly fuzz run bus_responses
+#![no_main]
+use at_cryptoauth::error::{Error, ErrorKind};
+use at_cryptoauth::{AtCaClient, Interface};
+use embedded_hal::delay::DelayNs;
+use libfuzzer_sys::fuzz_target;
+
+// Accepts every write and answers reads with the fuzz input.
+struct Bus<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Interface for Bus<'a> {
+    fn wake(&mut self, _address: u8) {}
+
+    fn send(&mut self, _address: u8, _bytes: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn receive(&mut self, _address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        if self.input.len() < buffer.len() {
+            return Err(ErrorKind::RxFail.into());
+        }
+        let (head, rest) = self.input.split_at(buffer.len());
+        buffer.copy_from_slice(head);
+        self.input = rest;
+        Ok(())
+    }
+}
+
+struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+fuzz_target!(|input: &[u8]| {
+    let mut atca = AtCaClient::new(Bus { input }, NoDelay);
+    atca.info().ok();
+    atca.random().ok();
+    atca.memory().config_zone().ok();
+    atca.sha().digest(&[0x00; 100]).ok();
+});
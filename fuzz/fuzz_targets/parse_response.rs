@@ -0,0 +1,11 @@
+// $ cargo +nightly fuzz run parse_response
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((&opcode, frame)) = data.split_first() {
+        if let Ok(pdu) = at_cryptoauth::parse_response(frame, opcode) {
+            assert_eq!(pdu.len() + 3, frame.len());
+        }
+    }
+});
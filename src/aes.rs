@@ -0,0 +1,424 @@
+// Block cipher modes, CMAC and AEAD on top of the device's single block
+// AES-128 engine, as in cryptoauthlib's atcab_aes_cbc, _ctr, _cmac, _ccm and
+// _gcm helpers. The key stays in its slot; IVs, counters and MAC state are
+// kept on the host.
+use super::client::Aes;
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind};
+use embedded_hal::delay::DelayNs;
+
+mod aead;
+
+pub use aead::{Ccm, CcmNonceSize, CcmTagSize, Gcm, Tag, GCM_NONCE_SIZE};
+
+pub const BLOCK_SIZE: usize = 16;
+
+pub type AesBlock = [u8; BLOCK_SIZE];
+
+/// A 128-bit block cipher, usually the device engine through `Aes`.
+pub trait BlockCipher {
+    fn encrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error>;
+    fn decrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error>;
+
+    /// Multiply in GF(2^128) for GHASH. Done in software unless the cipher
+    /// has a faster way.
+    fn gf_multiply(&mut self, h: &AesBlock, x: &AesBlock) -> Result<AesBlock, Error> {
+        Ok(aead::gf_multiply(h, x))
+    }
+}
+
+impl<C: BlockCipher + ?Sized> BlockCipher for &mut C {
+    fn encrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+        (**self).encrypt_block(block)
+    }
+
+    fn decrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+        (**self).decrypt_block(block)
+    }
+
+    fn gf_multiply(&mut self, h: &AesBlock, x: &AesBlock) -> Result<AesBlock, Error> {
+        (**self).gf_multiply(h, x)
+    }
+}
+
+impl<'a, PHY, D, const N: usize> BlockCipher for Aes<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn encrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+        self.encrypt_in_place(block)
+    }
+
+    fn decrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+        self.decrypt_in_place(block)
+    }
+
+    // The ATECC608 multiplies with its GFM mode.
+    fn gf_multiply(&mut self, h: &AesBlock, x: &AesBlock) -> Result<AesBlock, Error> {
+        self.gfm(h, x)
+    }
+}
+
+/// CBC encryption. Data is processed in place, whole blocks at a time, and
+/// the chaining value carries over between calls.
+pub struct CbcEncryptor<C> {
+    cipher: C,
+    iv: AesBlock,
+}
+
+impl<C: BlockCipher> CbcEncryptor<C> {
+    pub fn new(cipher: C, iv: &AesBlock) -> Self {
+        Self { cipher, iv: *iv }
+    }
+
+    pub fn update(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        for block in blocks(buffer)? {
+            xor(&mut self.iv, block);
+            self.cipher.encrypt_block(&mut self.iv)?;
+            block.copy_from_slice(&self.iv);
+        }
+        Ok(())
+    }
+}
+
+/// CBC decryption, the counterpart of `CbcEncryptor`.
+pub struct CbcDecryptor<C> {
+    cipher: C,
+    iv: AesBlock,
+}
+
+impl<C: BlockCipher> CbcDecryptor<C> {
+    pub fn new(cipher: C, iv: &AesBlock) -> Self {
+        Self { cipher, iv: *iv }
+    }
+
+    pub fn update(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        for block in blocks(buffer)? {
+            let mut plain = AesBlock::default();
+            plain.copy_from_slice(block);
+            let ciphertext = plain;
+            self.cipher.decrypt_block(&mut plain)?;
+            xor(&mut plain, &self.iv);
+            block.copy_from_slice(&plain);
+            self.iv = ciphertext;
+        }
+        Ok(())
+    }
+}
+
+/// CTR mode, for encryption and decryption alike. The counter occupies the
+/// last `counter_size` bytes of the initial block and is incremented big
+/// endian. Buffers may be of any length; unused key stream carries over.
+pub struct CtrCipher<C> {
+    cipher: C,
+    counter: AesBlock,
+    counter_size: usize,
+    key_stream: AesBlock,
+    used: usize,
+    wrapped: bool,
+}
+
+impl<C: BlockCipher> CtrCipher<C> {
+    pub fn new(cipher: C, initial: &AesBlock, counter_size: usize) -> Result<Self, Error> {
+        if !(1..=BLOCK_SIZE).contains(&counter_size) {
+            return Err(ErrorKind::BadParam.into());
+        }
+        Ok(Self {
+            cipher,
+            counter: *initial,
+            counter_size,
+            key_stream: AesBlock::default(),
+            used: BLOCK_SIZE,
+            wrapped: false,
+        })
+    }
+
+    pub fn apply(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        for byte in buffer.iter_mut() {
+            if self.used == BLOCK_SIZE {
+                self.next_key_stream()?;
+            }
+            *byte ^= self.key_stream[self.used];
+            self.used += 1;
+        }
+        Ok(())
+    }
+
+    // Encrypt the counter, then increment it. Once it has wrapped around,
+    // going on would reuse key stream.
+    fn next_key_stream(&mut self) -> Result<(), Error> {
+        if self.wrapped {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        self.key_stream = self.counter;
+        self.cipher.encrypt_block(&mut self.key_stream)?;
+        self.used = 0;
+
+        let counter = &mut self.counter[BLOCK_SIZE - self.counter_size..];
+        self.wrapped = counter.iter_mut().rev().all(|byte| {
+            *byte = byte.wrapping_add(1);
+            *byte == 0x00
+        });
+        Ok(())
+    }
+}
+
+/// AES-CMAC as specified in RFC 4493, for instance the LoRaWAN MIC.
+pub struct Cmac<C> {
+    cipher: C,
+    k1: AesBlock,
+    k2: AesBlock,
+    state: AesBlock,
+    // The last block is held back until `finalize` tells whether it is
+    // complete.
+    pending: AesBlock,
+    pending_len: usize,
+}
+
+impl<C: BlockCipher> Cmac<C> {
+    /// Derive the subkeys, which takes one block encryption.
+    pub fn new(mut cipher: C) -> Result<Self, Error> {
+        let mut l = AesBlock::default();
+        cipher.encrypt_block(&mut l)?;
+        let k1 = double(&l);
+        let k2 = double(&k1);
+        Ok(Self {
+            cipher,
+            k1,
+            k2,
+            state: AesBlock::default(),
+            pending: AesBlock::default(),
+            pending_len: 0,
+        })
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            if self.pending_len == BLOCK_SIZE {
+                xor(&mut self.state, &self.pending);
+                self.cipher.encrypt_block(&mut self.state)?;
+                self.pending_len = 0;
+            }
+            let n = data.len().min(BLOCK_SIZE - self.pending_len);
+            self.pending[self.pending_len..self.pending_len + n].copy_from_slice(&data[..n]);
+            self.pending_len += n;
+            data = &data[n..];
+        }
+        Ok(())
+    }
+
+    pub fn finalize(mut self) -> Result<AesBlock, Error> {
+        let subkey = if self.pending_len == BLOCK_SIZE {
+            self.k1
+        } else {
+            self.pending[self.pending_len..].fill(0x00);
+            self.pending[self.pending_len] = 0x80;
+            self.k2
+        };
+        xor(&mut self.state, &self.pending);
+        xor(&mut self.state, &subkey);
+        self.cipher.encrypt_block(&mut self.state)?;
+        Ok(self.state)
+    }
+}
+
+// Multiply by x in GF(2^128), the subkey derivation step.
+fn double(block: &AesBlock) -> AesBlock {
+    let mut doubled = AesBlock::default();
+    for i in 0..BLOCK_SIZE {
+        let carry = block.get(i + 1).map_or(0x00, |next| next >> 7);
+        doubled[i] = block[i] << 1 | carry;
+    }
+    if block[0] & 0x80 != 0x00 {
+        doubled[BLOCK_SIZE - 1] ^= 0x87;
+    }
+    doubled
+}
+
+fn blocks(buffer: &mut [u8]) -> Result<impl Iterator<Item = &mut [u8]>, Error> {
+    if !buffer.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(ErrorKind::InvalidSize.into());
+    }
+    Ok(buffer.chunks_mut(BLOCK_SIZE))
+}
+
+fn xor(dst: &mut [u8], src: &[u8]) {
+    dst.iter_mut().zip(src).for_each(|(d, s)| *d ^= s);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use ::aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+
+    // Stand-in for the device engine: XOR with a key, then rotate.
+    pub(crate) struct Toy(pub(crate) u8);
+
+    impl BlockCipher for Toy {
+        fn encrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+            block.iter_mut().for_each(|v| *v ^= self.0);
+            block.rotate_left(1);
+            Ok(())
+        }
+
+        fn decrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+            block.rotate_right(1);
+            block.iter_mut().for_each(|v| *v ^= self.0);
+            Ok(())
+        }
+    }
+
+    // Software AES-128, for known-answer tests.
+    pub(crate) struct Soft(::aes::Aes128);
+
+    impl Soft {
+        pub(crate) fn new(key: u128) -> Self {
+            Self(::aes::Aes128::new(&key.to_be_bytes().into()))
+        }
+    }
+
+    impl BlockCipher for Soft {
+        fn encrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+            self.0.encrypt_block(block.into());
+            Ok(())
+        }
+
+        fn decrypt_block(&mut self, block: &mut AesBlock) -> Result<(), Error> {
+            self.0.decrypt_block(block.into());
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "aead")]
+    impl ::aead::KeySizeUser for Soft {
+        type KeySize = ::aead::consts::U16;
+    }
+
+    #[cfg(feature = "aead")]
+    impl ::aead::KeyInit for Soft {
+        fn new(key: &::aead::Key<Self>) -> Self {
+            Self(::aes::Aes128::new(key))
+        }
+    }
+
+    // Bytes of a hex string, for test vectors.
+    pub(crate) fn bytes<const N: usize>(hex: &str) -> [u8; N] {
+        assert_eq!(hex.len(), 2 * N);
+        core::array::from_fn(|i| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap())
+    }
+
+    #[test]
+    fn cbc() {
+        let iv = [0x10; BLOCK_SIZE];
+        let plaintext: [u8; 48] = core::array::from_fn(|i| i as u8);
+        let mut buffer = plaintext;
+        let mut encryptor = CbcEncryptor::new(Toy(0x5a), &iv);
+        encryptor.update(&mut buffer[..16]).unwrap();
+        encryptor.update(&mut buffer[16..]).unwrap();
+
+        let mut first: AesBlock = core::array::from_fn(|i| i as u8 ^ 0x10);
+        Toy(0x5a).encrypt_block(&mut first).unwrap();
+        assert_eq!(buffer[..16], first);
+
+        CbcDecryptor::new(Toy(0x5a), &iv)
+            .update(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, plaintext);
+        assert!(CbcEncryptor::new(Toy(0x5a), &iv)
+            .update(&mut buffer[..15])
+            .is_err());
+    }
+
+    #[test]
+    fn cmac_subkeys() {
+        // RFC 4493, section 4.
+        let l = 0x7df76b0c1ab899b33e42f047b91b546fu128.to_be_bytes();
+        let k1 = double(&l);
+        assert_eq!(u128::from_be_bytes(k1), 0xfbeed618357133667c85e08f7236a8de);
+        assert_eq!(
+            u128::from_be_bytes(double(&k1)),
+            0xf7ddac306ae266ccf90bc11ee46d513b
+        );
+    }
+
+    #[test]
+    fn cmac() {
+        let mut l = AesBlock::default();
+        Toy(0x5a).encrypt_block(&mut l).unwrap();
+        let (k1, k2) = (double(&l), double(&double(&l)));
+
+        // A complete last block is masked with K1.
+        let mut cmac = Cmac::new(Toy(0x5a)).unwrap();
+        cmac.update(&[0x11; 10]).unwrap();
+        cmac.update(&[0x11; 6]).unwrap();
+        let mut expected = [0x11; BLOCK_SIZE];
+        xor(&mut expected, &k1);
+        Toy(0x5a).encrypt_block(&mut expected).unwrap();
+        assert_eq!(cmac.finalize().unwrap(), expected);
+
+        // A partial one is padded and masked with K2.
+        let mut cmac = Cmac::new(Toy(0x5a)).unwrap();
+        cmac.update(&[0x11; 20]).unwrap();
+        let mut state = [0x11; BLOCK_SIZE];
+        Toy(0x5a).encrypt_block(&mut state).unwrap();
+        let mut last = [0x00; BLOCK_SIZE];
+        last[..4].fill(0x11);
+        last[4] = 0x80;
+        xor(&mut state, &last);
+        xor(&mut state, &k2);
+        Toy(0x5a).encrypt_block(&mut state).unwrap();
+        assert_eq!(cmac.finalize().unwrap(), state);
+    }
+
+    // NIST SP 800-38B, appendix D.1, which RFC 4493 repeats.
+    #[test]
+    fn cmac_vectors() {
+        let key = 0x2b7e151628aed2a6abf7158809cf4f3c;
+        let message: [u8; 64] = bytes(concat!(
+            "6bc1bee22e409f96e93d7e117393172a",
+            "ae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52ef",
+            "f69f2445df4f9b17ad2b417be66c3710",
+        ));
+        let vectors = [
+            (0, 0xbb1d6929e95937287fa37d129b756746u128),
+            (16, 0x070a16b46b4d4144f79bdd9dd04a287c),
+            (40, 0xdfa66747de9ae63030ca32611497c827),
+            (64, 0x51f0bebf7e3b9d92fc49741779363cfe),
+        ];
+        for (len, mac) in vectors.iter() {
+            let mut cmac = Cmac::new(Soft::new(key)).unwrap();
+            cmac.update(&message[..*len]).unwrap();
+            assert_eq!(cmac.finalize().unwrap(), mac.to_be_bytes());
+        }
+    }
+
+    #[test]
+    fn ctr() {
+        let mut initial = [0x00; BLOCK_SIZE];
+        initial[15] = 0xff;
+        let plaintext = [0xa5; 40];
+        let mut buffer = plaintext;
+        let mut ctr = CtrCipher::new(Toy(0x5a), &initial, 2).unwrap();
+        ctr.apply(&mut buffer[..7]).unwrap();
+        ctr.apply(&mut buffer[7..]).unwrap();
+        // The counter carries into the next byte.
+        assert_eq!(ctr.counter[14..], [0x01, 0x02]);
+
+        let mut key_stream = initial;
+        Toy(0x5a).encrypt_block(&mut key_stream).unwrap();
+        assert_eq!(buffer[0], 0xa5 ^ key_stream[0]);
+
+        CtrCipher::new(Toy(0x5a), &initial, 2)
+            .unwrap()
+            .apply(&mut buffer)
+            .unwrap();
+        assert_eq!(buffer, plaintext);
+
+        // A one byte counter runs out after 256 blocks.
+        let mut ctr = CtrCipher::new(Toy(0x5a), &[0x00; BLOCK_SIZE], 1).unwrap();
+        assert!(ctr.apply(&mut [0x00; 256 * BLOCK_SIZE]).is_ok());
+        assert!(ctr.apply(&mut [0x00; BLOCK_SIZE]).is_err());
+    }
+}
@@ -0,0 +1,639 @@
+// Authenticated encryption built from the block cipher. CCM follows
+// NIST SP 800-38C and GCM SP 800-38D, with 96-bit nonces only. Both work in
+// place with a detached tag. With the aead feature they implement
+// aead::AeadInPlace, and KeyInit when the block cipher can be created from a
+// key. The cipher sits in a RefCell, as the trait takes shared references.
+use super::{xor, AesBlock, BlockCipher, CtrCipher, BLOCK_SIZE};
+use crate::error::{Error, ErrorKind};
+use core::cell::RefCell;
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use generic_array::typenum::{U10, U11, U12, U13, U14, U16, U4, U6, U7, U8, U9};
+use generic_array::ArrayLength;
+use heapless::Vec;
+
+#[cfg(feature = "aead")]
+use aead::{consts::U0, AeadCore, AeadInPlace, Key, KeyInit, KeySizeUser};
+
+/// Authentication tag, up to 16 bytes long.
+pub type Tag = Vec<u8, BLOCK_SIZE>;
+
+pub const GCM_NONCE_SIZE: usize = 12;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Tag sizes CCM allows: 4, 6, 8, 10, 12, 14 or 16 bytes.
+pub trait CcmTagSize: ArrayLength<u8> + sealed::Sealed {}
+
+/// Nonce sizes CCM allows: 7 to 13 bytes.
+pub trait CcmNonceSize: ArrayLength<u8> + sealed::Sealed {}
+
+macro_rules! sizes {
+    ($bound:path: $($size:ident),*) => {
+        $(impl $bound for $size {})*
+    };
+}
+
+sizes!(sealed::Sealed: U4, U6, U7, U8, U9, U10, U11, U12, U13, U14, U16);
+sizes!(CcmTagSize: U4, U6, U8, U10, U12, U14, U16);
+sizes!(CcmNonceSize: U7, U8, U9, U10, U11, U12, U13);
+
+/// AES-CCM with tags of `M` and nonces of `N` bytes.
+pub struct Ccm<C, M = U16, N = U13> {
+    cipher: RefCell<C>,
+    sizes: PhantomData<(M, N)>,
+}
+
+impl<C, M, N> Ccm<C, M, N>
+where
+    C: BlockCipher,
+    M: CcmTagSize,
+    N: CcmNonceSize,
+{
+    pub fn new(cipher: C) -> Self {
+        Self {
+            cipher: RefCell::new(cipher),
+            sizes: PhantomData,
+        }
+    }
+
+    pub fn encrypt_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<Tag, Error> {
+        Self::seal(&mut self.cipher.borrow_mut(), nonce, aad, buffer)
+    }
+
+    pub fn decrypt_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        Self::open(&mut self.cipher.borrow_mut(), nonce, aad, buffer, tag)
+    }
+
+    fn seal(cipher: &mut C, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Tag, Error> {
+        let nonce = Self::nonce(nonce)?;
+        let mac = cbc_mac(cipher, M::USIZE, nonce, aad, buffer)?;
+        apply(cipher, M::USIZE, nonce, mac, buffer)
+    }
+
+    fn open(
+        cipher: &mut C,
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        let nonce = Self::nonce(nonce)?;
+        // The keystream is the same both ways, and the MAC runs over the
+        // plaintext.
+        counter(&mut *cipher, nonce, 1)?.apply(buffer)?;
+        let mac = cbc_mac(cipher, M::USIZE, nonce, aad, buffer)?;
+        let expected = apply(cipher, M::USIZE, nonce, mac, &mut [])?;
+        verify(&expected, tag, buffer)
+    }
+
+    fn nonce(nonce: &[u8]) -> Result<&[u8], Error> {
+        if nonce.len() != N::USIZE {
+            return Err(ErrorKind::BadParam.into());
+        }
+        Ok(nonce)
+    }
+}
+
+// Encrypt `buffer` and the MAC, which becomes the tag.
+fn apply<C: BlockCipher>(
+    cipher: &mut C,
+    tag_len: usize,
+    nonce: &[u8],
+    mac: AesBlock,
+    buffer: &mut [u8],
+) -> Result<Tag, Error> {
+    let mut tag = mac;
+    counter(&mut *cipher, nonce, 0)?.apply(&mut tag)?;
+    counter(cipher, nonce, 1)?.apply(buffer)?;
+    Ok(tag[..tag_len].iter().copied().collect())
+}
+
+// Counter block A_i: flags, nonce and the counter in the remaining bytes.
+fn counter<C: BlockCipher>(cipher: C, nonce: &[u8], i: u8) -> Result<CtrCipher<C>, Error> {
+    let length_size = length_size(nonce)?;
+    let mut block = AesBlock::default();
+    block[0] = length_size as u8 - 1;
+    block[1..1 + nonce.len()].copy_from_slice(nonce);
+    block[BLOCK_SIZE - 1] = i;
+    CtrCipher::new(cipher, &block, length_size)
+}
+
+fn cbc_mac<C: BlockCipher>(
+    cipher: &mut C,
+    tag_len: usize,
+    nonce: &[u8],
+    aad: &[u8],
+    message: &[u8],
+) -> Result<AesBlock, Error> {
+    let length_size = length_size(nonce)?;
+    if length_size < 8 && message.len() >> (8 * length_size) != 0 {
+        return Err(ErrorKind::InvalidSize.into());
+    }
+
+    let mut b0 = AesBlock::default();
+    b0[0] = (!aad.is_empty() as u8) << 6 | ((tag_len as u8 - 2) / 2) << 3 | (length_size as u8 - 1);
+    b0[1..1 + nonce.len()].copy_from_slice(nonce);
+    let length = (message.len() as u64).to_be_bytes();
+    b0[BLOCK_SIZE - length_size..].copy_from_slice(&length[8 - length_size..]);
+
+    let mut mac = CbcMac::new(cipher);
+    mac.update(&b0)?;
+    if !aad.is_empty() {
+        match u16::try_from(aad.len()) {
+            Ok(len) if len < 0xff00 => mac.update(&len.to_be_bytes())?,
+            _ => {
+                let len =
+                    u32::try_from(aad.len()).map_err(|_| Error::from(ErrorKind::InvalidSize))?;
+                mac.update(&[0xff, 0xfe])?;
+                mac.update(&len.to_be_bytes())?;
+            }
+        }
+        mac.update(aad)?;
+        mac.pad()?;
+    }
+    mac.update(message)?;
+    mac.pad()?;
+    Ok(mac.state)
+}
+
+// Size of the length field, and of the counter, given the nonce.
+fn length_size(nonce: &[u8]) -> Result<usize, Error> {
+    match nonce.len() {
+        7..=13 => Ok(BLOCK_SIZE - 1 - nonce.len()),
+        _ => Err(ErrorKind::BadParam.into()),
+    }
+}
+
+// CBC-MAC over zero padded segments.
+struct CbcMac<'c, C> {
+    cipher: &'c mut C,
+    state: AesBlock,
+    filled: usize,
+}
+
+impl<'c, C: BlockCipher> CbcMac<'c, C> {
+    fn new(cipher: &'c mut C) -> Self {
+        Self {
+            cipher,
+            state: AesBlock::default(),
+            filled: 0,
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) -> Result<(), Error> {
+        for byte in data {
+            self.state[self.filled] ^= byte;
+            self.filled += 1;
+            if self.filled == BLOCK_SIZE {
+                self.cipher.encrypt_block(&mut self.state)?;
+                self.filled = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn pad(&mut self) -> Result<(), Error> {
+        if self.filled != 0 {
+            self.cipher.encrypt_block(&mut self.state)?;
+            self.filled = 0;
+        }
+        Ok(())
+    }
+}
+
+/// AES-GCM with 12-byte nonces and 16-byte tags.
+pub struct Gcm<C> {
+    cipher: RefCell<C>,
+}
+
+impl<C: BlockCipher> Gcm<C> {
+    pub fn new(cipher: C) -> Self {
+        Self {
+            cipher: RefCell::new(cipher),
+        }
+    }
+
+    pub fn encrypt_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<Tag, Error> {
+        Self::seal(&mut self.cipher.borrow_mut(), nonce, aad, buffer)
+    }
+
+    pub fn decrypt_in_place_detached(
+        &self,
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        Self::open(&mut self.cipher.borrow_mut(), nonce, aad, buffer, tag)
+    }
+
+    fn seal(cipher: &mut C, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Tag, Error> {
+        let j0 = j0(nonce)?;
+        CtrCipher::new(&mut *cipher, &inc32(&j0), 4)?.apply(buffer)?;
+        ghash_tag(cipher, &j0, aad, buffer)
+    }
+
+    fn open(
+        cipher: &mut C,
+        nonce: &[u8],
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        let j0 = j0(nonce)?;
+        let expected = ghash_tag(cipher, &j0, aad, buffer)?;
+        verify(&expected, tag, &mut [])?;
+        CtrCipher::new(cipher, &inc32(&j0), 4)?.apply(buffer)
+    }
+}
+
+fn ghash_tag<C: BlockCipher>(
+    cipher: &mut C,
+    j0: &AesBlock,
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Tag, Error> {
+    let mut h = AesBlock::default();
+    cipher.encrypt_block(&mut h)?;
+
+    let mut y = AesBlock::default();
+    let mut lengths = AesBlock::default();
+    lengths[..8].copy_from_slice(&(aad.len() as u64 * 8).to_be_bytes());
+    lengths[8..].copy_from_slice(&(ciphertext.len() as u64 * 8).to_be_bytes());
+    for block in aad
+        .chunks(BLOCK_SIZE)
+        .chain(ciphertext.chunks(BLOCK_SIZE))
+        .chain(core::iter::once(&lengths[..]))
+    {
+        xor(&mut y, block);
+        y = cipher.gf_multiply(&h, &y)?;
+    }
+
+    let mut tag = *j0;
+    cipher.encrypt_block(&mut tag)?;
+    xor(&mut tag, &y);
+    Ok(tag.iter().copied().collect())
+}
+
+#[cfg(feature = "aead")]
+impl<C, M, N> AeadCore for Ccm<C, M, N>
+where
+    M: CcmTagSize,
+    N: CcmNonceSize,
+{
+    type NonceSize = N;
+    type TagSize = M;
+    type CiphertextOverhead = U0;
+}
+
+#[cfg(feature = "aead")]
+impl<C, M, N> AeadInPlace for Ccm<C, M, N>
+where
+    C: BlockCipher,
+    M: CcmTagSize,
+    N: CcmNonceSize,
+{
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<aead::Tag<Self>> {
+        let tag = Self::seal(&mut self.cipher.borrow_mut(), nonce, aad, buffer)
+            .map_err(|_| aead::Error)?;
+        Ok(aead::Tag::<Self>::clone_from_slice(&tag))
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &aead::Tag<Self>,
+    ) -> aead::Result<()> {
+        Self::open(&mut self.cipher.borrow_mut(), nonce, aad, buffer, tag).map_err(|_| aead::Error)
+    }
+}
+
+#[cfg(feature = "aead")]
+impl<C: KeySizeUser, M, N> KeySizeUser for Ccm<C, M, N> {
+    type KeySize = C::KeySize;
+}
+
+#[cfg(feature = "aead")]
+impl<C, M, N> KeyInit for Ccm<C, M, N>
+where
+    C: BlockCipher + KeyInit,
+    M: CcmTagSize,
+    N: CcmNonceSize,
+{
+    fn new(key: &Key<Self>) -> Self {
+        Self::new(C::new(key))
+    }
+}
+
+#[cfg(feature = "aead")]
+impl<C> AeadCore for Gcm<C> {
+    type NonceSize = aead::consts::U12;
+    type TagSize = aead::consts::U16;
+    type CiphertextOverhead = U0;
+}
+
+#[cfg(feature = "aead")]
+impl<C: BlockCipher> AeadInPlace for Gcm<C> {
+    fn encrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buffer: &mut [u8],
+    ) -> aead::Result<aead::Tag<Self>> {
+        let tag = Self::seal(&mut self.cipher.borrow_mut(), nonce, aad, buffer)
+            .map_err(|_| aead::Error)?;
+        Ok(aead::Tag::<Self>::clone_from_slice(&tag))
+    }
+
+    fn decrypt_in_place_detached(
+        &self,
+        nonce: &aead::Nonce<Self>,
+        aad: &[u8],
+        buffer: &mut [u8],
+        tag: &aead::Tag<Self>,
+    ) -> aead::Result<()> {
+        Self::open(&mut self.cipher.borrow_mut(), nonce, aad, buffer, tag).map_err(|_| aead::Error)
+    }
+}
+
+#[cfg(feature = "aead")]
+impl<C: KeySizeUser> KeySizeUser for Gcm<C> {
+    type KeySize = C::KeySize;
+}
+
+#[cfg(feature = "aead")]
+impl<C: BlockCipher + KeyInit> KeyInit for Gcm<C> {
+    fn new(key: &Key<Self>) -> Self {
+        Self::new(C::new(key))
+    }
+}
+
+fn j0(nonce: &[u8]) -> Result<AesBlock, Error> {
+    if nonce.len() != GCM_NONCE_SIZE {
+        return Err(ErrorKind::BadParam.into());
+    }
+    let mut j0 = AesBlock::default();
+    j0[..GCM_NONCE_SIZE].copy_from_slice(nonce);
+    j0[BLOCK_SIZE - 1] = 0x01;
+    Ok(j0)
+}
+
+fn inc32(block: &AesBlock) -> AesBlock {
+    let mut next = *block;
+    let counter = u32::from_be_bytes([block[12], block[13], block[14], block[15]]);
+    next[12..].copy_from_slice(&counter.wrapping_add(1).to_be_bytes());
+    next
+}
+
+// Compare tags in constant time. On a mismatch the buffer is cleared so no
+// unauthenticated plaintext is handed out.
+fn verify(expected: &[u8], tag: &[u8], buffer: &mut [u8]) -> Result<(), Error> {
+    let diff = expected
+        .iter()
+        .zip(tag)
+        .fold(0x00, |diff, (a, b)| diff | (a ^ b));
+    if diff != 0x00 || expected.len() != tag.len() {
+        buffer.fill(0x00);
+        return Err(ErrorKind::AuthenticationFailed.into());
+    }
+    Ok(())
+}
+
+// Software GHASH multiplication, SP 800-38D algorithm 1.
+pub(crate) fn gf_multiply(x: &AesBlock, y: &AesBlock) -> AesBlock {
+    let x = u128::from_be_bytes(*x);
+    let mut v = u128::from_be_bytes(*y);
+    let mut z = 0u128;
+    for i in 0..128 {
+        if x >> (127 - i) & 0x01 != 0x00 {
+            z ^= v;
+        }
+        v = if v & 0x01 != 0x00 {
+            v >> 1 ^ 0xe1 << 120
+        } else {
+            v >> 1
+        };
+    }
+    z.to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aes::tests::{bytes, Soft, Toy};
+
+    #[test]
+    fn gf() {
+        let x = 0x66e94bd4ef8a2c3b884cfa59ca342b2eu128.to_be_bytes();
+        let y = 0x0388dace60b6a392f328c2b971b2fe78u128.to_be_bytes();
+        let z = 0x1234567890abcdef1234567890abcdefu128.to_be_bytes();
+        // The multiplicative identity has only the leftmost bit set.
+        let one = (1u128 << 127).to_be_bytes();
+        assert_eq!(gf_multiply(&x, &one), x);
+        assert_eq!(gf_multiply(&x, &y), gf_multiply(&y, &x));
+        let mut y_z = y;
+        xor(&mut y_z, &z);
+        let mut expected = gf_multiply(&x, &y);
+        xor(&mut expected, &gf_multiply(&x, &z));
+        assert_eq!(gf_multiply(&x, &y_z), expected);
+        // H and C of GCM test case 2 in the original specification.
+        assert_eq!(
+            gf_multiply(&x, &y),
+            0x5e2ec746917062882c85b0685353deb7u128.to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn ccm() {
+        let nonce = [0x10; 13];
+        let plaintext = [0x42; 23];
+        let mut buffer = plaintext;
+        let ccm = Ccm::<_, U16, U13>::new(Toy(0x5a));
+        let tag = ccm
+            .encrypt_in_place_detached(&nonce, b"header", &mut buffer)
+            .unwrap();
+        assert_eq!(tag.len(), 16);
+        assert_ne!(buffer, plaintext);
+
+        let mut tampered = buffer;
+        tampered[0] ^= 0x01;
+        assert!(ccm
+            .decrypt_in_place_detached(&nonce, b"header", &mut tampered, &tag)
+            .is_err());
+        assert_eq!(tampered, [0x00; 23]);
+        assert!(ccm
+            .decrypt_in_place_detached(&nonce, b"other", &mut buffer.clone(), &tag)
+            .is_err());
+
+        ccm.decrypt_in_place_detached(&nonce, b"header", &mut buffer, &tag)
+            .unwrap();
+        assert_eq!(buffer, plaintext);
+        assert!(ccm
+            .encrypt_in_place_detached(&[0x00; 6], &[], &mut buffer)
+            .is_err());
+    }
+
+    #[test]
+    fn gcm() {
+        let nonce = [0x10; GCM_NONCE_SIZE];
+        let plaintext = [0x42; 37];
+        let mut buffer = plaintext;
+        let gcm = Gcm::new(Toy(0x5a));
+        let tag = gcm
+            .encrypt_in_place_detached(&nonce, b"header", &mut buffer)
+            .unwrap();
+
+        // The first block is XORed with E(J0 + 1).
+        let mut key_stream = j0(&nonce).unwrap();
+        key_stream[BLOCK_SIZE - 1] = 0x02;
+        Toy(0x5a).encrypt_block(&mut key_stream).unwrap();
+        assert_eq!(buffer[0], 0x42 ^ key_stream[0]);
+
+        let mut tampered = tag.clone();
+        tampered[15] ^= 0x01;
+        assert!(gcm
+            .decrypt_in_place_detached(&nonce, b"header", &mut buffer.clone(), &tampered)
+            .is_err());
+        gcm.decrypt_in_place_detached(&nonce, b"header", &mut buffer, &tag)
+            .unwrap();
+        assert_eq!(buffer, plaintext);
+    }
+
+    // NIST SP 800-38C, appendix C, examples 1 to 3.
+    #[test]
+    fn ccm_vectors() {
+        let key = 0x404142434445464748494a4b4c4d4e4f;
+
+        let ccm = Ccm::<_, U4, U7>::new(Soft::new(key));
+        let mut buffer: [u8; 4] = bytes("20212223");
+        let tag = ccm
+            .encrypt_in_place_detached(
+                &bytes::<7>("10111213141516"),
+                &bytes::<8>("0001020304050607"),
+                &mut buffer,
+            )
+            .unwrap();
+        assert_eq!(buffer, bytes::<4>("7162015b"));
+        assert_eq!(tag, bytes::<4>("4dac255d"));
+
+        let ccm = Ccm::<_, U6, U8>::new(Soft::new(key));
+        let mut buffer: [u8; 16] = bytes("202122232425262728292a2b2c2d2e2f");
+        let aad: [u8; 16] = bytes("000102030405060708090a0b0c0d0e0f");
+        let nonce: [u8; 8] = bytes("1011121314151617");
+        let tag = ccm
+            .encrypt_in_place_detached(&nonce, &aad, &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, bytes::<16>("d2a1f0e051ea5f62081a7792073d593d"));
+        assert_eq!(tag, bytes::<6>("1fc64fbfaccd"));
+        ccm.decrypt_in_place_detached(&nonce, &aad, &mut buffer, &tag)
+            .unwrap();
+        assert_eq!(buffer, bytes::<16>("202122232425262728292a2b2c2d2e2f"));
+
+        let ccm = Ccm::<_, U8, U12>::new(Soft::new(key));
+        let mut buffer: [u8; 24] = bytes("202122232425262728292a2b2c2d2e2f3031323334353637");
+        let aad: [u8; 20] = bytes("000102030405060708090a0b0c0d0e0f10111213");
+        let tag = ccm
+            .encrypt_in_place_detached(&bytes::<12>("101112131415161718191a1b"), &aad, &mut buffer)
+            .unwrap();
+        assert_eq!(
+            buffer,
+            bytes::<24>("e3b201a9f5b71a7a9b1ceaeccd97e70b6176aad9a4428aa5")
+        );
+        assert_eq!(tag, bytes::<8>("484392fbc1b09951"));
+    }
+
+    // Test cases 2 and 4 of the GCM specification, which NIST's validation
+    // vectors include.
+    #[test]
+    fn gcm_vectors() {
+        let gcm = Gcm::new(Soft::new(0));
+        let mut buffer = [0x00; 16];
+        let tag = gcm
+            .encrypt_in_place_detached(&[0x00; GCM_NONCE_SIZE], &[], &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, 0x0388dace60b6a392f328c2b971b2fe78u128.to_be_bytes());
+        assert_eq!(tag, 0xab6e47d42cec13bdf53a67b21257bddfu128.to_be_bytes());
+
+        let gcm = Gcm::new(Soft::new(0xfeffe9928665731c6d6a8f9467308308));
+        let nonce: [u8; GCM_NONCE_SIZE] = bytes("cafebabefacedbaddecaf888");
+        let aad: [u8; 20] = bytes("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+        let plaintext: [u8; 60] = bytes(concat!(
+            "d9313225f88406e5a55909c5aff5269a",
+            "86a7a9531534f7da2e4c303d8a318a72",
+            "1c3c0c95956809532fcf0e2449a6b525",
+            "b16aedf5aa0de657ba637b39",
+        ));
+        let mut buffer = plaintext;
+        let tag = gcm
+            .encrypt_in_place_detached(&nonce, &aad, &mut buffer)
+            .unwrap();
+        let ciphertext: [u8; 60] = bytes(concat!(
+            "42831ec2217774244b7221b784d0d49c",
+            "e3aa212f2c02a4e035c17e2329aca12e",
+            "21d514b25466931c7d8f6a5aac84aa05",
+            "1ba30b396a0aac973d58e091",
+        ));
+        assert_eq!(buffer, ciphertext);
+        assert_eq!(tag, 0x5bc94fbc3221a5db94fae95ae7121a47u128.to_be_bytes());
+        gcm.decrypt_in_place_detached(&nonce, &aad, &mut buffer, &tag)
+            .unwrap();
+        assert_eq!(buffer, plaintext);
+    }
+
+    #[cfg(feature = "aead")]
+    #[test]
+    fn aead_traits() {
+        use aead::{AeadInPlace, KeyInit};
+
+        let key = 0xfeffe9928665731c6d6a8f9467308308u128.to_be_bytes();
+        let gcm = <Gcm<Soft> as KeyInit>::new(&key.into());
+        let nonce = bytes::<GCM_NONCE_SIZE>("cafebabefacedbaddecaf888").into();
+        let mut buffer = Vec::<u8, 64>::new();
+        buffer.extend_from_slice(b"plaintext").unwrap();
+        gcm.encrypt_in_place(&nonce, b"header", &mut buffer)
+            .unwrap();
+        assert_eq!(buffer.len(), 9 + 16);
+        gcm.decrypt_in_place(&nonce, b"header", &mut buffer)
+            .unwrap();
+        assert_eq!(buffer, b"plaintext");
+
+        // The inherent methods take precedence, so call through the trait.
+        let ccm = Ccm::<_, U8, U12>::new(Soft::new(0x404142434445464748494a4b4c4d4e4f));
+        let nonce = bytes::<12>("101112131415161718191a1b").into();
+        let mut buffer: [u8; 24] = bytes("202122232425262728292a2b2c2d2e2f3031323334353637");
+        let aad: [u8; 20] = bytes("000102030405060708090a0b0c0d0e0f10111213");
+        let tag = AeadInPlace::encrypt_in_place_detached(&ccm, &nonce, &aad, &mut buffer).unwrap();
+        assert_eq!(tag.as_slice(), bytes::<8>("484392fbc1b09951"));
+        let mut tampered = tag;
+        tampered[0] ^= 0x01;
+        let result =
+            AeadInPlace::decrypt_in_place_detached(&ccm, &nonce, &aad, &mut buffer, &tampered);
+        assert!(result.is_err());
+    }
+}
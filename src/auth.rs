@@ -0,0 +1,107 @@
+// Slots with KeyConfig.ReqAuth set can only be used once CheckMac has
+// succeeded against the slot named by their AuthKey. The device keeps that
+// authorization until it sleeps. An authorization session runs the CheckMac
+// and lets slot operations that need it through; without one, the client
+// rejects them before sending anything, provided it knows the slot
+// configuration from a config zone read.
+use super::client::{AtCaClient, BUFFER_SIZE};
+use super::command::{Block, MacMode};
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind, Status};
+use super::host;
+use super::memory::Slot;
+use core::convert::TryFrom;
+use core::ops::{Deref, DerefMut};
+use embedded_hal::delay::DelayNs;
+
+// OtherData of the authorizing CheckMac. Any value works as long as the
+// host digest uses the same.
+const OTHER_DATA: [u8; 13] = [0x00; 13];
+
+pub struct AuthSession<'a, PHY, D, const N: usize = BUFFER_SIZE>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    atca: &'a mut AtCaClient<PHY, D, N>,
+    auth_key: Slot,
+}
+
+impl<'a, PHY, D, const N: usize> AuthSession<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    // Prove knowledge of `key`, the secret held in `auth_key`, with CheckMac
+    // over a fresh random challenge.
+    pub(crate) fn new(
+        atca: &'a mut AtCaClient<PHY, D, N>,
+        auth_key: Slot,
+        key: &Block,
+    ) -> Result<Self, Error> {
+        authorize(atca, auth_key, key)?;
+        Ok(Self { atca, auth_key })
+    }
+
+    /// Slot whose key authorized the session.
+    pub fn auth_key(&self) -> Slot {
+        self.auth_key
+    }
+}
+
+// Run the authorizing CheckMac and record the authorization on the host.
+pub(crate) fn authorize<PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    auth_key: Slot,
+    key: &Block,
+) -> Result<(), Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    let serial = atca.memory().serial_number()?;
+    let challenge = atca.random()?;
+    let mode = MacMode::default();
+    let digest = host::check_mac(mode, key, &challenge, &OTHER_DATA, &serial, None)
+        .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+    let response = Block::try_from(digest.as_ref())?;
+    if !atca.checkmac(auth_key, mode, &challenge, &response, &OTHER_DATA)? {
+        return Err(Status::CheckmacVerifyFailed.into());
+    }
+    atca.authorized.replace(auth_key);
+    Ok(())
+}
+
+impl<'a, PHY, D, const N: usize> Deref for AuthSession<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    type Target = AtCaClient<PHY, D, N>;
+
+    fn deref(&self) -> &Self::Target {
+        self.atca
+    }
+}
+
+impl<'a, PHY, D, const N: usize> DerefMut for AuthSession<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.atca
+    }
+}
+
+// Only the host side ends here. The device stays authorized until it
+// sleeps; call `sleep` on the session to revoke it there too.
+impl<'a, PHY, D, const N: usize> Drop for AuthSession<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn drop(&mut self) {
+        self.atca.authorized.take();
+    }
+}
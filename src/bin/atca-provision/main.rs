@@ -0,0 +1,165 @@
+// Factory provisioning tool for devices on a Linux I2C bus.
+//
+// $ cargo build --release --features cli --bin atca-provision
+// $ atca-provision --bus /dev/i2c-1 dump-config
+// $ atca-provision apply template.toml --lock
+// $ atca-provision genkey 0
+// $ atca-provision csr 0 "Device 0001" device.csr
+// $ atca-provision write-cert 10 device.der
+// $ atca-provision lock data
+//
+// See `template.rs` for the configuration template format.
+mod template;
+
+use at_cryptoauth::cert::{create_csr, Attribute};
+use at_cryptoauth::linux::LinuxI2c;
+use at_cryptoauth::memory::{LockToken, Zone};
+use at_cryptoauth::AtCaClient;
+use linux_embedded_hal::{Delay, I2cdev};
+use std::process::ExitCode;
+use std::{env, fs};
+
+const USAGE: &str = "\
+usage: atca-provision [--bus PATH] [--address ADDR] COMMAND
+
+commands:
+    dump-config                 print the config zone and lock state
+    apply TEMPLATE [--lock]     write a configuration template
+    genkey SLOT                 generate a private key, print its SEC1 public key
+    pubkey SLOT                 print the SEC1 public key of a private key slot
+    csr SLOT COMMON_NAME FILE   write a DER signing request for a slot key
+    write-cert SLOT FILE        write a DER certificate into a slot
+    lock config|data|SLOT       lock a zone or a single slot";
+
+type Client = AtCaClient<LinuxI2c<I2cdev>, Delay>;
+
+// Unit of slot writes.
+const WORD_SIZE: usize = 4;
+
+fn main() -> ExitCode {
+    match run(env::args().skip(1).collect()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("atca-provision: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(mut args: Vec<String>) -> Result<(), String> {
+    let bus = option(&mut args, "--bus")?.unwrap_or_else(|| "/dev/i2c-1".into());
+    let address = match option(&mut args, "--address")? {
+        Some(address) => template::integer(&address)? as u8,
+        None => 0x60,
+    };
+    let lock = flag(&mut args, "--lock");
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if args.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let i2c = I2cdev::new(&bus).map_err(|e| format!("{}: {}", bus, e))?;
+    let mut atca = AtCaClient::new(LinuxI2c::new(i2c), Delay).with_address(address);
+    let result = match args.as_slice() {
+        ["dump-config"] => dump_config(&mut atca),
+        ["apply", path] => apply(&mut atca, path, lock),
+        ["genkey", slot] => {
+            let slot = template::parse_slot(slot)?;
+            let public_key = atca.generate_key(slot).map_err(device)?;
+            println!("04{}", hex(public_key));
+            Ok(())
+        }
+        ["pubkey", slot] => {
+            let slot = template::parse_slot(slot)?;
+            let public_key = atca.public_key(slot).map_err(device)?;
+            println!("04{}", hex(public_key));
+            Ok(())
+        }
+        ["csr", slot, common_name, path] => {
+            let slot = template::parse_slot(slot)?;
+            let subject = [(Attribute::CommonName, *common_name)];
+            let csr = create_csr(&mut atca, slot, &subject).map_err(device)?;
+            fs::write(path, &csr).map_err(|e| format!("{}: {}", path, e))
+        }
+        ["write-cert", slot, path] => write_cert(&mut atca, slot, path),
+        ["lock", "config"] => atca
+            .memory()
+            .lock(Zone::Config, LockToken::i_understand_this_is_irreversible())
+            .map_err(device),
+        ["lock", "data"] => atca
+            .memory()
+            .lock(Zone::Data, LockToken::i_understand_this_is_irreversible())
+            .map_err(device),
+        ["lock", slot] => {
+            let slot = template::parse_slot(slot)?;
+            atca.memory()
+                .lock_slot(slot, LockToken::i_understand_this_is_irreversible())
+                .map_err(device)
+        }
+        _ => Err(USAGE.into()),
+    };
+    atca.sleep().ok();
+    result
+}
+
+fn dump_config(atca: &mut Client) -> Result<(), String> {
+    let config = atca.memory().config_zone().map_err(device)?;
+    for (index, row) in config.as_bytes().chunks(16).enumerate() {
+        println!("{:02x}: {}", index * 16, hex(row));
+    }
+    println!("config zone locked: {}", config.is_config_locked());
+    println!("data zone locked: {}", config.is_data_locked());
+    Ok(())
+}
+
+fn apply(atca: &mut Client, path: &str, lock: bool) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let provisioner = template::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+    atca.memory()
+        .provision(
+            &provisioner,
+            lock.then(LockToken::i_understand_this_is_irreversible),
+        )
+        .map_err(device)?;
+    Ok(())
+}
+
+// Slots are written a word at a time, so the certificate is padded with
+// zeros. DER carries its own length, which tells where it ends.
+fn write_cert(atca: &mut Client, slot: &str, path: &str) -> Result<(), String> {
+    let slot = template::parse_slot(slot)?;
+    let mut der = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    der.resize(der.len().next_multiple_of(WORD_SIZE), 0x00);
+    atca.memory().write_slot(slot, &der).map_err(device)
+}
+
+// Value of `--name VALUE`, removed from `args`.
+fn option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == name) {
+        Some(index) if index + 1 < args.len() => {
+            let value = args.remove(index + 1);
+            args.remove(index);
+            Ok(Some(value))
+        }
+        Some(_) => Err(format!("{} needs a value", name)),
+        None => Ok(None),
+    }
+}
+
+fn flag(args: &mut Vec<String>, name: &str) -> bool {
+    let count = args.len();
+    args.retain(|arg| arg != name);
+    args.len() != count
+}
+
+fn device(error: at_cryptoauth::error::Error) -> String {
+    format!("device error: {}", error)
+}
+
+fn hex(bytes: impl AsRef<[u8]>) -> String {
+    bytes
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
@@ -0,0 +1,139 @@
+// Configuration templates, written in a small subset of TOML:
+//
+//     # Start from a known layout, "trust-and-go" or "trust-flex".
+//     preset = "trust-flex"
+//     chip_options = 0x0000
+//
+//     [slots]
+//     0 = "private-key"
+//     2 = "private-key,ecdh,regenerable"
+//     5 = "aes-key,write-key=6"
+//     8 = "certificate"
+//
+// Slot purposes are named after `SlotPurpose` variants. Options follow the
+// purpose, separated by commas.
+use at_cryptoauth::memory::Slot;
+use at_cryptoauth::provision::{Provisioner, SlotPurpose};
+
+pub fn parse(text: &str) -> Result<Provisioner, String> {
+    let mut provisioner = Provisioner::new();
+    let mut section = "";
+    for (number, line) in text.lines().enumerate() {
+        let context = |error: String| format!("line {}: {}", number + 1, error);
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim();
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim()))
+            .ok_or_else(|| context("expected key = value".into()))?;
+        provisioner = match (section, key) {
+            ("", "preset") => match string(value).map_err(context)? {
+                "trust-and-go" => Provisioner::trust_and_go(),
+                "trust-flex" => Provisioner::trust_flex(),
+                preset => return Err(context(format!("unknown preset {}", preset))),
+            },
+            ("", "chip_options") => provisioner.chip_options(integer(value).map_err(context)?),
+            ("slots", slot) => {
+                let slot = parse_slot(slot).map_err(context)?;
+                let purpose = purpose(string(value).map_err(context)?).map_err(context)?;
+                provisioner.slot(slot, purpose)
+            }
+            (section, key) => return Err(context(format!("unknown key {}.{}", section, key))),
+        };
+    }
+    Ok(provisioner)
+}
+
+pub fn parse_slot(value: &str) -> Result<Slot, String> {
+    let index = integer(value)?;
+    Slot::keys()
+        .nth(usize::from(index))
+        .ok_or_else(|| format!("no slot {}", value))
+}
+
+pub fn integer(value: &str) -> Result<u16, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| format!("invalid number {}", value))
+}
+
+fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(position) if line[..position].matches('"').count().is_multiple_of(2) => {
+            &line[..position]
+        }
+        _ => line,
+    }
+}
+
+fn string(value: &str) -> Result<&str, String> {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a quoted string, found {}", value))
+}
+
+fn purpose(value: &str) -> Result<SlotPurpose, String> {
+    let mut items = value.split(',').map(str::trim);
+    let name = items.next().unwrap_or_default();
+    let mut ecdh = false;
+    let mut regenerable = false;
+    let mut write_key = None;
+    for option in items {
+        match option.split_once('=') {
+            Some(("write-key", slot)) => write_key = Some(parse_slot(slot.trim())?),
+            None if option == "ecdh" => ecdh = true,
+            None if option == "regenerable" => regenerable = true,
+            _ => return Err(format!("unknown option {}", option)),
+        }
+    }
+    let purpose = match name {
+        "private-key" => SlotPurpose::PrivateKey { ecdh, regenerable },
+        "internal-sign-key" => SlotPurpose::InternalSignKey,
+        "aes-key" => SlotPurpose::AesKey { write_key },
+        "secret" => SlotPurpose::Secret { write_key },
+        "io-protection-key" => SlotPurpose::IoProtectionKey,
+        "public-key" => SlotPurpose::PublicKey,
+        "certificate" => SlotPurpose::Certificate,
+        "read-only-data" => SlotPurpose::ReadOnlyData,
+        _ => return Err(format!("unknown slot purpose {}", name)),
+    };
+    Ok(purpose)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template() {
+        let text = r#"
+            preset = "trust-flex" # TrustFLEX layout
+            chip_options = 0x0000
+
+            [slots]
+            2 = "private-key, ecdh,regenerable"
+            0x05 = "aes-key,write-key=6"
+        "#;
+        assert!(parse(text).is_ok());
+        assert_eq!(parse_slot("0x0f"), Ok(Slot::Certificate0f));
+        assert_eq!(
+            purpose("private-key,ecdh"),
+            Ok(SlotPurpose::PrivateKey {
+                ecdh: true,
+                regenerable: false
+            })
+        );
+        assert!(parse("[slots]\n16 = \"secret\"").is_err());
+        assert!(parse("[slots]\n1 = \"private-key,shared\"").is_err());
+        assert!(parse("preset = trust-flex").is_err());
+    }
+}
@@ -0,0 +1,21 @@
+// Compressed certificates. Microchip's pre-provisioned devices keep X.509
+// certificates in a 72-byte form holding only what differs between devices:
+// the signature, validity dates and the signer ID. Everything else comes
+// from a template known to the host. A certificate definition names where
+// each of those elements sits in the template, and rebuilding a certificate
+// is a matter of filling them in.
+//
+// Certificate signing requests and child certificates are assembled from
+// scratch and signed with a slot key, which makes the device a small CA.
+mod ca;
+mod compressed;
+mod csr;
+mod def;
+mod der;
+mod name;
+
+pub use ca::{sign_certificate, TbsCertificate};
+pub use compressed::{CompressedCertificate, Date, DateFormat, COMPRESSED_SIZE};
+pub use csr::create_csr;
+pub use def::{CertDef, Certificate, DateLocation, Location, SnSource, CERT_SIZE_MAX};
+pub use name::{Attribute, Name};
@@ -0,0 +1,122 @@
+use super::compressed::{Date, DateFormat};
+use super::csr::signed;
+use super::def::Certificate;
+use super::der::{Writer, INTEGER, SEQUENCE};
+use super::name::Name;
+use crate::client::AtCaClient;
+use crate::command::PublicKey;
+use crate::datalink::Interface;
+use crate::error::Error;
+use crate::memory::Slot;
+use embedded_hal::delay::DelayNs;
+
+// Explicit version tag, context specific and constructed.
+const VERSION: u8 = 0xa0;
+
+/// Fields of an X.509 v3 certificate without extensions.
+#[derive(Clone, Copy, Debug)]
+pub struct TbsCertificate<'a> {
+    /// Positive big-endian integer, at most 20 bytes.
+    pub serial_number: &'a [u8],
+    pub issuer: &'a Name<'a>,
+    pub subject: &'a Name<'a>,
+    pub not_before: Date,
+    pub not_after: Date,
+    pub public_key: &'a PublicKey,
+}
+
+impl<'a> TbsCertificate<'a> {
+    /// DER encoded TBSCertificate, ready for `sign_certificate`.
+    pub fn encode(&self) -> Result<Certificate, Error> {
+        let mut tbs = Certificate::new();
+        Writer::new(&mut tbs).constructed(SEQUENCE, |w| {
+            w.constructed(VERSION, |w| w.primitive(INTEGER, &[0x02]))?;
+            w.unsigned(self.serial_number)?;
+            w.signature_algorithm()?;
+            w.name(self.issuer)?;
+            w.constructed(SEQUENCE, |w| {
+                time(w, &self.not_before)?;
+                time(w, &self.not_after)
+            })?;
+            w.name(self.subject)?;
+            w.public_key_info(self.public_key)
+        })?;
+        Ok(tbs)
+    }
+}
+
+/// Sign a DER encoded TBSCertificate with the CA key in `key_id` and return
+/// the complete certificate. The hash is computed on the device.
+pub fn sign_certificate<PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    key_id: Slot,
+    tbs: &[u8],
+) -> Result<Certificate, Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    let digest = atca.sha().digest(tbs)?;
+    let signature = atca.sign(key_id, &digest)?;
+    signed(tbs, &signature)
+}
+
+// Validity times use UTCTime through 2049 and GeneralizedTime from 2050,
+// as RFC 5280 requires.
+fn time(w: &mut Writer<'_>, date: &Date) -> Result<(), Error> {
+    let (tag, format) = match date.year {
+        1950..=2049 => (0x17, DateFormat::UtcTime),
+        _ => (0x18, DateFormat::GeneralizedTime),
+    };
+    let mut text = [0x00; 15];
+    let text = &mut text[..format.encoded_len()];
+    format.encode(date, text)?;
+    w.primitive(tag, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cert::Attribute;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn tbs() {
+        let public_key = PublicKey::try_from([0x11; 64].as_ref()).unwrap();
+        let not_before = Date {
+            year: 2024,
+            month: 1,
+            day: 2,
+            ..Date::default()
+        };
+        let tbs = TbsCertificate {
+            serial_number: &[0x00, 0x81],
+            issuer: &[(Attribute::CommonName, "CA")],
+            subject: &[(Attribute::CommonName, "node")],
+            not_before,
+            not_after: Date {
+                year: 2054,
+                ..not_before
+            },
+            public_key: &public_key,
+        }
+        .encode()
+        .unwrap();
+
+        assert_eq!(&tbs[..3], &[0x30, 0x81, 0xb2]);
+        // Version 3, then the serial number padded to stay positive.
+        assert_eq!(
+            &tbs[3..12],
+            &[0xa0, 0x03, 0x02, 0x01, 0x02, 0x02, 0x02, 0x00, 0x81]
+        );
+        let validity = [
+            &[0x30, 0x20, 0x17, 0x0d][..],
+            b"240102000000Z",
+            &[0x18, 0x0f],
+            b"20540102000000Z",
+        ]
+        .concat();
+        assert!(tbs.windows(validity.len()).any(|w| w == &validity[..]));
+        assert!(tbs.ends_with(public_key.as_ref()));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::command::Signature;
+use crate::error::{Error, ErrorKind};
+use core::convert::TryFrom;
+
+pub const COMPRESSED_SIZE: usize = 72;
+
+// Layout of the compressed certificate.
+const SIGNATURE: usize = 0;
+const ENCODED_DATES: usize = 64;
+const SIGNER_ID: usize = 67;
+const TEMPLATE_CHAIN_ID: usize = 69;
+const SN_SOURCE_FORMAT: usize = 70;
+
+/// Date and time in UTC, as found in certificate validity fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Date {
+    // Expiry used when the certificate does not expire.
+    pub(crate) const NEVER: Self = Self {
+        year: 9999,
+        month: 12,
+        day: 31,
+        hour: 23,
+        minute: 59,
+        second: 59,
+    };
+}
+
+/// ASN.1 time types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DateFormat {
+    /// YYMMDDhhmmssZ
+    UtcTime,
+    /// YYYYMMDDhhmmssZ
+    GeneralizedTime,
+}
+
+impl DateFormat {
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Self::UtcTime => 13,
+            Self::GeneralizedTime => 15,
+        }
+    }
+
+    /// Write `date` as ASCII into `dst`, which must be `encoded_len()` bytes long.
+    pub fn encode(&self, date: &Date, dst: &mut [u8]) -> Result<(), Error> {
+        if dst.len() != self.encoded_len() {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        let (year, rest) = match self {
+            Self::UtcTime => dst.split_at_mut(2),
+            Self::GeneralizedTime => dst.split_at_mut(4),
+        };
+        let digits = year.len() as u32;
+        year.iter_mut().enumerate().for_each(|(i, digit)| {
+            let divisor = 10u16.pow(digits - 1 - i as u32);
+            *digit = b'0' + (date.year / divisor % 10) as u8;
+        });
+        [date.month, date.day, date.hour, date.minute, date.second]
+            .iter()
+            .zip(rest.chunks_mut(2))
+            .for_each(|(value, digits)| {
+                digits[0] = b'0' + value / 10;
+                digits[1] = b'0' + value % 10;
+            });
+        rest[rest.len() - 1] = b'Z';
+        Ok(())
+    }
+
+    /// Parse an ASCII date written by `encode`.
+    pub fn decode(&self, src: &[u8]) -> Result<Date, Error> {
+        if src.len() != self.encoded_len() || src[src.len() - 1] != b'Z' {
+            return Err(ErrorKind::BadParam.into());
+        }
+        let number = |digits: &[u8]| {
+            digits.iter().try_fold(0u16, |n, digit| match digit {
+                b'0'..=b'9' => Ok(n * 10 + u16::from(digit - b'0')),
+                _ => Err(Error::from(ErrorKind::BadParam)),
+            })
+        };
+        let (year, rest) = match self {
+            // Years 50 to 99 are 1950 to 1999 in UTCTime.
+            Self::UtcTime => match number(&src[..2])? {
+                year if year >= 50 => (1900 + year, &src[2..]),
+                year => (2000 + year, &src[2..]),
+            },
+            Self::GeneralizedTime => (number(&src[..4])?, &src[4..]),
+        };
+        Ok(Date {
+            year,
+            month: number(&rest[0..2])? as u8,
+            day: number(&rest[2..4])? as u8,
+            hour: number(&rest[4..6])? as u8,
+            minute: number(&rest[6..8])? as u8,
+            second: number(&rest[8..10])? as u8,
+        })
+    }
+}
+
+/// Certificate in the 72-byte compressed form.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CompressedCertificate([u8; COMPRESSED_SIZE]);
+
+impl CompressedCertificate {
+    /// Pack the dynamic elements of a certificate. Issue dates must fall
+    /// within 2000 to 2031 and only the hour is kept. The expiry is given
+    /// as a validity in whole years, zero meaning no expiry.
+    pub fn new(
+        signature: &Signature,
+        issue_date: &Date,
+        expire_years: u8,
+        signer_id: u16,
+        template_id: u8,
+        chain_id: u8,
+        sn_source: u8,
+    ) -> Result<Self, Error> {
+        let year = issue_date
+            .year
+            .checked_sub(2000)
+            .filter(|year| *year < 32)
+            .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+        if expire_years > 0x1f || template_id > 0x0f || chain_id > 0x0f || sn_source > 0x0f {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        let mut bytes = [0x00; COMPRESSED_SIZE];
+        bytes[SIGNATURE..SIGNATURE + 64].copy_from_slice(signature.as_ref());
+        let dates = u32::from(year) << 19
+            | u32::from(issue_date.month & 0x0f) << 15
+            | u32::from(issue_date.day & 0x1f) << 10
+            | u32::from(issue_date.hour & 0x1f) << 5
+            | u32::from(expire_years);
+        bytes[ENCODED_DATES..ENCODED_DATES + 3].copy_from_slice(&dates.to_be_bytes()[1..]);
+        bytes[SIGNER_ID..SIGNER_ID + 2].copy_from_slice(&signer_id.to_be_bytes());
+        bytes[TEMPLATE_CHAIN_ID] = template_id << 4 | chain_id;
+        bytes[SN_SOURCE_FORMAT] = sn_source << 4;
+        Ok(Self(bytes))
+    }
+
+    /// Signature as R followed by S.
+    pub fn signature(&self) -> Signature {
+        Signature::try_from(&self.0[SIGNATURE..SIGNATURE + 64]).unwrap_or_else(|_| unreachable!())
+    }
+
+    /// Issue year, month, day and hour, and the validity in years, packed
+    /// into 24 bits.
+    pub fn encoded_dates(&self) -> [u8; 3] {
+        let mut dates = [0x00; 3];
+        dates.copy_from_slice(&self.0[ENCODED_DATES..ENCODED_DATES + 3]);
+        dates
+    }
+
+    pub fn issue_date(&self) -> Date {
+        let dates = self.encoded_dates();
+        Date {
+            year: 2000 + u16::from(dates[0] >> 3),
+            month: (dates[0] & 0x07) << 1 | dates[1] >> 7,
+            day: (dates[1] >> 2) & 0x1f,
+            hour: (dates[1] & 0x03) << 3 | dates[2] >> 5,
+            minute: 0,
+            second: 0,
+        }
+    }
+
+    /// Number of years the certificate is valid for. Zero for no expiry.
+    pub fn expire_years(&self) -> u8 {
+        self.0[ENCODED_DATES + 2] & 0x1f
+    }
+
+    pub fn expire_date(&self) -> Date {
+        match self.expire_years() {
+            0 => Date::NEVER,
+            years => Date {
+                year: self.issue_date().year + u16::from(years),
+                ..self.issue_date()
+            },
+        }
+    }
+
+    pub fn signer_id(&self) -> u16 {
+        u16::from_be_bytes([self.0[SIGNER_ID], self.0[SIGNER_ID + 1]])
+    }
+
+    pub fn template_id(&self) -> u8 {
+        self.0[TEMPLATE_CHAIN_ID] >> 4
+    }
+
+    pub fn chain_id(&self) -> u8 {
+        self.0[TEMPLATE_CHAIN_ID] & 0x0f
+    }
+
+    /// Where the certificate serial number comes from. See `SnSource`.
+    pub fn sn_source(&self) -> u8 {
+        self.0[SN_SOURCE_FORMAT] >> 4
+    }
+
+    pub fn format_version(&self) -> u8 {
+        self.0[SN_SOURCE_FORMAT] & 0x0f
+    }
+
+    pub fn as_bytes(&self) -> &[u8; COMPRESSED_SIZE] {
+        &self.0
+    }
+}
+
+impl From<[u8; COMPRESSED_SIZE]> for CompressedCertificate {
+    fn from(bytes: [u8; COMPRESSED_SIZE]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for CompressedCertificate {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        let bytes = buffer
+            .get(..COMPRESSED_SIZE)
+            .ok_or_else(|| Error::from(ErrorKind::InvalidSize))?;
+        let mut compressed = [0x00; COMPRESSED_SIZE];
+        compressed.copy_from_slice(bytes);
+        Ok(Self(compressed))
+    }
+}
+
+impl AsRef<[u8]> for CompressedCertificate {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        let mut bytes = [0x00; COMPRESSED_SIZE];
+        // 2019-03-07 14:00, valid for 28 years.
+        let dates: u32 = 19 << 19 | 3 << 15 | 7 << 10 | 14 << 5 | 28;
+        bytes[ENCODED_DATES..ENCODED_DATES + 3].copy_from_slice(&dates.to_be_bytes()[1..]);
+        bytes[SIGNER_ID..SIGNER_ID + 2].copy_from_slice(&[0xc0, 0x01]);
+        bytes[TEMPLATE_CHAIN_ID] = 0x20;
+        bytes[SN_SOURCE_FORMAT] = 0xa0;
+        let compressed = CompressedCertificate::from(bytes);
+
+        let issued = compressed.issue_date();
+        assert_eq!((issued.year, issued.month, issued.day), (2019, 3, 7));
+        assert_eq!(issued.hour, 14);
+        assert_eq!(compressed.expire_date().year, 2047);
+        assert_eq!(compressed.signer_id(), 0xc001);
+        assert_eq!(compressed.template_id(), 2);
+        assert_eq!(compressed.sn_source(), 0x0a);
+
+        let mut text = [0x00; 13];
+        DateFormat::UtcTime.encode(&issued, &mut text).unwrap();
+        assert_eq!(&text, b"190307140000Z");
+        assert_eq!(DateFormat::UtcTime.decode(&text).unwrap(), issued);
+
+        let mut text = [0x00; 15];
+        DateFormat::GeneralizedTime
+            .encode(&Date::NEVER, &mut text)
+            .unwrap();
+        assert_eq!(&text, b"99991231235959Z");
+    }
+}
@@ -0,0 +1,91 @@
+use super::def::Certificate;
+use super::der::{Writer, INTEGER, SEQUENCE};
+use super::name::Name;
+use crate::client::AtCaClient;
+use crate::command::{PublicKey, Signature};
+use crate::datalink::Interface;
+use crate::error::Error;
+use crate::memory::Slot;
+use embedded_hal::delay::DelayNs;
+
+// Empty attributes, context specific tag 0.
+const NO_ATTRIBUTES: [u8; 2] = [0xa0, 0x00];
+
+/// Build a PKCS#10 certificate signing request for the key in `key_id`. The
+/// request is hashed on the device and signed with that same key, so the
+/// private key never needs to leave it.
+pub fn create_csr<PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    key_id: Slot,
+    subject: &Name<'_>,
+) -> Result<Certificate, Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    let public_key = atca.public_key(key_id)?;
+    let info = request_info(&public_key, subject)?;
+    let digest = atca.sha().digest(&info)?;
+    let signature = atca.sign(key_id, &digest)?;
+    signed(&info, &signature)
+}
+
+// CertificationRequestInfo, the part the signature covers.
+fn request_info(public_key: &PublicKey, subject: &Name<'_>) -> Result<Certificate, Error> {
+    let mut info = Certificate::new();
+    Writer::new(&mut info).constructed(SEQUENCE, |w| {
+        w.primitive(INTEGER, &[0x00])?;
+        w.name(subject)?;
+        w.public_key_info(public_key)?;
+        w.raw(&NO_ATTRIBUTES)
+    })?;
+    Ok(info)
+}
+
+// Wrap a signed part with the algorithm and signature, as done by both
+// certificates and signing requests.
+pub(crate) fn signed(tbs: &[u8], signature: &Signature) -> Result<Certificate, Error> {
+    let mut der = Certificate::new();
+    Writer::new(&mut der).constructed(SEQUENCE, |w| {
+        w.raw(tbs)?;
+        w.signature_algorithm()?;
+        w.signature(signature)
+    })?;
+    Ok(der)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cert::Attribute;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn csr() {
+        let public_key = PublicKey::try_from([0x11; 64].as_ref()).unwrap();
+        let subject = [(Attribute::CommonName, "atecc")];
+        let info = request_info(&public_key, &subject).unwrap();
+        // version, the subject name and the start of the public key info.
+        assert_eq!(&info[..2], &[0x30, 0x72]);
+        assert_eq!(&info[2..5], &[0x02, 0x01, 0x00]);
+        assert_eq!(
+            &info[5..25],
+            &[
+                0x30, 0x10, 0x31, 0x0e, 0x30, 0x0c, 0x06, 0x03, 0x55, 0x04, 0x03, 0x0c, 0x05, b'a',
+                b't', b'e', b'c', b'c', 0x30, 0x59
+            ]
+        );
+        assert_eq!(
+            &info[info.len() - 66..info.len() - 2][..],
+            public_key.as_ref()
+        );
+        assert!(info.ends_with(&NO_ATTRIBUTES));
+
+        let signature = Signature::try_from([0x80; 64].as_ref()).unwrap();
+        let csr = signed(&info, &signature).unwrap();
+        // Both integers are padded to 33 bytes.
+        assert_eq!(&csr[..3], &[0x30, 0x81, 0xcb]);
+        assert_eq!(&csr[3..3 + info.len()], &info[..]);
+        assert_eq!(csr.len(), 3 + info.len() + 12 + 5 + 2 * 35);
+    }
+}
@@ -0,0 +1,407 @@
+use super::compressed::{CompressedCertificate, Date, DateFormat};
+use super::der::Writer;
+use crate::command::{PublicKey, Serial, Signature};
+use crate::error::{Error, ErrorKind};
+use crate::host::{Sha1, Sha256};
+use core::convert::TryFrom;
+use heapless::Vec;
+
+/// Largest certificate that can be rebuilt.
+pub const CERT_SIZE_MAX: usize = 1024;
+
+/// DER encoded certificate.
+pub type Certificate = Vec<u8, CERT_SIZE_MAX>;
+
+// Size of a key identifier, a SHA-1 digest.
+const KEY_ID_SIZE: usize = 20;
+
+/// Where the certificate serial number comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnSource {
+    /// The 9-byte device serial number.
+    DeviceSn = 0x8,
+    /// SHA-256 over the public key and encoded dates, made positive with
+    /// the second most significant bit set.
+    PubKeyHash = 0xA,
+    /// SHA-256 over the device serial number and encoded dates, made
+    /// positive with the second most significant bit set.
+    DeviceSnHash = 0xB,
+    /// Same as `PubKeyHash`, only made positive.
+    PubKeyHashPos = 0xC,
+    /// Same as `DeviceSnHash`, only made positive.
+    DeviceSnHashPos = 0xD,
+    /// Same as `PubKeyHash`, unmodified.
+    PubKeyHashRaw = 0xE,
+    /// Same as `DeviceSnHash`, unmodified.
+    DeviceSnHashRaw = 0xF,
+}
+
+/// Span of an element in the template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    pub offset: usize,
+    pub count: usize,
+}
+
+/// Validity date element in the template.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateLocation {
+    pub offset: usize,
+    pub format: DateFormat,
+}
+
+/// Certificate definition: a DER template and the location of each element
+/// that varies between devices. The template must start with a SEQUENCE
+/// with a two-byte length and end with the signature algorithm, right
+/// before the signature value placed at `signature`.
+#[derive(Clone, Copy, Debug)]
+pub struct CertDef<'a> {
+    pub template: &'a [u8],
+    /// Identifies the template among those of a chain, stored with the
+    /// compressed certificate.
+    pub template_id: u8,
+    /// Identifies the certificate chain.
+    pub chain_id: u8,
+    pub sn_source: SnSource,
+    pub serial_number: Location,
+    pub issue_date: Option<DateLocation>,
+    pub expire_date: Option<DateLocation>,
+    /// Offsets of the signer ID, written as four upper case hex digits.
+    pub signer_id: &'a [usize],
+    /// Offset of the 9-byte device serial number, written as upper case hex
+    /// digits.
+    pub device_sn: Option<usize>,
+    /// Offset of the 64-byte public key.
+    pub public_key: usize,
+    /// Offset of the subject key identifier.
+    pub subject_key_id: Option<usize>,
+    /// Offset of the authority key identifier.
+    pub authority_key_id: Option<usize>,
+    /// TBSCertificate, the part the signature covers.
+    pub tbs: Location,
+    /// Offset of the signature value.
+    pub signature: usize,
+}
+
+impl<'a> CertDef<'a> {
+    /// Rebuild the certificate of `public_key` from its compressed form.
+    /// `device_sn` is needed when the serial number derives from it or the
+    /// template carries it, and
+    /// `signer_public_key` when the template carries an authority key
+    /// identifier to fill in.
+    pub fn build(
+        &self,
+        compressed: &CompressedCertificate,
+        public_key: &PublicKey,
+        device_sn: Option<&Serial>,
+        signer_public_key: Option<&PublicKey>,
+    ) -> Result<Certificate, Error> {
+        let mut cert = Certificate::new();
+        let template = self
+            .template
+            .get(..self.signature)
+            .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+        cert.extend_from_slice(template)
+            .map_err(|()| Error::from(ErrorKind::SmallBuffer))?;
+
+        element(&mut cert, self.public_key, 64)?.copy_from_slice(public_key.as_ref());
+
+        if let Some(issue_date) = self.issue_date {
+            let dst = element(
+                &mut cert,
+                issue_date.offset,
+                issue_date.format.encoded_len(),
+            )?;
+            issue_date.format.encode(&compressed.issue_date(), dst)?;
+        }
+        if let Some(expire_date) = self.expire_date {
+            let dst = element(
+                &mut cert,
+                expire_date.offset,
+                expire_date.format.encoded_len(),
+            )?;
+            expire_date.format.encode(&compressed.expire_date(), dst)?;
+        }
+
+        for offset in self.signer_id {
+            let dst = element(&mut cert, *offset, 4)?;
+            hex(compressed.signer_id(), dst);
+        }
+
+        if let Some(offset) = self.device_sn {
+            let device_sn = device_sn.ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+            let dst = element(&mut cert, offset, 2 * device_sn.as_ref().len())?;
+            for (byte, digits) in device_sn.as_ref().iter().zip(dst.chunks_mut(2)) {
+                hex(*byte as u16, digits);
+            }
+        }
+
+        let sn = self.serial_number;
+        let serial = self.generate_serial_number(compressed, public_key, device_sn)?;
+        let src = serial
+            .get(..sn.count)
+            .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+        element(&mut cert, sn.offset, sn.count)?.copy_from_slice(src);
+
+        if let Some(offset) = self.subject_key_id {
+            element(&mut cert, offset, KEY_ID_SIZE)?.copy_from_slice(&key_id(public_key));
+        }
+        if let (Some(offset), Some(signer)) = (self.authority_key_id, signer_public_key) {
+            element(&mut cert, offset, KEY_ID_SIZE)?.copy_from_slice(&key_id(signer));
+        }
+
+        set_signature(&mut cert, &compressed.signature())?;
+        Ok(cert)
+    }
+
+    /// Compress a full certificate issued from this template, typically to
+    /// store a newly issued one back into its slot. The validity must be a
+    /// whole number of years from the issue date, or end in 9999.
+    pub fn compress(&self, cert: &[u8]) -> Result<CompressedCertificate, Error> {
+        let issue_date = match self.issue_date {
+            Some(location) => location.format.decode(field(
+                cert,
+                location.offset,
+                location.format.encoded_len(),
+            )?)?,
+            None => Date::default(),
+        };
+        let expire_years = match self.expire_date {
+            Some(location) => {
+                let expire_date = location.format.decode(field(
+                    cert,
+                    location.offset,
+                    location.format.encoded_len(),
+                )?)?;
+                expire_years(&issue_date, &expire_date)?
+            }
+            None => 0,
+        };
+        let signer_id = match self.signer_id.first() {
+            Some(offset) => unhex(field(cert, *offset, 4)?)?,
+            None => 0,
+        };
+        CompressedCertificate::new(
+            &self.signature_value(cert)?,
+            &issue_date,
+            expire_years,
+            signer_id,
+            self.template_id,
+            self.chain_id,
+            self.sn_source as u8,
+        )
+    }
+
+    /// Public key found in `cert`.
+    pub fn public_key(&self, cert: &[u8]) -> Result<PublicKey, Error> {
+        PublicKey::try_from(field(cert, self.public_key, 64)?)
+    }
+
+    /// Serial number found in `cert`.
+    pub fn serial_number<'c>(&self, cert: &'c [u8]) -> Result<&'c [u8], Error> {
+        field(cert, self.serial_number.offset, self.serial_number.count)
+    }
+
+    /// Signature found in `cert`, as R followed by S.
+    pub fn signature_value(&self, cert: &[u8]) -> Result<Signature, Error> {
+        let malformed = || Error::from(ErrorKind::BadParam);
+        let bit_string = cert.get(self.signature..).ok_or_else(malformed)?;
+        let (tag, value, _) = tlv(bit_string)?;
+        if tag != 0x03 || value.first() != Some(&0x00) {
+            return Err(malformed());
+        }
+        Signature::from_der(&value[1..])
+    }
+
+    // Serial number as generated by the source, at full length.
+    fn generate_serial_number(
+        &self,
+        compressed: &CompressedCertificate,
+        public_key: &PublicKey,
+        device_sn: Option<&Serial>,
+    ) -> Result<[u8; 32], Error> {
+        use SnSource::*;
+        let device_sn = || device_sn.ok_or_else(|| Error::from(ErrorKind::BadParam));
+        let mut serial = [0x00; 32];
+        match self.sn_source {
+            DeviceSn => {
+                serial[..9].copy_from_slice(device_sn()?.as_ref());
+                return Ok(serial);
+            }
+            PubKeyHash | PubKeyHashPos | PubKeyHashRaw => {
+                let digest = Sha256::new()
+                    .update(public_key)
+                    .update(compressed.encoded_dates())
+                    .finalize();
+                serial.copy_from_slice(digest.as_ref());
+            }
+            DeviceSnHash | DeviceSnHashPos | DeviceSnHashRaw => {
+                let digest = Sha256::new()
+                    .update(device_sn()?)
+                    .update(compressed.encoded_dates())
+                    .finalize();
+                serial.copy_from_slice(digest.as_ref());
+            }
+        }
+        match self.sn_source {
+            PubKeyHash | DeviceSnHash => serial[0] = serial[0] & 0x7f | 0x40,
+            PubKeyHashPos | DeviceSnHashPos => serial[0] &= 0x7f,
+            _ => {}
+        }
+        Ok(serial)
+    }
+}
+
+fn element(cert: &mut [u8], offset: usize, count: usize) -> Result<&mut [u8], Error> {
+    cert.get_mut(offset..offset + count)
+        .ok_or_else(|| ErrorKind::BadParam.into())
+}
+
+fn field(cert: &[u8], offset: usize, count: usize) -> Result<&[u8], Error> {
+    cert.get(offset..offset + count)
+        .ok_or_else(|| ErrorKind::BadParam.into())
+}
+
+// Validity in whole years, zero for the no expiry date.
+fn expire_years(issue_date: &Date, expire_date: &Date) -> Result<u8, Error> {
+    if *expire_date == Date::NEVER {
+        return Ok(0);
+    }
+    let years = expire_date
+        .year
+        .checked_sub(issue_date.year)
+        .filter(|years| (1..32).contains(years))
+        .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+    let anniversary = Date {
+        year: expire_date.year,
+        minute: 0,
+        second: 0,
+        ..*issue_date
+    };
+    if *expire_date != anniversary {
+        return Err(ErrorKind::BadParam.into());
+    }
+    Ok(years as u8)
+}
+
+// Upper case hex digits of `value`, as many as `dst` holds.
+fn hex(value: u16, dst: &mut [u8]) {
+    const DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    let len = dst.len();
+    dst.iter_mut().enumerate().for_each(|(i, digit)| {
+        *digit = DIGITS[(value >> (4 * (len - 1 - i)) & 0x0f) as usize];
+    });
+}
+
+fn unhex(src: &[u8]) -> Result<u16, Error> {
+    src.iter().try_fold(0u16, |value, digit| {
+        let nibble = (*digit as char)
+            .to_digit(16)
+            .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+        Ok(value << 4 | nibble as u16)
+    })
+}
+
+// Tag, value and what follows of the DER element at the start of `src`.
+// Only short form lengths are handled, which is all a P-256 signature needs.
+fn tlv(src: &[u8]) -> Result<(u8, &[u8], &[u8]), Error> {
+    match src {
+        [tag, len, rest @ ..] if *len < 0x80 && rest.len() >= *len as usize => {
+            let (value, rest) = rest.split_at(*len as usize);
+            Ok((*tag, value, rest))
+        }
+        _ => Err(ErrorKind::BadParam.into()),
+    }
+}
+
+// Key identifier as SHA-1 over the uncompressed point.
+fn key_id(public_key: &PublicKey) -> [u8; KEY_ID_SIZE] {
+    Sha1::new().update([0x04]).update(public_key).finalize()
+}
+
+// Append the signature, then fix the length of the outer SEQUENCE.
+fn set_signature(cert: &mut Certificate, signature: &Signature) -> Result<(), Error> {
+    Writer::new(cert).signature(signature)?;
+
+    if !cert.starts_with(&[0x30, 0x82]) {
+        return Err(ErrorKind::BadParam.into());
+    }
+    let outer = (cert.len() - 4) as u16;
+    cert[2..4].copy_from_slice(&outer.to_be_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cert::COMPRESSED_SIZE;
+
+    const SIGNER_ID: [usize; 1] = [62];
+
+    fn def(template: &[u8]) -> CertDef<'_> {
+        CertDef {
+            template,
+            template_id: 0,
+            chain_id: 0,
+            sn_source: SnSource::PubKeyHash,
+            serial_number: Location {
+                offset: 10,
+                count: 16,
+            },
+            issue_date: Some(DateLocation {
+                offset: 30,
+                format: DateFormat::UtcTime,
+            }),
+            expire_date: Some(DateLocation {
+                offset: 45,
+                format: DateFormat::GeneralizedTime,
+            }),
+            signer_id: &SIGNER_ID,
+            device_sn: None,
+            public_key: 66,
+            subject_key_id: Some(131),
+            authority_key_id: None,
+            tbs: Location {
+                offset: 4,
+                count: 150,
+            },
+            signature: 160,
+        }
+    }
+
+    #[test]
+    fn build() {
+        let mut template = [0x00; 170];
+        template[..4].copy_from_slice(&[0x30, 0x82, 0xff, 0xff]);
+
+        let mut compressed = [0x00; COMPRESSED_SIZE];
+        compressed[1] = 0x01; // R starts with a zero byte
+        compressed[32] = 0x80; // S needs padding
+        compressed[64..67].copy_from_slice(&[0x99, 0x9c, 0x00]);
+        compressed[67..69].copy_from_slice(&[0xc0, 0x01]);
+        compressed[70] = 0xa0;
+        let compressed = CompressedCertificate::from(compressed);
+        let public_key = PublicKey::try_from([0x11; 64].as_ref()).unwrap();
+
+        let cert = def(&template)
+            .build(&compressed, &public_key, None, None)
+            .unwrap();
+        assert_eq!(&cert[30..43], b"190307000000Z");
+        assert_eq!(&cert[45..60], b"99991231235959Z");
+        assert_eq!(&cert[62..66], b"C001");
+        assert_eq!(&cert[66..130], public_key.as_ref());
+        assert_eq!(cert[131..151], key_id(&public_key));
+        assert_eq!(cert[10] & 0xc0, 0x40);
+
+        // BIT STRING, SEQUENCE, then INTEGER of 31 and 33 bytes.
+        assert_eq!(&cert[160..165], &[0x03, 0x47, 0x00, 0x30, 0x44]);
+        assert_eq!(&cert[165..167], &[0x02, 0x1f]);
+        assert_eq!(&cert[198..201], &[0x02, 0x21, 0x00]);
+        assert_eq!(cert.len(), 160 + 5 + 0x44);
+        assert_eq!(&cert[2..4], &((cert.len() - 4) as u16).to_be_bytes());
+
+        let def = def(&template);
+        assert_eq!(def.public_key(&cert).unwrap().as_ref(), public_key.as_ref());
+        assert_eq!(def.compress(&cert).unwrap(), compressed);
+    }
+}
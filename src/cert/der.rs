@@ -0,0 +1,153 @@
+// Just enough of a DER writer to assemble certificates and signing requests
+// in a fixed size buffer. Constructed elements are written in place and
+// their length patched afterwards, moving the contents when the length
+// needs more than one byte.
+use super::def::Certificate;
+use super::name::Name;
+use crate::command::{PublicKey, Signature};
+use crate::error::{Error, ErrorKind};
+
+pub(crate) const SEQUENCE: u8 = 0x30;
+pub(crate) const SET: u8 = 0x31;
+pub(crate) const INTEGER: u8 = 0x02;
+pub(crate) const BIT_STRING: u8 = 0x03;
+pub(crate) const OBJECT_IDENTIFIER: u8 = 0x06;
+
+// id-ecPublicKey, prime256v1 and ecdsa-with-SHA256.
+const EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+
+pub(crate) struct Writer<'b> {
+    buffer: &'b mut Certificate,
+}
+
+impl<'b> Writer<'b> {
+    pub(crate) fn new(buffer: &'b mut Certificate) -> Self {
+        Self { buffer }
+    }
+
+    pub(crate) fn raw(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.buffer
+            .extend_from_slice(bytes)
+            .map_err(|()| ErrorKind::SmallBuffer.into())
+    }
+
+    pub(crate) fn primitive(&mut self, tag: u8, value: &[u8]) -> Result<(), Error> {
+        self.constructed(tag, |w| w.raw(value))
+    }
+
+    // Write an element whose contents are produced by `f`.
+    pub(crate) fn constructed(
+        &mut self,
+        tag: u8,
+        f: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.raw(&[tag, 0x00])?;
+        let start = self.buffer.len();
+        f(self)?;
+        let len = self.buffer.len() - start;
+        let extra = match len {
+            0..=0x7f => 0,
+            0x80..=0xff => 1,
+            _ => 2,
+        };
+        if extra > 0 {
+            let end = self.buffer.len();
+            self.buffer
+                .resize(end + extra, 0x00)
+                .map_err(|()| Error::from(ErrorKind::SmallBuffer))?;
+            self.buffer.copy_within(start..end, start + extra);
+        }
+        let header = &mut self.buffer[start - 1..start + extra];
+        match extra {
+            0 => header[0] = len as u8,
+            _ => {
+                header[0] = 0x80 | extra as u8;
+                header[1..].copy_from_slice(&(len as u16).to_be_bytes()[2 - extra..]);
+            }
+        }
+        Ok(())
+    }
+
+    // Unsigned big-endian integer, leading zeros stripped.
+    pub(crate) fn unsigned(&mut self, int: &[u8]) -> Result<(), Error> {
+        let int = match int.iter().position(|byte| *byte != 0x00) {
+            Some(start) => &int[start..],
+            None => &[0x00],
+        };
+        self.constructed(INTEGER, |w| {
+            if int[0] & 0x80 != 0x00 {
+                w.raw(&[0x00])?;
+            }
+            w.raw(int)
+        })
+    }
+
+    pub(crate) fn name(&mut self, name: &Name<'_>) -> Result<(), Error> {
+        self.constructed(SEQUENCE, |w| {
+            name.iter().try_for_each(|(attribute, value)| {
+                w.constructed(SET, |w| {
+                    w.constructed(SEQUENCE, |w| {
+                        w.primitive(OBJECT_IDENTIFIER, &attribute.oid())?;
+                        w.primitive(attribute.string_tag(), value.as_bytes())
+                    })
+                })
+            })
+        })
+    }
+
+    // SubjectPublicKeyInfo of a P-256 key.
+    pub(crate) fn public_key_info(&mut self, public_key: &PublicKey) -> Result<(), Error> {
+        self.constructed(SEQUENCE, |w| {
+            w.constructed(SEQUENCE, |w| {
+                w.primitive(OBJECT_IDENTIFIER, EC_PUBLIC_KEY)?;
+                w.primitive(OBJECT_IDENTIFIER, PRIME256V1)
+            })?;
+            w.constructed(BIT_STRING, |w| {
+                w.raw(&[0x00, 0x04])?;
+                w.raw(public_key.as_ref())
+            })
+        })
+    }
+
+    // AlgorithmIdentifier of ecdsa-with-SHA256, parameters absent.
+    pub(crate) fn signature_algorithm(&mut self) -> Result<(), Error> {
+        self.constructed(SEQUENCE, |w| {
+            w.primitive(OBJECT_IDENTIFIER, ECDSA_WITH_SHA256)
+        })
+    }
+
+    // Signature value as a BIT STRING holding the ECDSA-Sig-Value sequence.
+    pub(crate) fn signature(&mut self, signature: &Signature) -> Result<(), Error> {
+        self.constructed(BIT_STRING, |w| {
+            w.raw(&[0x00])?;
+            w.raw(&signature.to_der())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths() {
+        let mut buffer = Certificate::new();
+        let mut w = Writer::new(&mut buffer);
+        w.constructed(SEQUENCE, |w| {
+            w.primitive(0x04, &[0xaa; 0x90])?;
+            w.unsigned(&[0x00, 0x00, 0x80])
+        })
+        .unwrap();
+        assert_eq!(&buffer[..6], &[0x30, 0x81, 0x97, 0x04, 0x81, 0x90]);
+        assert_eq!(&buffer[0x96..], &[0x02, 0x02, 0x00, 0x80]);
+
+        let mut buffer = Certificate::new();
+        Writer::new(&mut buffer)
+            .primitive(0x04, &[0xaa; 0x100])
+            .unwrap();
+        assert_eq!(&buffer[..4], &[0x04, 0x82, 0x01, 0x00]);
+        assert_eq!(buffer.len(), 0x104);
+    }
+}
@@ -0,0 +1,38 @@
+/// Attribute types allowed in a distinguished name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Attribute {
+    CommonName,
+    SerialNumber,
+    Country,
+    Locality,
+    State,
+    Organization,
+    OrganizationalUnit,
+}
+
+impl Attribute {
+    pub(crate) fn oid(&self) -> [u8; 3] {
+        let id = match self {
+            Self::CommonName => 0x03,
+            Self::SerialNumber => 0x05,
+            Self::Country => 0x06,
+            Self::Locality => 0x07,
+            Self::State => 0x08,
+            Self::Organization => 0x0a,
+            Self::OrganizationalUnit => 0x0b,
+        };
+        [0x55, 0x04, id]
+    }
+
+    // Country and serial number are PrintableString, everything else is
+    // written as UTF8String.
+    pub(crate) fn string_tag(&self) -> u8 {
+        match self {
+            Self::Country | Self::SerialNumber => 0x13,
+            _ => 0x0c,
+        }
+    }
+}
+
+/// Distinguished name as a list of attributes, each in its own RDN.
+pub type Name<'a> = [(Attribute, &'a str)];
@@ -1,33 +1,57 @@
-use crate::command::{Ecdh, SharedSecret};
-
-use super::clock_divider::ClockDivider;
+use super::auth::AuthSession;
+use super::cert::CompressedCertificate;
+use super::clock_divider::{ClockDivider, ExecTime};
 use super::command::{
-    self, GenKey, Info, Lock, NonceCtx, PrivWrite, PublicKey, Random, Serial, Word,
+    self, CheckMac, CountMatch, Counter, CounterId, CounterValue, DeriveKey, DeviceState, Ecdh,
+    EcdhTarget, GenDig, GenDigSource, GenKey, HkdfMessage, Info, KdfSource, KdfTarget, KeyValid,
+    Lock, Mac, MacMode, NonceCtx, NonceTarget, OpCode, Pause, PremasterSecret, PrivWrite,
+    PublicKey, Random, SelfTest, SelfTestMode, SelfTestResult, Serial, Sha256Context, UpdateExtra,
+    UpdateExtraMode, VolatileKeyPermit, Word,
+};
+use super::config::{
+    count_match_byte, count_match_key, ConfigDiff, ConfigField, ConfigZone, SlotCaps, SlotConfig,
+    CONFIG_FIELDS_MAX, CONFIG_FIELD_MAX_SIZE, CONFIG_ZONE_SIZE,
 };
-use super::datalink::I2c;
-use super::error::{Error, ErrorKind};
-use super::memory::{CertificateRepr, Size, Slot, Zone};
-use super::packet::{Packet, PacketBuilder, Response};
+use super::datalink::shared::SharedI2c;
+use super::datalink::{I2c, Interface, PowerState};
+use super::device::{DeviceType, Revision};
+use super::error::{Error, ErrorKind, Phase, Status};
+use super::host;
+use super::memory::{DataZoneImage, LockToken, Size, Slot, Zone, OTP_ZONE_SIZE, SLOT_MAX_SIZE};
+use super::nb;
+use super::packet::{crc16, Packet, PacketBuilder, Response};
+use super::profile::DeviceProfile;
+use super::provision::Provisioner;
+use super::retry::RetryPolicy;
+use super::secure_boot::SecureBoot;
+use super::session::{Ready, Session};
+use super::tempkey::{TempKeySource, TempKeyState};
+use super::tls::Tls12;
 use super::tngtls::TrustAndGo;
-use super::{Block, Digest, Signature};
+use super::transaction::Transaction;
+use super::{Block, DerSignature, Digest, Signature};
 use core::cell::RefCell;
+use core::convert::TryFrom;
 use core::convert::TryInto;
-use core::convert::{identity, TryFrom};
+use core::ops::Range;
 use embedded_hal::delay::DelayNs;
 use embedded_hal::i2c;
 use heapless::Vec;
+use signature::hazmat::{PrehashSigner, PrehashVerifier};
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
 
-pub struct Verifier<'a, PHY, D>(RefCell<Verify<'a, PHY, D>>);
+pub struct Verifier<'a, PHY, D, const N: usize = BUFFER_SIZE>(RefCell<Verify<'a, PHY, D, N>>);
 
-impl<'a, PHY, D> From<Verify<'a, PHY, D>> for Verifier<'a, PHY, D> {
-    fn from(verify: Verify<'a, PHY, D>) -> Self {
+impl<'a, PHY, D, const N: usize> From<Verify<'a, PHY, D, N>> for Verifier<'a, PHY, D, N> {
+    fn from(verify: Verify<'a, PHY, D, N>) -> Self {
         Self(RefCell::new(verify))
     }
 }
 
-impl<'a, PHY, D> signature::Verifier<Signature> for Verifier<'a, PHY, D>
+impl<'a, PHY, D, const N: usize> signature::Verifier<Signature> for Verifier<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     fn verify(&self, msg: &[u8], signature: &Signature) -> Result<(), signature::Error> {
@@ -43,7 +67,32 @@ where
             .0
             .borrow_mut()
             .atca
-            .generate_pubkey(key_id)
+            .public_key(key_id)
+            .map_err(|_| signature::Error::new())?;
+        self.0
+            .borrow_mut()
+            .verify_digest(&digest, signature, &public_key)
+            .map_err(|_| signature::Error::new())
+    }
+}
+
+impl<'a, PHY, D, const N: usize> PrehashVerifier<Signature> for Verifier<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn verify_prehash(
+        &self,
+        prehash: &[u8],
+        signature: &Signature,
+    ) -> Result<(), signature::Error> {
+        let digest = Digest::try_from(prehash).map_err(|_| signature::Error::new())?;
+        let key_id = self.0.borrow().key_id;
+        let public_key = self
+            .0
+            .borrow_mut()
+            .atca
+            .public_key(key_id)
             .map_err(|_| signature::Error::new())?;
         self.0
             .borrow_mut()
@@ -52,17 +101,18 @@ where
     }
 }
 
-pub struct Signer<'a, PHY, D>(RefCell<Sign<'a, PHY, D>>);
+// A private key slot usable wherever RustCrypto expects a signer.
+pub struct SlotSigner<'a, PHY, D, const N: usize = BUFFER_SIZE>(RefCell<Sign<'a, PHY, D, N>>);
 
-impl<'a, PHY, D> From<Sign<'a, PHY, D>> for Signer<'a, PHY, D> {
-    fn from(sign: Sign<'a, PHY, D>) -> Self {
+impl<'a, PHY, D, const N: usize> From<Sign<'a, PHY, D, N>> for SlotSigner<'a, PHY, D, N> {
+    fn from(sign: Sign<'a, PHY, D, N>) -> Self {
         Self(RefCell::new(sign))
     }
 }
 
-impl<'a, PHY, D> signature::Signer<Signature> for Signer<'a, PHY, D>
+impl<'a, PHY, D, const N: usize> signature::Signer<Signature> for SlotSigner<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     fn try_sign(&self, msg: &[u8]) -> Result<Signature, signature::Error> {
@@ -80,24 +130,179 @@ where
     }
 }
 
-pub struct AtCaClient<PHY, D> {
+impl<'a, PHY, D, const N: usize> SlotSigner<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    /// ECDSA P-256 SHA-256 signature over `msg`, DER encoded as TLS expects.
+    /// See `rustls::SlotKey` for a rustls signing key.
+    pub fn sign_der(&self, msg: &[u8]) -> Result<DerSignature, Error> {
+        let digest = self.0.borrow_mut().atca.sha().digest(msg)?;
+        let signature = self.0.borrow_mut().sign_digest(&digest)?;
+        Ok(signature.into())
+    }
+}
+
+// DER output for TLS stacks, which exchange DER signatures. The embedded-tls
+// provider signs through the signature 2.x counterpart of this impl.
+impl<'a, PHY, D, const N: usize> signature::Signer<DerSignature> for SlotSigner<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn try_sign(&self, msg: &[u8]) -> Result<DerSignature, signature::Error> {
+        signature::Signer::<Signature>::try_sign(self, msg).map(DerSignature::from)
+    }
+}
+
+// Stands in for `DigestSigner`. Hash the message with any SHA-256
+// implementation and hand the 32-byte digest to the device.
+impl<'a, PHY, D, const N: usize> PrehashSigner<Signature> for SlotSigner<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn sign_prehash(&self, prehash: &[u8]) -> Result<Signature, signature::Error> {
+        let digest = Digest::try_from(prehash).map_err(|_| signature::Error::new())?;
+        self.0
+            .borrow_mut()
+            .sign_digest(&digest)
+            .map_err(|_| signature::Error::new())
+    }
+}
+
+/// Size of the command buffer of `AtCaClient::new`.
+pub const BUFFER_SIZE: usize = 192;
+
+/// Smallest command buffer: word address and KDF with its largest message.
+pub const BUFFER_SIZE_MIN: usize = 1 + 7 + 4 + command::Kdf::MESSAGE_SIZE_MAX;
+
+// Default addresses of the factory configurations: blank ATECC608 and
+// ATECC508, TrustAndGo, TrustFLEX and ATSHA204A.
+const KNOWN_ADDRESSES: [u8; 4] = [0x60, 0x35, 0x36, 0x64];
+
+pub struct AtCaClient<PHY, D, const N: usize = BUFFER_SIZE> {
     i2c: I2c<PHY, D>,
-    buffer: Vec<u8, 192>,
+    buffer: Vec<u8, N>,
     clock_divider: ClockDivider,
+    device: Option<DeviceType>,
+    retry_policy: RetryPolicy,
+    pub(crate) io_key: Option<Block>,
+    pub(crate) tempkey: TempKeyState,
+    // Persistent latch as last seen, and the slots known to need it from
+    // their KeyConfig.PersistentDisable bit.
+    persistent_latch: Option<bool>,
+    latched_keys: u16,
+    // Slot authorized by the last successful CheckMac of an `AuthSession`,
+    // and the AuthKey each slot requires, if known.
+    pub(crate) authorized: Option<Slot>,
+    required_auth: [Option<u8>; 16],
+    // What each slot permits, once the config zone has been read.
+    capabilities: [Option<SlotCaps>; 16],
+    // Command started without waiting for its response.
+    pending: Option<OpCode>,
+}
+
+impl<'a, I, D> AtCaClient<SharedI2c<'a, I>, D> {
+    // Drive the device over a bus shared with other drivers. The bus is only
+    // borrowed for the duration of each I2C transaction.
+    pub fn new_shared(bus: &'a RefCell<I>, delay: D) -> Self {
+        Self::new(SharedI2c::new(bus), delay)
+    }
 }
 
 impl<PHY, D> AtCaClient<PHY, D> {
     pub fn new(phy: PHY, delay: D) -> Self {
+        Self::with_buffer_size(phy, delay)
+    }
+}
+
+impl<PHY, D, const N: usize> AtCaClient<PHY, D, N> {
+    // Rejected at compile time unless the command buffer fits the largest
+    // command.
+    // Spelled out instead of `assert!`, which the defmt feature replaces with
+    // a macro unusable in constants.
+    const BUFFER_SIZE_CHECK: () = if N < BUFFER_SIZE_MIN {
+        core::panic!("command buffer too small")
+    };
+
+    // Client with an `N`-byte command buffer instead of the default
+    // `BUFFER_SIZE`, as in `AtCaClient::<_, _, 140>::with_buffer_size(i2c,
+    // delay)`. At least `BUFFER_SIZE_MIN`.
+    pub fn with_buffer_size(phy: PHY, delay: D) -> Self {
+        let () = Self::BUFFER_SIZE_CHECK;
         let i2c = I2c::new(phy, delay);
         let buffer = Vec::new();
         Self {
             i2c,
             buffer,
             clock_divider: ClockDivider::Zero,
+            device: None,
+            retry_policy: RetryPolicy::default(),
+            io_key: None,
+            tempkey: TempKeyState::default(),
+            persistent_latch: None,
+            latched_keys: 0x0000,
+            authorized: None,
+            required_auth: [None; 16],
+            capabilities: [None; 16],
+            pending: None,
+        }
+    }
+
+    // Release the underlying bus.
+    pub fn release(self) -> (PHY, D) {
+        self.i2c.release()
+    }
+
+    // Talk to the device at `address` (7-bit) instead of the default 0x60,
+    // for example to drive several devices on one bus.
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.i2c.set_address(address);
+        self
+    }
+
+    pub fn address(&self) -> u8 {
+        self.i2c.address()
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    // Key held in the I/O protection key slot. Once set, ECDH and KDF output
+    // travels encrypted and is decrypted on the host, and Verify results come
+    // with a MAC checked on the host.
+    pub fn with_io_protection_key(mut self, io_key: &Block) -> Self {
+        self.io_key = Some(*io_key);
+        self
+    }
+
+    // What the host knows about the device's TempKey.
+    pub fn tempkey(&self) -> &TempKeyState {
+        &self.tempkey
+    }
+
+    pub fn power_state(&self) -> PowerState {
+        self.i2c.power_state()
+    }
+
+    // Worst case execution time of a command on this device.
+    pub(crate) fn execution_time(&self, opcode: &OpCode) -> ExecTime {
+        match self.device {
+            Some(device) => device.execution_time(&self.clock_divider, opcode),
+            None => self.clock_divider.execution_time(opcode),
         }
+        .unwrap_or_default()
     }
 
-    fn packet_builder(&mut self) -> PacketBuilder<'_> {
+    pub(crate) fn awake_ms(&self) -> u32 {
+        self.i2c.awake_ms()
+    }
+
+    pub(crate) fn packet_builder(&mut self) -> PacketBuilder<'_> {
         let capacity = self.buffer.capacity();
         self.buffer.clear();
         self.buffer
@@ -106,15 +311,38 @@ impl<PHY, D> AtCaClient<PHY, D> {
         PacketBuilder::new(&mut self.buffer)
     }
 
-    pub fn memory(&mut self) -> Memory<'_, PHY, D> {
+    // Zero the command buffer, which holds the last command and response
+    // until the next command overwrites it.
+    pub fn wipe_buffer(&mut self) {
+        self.buffer.iter_mut().for_each(|byte| *byte = 0x00);
+    }
+
+    pub fn memory(&mut self) -> Memory<'_, PHY, D, N> {
         Memory { atca: self }
     }
 
-    pub fn aes(&mut self, key_id: Slot) -> Aes<'_, PHY, D> {
-        Aes { atca: self, key_id }
+    pub fn rng(&mut self) -> DeviceRng<'_, PHY, D, N> {
+        DeviceRng {
+            atca: self,
+            pool: Block::default(),
+            remaining: 0,
+        }
+    }
+
+    pub fn aes(&mut self, key_id: Slot) -> Aes<'_, PHY, D, N> {
+        Aes {
+            atca: self,
+            key_id,
+            key_block: 0,
+        }
+    }
+
+    pub fn kdf(&mut self) -> Kdf<'_, PHY, D, N> {
+        let io_key = self.io_key;
+        Kdf { atca: self, io_key }
     }
 
-    pub fn sha(&mut self) -> Sha<'_, PHY, D> {
+    pub fn sha(&mut self) -> Sha<'_, PHY, D, N> {
         let remaining_bytes = Vec::new();
         Sha {
             atca: self,
@@ -122,248 +350,1699 @@ impl<PHY, D> AtCaClient<PHY, D> {
         }
     }
 
-    pub fn sign(&mut self, key_id: Slot) -> Sign<'_, PHY, D> {
-        Sign { atca: self, key_id }
-    }
-
-    pub fn verify(&mut self, key_id: Slot) -> Verify<'_, PHY, D> {
+    pub fn verify(&mut self, key_id: Slot) -> Verify<'_, PHY, D, N> {
         Verify { atca: self, key_id }
     }
 }
 
+// Probing borrows the bus once per address, which relies on `&mut` of an
+// I2C bus being a bus as well.
 impl<PHY, D> AtCaClient<PHY, D>
 where
     PHY: i2c::I2c,
     D: DelayNs,
 {
-    fn execute(&mut self, packet: Packet) -> Result<Response<'_>, Error> {
-        let exec_time = self.clock_divider.execution_time(packet.opcode());
-        self.i2c.execute(&mut self.buffer, packet, exec_time)
+    // Scan the addresses CryptoAuth devices ship at and return the first one
+    // answering Info. Pass `&mut` references to keep the bus and delay.
+    pub fn probe(i2c: PHY, delay: D) -> Option<(u8, DeviceType)> {
+        Self::probe_all(i2c, delay).first().copied()
+    }
+
+    // Same as `probe`, reporting every device found.
+    pub fn probe_all(
+        mut i2c: PHY,
+        mut delay: D,
+    ) -> Vec<(u8, DeviceType), { KNOWN_ADDRESSES.len() }> {
+        KNOWN_ADDRESSES
+            .iter()
+            .filter_map(|&address| {
+                AtCaClient::new(&mut i2c, &mut delay)
+                    .with_address(address)
+                    .with_retry_policy(RetryPolicy::none())
+                    .device_type()
+                    .ok()
+                    .map(|device| (address, device))
+            })
+            .collect()
+    }
+}
+
+impl<PHY, D, const N: usize> AtCaClient<PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    pub(crate) fn execute(&mut self, packet: Packet) -> Result<Response<'_>, Error> {
+        let opcode = packet.opcode();
+        // Op-codes the crate does not know, sent through `execute_raw`, are
+        // polled up to the watchdog timeout and left for the device to
+        // refuse.
+        let exec_time = match OpCode::try_from(opcode) {
+            Ok(known) => {
+                if let Some(device) = self.device {
+                    if !device.supports(&known) {
+                        return Err(ErrorKind::UnsupportedByDevice.into());
+                    }
+                }
+                self.execution_time(&known)
+            }
+            Err(_) => ExecTime::default(),
+        };
+        trace!(
+            "op-code {:#x} mode {:#x} param2 {:#x}",
+            opcode,
+            packet.mode(),
+            packet.param2()
+        );
+        // The response overwrites the packet. Keep a copy to send it again.
+        let mut sent = Vec::<u8, N>::new();
+        sent.extend_from_slice(packet.buffer(&self.buffer))
+            .unwrap_or_else(|()| unreachable!());
+
+        let mut attempt = 1;
+        let mut watchdog_restarted = false;
+        let result = loop {
+            let result = self
+                .i2c
+                .transfer(&mut self.buffer, packet, exec_time)
+                .and_then(|length| Response::new(&self.buffer[..length], opcode).map(|_| length));
+            match result {
+                // The device refused to start a command it could not finish
+                // before the watchdog expires. Restart the watchdog through
+                // idle, keeping TempKey, and send it once more.
+                Err(e)
+                    if e.status() == Some(Status::WatchdogAboutToExpire) && !watchdog_restarted =>
+                {
+                    if self.i2c.power_state() == PowerState::Awake {
+                        if let Err(e) = self.i2c.restart_watchdog() {
+                            break Err(e.in_phase(Phase::Wake));
+                        }
+                    }
+                    self.buffer[..sent.len()].copy_from_slice(&sent);
+                    watchdog_restarted = true;
+                }
+                Err(e) if self.retry_policy.should_retry(attempt, &e) => {
+                    self.i2c.delay_us(self.retry_policy.backoff(attempt));
+                    self.buffer[..sent.len()].copy_from_slice(&sent);
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        if self.i2c.take_watchdog_expired() {
+            self.tempkey.invalidate();
+            self.authorized.take();
+        }
+        match &result {
+            Ok(length) => trace!("op-code {:#x} returned {} bytes", opcode, length),
+            Err(error) => debug!("op-code {:#x} failed: {:?}", opcode, error),
+        }
+        #[cfg(feature = "zeroize")]
+        sent[..].zeroize();
+        result
+            .and_then(move |length| Response::new(&self.buffer[..length], opcode))
+            .map_err(|e| e.with_opcode(opcode))
+    }
+
+    // Send a command and return without waiting for it. The response is
+    // collected by `poll_response`. Retries do not apply.
+    fn start_command(&mut self, packet: Packet) -> Result<(), Error> {
+        let opcode = OpCode::try_from(packet.opcode())?;
+        if let Some(device) = self.device {
+            if !device.supports(&opcode) {
+                return Err(ErrorKind::UnsupportedByDevice.into());
+            }
+        }
+        let exec_time = self.execution_time(&opcode);
+        self.i2c
+            .start(&self.buffer, packet, exec_time)
+            .map_err(|e| e.with_opcode(opcode as u8))?;
+        self.pending.replace(opcode);
+        Ok(())
+    }
+
+    // Non-blocking counterpart of `sign` for firmware running a super-loop.
+    // Returns once Sign is sent; `poll_response::<Signature>` then yields
+    // the signature. The preparatory commands still block, briefly.
+    pub fn start_sign(&mut self, key_id: Slot, digest: &Digest) -> Result<(), Error> {
+        self.require_key_usable(key_id)?;
+        self.random()?;
+        self.write_message_digest_buffer(digest)?;
+        let packet = command::Sign::new(self.packet_builder()).external(key_id)?;
+        self.start_command(packet)
+    }
+
+    // Non-blocking counterpart of `generate_key`, completed by
+    // `poll_response::<PublicKey>`.
+    pub fn start_generate_key(&mut self, key_id: Slot) -> Result<(), Error> {
+        let packet = GenKey::new(self.packet_builder()).private_key(key_id)?;
+        self.start_command(packet)
+    }
+
+    // Response of the command started last, or `WouldBlock` while the device
+    // is still busy. `T` is the result of the command, as returned by its
+    // blocking counterpart. No other command may run in between. The driver
+    // has no clock here, so giving up after the command's maximum execution
+    // time is up to the caller.
+    pub fn poll_response<T>(&mut self) -> nb::Result<T, Error>
+    where
+        T: for<'b> TryFrom<&'b [u8], Error = Error>,
+    {
+        let opcode = self
+            .pending
+            .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+        let result = self
+            .i2c
+            .try_receive(&mut self.buffer)
+            .ok_or(nb::Error::WouldBlock)?;
+        self.pending = None;
+        if self.i2c.take_watchdog_expired() {
+            self.tempkey.invalidate();
+            self.authorized.take();
+        }
+        result
+            .and_then(|length| Response::new(&self.buffer[..length], opcode as u8))
+            .and_then(|response| T::try_from(response.as_ref()))
+            .map_err(|e| nb::Error::Other(e.with_opcode(opcode as u8)))
     }
 
-    pub fn signer(&mut self, key_id: Slot) -> Signer<'_, PHY, D> {
-        self.sign(key_id).into()
+    // Whether a command started by one of the `start_*` methods has not been
+    // collected by `poll_response` yet.
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
     }
 
-    pub fn verifier(&mut self, key_id: Slot) -> Verifier<'_, PHY, D> {
+    // Forget a started command and put the device to sleep, which drops
+    // whatever the interrupted sequence left in its volatile state.
+    pub(crate) fn abandon(&mut self) -> Result<(), Error> {
+        if self.pending.take().is_some() {
+            self.i2c.abandon();
+        }
+        self.sleep()
+    }
+
+    // Run `f` and wipe the command buffer afterwards, whether it succeeded
+    // or not. For commands carrying secrets in the clear.
+    pub(crate) fn wiping<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let result = f(self);
+        self.wipe_buffer();
+        result
+    }
+
+    pub fn signer(&mut self, key_id: Slot) -> SlotSigner<'_, PHY, D, N> {
+        Sign { atca: self, key_id }.into()
+    }
+
+    pub fn verifier(&mut self, key_id: Slot) -> Verifier<'_, PHY, D, N> {
         self.verify(key_id).into()
     }
 
-    pub fn tng(&mut self) -> Result<TrustAndGo<'_, PHY, D>, Error> {
-        self.try_into()
+    // Write the TrustAndGo layout to an unlocked config zone, locking it if
+    // `lock` is given. The data zone is left unlocked.
+    pub fn tng(&mut self, lock: Option<LockToken>) -> Result<TrustAndGo<'_, PHY, D, N>, Error> {
+        (self, lock).try_into()
     }
 
-    pub fn sleep(&mut self) -> Result<(), Error> {
-        self.i2c.sleep()
+    pub fn secure_boot(&mut self) -> SecureBoot<'_, PHY, D, N> {
+        SecureBoot::new(self)
     }
 
-    pub fn info(&mut self) -> Result<Word, Error> {
-        let packet = Info::new(self.packet_builder()).revision()?;
-        self.execute(packet)?.as_ref().try_into()
+    pub fn tls(&mut self) -> Tls12<'_, PHY, D, N> {
+        Tls12::new(self)
     }
 
-    pub fn random(&mut self) -> Result<Block, Error> {
-        let packet = Random::new(self.packet_builder()).random()?;
-        self.execute(packet)?.as_ref().try_into()
+    // Keep the device awake between commands until `idle` or `sleep`. The
+    // driver re-wakes it through idle before a command that would outlast
+    // the watchdog, so TempKey survives long sequences.
+    pub fn wake(&mut self) -> Result<(), Error> {
+        match self.i2c.power_state() {
+            PowerState::Awake => Ok(()),
+            _ => self.i2c.wake(),
+        }
     }
 
-    // Write to device's digest message buffer.
-    pub fn write_message_digest_buffer(&mut self, msg: &Digest) -> Result<(), Error> {
-        let packet = NonceCtx::new(self.packet_builder()).message_digest_buffer(msg)?;
-        self.execute(packet).map(drop)
+    pub fn idle(&mut self) -> Result<(), Error> {
+        self.i2c.idle()
     }
 
-    // Create private key and output its public key.
-    pub fn create_private_key(&mut self, key_id: Slot) -> Result<PublicKey, Error> {
-        let packet = GenKey::new(self.packet_builder()).private_key(key_id)?;
-        self.execute(packet)?.as_ref().try_into()
+    pub fn sleep(&mut self) -> Result<(), Error> {
+        self.tempkey.invalidate();
+        self.authorized.take();
+        self.i2c.sleep()
     }
 
-    // Write private key.
-    pub fn write_private_key(&mut self, key_id: Slot, private_key: &Block) -> Result<(), Error> {
-        let packet =
-            PrivWrite::new(self.packet_builder()).write_private_key(key_id, private_key)?;
-        self.execute(packet).map(drop)
+    // Run dependent commands in a single wake window.
+    pub fn transaction(&mut self) -> Result<Transaction<'_, PHY, D, N>, Error> {
+        Transaction::new(self)
     }
 
-    // Given a private key created and stored in advance, calculate its public key.
-    pub fn generate_pubkey(&mut self, key_id: Slot) -> Result<PublicKey, Error> {
-        let packet = GenKey::new(self.packet_builder()).public_key(key_id)?;
-        self.execute(packet)?.as_ref().try_into()
+    // Run dependent commands in a session whose type tracks what they
+    // loaded into the device.
+    pub fn session(&mut self) -> Result<Session<'_, PHY, D, Ready, N>, Error> {
+        Session::new(self)
     }
 
-    pub fn diffie_hellman(
+    // Authorize the use of slots whose AuthKey is `auth_key`, proving
+    // knowledge of its secret `key`.
+    pub fn authorize(
         &mut self,
-        key_id: Slot,
-        public_key: PublicKey,
-    ) -> Result<SharedSecret, Error> {
-        let packet = Ecdh::new(self.packet_builder()).diffie_hellman(key_id, public_key)?;
-        self.execute(packet)?.as_ref().try_into()
+        auth_key: Slot,
+        key: &Block,
+    ) -> Result<AuthSession<'_, PHY, D, N>, Error> {
+        AuthSession::new(self, auth_key, key)
     }
-}
-
-// Memory zones consist of config, data and OTP.
-pub struct Memory<'a, PHY, D> {
-    atca: &'a mut AtCaClient<PHY, D>,
-}
 
-impl<'a, PHY, D> Memory<'a, PHY, D> {
-    pub(crate) const SLOT_CONFIG_INDEX: usize = 20;
-    pub(crate) const CHIP_OPTIONS_INDEX: usize = 90;
-    pub(crate) const KEY_CONFIG_INDEX: usize = 96;
-}
+    pub(crate) fn restart_watchdog(&mut self) -> Result<(), Error> {
+        self.i2c.restart_watchdog()
+    }
 
-impl<'a, PHY, D> Memory<'a, PHY, D>
-where
-    PHY: i2c::I2c,
-    D: DelayNs,
-{
+    // Read the 9-byte serial number from the config zone, which is readable
+    // whether or not it is locked. Same as `memory().serial_number()`.
     pub fn serial_number(&mut self) -> Result<Serial, Error> {
+        self.memory().serial_number()
+    }
+
+    pub fn info(&mut self) -> Result<Revision, Error> {
+        let packet = Info::new(self.packet_builder()).revision()?;
+        self.execute(packet)?.as_ref().try_into()
+    }
+
+    // Send a command the crate does not wrap, or with parameters it does not
+    // offer, and copy the response data into `response`. Wake, retries, CRC
+    // and execution time are handled as for any other command. An op-code
+    // the crate does not know is sent as given, without checking that the
+    // device type supports it, and polled for up to the watchdog timeout.
+    // Returns the length of the response data. The client no longer trusts what it knew
+    // of TempKey and authorization, since the command may have changed them.
+    pub fn execute_raw(
+        &mut self,
+        opcode: u8,
+        mode: u8,
+        param2: u16,
+        data: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, Error> {
         let packet =
-            command::Read::new(self.atca.packet_builder()).read(Zone::Config, Size::Block, 0, 0)?;
-        self.atca.execute(packet)?.as_ref().try_into()
+            command::Raw::new(self.packet_builder()).command(opcode, mode, param2, data)?;
+        let result = self.wiping(|atca| {
+            let pdu = atca.execute(packet)?;
+            response
+                .get_mut(..pdu.as_ref().len())
+                .ok_or_else(|| Error::from(ErrorKind::SmallBuffer))?
+                .copy_from_slice(pdu.as_ref());
+            Ok(pdu.as_ref().len())
+        });
+        self.tempkey.invalidate();
+        self.authorized.take();
+        result
     }
 
-    pub fn pubkey(&mut self, key_id: Slot) -> Result<PublicKey, Error> {
-        let mut pubkey = PublicKey::default();
-        CertificateRepr::new()
-            .enumerate()
-            .scan(0, |offset, (i, ranges)| {
-                let result = command::Read::new(self.atca.packet_builder())
-                    .slot(key_id, i as u8)
-                    .and_then(|packet| {
-                        let response = self.atca.execute(packet)?;
-                        for range in ranges {
-                            let dst = *offset..*offset + range.len();
-                            pubkey.as_mut()[dst].copy_from_slice(&response.as_ref()[range.clone()]);
-                            *offset += range.len();
-                        }
-                        Ok(())
-                    });
-                Some(result)
-            })
-            .try_for_each(identity)
-            .map(|()| pubkey)
+    // Whether `key_id` holds a usable private key. Run at first boot to
+    // decide whether GenKey is needed.
+    pub fn key_valid(&mut self, key_id: Slot) -> Result<KeyValid, Error> {
+        let packet = Info::new(self.packet_builder()).key_valid(key_id)?;
+        self.execute(packet)?.as_ref().try_into()
     }
 
-    pub fn write_pubkey(&mut self, key_id: Slot, pubkey: impl AsRef<[u8]>) -> Result<(), Error> {
-        let mut data = Block::default();
-        CertificateRepr::new()
-            .enumerate()
-            .scan(0, |offset, (i, ranges)| {
-                // Initialize block sized buffer
-                data.as_mut().iter_mut().for_each(|value| *value = 0);
-
-                for range in ranges {
-                    let src = *offset..*offset + range.len();
-                    data.as_mut()[range.clone()].copy_from_slice(&pubkey.as_ref()[src]);
-                    *offset += range.len();
-                }
+    // TempKey flags and authorization state as the device sees them.
+    pub fn device_state(&mut self) -> Result<DeviceState, Error> {
+        let packet = Info::new(self.packet_builder()).state()?;
+        self.execute(packet)?.as_ref().try_into()
+    }
 
-                let result = command::Write::new(self.atca.packet_builder())
-                    .slot(key_id, i as u8, &data)
-                    .and_then(|packet| self.atca.execute(packet).map(drop));
+    // Keys with KeyConfig.PersistentDisable set can only be used while the
+    // persistent latch is set. Set it once the application has authorized
+    // their use. It survives sleep and is cleared at power-up.
+    pub fn persistent_latch_get(&mut self) -> Result<VolatileKeyPermit, Error> {
+        let packet = Info::new(self.packet_builder()).volatile_key_permit()?;
+        let permit = VolatileKeyPermit::try_from(self.execute(packet)?.as_ref())?;
+        self.persistent_latch.replace(permit.is_permitted());
+        Ok(permit)
+    }
 
-                Some(result)
-            })
-            .try_for_each(identity)
+    pub fn persistent_latch_set(&mut self) -> Result<(), Error> {
+        self.set_persistent_latch(true)
     }
 
-    pub fn write_aes_key(&mut self, key_id: Slot, aes_key: impl AsRef<[u8]>) -> Result<(), Error> {
-        let mut data = Block::default();
-        data.as_mut()[..0x10].copy_from_slice(aes_key.as_ref());
-        let packet =
-            command::Write::new(self.atca.packet_builder()).slot(key_id, 0 as u8, &data)?;
-        self.atca.execute(packet).map(drop)
+    pub fn persistent_latch_clear(&mut self) -> Result<(), Error> {
+        self.set_persistent_latch(false)
     }
 
-    pub fn is_slot_locked(&mut self, slot: Slot) -> Result<bool, Error> {
-        let zone = Zone::Config;
-        let size = Size::Word;
-        let block = 2;
-        let word_offset = 6;
-        let packet =
-            command::Read::new(self.atca.packet_builder()).read(zone, size, block, word_offset)?;
-        let response = self.atca.execute(packet)?;
-        let word = Word::try_from(response.as_ref())?;
-        let slot_locked_bytes = word.as_ref()[..2]
-            .try_into()
-            .map(u16::from_le_bytes)
-            .unwrap_or_else(|_| unreachable!());
-        Ok(slot_locked_bytes & (0x01u16 << slot as u32) == 0x00)
+    fn set_persistent_latch(&mut self, enable: bool) -> Result<(), Error> {
+        let packet = Info::new(self.packet_builder()).set_volatile_key_permit(enable)?;
+        self.execute(packet)?;
+        self.persistent_latch.replace(enable);
+        Ok(())
     }
 
-    pub fn is_locked(&mut self, zone: Zone) -> Result<bool, Error> {
-        let size = Size::Word;
-        let block = 2;
-        let word_offset = 5;
-        let packet = command::Read::new(self.atca.packet_builder()).read(
-            Zone::Config,
-            size,
-            block,
-            word_offset,
-        )?;
-        let response = self.atca.execute(packet)?;
-        let word = Word::try_from(response.as_ref())?;
-        match zone {
-            Zone::Config => Ok(word.as_ref()[3] != 0x55),
-            Zone::Data => Ok(word.as_ref()[2] != 0x55),
-            Zone::Otp => Err(ErrorKind::BadParam.into()),
+    // Reject a key that is known to be disabled by a clear persistent latch
+    // or to require an authorization that is not in place. Keys are known
+    // from the last config zone read.
+    pub(crate) fn require_key_usable(&self, key_id: Slot) -> Result<(), Error> {
+        if self.persistent_latch == Some(false) && self.latched_keys & 1 << key_id as u16 != 0 {
+            return Err(ErrorKind::PersistentLatchClear.into());
         }
+        if let Some(auth_key) = self.required_auth[key_id as usize] {
+            if self.authorized.map(|slot| slot as u8) != Some(auth_key) {
+                return Err(ErrorKind::AuthorizationRequired.into());
+            }
+        }
+        Ok(())
     }
 
-    pub fn lock_slot(&mut self, key_id: Slot) -> Result<(), Error> {
-        let packet = Lock::new(self.atca.packet_builder()).slot(key_id)?;
-        self.atca.execute(packet).map(drop)
+    // Reject a command the slot configuration does not allow, before sending
+    // it. Slots are not checked until the config zone has been read.
+    pub(crate) fn require_capability(
+        &self,
+        key_id: Slot,
+        opcode: OpCode,
+        permitted: fn(&SlotCaps) -> bool,
+    ) -> Result<(), Error> {
+        match self.capabilities[key_id as usize] {
+            Some(caps) if !permitted(&caps) => {
+                Err(Error::from(ErrorKind::KeyNotPermitted).with_opcode(opcode as u8))
+            }
+            _ => Ok(()),
+        }
     }
 
-    pub fn lock(&mut self, zone: Zone) -> Result<(), Error> {
-        let packet = Lock::new(self.atca.packet_builder()).zone(zone, None)?;
-        self.atca.execute(packet).map(drop)
+    // SHA-256 of a message of any length, for instance a firmware image.
+    pub fn sha_digest(&mut self, data: &[u8]) -> Result<Digest, Error> {
+        self.sha().digest(data)
     }
 
-    pub fn lock_crc(&mut self, zone: Zone, crc: u16) -> Result<(), Error> {
-        let packet = Lock::new(self.atca.packet_builder()).zone(zone, Some(crc))?;
-        self.atca.execute(packet).map(drop)
+    // Identify the device from its revision. Once known, commands the device
+    // does not implement are rejected without being sent.
+    pub fn device_type(&mut self) -> Result<DeviceType, Error> {
+        if let Some(device) = self.device {
+            return Ok(device);
+        }
+        let device = self.info()?.device_type();
+        self.device.replace(device);
+        Ok(device)
     }
 
-    pub fn chip_options(&mut self) -> Result<u16, Error> {
-        let (block, offset, pos) = Zone::locate_index(Self::CHIP_OPTIONS_INDEX);
-        let range = pos as usize..pos as usize + 2;
-        self.read_config(Size::Word, block, offset).map(|resp| {
-            resp.as_ref()[range]
-                .try_into()
-                .map(u16::from_le_bytes)
-                .unwrap_or_else(|_| unreachable!())
-        })
+    // Run the selected built-in self tests. If any of them fails, the device
+    // refuses further cryptographic commands until it passes again.
+    pub fn self_test(&mut self, mode: SelfTestMode) -> Result<SelfTestResult, Error> {
+        let packet = SelfTest::new(self.packet_builder()).self_test(mode)?;
+        self.execute(packet)?.as_ref().try_into()
     }
 
-    pub fn permission(&mut self, slot: Slot) -> Result<u16, Error> {
-        let index = Self::SLOT_CONFIG_INDEX + (slot as usize * 2);
-        let (block, offset, pos) = Zone::locate_index(index);
-        let range = pos as usize..pos as usize + 2;
-        self.read_config(Size::Word, block, offset).map(|resp| {
-            resp.as_ref()[range]
-                .try_into()
-                .map(u16::from_le_bytes)
-                .unwrap_or_else(|_| unreachable!())
+    pub fn random(&mut self) -> Result<Block, Error> {
+        self.wiping(|atca| {
+            let packet = Random::new(atca.packet_builder()).random()?;
+            atca.execute(packet)?.as_ref().try_into()
         })
     }
 
-    pub fn key_type(&mut self, slot: Slot) -> Result<u16, Error> {
-        let index = Self::KEY_CONFIG_INDEX + (slot as usize * 2);
-        let (block, offset, pos) = Zone::locate_index(index);
-        let range = pos as usize..pos as usize + 2;
-        self.read_config(Size::Word, block, offset).map(|resp| {
-            resp.as_ref()[range]
-                .try_into()
-                .map(u16::from_le_bytes)
-                .unwrap_or_else(|_| unreachable!())
+    // Same as `random` without updating the seed in EEPROM, for callers
+    // drawing random numbers often.
+    pub fn random_no_seed_update(&mut self) -> Result<Block, Error> {
+        self.wiping(|atca| {
+            let packet = Random::new(atca.packet_builder()).random_no_seed_update()?;
+            atca.execute(packet)?.as_ref().try_into()
         })
     }
 
-    // TODO: Testing purpose only.
-    pub fn read_config(
+    // Check at startup that random numbers are usable. Until the
+    // configuration zone is locked, the device answers Random with the fixed
+    // pattern ff ff 00 00 ..., which is reported as ConfigNotLocked.
+    pub fn rng_health_check(&mut self) -> Result<(), Error> {
+        let random = self.random_no_seed_update()?;
+        let test_pattern = random
+            .as_ref()
+            .chunks(4)
+            .all(|word| word == [0xff, 0xff, 0x00, 0x00]);
+        if test_pattern {
+            return Err(ErrorKind::ConfigNotLocked.into());
+        }
+        Ok(())
+    }
+
+    // Load TempKey with a random nonce combined with `num_in`. Returns the
+    // random number; see `host::nonce` for the resulting TempKey.
+    pub fn nonce_random(&mut self, num_in: &[u8; 20]) -> Result<Block, Error> {
+        let packet = NonceCtx::new(self.packet_builder()).random(num_in, true)?;
+        let rand_out = self.execute(packet)?.as_ref().try_into()?;
+        self.tempkey.load(TempKeySource::Random);
+        Ok(rand_out)
+    }
+
+    // Load a fixed value into TempKey, the message digest buffer or the
+    // alternate key buffer. `data` is either 32 or 64 bytes long.
+    pub fn nonce(&mut self, target: NonceTarget, data: &[u8]) -> Result<(), Error> {
+        self.wiping(|atca| {
+            let packet = NonceCtx::new(atca.packet_builder()).passthrough(target, data)?;
+            atca.execute(packet).map(drop)
+        })?;
+        if target == NonceTarget::TempKey {
+            self.tempkey.load(TempKeySource::Input);
+        }
+        Ok(())
+    }
+
+    // Write to device's digest message buffer.
+    pub fn write_message_digest_buffer(&mut self, msg: &Digest) -> Result<(), Error> {
+        let packet = NonceCtx::new(self.packet_builder()).message_digest_buffer(msg)?;
+        self.execute(packet).map(drop)
+    }
+
+    // Generate a new private key in the slot and output its public key.
+    pub fn generate_key(&mut self, key_id: Slot) -> Result<PublicKey, Error> {
+        let packet = GenKey::new(self.packet_builder()).private_key(key_id)?;
+        self.execute(packet)?.as_ref().try_into()
+    }
+
+    // Write private key.
+    pub fn write_private_key(&mut self, key_id: Slot, private_key: &Block) -> Result<(), Error> {
+        self.wiping(|atca| {
+            let packet =
+                PrivWrite::new(atca.packet_builder()).write_private_key(key_id, private_key)?;
+            atca.execute(packet).map(drop)
+        })
+    }
+
+    // Inject an externally generated private key over an encrypted channel.
+    // Keys generated inside the device never leave it; prefer `generate_key`
+    // unless provisioning requires otherwise. `write_key_id` and `write_key`
+    // name the slot's WriteKey, which encrypts and authenticates the input.
+    pub fn dangerous_priv_write(
+        &mut self,
+        key_id: Slot,
+        private_key: &Block,
+        write_key_id: Slot,
+        write_key: &Block,
+        num_in: &[u8; 20],
+    ) -> Result<(), Error> {
+        let serial = self.memory().serial_number()?;
+        let mut atca = self.transaction()?;
+        atca.begin_sequence(&[OpCode::Nonce, OpCode::GenDig, OpCode::PrivWrite])?;
+        let rand_out = atca.nonce_random(num_in)?;
+        let tempkey = host::nonce(&rand_out, num_in);
+
+        atca.gen_dig(write_key_id)?;
+        #[allow(unused_mut)]
+        let mut session_key = host::gen_dig(write_key, write_key_id, &serial, &tempkey);
+
+        atca.tempkey.require_gen_dig(write_key_id)?;
+        let (value, mac) = host::priv_write(&session_key, key_id, &serial, private_key);
+        #[cfg(feature = "zeroize")]
+        session_key.zeroize();
+        let packet = PrivWrite::new(atca.packet_builder())
+            .write_private_key_encrypted(key_id, &value, &mac)?;
+        let result = atca.execute(packet).map(drop);
+        atca.tempkey.invalidate();
+        result
+    }
+
+    // Combine the key in `key_id` into TempKey. See `host::gen_dig`.
+    pub(crate) fn gen_dig(&mut self, key_id: Slot) -> Result<(), Error> {
+        self.gen_dig_source(GenDigSource::Data(key_id))
+    }
+
+    // Combine a key, counter, configuration or nonce into TempKey. Some slot
+    // configurations need these before an encrypted read or write.
+    pub fn gen_dig_source(&mut self, source: GenDigSource) -> Result<(), Error> {
+        if let Some(key_id) = source.key_id() {
+            self.require_key_usable(key_id)?;
+        }
+        self.tempkey.require_valid()?;
+        let packet = GenDig::new(self.packet_builder()).source(source)?;
+        self.execute(packet)?;
+        match source.key_id() {
+            Some(key_id) => self.tempkey.gen_dig(key_id),
+            None => self.tempkey.clear_gen_dig(),
+        }
+        Ok(())
+    }
+
+    pub fn hmac_context(&mut self, key_id: Slot) -> Hmac<'_, PHY, D, N> {
+        Hmac {
+            atca: self,
+            key_id,
+            remaining_bytes: Vec::new(),
+        }
+    }
+
+    // Calculate HMAC-SHA256 over `msg` keyed by the secret in `key_id`.
+    pub fn hmac(&mut self, key_id: Slot, msg: &[u8]) -> Result<Digest, Error> {
+        self.hmac_context(key_id).digest(msg)
+    }
+
+    // Sign a 32-byte digest, typically the SHA256 hash of the full message,
+    // with the private key in `key_id`. The signature is R and S integers in
+    // big-endian format.
+    pub fn sign(&mut self, key_id: Slot, digest: &Digest) -> Result<Signature, Error> {
+        self.require_capability(key_id, OpCode::Sign, |caps| caps.sign_external)?;
+        self.require_key_usable(key_id)?;
+        // 1. Random value generation
+        self.random()?;
+        // 2. Nonce load
+        self.write_message_digest_buffer(digest)?;
+        // 3. Sign
+        let packet = command::Sign::new(self.packet_builder()).external(key_id)?;
+        self.execute(packet)?.as_ref().try_into()
+    }
+
+    // Sign the message the device builds from TempKey, as loaded by a nonce
+    // and GenDig over a data slot or GenKey in digest mode, and from its own
+    // state. Key attestation and signatures over derived keys work this way.
+    // Returns the message besides TempKey, from which a verifier computes the
+    // signed digest.
+    pub fn sign_internal(
+        &mut self,
+        key_id: Slot,
+        include_full_sn: bool,
+    ) -> Result<(Signature, host::SignInternalMessage), Error> {
+        self.require_capability(key_id, OpCode::Sign, |caps| caps.sign_internal)?;
+        self.require_key_usable(key_id)?;
+        let source = self.tempkey.require_valid()?;
+        // TempKeyFlags: KeyId, SourceFlag, GenDigData, GenKeyData, NoMacFlag.
+        let (temp_key_id, mut flags) = match self.tempkey.gen_dig_key() {
+            Some(key) => (key, 0x20),
+            None => self
+                .tempkey
+                .gen_key_slot()
+                .map(|key| (key, 0x40))
+                .ok_or_else(|| Error::from(ErrorKind::TempKeySourceMismatch))?,
+        };
+
+        let mut memory = self.memory();
+        let serial = memory.serial_number()?;
+        let slot_config = memory.read_config_field(ConfigField::SlotConfig(temp_key_id))?;
+        let key_config = memory.read_config_field(ConfigField::KeyConfig(temp_key_id))?;
+        let slot_locked = memory.is_slot_locked(temp_key_id)?;
+        let no_mac =
+            SlotConfig::from(u16::from_le_bytes([slot_config[0], slot_config[1]])).no_mac();
+
+        flags |= temp_key_id as u8;
+        if source == TempKeySource::Input {
+            flags |= 0x10;
+        }
+        if no_mac {
+            flags |= 0x80;
+        }
+
+        let mode = command::Sign::internal_mode(include_full_sn);
+        let sn = serial.as_ref();
+        let mut message = [0x00; host::SignInternalMessage::SIZE];
+        message[0] = OpCode::Sign as u8;
+        message[1] = mode;
+        message[2..4].copy_from_slice(&(key_id as u16).to_le_bytes());
+        message[4..6].copy_from_slice(&slot_config);
+        message[6..8].copy_from_slice(&key_config);
+        message[8] = flags;
+        message[11] = sn[8];
+        message[16..18].copy_from_slice(&sn[0..2]);
+        if include_full_sn {
+            message[12..16].copy_from_slice(&sn[4..8]);
+            message[18..20].copy_from_slice(&sn[2..4]);
+        }
+        message[20] = u8::from(!slot_locked);
+
+        let packet = command::Sign::new(self.packet_builder()).internal(key_id, include_full_sn)?;
+        let signature = self.execute(packet)?.as_ref().try_into()?;
+        Ok((signature, message.into()))
+    }
+
+    // Digest the public key stored in `key_id`, or that of the private key
+    // there, with OtherData into TempKey, which must hold a nonce. See
+    // `host::gen_key_digest`.
+    pub fn gen_key_digest(&mut self, key_id: Slot, other_data: &[u8; 3]) -> Result<(), Error> {
+        self.tempkey.require_valid()?;
+        let packet = GenKey::new(self.packet_builder()).public_key_digest(key_id, other_data)?;
+        self.execute(packet)?;
+        self.tempkey.gen_key(key_id);
+        Ok(())
+    }
+
+    // Prove that the private key in `key_id` was generated inside the
+    // device. Its public key is digested with the verifier's `challenge`
+    // and signed by Sign Internal with `attestation_key`, including the full
+    // serial number. The verifier checks the signature over
+    // `host::attestation_digest`.
+    pub fn attest_key(
+        &mut self,
+        key_id: Slot,
+        attestation_key: Slot,
+        challenge: &Block,
+        other_data: &[u8; 3],
+    ) -> Result<(PublicKey, Signature, host::SignInternalMessage), Error> {
+        let public_key = self.public_key(key_id)?;
+        let mut atca = self.transaction()?;
+        atca.begin_sequence(&[
+            OpCode::Nonce,
+            OpCode::GenKey,
+            OpCode::Read,
+            OpCode::Read,
+            OpCode::Read,
+            OpCode::Read,
+            OpCode::Sign,
+        ])?;
+        atca.nonce(NonceTarget::TempKey, challenge.as_ref())?;
+        atca.gen_key_digest(key_id, other_data)?;
+        let (signature, message) = atca.sign_internal(attestation_key, true)?;
+        Ok((public_key, signature, message))
+    }
+
+    // Given a private key created and stored in advance, calculate its public key.
+    pub fn public_key(&mut self, key_id: Slot) -> Result<PublicKey, Error> {
+        let packet = GenKey::new(self.packet_builder()).public_key(key_id)?;
+        self.execute(packet)?.as_ref().try_into()
+    }
+
+    // Verify a signature over `msg` against an external P-256 public key. The
+    // message is hashed on the device. Returns `Ok(false)` when the device
+    // reports a signature mismatch.
+    pub fn verify_external(
+        &mut self,
+        msg: &[u8],
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<bool, Error> {
+        let digest = self.sha().digest(msg)?;
+        self.verify_external_digest(&digest, signature, public_key)
+    }
+
+    // Same as `verify_external`, taking a precomputed SHA-256 digest.
+    pub fn verify_external_digest(
+        &mut self,
+        digest: &Digest,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<bool, Error> {
+        // 1. Nonce load
+        let mac = self.load_verify_message(digest)?;
+        // 2. Verify
+        let mut verify = command::Verify::new(self.packet_builder());
+        let packet = match mac {
+            Some(_) => verify.external_mac(signature, public_key)?,
+            None => verify.external(signature, public_key)?,
+        };
+        let result = self
+            .execute(packet)
+            .and_then(|response| check_verify_mac(mac, response.as_ref(), digest, signature, None));
+        verification(result)
+    }
+
+    // Verify a signature over `msg` against the public key stored in
+    // `key_id`. Returns `Ok(false)` when the device reports a signature
+    // mismatch. A key that requires validation but has been invalidated is
+    // rejected with an execution error.
+    pub fn verify_stored(
+        &mut self,
+        key_id: Slot,
+        msg: &[u8],
+        signature: &Signature,
+    ) -> Result<bool, Error> {
+        let digest = self.sha().digest(msg)?;
+        self.verify_stored_digest(key_id, &digest, signature)
+    }
+
+    // Same as `verify_stored`, taking a precomputed SHA-256 digest.
+    pub fn verify_stored_digest(
+        &mut self,
+        key_id: Slot,
+        digest: &Digest,
+        signature: &Signature,
+    ) -> Result<bool, Error> {
+        // 1. Nonce load
+        let mac = self.load_verify_message(digest)?;
+        // 2. Verify
+        let mut verify = command::Verify::new(self.packet_builder());
+        let packet = match mac {
+            Some(_) => verify.stored_mac(key_id, signature)?,
+            None => verify.stored(key_id, signature)?,
+        };
+        let result = self.execute(packet).and_then(|response| {
+            check_verify_mac(mac, response.as_ref(), digest, signature, Some(key_id))
+        });
+        verification(result)
+    }
+
+    // Load the message digest buffer for Verify. With an I/O protection key
+    // a system nonce from the RNG follows the digest, so that Verify
+    // answers with a MAC a forged success on the bus cannot reproduce.
+    // Returns the key and nonce to check that MAC with.
+    fn load_verify_message(&mut self, digest: &Digest) -> Result<Option<(Block, Block)>, Error> {
+        let io_key = match self.io_key {
+            Some(io_key) => io_key,
+            None => return self.write_message_digest_buffer(digest).map(|()| None),
+        };
+        let system_nonce = self.random()?;
+        let mut buffer = [0x00; 2 * Size::Block as usize];
+        buffer[..Size::Block.len()].copy_from_slice(digest.as_ref());
+        buffer[Size::Block.len()..].copy_from_slice(system_nonce.as_ref());
+        let packet =
+            NonceCtx::new(self.packet_builder()).passthrough(NonceTarget::MsgDigBuf, &buffer)?;
+        self.execute(packet)?;
+        Ok(Some((io_key, system_nonce)))
+    }
+
+    // Mark the public key stored in `key_id` valid so that Verify accepts it,
+    // if `signature` by the validating key, named by the slot's ReadKey,
+    // verifies over `host::validation_digest`. TempKey must hold the public
+    // key digest of `key_id` from `gen_key_digest`. Returns `Ok(false)` when
+    // the signature does not verify.
+    pub fn validate_public_key(
+        &mut self,
+        key_id: Slot,
+        signature: &Signature,
+        other_data: &[u8; 19],
+    ) -> Result<bool, Error> {
+        self.set_public_key_validity(key_id, signature, other_data, true)
+    }
+
+    // Same as `validate_public_key`, marking the key invalid instead.
+    pub fn invalidate_public_key(
+        &mut self,
+        key_id: Slot,
+        signature: &Signature,
+        other_data: &[u8; 19],
+    ) -> Result<bool, Error> {
+        self.set_public_key_validity(key_id, signature, other_data, false)
+    }
+
+    fn set_public_key_validity(
+        &mut self,
+        key_id: Slot,
+        signature: &Signature,
+        other_data: &[u8; 19],
+        valid: bool,
+    ) -> Result<bool, Error> {
+        self.tempkey.require_valid()?;
+        if self.tempkey.gen_key_slot() != Some(key_id) {
+            return Err(ErrorKind::TempKeySourceMismatch.into());
+        }
+        let packet = command::Verify::new(self.packet_builder())
+            .validate(key_id, signature, other_data, valid)?;
+        verification(self.execute(packet).map(drop))
+    }
+
+    // Compute a SHA-256 MAC over the key in `key_id` and `challenge`. Pass
+    // `None` as the challenge if the mode takes it from TempKey.
+    pub fn mac(
+        &mut self,
+        key_id: Slot,
+        mode: MacMode,
+        challenge: Option<&Block>,
+    ) -> Result<Digest, Error> {
+        self.require_key_usable(key_id)?;
+        if mode.uses_tempkey() {
+            self.tempkey.require_source(mode.tempkey_source())?;
+        }
+        let packet = Mac::new(self.packet_builder()).mac(mode, key_id, challenge)?;
+        self.execute(packet)?.as_ref().try_into()
+    }
+
+    // Authenticate a host against the secret in `key_id`. `response` is the
+    // SHA-256 MAC the host computed over the key, `challenge` and
+    // `other_data`. Returns `Ok(false)` when the device calculates a
+    // different MAC.
+    pub fn checkmac(
+        &mut self,
+        key_id: Slot,
+        mode: MacMode,
+        challenge: &Block,
+        response: &Block,
+        other_data: &[u8; 13],
+    ) -> Result<bool, Error> {
+        if mode.uses_tempkey() {
+            self.tempkey.require_source(mode.tempkey_source())?;
+        }
+        let packet = CheckMac::new(self.packet_builder())
+            .checkmac(mode, key_id, challenge, response, other_data)?;
+        // A failed CheckMac clears the authorization state of the device.
+        let verified = verification(self.execute(packet).map(drop));
+        if !matches!(verified, Ok(true)) {
+            self.authorized.take();
+        }
+        verified
+    }
+
+    // Authenticate an accessory whose `key_id` holds a key diversified from
+    // `root_key` by its serial number; see `host::diversify_key`. The
+    // accessory answers `challenge`, which should be fresh for every call.
+    pub fn authenticate_accessory(
+        &mut self,
+        key_id: Slot,
+        root_key: &Block,
+        challenge: &Block,
+    ) -> Result<bool, Error> {
+        let serial = self.memory().serial_number()?;
+        let key = host::diversify_key(root_key, &serial);
+        let mode = MacMode::default().include_serial();
+        let response = self.mac(key_id, mode, Some(challenge))?;
+        let expected = host::mac(mode, key_id, &key, challenge, &serial, None)
+            .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+        // Compare without an early exit.
+        let diff = response
+            .as_ref()
+            .iter()
+            .zip(expected.as_ref())
+            .fold(0x00, |acc, (a, b)| acc | (a ^ b));
+        Ok(diff == 0x00)
+    }
+
+    pub fn counter_read(&mut self, counter_id: CounterId) -> Result<CounterValue, Error> {
+        let packet = Counter::new(self.packet_builder()).read(counter_id)?;
+        self.execute(packet)?.as_ref().try_into()
+    }
+
+    // Increment the counter and return its new value. The operation is
+    // irreversible.
+    pub fn counter_increment(&mut self, counter_id: CounterId) -> Result<CounterValue, Error> {
+        let packet = Counter::new(self.packet_builder()).increment(counter_id)?;
+        self.execute(packet)?.as_ref().try_into()
+    }
+
+    // Signatures left before Counter 0 reaches the count-match value, after
+    // which keys with LimitedUse stop working. `None` if CountMatch is not
+    // enabled. Raise the limit with `Memory::write_count_match`.
+    pub fn remaining_signatures(&mut self) -> Result<Option<u32>, Error> {
+        let count_match = self.memory().read_config_field(ConfigField::CountMatch)?;
+        let key_id = match count_match.first().copied().and_then(count_match_key) {
+            Some(key_id) => key_id,
+            None => return Ok(None),
+        };
+        let limit = self.memory().count_match(key_id)?;
+        let counter = self.counter_read(CounterId::Zero)?;
+        Ok(Some(limit.remaining(counter)))
+    }
+
+    // Pause every device on a shared bus except the one whose Selector
+    // configuration byte matches `selector`. Paused devices resume on the
+    // next wake.
+    pub fn pause(&mut self, selector: u8) -> Result<(), Error> {
+        let packet = Pause::new(self.packet_builder()).pause(selector)?;
+        self.execute(packet).map(drop)
+    }
+
+    pub fn update_extra(&mut self, mode: UpdateExtraMode, value: u16) -> Result<(), Error> {
+        let packet = UpdateExtra::new(self.packet_builder()).update_extra(mode, value)?;
+        self.execute(packet).map(drop)
+    }
+
+    // Set the UserExtra configuration byte. Fails unless it is still zero.
+    pub fn set_user_extra(&mut self, value: u8) -> Result<(), Error> {
+        self.update_extra(UpdateExtraMode::UserExtra, value.into())
+    }
+
+    // Set the UserExtraAdd configuration byte. Fails unless it is still zero.
+    pub fn set_user_extra_add(&mut self, value: u8) -> Result<(), Error> {
+        self.update_extra(UpdateExtraMode::UserExtraAdd, value.into())
+    }
+
+    // Consume one use of a slot whose LimitedUse bit is set.
+    pub fn decrement_limited_use(&mut self, key_id: Slot) -> Result<(), Error> {
+        self.update_extra(UpdateExtraMode::DecrementLimitedUse, key_id as u16)
+    }
+
+    // Derive a new key into `target` from its parent key and TempKey, which
+    // must have been loaded by a random nonce. Pass the authorizing MAC if
+    // the slot requires one; see `host::derive_key_mac`.
+    pub fn derive_key(&mut self, target: Slot, mac: Option<&Block>) -> Result<(), Error> {
+        self.tempkey.require_source(TempKeySource::Random)?;
+        let packet = DeriveKey::new(self.packet_builder()).derive_key(target, mac)?;
+        let result = self.execute(packet).map(drop);
+        self.tempkey.invalidate();
+        result
+    }
+
+    // Perform ECDH key agreement between the private key in `key_id` and the
+    // peer's public key. The premaster secret crosses the bus in the clear.
+    #[deprecated(note = "use `ecdh` and choose the target explicitly")]
+    pub fn diffie_hellman(
+        &mut self,
+        key_id: Slot,
+        public_key: &PublicKey,
+    ) -> Result<PremasterSecret, Error> {
+        self.ecdh(key_id, public_key, EcdhTarget::Output)?
+            .ok_or_else(|| ErrorKind::BadParam.into())
+    }
+
+    // Perform ECDH and put the premaster secret where `target` says. Only
+    // output targets return it; `NextSlot` and `TempKey` keep it on the
+    // device for a subsequent KDF. `EncryptedOutput` needs an I/O
+    // protection key.
+    pub fn ecdh(
+        &mut self,
+        key_id: Slot,
+        public_key: &PublicKey,
+        target: EcdhTarget,
+    ) -> Result<Option<PremasterSecret>, Error> {
+        self.require_capability(key_id, OpCode::Ecdh, |caps| caps.ecdh)?;
+        self.require_key_usable(key_id)?;
+        let io_key =
+            match target {
+                EcdhTarget::EncryptedOutput => Some(self.io_key.ok_or_else(|| {
+                    Error::from(ErrorKind::BadParam).with_opcode(OpCode::Ecdh as u8)
+                })?),
+                _ => None,
+            };
+        let result = self.wiping(|atca| {
+            let packet =
+                Ecdh::new(atca.packet_builder()).diffie_hellman(key_id, public_key, target)?;
+            let response = atca.execute(packet)?;
+            if !target.is_output() {
+                return Ok(None);
+            }
+            match io_key {
+                Some(io_key) => {
+                    PremasterSecret::try_from(&io_decrypt::<32>(&io_key, response.as_ref())?[..])
+                }
+                None => response.as_ref().try_into(),
+            }
+            .map(Some)
+        });
+        if target == EcdhTarget::TempKey {
+            match result {
+                Ok(_) => self.tempkey.load(TempKeySource::Input),
+                Err(_) => self.tempkey.invalidate(),
+            }
+        }
+        result
+    }
+
+    // Ephemeral ECDH: generate a private key in TempKey, where it never
+    // occupies a slot, and agree on a premaster secret with the peer.
+    // Returns our public key to send to the peer, and the premaster secret,
+    // encrypted on the bus if an I/O protection key is set. TempKey is
+    // unusable afterwards. ATECC608 only.
+    pub fn ecdhe(&mut self, public_key: &PublicKey) -> Result<(PublicKey, PremasterSecret), Error> {
+        let io_key = self.io_key;
+        let result = self.wiping(|atca| {
+            let packet = GenKey::new(atca.packet_builder()).private_key_tempkey()?;
+            let own_public_key = atca.execute(packet)?.as_ref().try_into()?;
+            let packet = Ecdh::new(atca.packet_builder())
+                .diffie_hellman_ephemeral(public_key, io_key.is_some())?;
+            let response = atca.execute(packet)?;
+            let premaster_secret = match io_key {
+                Some(io_key) => {
+                    PremasterSecret::try_from(&io_decrypt::<32>(&io_key, response.as_ref())?[..])?
+                }
+                None => response.as_ref().try_into()?,
+            };
+            Ok((own_public_key, premaster_secret))
+        });
+        self.tempkey.invalidate();
+        result
+    }
+
+    // Fill the I/O protection key slot with a random key and use it from
+    // now on. The host must keep the returned key to decrypt output after a
+    // restart.
+    pub fn establish_io_protection_key(&mut self, key_id: Slot) -> Result<Block, Error> {
+        let io_key = self.random()?;
+        self.wiping(|atca| {
+            let packet = command::Write::new(atca.packet_builder()).slot(key_id, 0, &io_key)?;
+            atca.execute(packet).map(drop)
+        })?;
+        self.io_key = Some(io_key);
+        Ok(io_key)
+    }
+
+    // ECDH whose premaster secret never leaves the device. It is kept in
+    // TempKey for a subsequent KDF or GenDig.
+    pub fn diffie_hellman_tempkey(
+        &mut self,
+        key_id: Slot,
+        public_key: &PublicKey,
+    ) -> Result<(), Error> {
+        self.ecdh(key_id, public_key, EcdhTarget::TempKey).map(drop)
+    }
+}
+
+// Compare the MAC returned by Verify against the one expected from the host
+// side, if one was asked for.
+fn check_verify_mac(
+    mac: Option<(Block, Block)>,
+    response: &[u8],
+    digest: &Digest,
+    signature: &Signature,
+    key_id: Option<Slot>,
+) -> Result<(), Error> {
+    let (io_key, system_nonce) = match mac {
+        Some(mac) => mac,
+        None => return Ok(()),
+    };
+    let expected = host::verify_mac(&io_key, digest, &system_nonce, signature, key_id);
+    if response != expected.as_ref() {
+        return Err(Error::from(ErrorKind::AuthenticationFailed).with_opcode(OpCode::Verify as u8));
+    }
+    Ok(())
+}
+
+// Split protected output from the 32-byte output nonce that follows it and
+// decrypt it.
+fn io_decrypt<const N: usize>(io_key: &Block, response: &[u8]) -> Result<Vec<u8, N>, Error> {
+    let split = response
+        .len()
+        .checked_sub(Size::Block.len())
+        .ok_or_else(|| Error::from(ErrorKind::InvalidSize))?;
+    let (data, nonce) = response.split_at(split);
+    let mut output = Vec::new();
+    output
+        .extend_from_slice(data)
+        .map_err(|()| Error::from(ErrorKind::InvalidSize))?;
+    host::io_decrypt(io_key, &Block::try_from(nonce)?, &mut output);
+    Ok(output)
+}
+
+// Memory zones consist of config, data and OTP.
+pub struct Memory<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
+}
+
+impl<'a, PHY, D, const N: usize> Memory<'a, PHY, D, N> {
+    pub(crate) const I2C_ADDRESS_INDEX: usize = 16;
+    pub(crate) const SLOT_CONFIG_INDEX: usize = 20;
+    // UserExtra, UserExtraAdd and the lock bytes, not writable by Write.
+    pub(crate) const USER_EXTRA_INDEX: usize = 84;
+    pub(crate) const CHIP_OPTIONS_INDEX: usize = 90;
+    pub(crate) const KEY_CONFIG_INDEX: usize = 96;
+}
+
+impl<'a, PHY, D, const N: usize> Memory<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    pub fn serial_number(&mut self) -> Result<Serial, Error> {
+        let packet =
+            command::Read::new(self.atca.packet_builder()).read(Zone::Config, Size::Block, 0, 0)?;
+        self.atca.execute(packet)?.as_ref().try_into()
+    }
+
+    // Read a public key stored in a slot, as written by `write_pubkey`.
+    pub fn read_pubkey(&mut self, key_id: Slot) -> Result<PublicKey, Error> {
+        if key_id.size() < PublicKey::SLOT_FORMAT_LEN {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        let mut data = [0x00; PublicKey::SLOT_FORMAT_LEN];
+        self.read_slot_blocks(key_id, &mut data)?;
+        PublicKey::from_slot_format(&data)
+    }
+
+    // Read a certificate stored in the 72-byte compressed form.
+    pub fn compressed_certificate(&mut self, key_id: Slot) -> Result<CompressedCertificate, Error> {
+        CompressedCertificate::try_from(self.read_slot(key_id)?.as_ref())
+    }
+
+    // Store a certificate in the compressed form, filling a 72-byte slot.
+    pub fn write_compressed_certificate(
+        &mut self,
+        key_id: Slot,
+        compressed: &CompressedCertificate,
+    ) -> Result<(), Error> {
+        self.write_slot(key_id, compressed.as_ref())
+    }
+
+    // Read the whole contents of a slot: 36, 72 or 416 bytes depending on
+    // the slot. Full blocks are read at once, the tail word by word.
+    pub fn read_slot(&mut self, key_id: Slot) -> Result<Vec<u8, SLOT_MAX_SIZE>, Error> {
+        let mut data = Vec::new();
+        data.resize(key_id.size(), 0x00)
+            .map_err(|()| Error::from(ErrorKind::InvalidSize))?;
+        self.read_slot_into(key_id, &mut data)?;
+        Ok(data)
+    }
+
+    // Same as `read_slot`, into a buffer of the caller sized for the slot.
+    // Returns the number of bytes written.
+    pub fn read_slot_into(&mut self, key_id: Slot, buffer: &mut [u8]) -> Result<usize, Error> {
+        let data = buffer
+            .get_mut(..key_id.size())
+            .ok_or_else(|| Error::from(ErrorKind::SmallBuffer))?;
+        self.atca
+            .wiping(|atca| atca.memory().read_slot_blocks(key_id, data))?;
+        Ok(data.len())
+    }
+
+    fn read_slot_blocks(&mut self, key_id: Slot, data: &mut [u8]) -> Result<(), Error> {
+        let mut index = 0;
+        for (block, offset, size) in slot_chunks(data.len()) {
+            let packet = match size {
+                Size::Block => {
+                    command::Read::new(self.atca.packet_builder()).slot(key_id, block)?
+                }
+                Size::Word => command::Read::new(self.atca.packet_builder())
+                    .slot_word(key_id, block, offset)?,
+            };
+            let response = self.atca.execute(packet)?;
+            let end = index + response.as_ref().len();
+            data.get_mut(index..end)
+                .ok_or_else(|| Error::from(ErrorKind::InvalidSize))?
+                .copy_from_slice(response.as_ref());
+            index = end;
+        }
+        Ok(())
+    }
+
+    // Write `data` to the start of a slot. Its length has to be a multiple
+    // of 4 bytes, and no more than the slot holds.
+    pub fn write_slot(&mut self, key_id: Slot, data: &[u8]) -> Result<(), Error> {
+        if data.len() > key_id.size() || !data.len().is_multiple_of(Size::Word.len()) {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        self.atca
+            .wiping(|atca| atca.memory().write_slot_blocks(key_id, data))
+    }
+
+    fn write_slot_blocks(&mut self, key_id: Slot, mut data: &[u8]) -> Result<(), Error> {
+        for (block, offset, size) in slot_chunks(data.len()) {
+            let (chunk, rest) = data.split_at(size.len());
+            let packet = match size {
+                Size::Block => command::Write::new(self.atca.packet_builder()).slot(
+                    key_id,
+                    block,
+                    &Block::try_from(chunk)?,
+                )?,
+                Size::Word => command::Write::new(self.atca.packet_builder()).slot_word(
+                    key_id,
+                    block,
+                    offset,
+                    &Word::try_from(chunk)?,
+                )?,
+            };
+            self.atca.execute(packet)?;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    // Store a public key in the padded form that Verify and GenKey read from
+    // a slot. The slot has to hold at least 72 bytes.
+    pub fn write_pubkey(&mut self, key_id: Slot, pubkey: &PublicKey) -> Result<(), Error> {
+        if key_id.size() < PublicKey::SLOT_FORMAT_LEN {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        self.write_slot_blocks(key_id, &pubkey.to_slot_format())
+    }
+
+    // Write every slot of `image` that takes a plain write, then read them
+    // back to verify. Private key slots and locked slots are skipped, as are
+    // slots that cannot be read back once the data zone is locked. Returns
+    // the slots written.
+    pub fn write_data_image(&mut self, image: &DataZoneImage) -> Result<Vec<Slot, 16>, Error> {
+        let zone = self.config_zone()?;
+        if !zone.is_config_locked() {
+            return Err(ErrorKind::ConfigNotLocked.into());
+        }
+        let mut written = Vec::<Slot, 16>::new();
+        for slot in Slot::keys() {
+            if zone.key_config(slot).private() || zone.is_slot_locked(slot) {
+                continue;
+            }
+            self.write_slot_blocks(slot, image.slot(slot))?;
+            written.push(slot).unwrap_or_else(|_| unreachable!());
+        }
+
+        let mut buffer = [0x00; SLOT_MAX_SIZE];
+        let result = written
+            .iter()
+            .filter(|slot| !zone.is_data_locked() || zone.slot_capabilities(**slot).clear_read)
+            .try_for_each(|slot| {
+                let length = self.read_slot_into(*slot, &mut buffer)?;
+                if buffer[..length] != *image.slot(*slot) {
+                    return Err(ErrorKind::AssertFailure.into());
+                }
+                Ok(())
+            });
+        buffer.iter_mut().for_each(|value| *value = 0x00);
+        result.map(|()| written)
+    }
+
+    // Read the count-match value from the start of `key_id`.
+    pub fn count_match(&mut self, key_id: Slot) -> Result<CountMatch, Error> {
+        let mut data = [0x00; 8];
+        self.read_slot_blocks(key_id, &mut data)?;
+        CountMatch::try_from(data.as_ref())
+    }
+
+    // Store a new count-match value at the start of `key_id`, as the slot's
+    // write policy allows.
+    pub fn write_count_match(&mut self, key_id: Slot, limit: &CountMatch) -> Result<(), Error> {
+        self.write_slot_blocks(key_id, &limit.to_bytes())
+    }
+
+    // Make `key_id` the count-match slot, or disable CountMatch, in the
+    // unlocked config zone.
+    pub fn enable_count_match(&mut self, key_id: Option<Slot>) -> Result<(), Error> {
+        self.write_config_field(ConfigField::CountMatch, &[count_match_byte(key_id)])
+    }
+
+    // Read a block of a slot configured with EncryptRead. The block is
+    // encrypted by the device with a session key derived from the slot's
+    // ReadKey, given here as `read_key_id` and `read_key`. Every block takes
+    // a fresh Nonce and GenDig.
+    pub fn read_slot_encrypted(
+        &mut self,
+        key_id: Slot,
+        block: u8,
+        read_key_id: Slot,
+        read_key: &Block,
+    ) -> Result<Block, Error> {
+        Zone::Data.get_slot_addr(key_id, block)?;
+        let serial = self.serial_number()?;
+        let mut atca = self.atca.transaction()?;
+        atca.begin_sequence(&[OpCode::Nonce, OpCode::GenDig, OpCode::Read])?;
+        #[allow(unused_mut)]
+        let mut session_key = atca.memory().session_key(&serial, read_key_id, read_key)?;
+
+        let packet = command::Read::new(atca.packet_builder()).slot(key_id, block)?;
+        let response = atca.execute(packet)?;
+        let ciphertext = Block::try_from(response.as_ref())?;
+        let plaintext = host::encrypt_block(&session_key, &ciphertext);
+        #[cfg(feature = "zeroize")]
+        session_key.zeroize();
+        Ok(plaintext)
+    }
+
+    // Write a block of a slot whose SlotConfig requires encrypted writes.
+    // `write_key_id` and `write_key` name the slot's WriteKey. Every block
+    // takes a fresh Nonce and GenDig, and its MAC covers the block address.
+    pub fn write_slot_encrypted(
+        &mut self,
+        key_id: Slot,
+        block: u8,
+        data: &Block,
+        write_key_id: Slot,
+        write_key: &Block,
+    ) -> Result<(), Error> {
+        Zone::Data.get_slot_addr(key_id, block)?;
+        let serial = self.serial_number()?;
+        let mut atca = self.atca.transaction()?;
+        atca.begin_sequence(&[OpCode::Nonce, OpCode::GenDig, OpCode::Write])?;
+        #[allow(unused_mut)]
+        let mut session_key = atca
+            .memory()
+            .session_key(&serial, write_key_id, write_key)?;
+
+        let mac = host::write_mac(&session_key, key_id, block, &serial, data)
+            .ok_or_else(|| Error::from(ErrorKind::BadParam))?;
+        let ciphertext = host::encrypt_block(&session_key, data);
+        #[cfg(feature = "zeroize")]
+        session_key.zeroize();
+        let packet = command::Write::new(atca.packet_builder()).slot_encrypted(
+            key_id,
+            block,
+            &ciphertext,
+            &mac,
+        )?;
+        let result = atca.execute(packet).map(drop);
+        atca.tempkey.invalidate();
+        result
+    }
+
+    pub fn write_aes_key(&mut self, key_id: Slot, aes_key: impl AsRef<[u8]>) -> Result<(), Error> {
+        let mut data = Block::default();
+        data.as_mut()[..0x10].copy_from_slice(aes_key.as_ref());
+        let result = self.atca.wiping(|atca| {
+            let packet = command::Write::new(atca.packet_builder()).slot(key_id, 0, &data)?;
+            atca.execute(packet).map(drop)
+        });
+        #[cfg(feature = "zeroize")]
+        data.zeroize();
+        result
+    }
+
+    pub fn is_slot_locked(&mut self, slot: Slot) -> Result<bool, Error> {
+        let zone = Zone::Config;
+        let size = Size::Word;
+        let block = 2;
+        let word_offset = 6;
+        let packet =
+            command::Read::new(self.atca.packet_builder()).read(zone, size, block, word_offset)?;
+        let response = self.atca.execute(packet)?;
+        let word = Word::try_from(response.as_ref())?;
+        let slot_locked_bytes = word.as_ref()[..2]
+            .try_into()
+            .map(u16::from_le_bytes)
+            .unwrap_or_else(|_| unreachable!());
+        Ok(slot_locked_bytes & (0x01u16 << slot as u32) == 0x00)
+    }
+
+    // Lock bits of the config zone, byte 87.
+    pub fn is_config_locked(&mut self) -> Result<bool, Error> {
+        self.is_locked(Zone::Config)
+    }
+
+    // Lock bits of the data and OTP zones, byte 86.
+    pub fn is_data_locked(&mut self) -> Result<bool, Error> {
+        self.is_locked(Zone::Data)
+    }
+
+    // Fail with ConfigNotLocked or DataNotLocked unless `zone` is locked.
+    fn require_locked(&mut self, zone: Zone) -> Result<(), Error> {
+        match zone {
+            Zone::Config if !self.is_config_locked()? => Err(ErrorKind::ConfigNotLocked.into()),
+            Zone::Data if !self.is_data_locked()? => Err(ErrorKind::DataNotLocked.into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_locked(&mut self, zone: Zone) -> Result<bool, Error> {
+        let size = Size::Word;
+        let block = 2;
+        let word_offset = 5;
+        let packet = command::Read::new(self.atca.packet_builder()).read(
+            Zone::Config,
+            size,
+            block,
+            word_offset,
+        )?;
+        let response = self.atca.execute(packet)?;
+        let word = Word::try_from(response.as_ref())?;
+        match zone {
+            Zone::Config => Ok(word.as_ref()[3] != 0x55),
+            Zone::Data => Ok(word.as_ref()[2] != 0x55),
+            Zone::Otp => Err(ErrorKind::BadParam.into()),
+        }
+    }
+
+    pub fn lock_slot(&mut self, key_id: Slot, _token: LockToken) -> Result<(), Error> {
+        let packet = Lock::new(self.atca.packet_builder()).slot(key_id, None)?;
+        self.atca.execute(packet).map(drop)
+    }
+
+    pub fn lock_slot_crc(
+        &mut self,
+        key_id: Slot,
+        crc: u16,
+        _token: LockToken,
+    ) -> Result<(), Error> {
+        let packet = Lock::new(self.atca.packet_builder()).slot(key_id, Some(crc))?;
+        self.atca.execute(packet).map(drop)
+    }
+
+    // Lock the slot only if its contents equal `data`.
+    pub fn lock_slot_image(
+        &mut self,
+        key_id: Slot,
+        data: impl AsRef<[u8]>,
+        token: LockToken,
+    ) -> Result<(), Error> {
+        self.lock_slot_crc(key_id, crc16(data.as_ref()), token)
+    }
+
+    // The data zone can only be locked after the config zone.
+    pub fn lock(&mut self, zone: Zone, _token: LockToken) -> Result<(), Error> {
+        if matches!(zone, Zone::Data) {
+            self.require_locked(Zone::Config)?;
+        }
+        let packet = Lock::new(self.atca.packet_builder()).zone(zone, None)?;
+        self.atca.execute(packet).map(drop)
+    }
+
+    pub fn lock_crc(&mut self, zone: Zone, crc: u16, _token: LockToken) -> Result<(), Error> {
+        if matches!(zone, Zone::Data) {
+            self.require_locked(Zone::Config)?;
+        }
+        let packet = Lock::new(self.atca.packet_builder()).zone(zone, Some(crc))?;
+        self.atca.execute(packet).map(drop)
+    }
+
+    // Lock the zone only if its contents equal `image`: the 128 bytes of the
+    // config zone, or all data slots in order followed by the OTP zone.
+    pub fn lock_image(
+        &mut self,
+        zone: Zone,
+        image: impl AsRef<[u8]>,
+        token: LockToken,
+    ) -> Result<(), Error> {
+        self.lock_crc(zone, crc16(image.as_ref()), token)
+    }
+
+    pub fn chip_options(&mut self) -> Result<u16, Error> {
+        let (block, offset, pos) = Zone::locate_index(Self::CHIP_OPTIONS_INDEX);
+        let range = pos as usize..pos as usize + 2;
+        self.read_config(Size::Word, block, offset).map(|resp| {
+            resp.as_ref()[range]
+                .try_into()
+                .map(u16::from_le_bytes)
+                .unwrap_or_else(|_| unreachable!())
+        })
+    }
+
+    pub fn permission(&mut self, slot: Slot) -> Result<u16, Error> {
+        let index = Self::SLOT_CONFIG_INDEX + (slot as usize * 2);
+        let (block, offset, pos) = Zone::locate_index(index);
+        let range = pos as usize..pos as usize + 2;
+        self.read_config(Size::Word, block, offset).map(|resp| {
+            resp.as_ref()[range]
+                .try_into()
+                .map(u16::from_le_bytes)
+                .unwrap_or_else(|_| unreachable!())
+        })
+    }
+
+    pub fn key_type(&mut self, slot: Slot) -> Result<u16, Error> {
+        let index = Self::KEY_CONFIG_INDEX + (slot as usize * 2);
+        let (block, offset, pos) = Zone::locate_index(index);
+        let range = pos as usize..pos as usize + 2;
+        self.read_config(Size::Word, block, offset).map(|resp| {
+            resp.as_ref()[range]
+                .try_into()
+                .map(u16::from_le_bytes)
+                .unwrap_or_else(|_| unreachable!())
+        })
+    }
+
+    // Load TempKey with a random nonce and combine it with `key` by GenDig.
+    // The device's random number keeps the session key fresh, so a constant
+    // NumIn is sufficient.
+    fn session_key(&mut self, serial: &Serial, key_id: Slot, key: &Block) -> Result<Block, Error> {
+        let num_in = [0x00; 20];
+        let rand_out = self.atca.nonce_random(&num_in)?;
+        let tempkey = host::nonce(&rand_out, &num_in);
+        self.atca.gen_dig(key_id)?;
+        Ok(host::gen_dig(key, key_id, serial, &tempkey))
+    }
+
+    // Read the whole configuration zone, four blocks of 32 bytes.
+    // Bytes past the end of a smaller config zone, as on ATSHA204A, read as
+    // zeros.
+    pub fn config_zone(&mut self) -> Result<ConfigZone, Error> {
+        let size = self
+            .atca
+            .device
+            .map_or(CONFIG_ZONE_SIZE, |device| device.config_zone_size());
+        let mut bytes = [0x00; CONFIG_ZONE_SIZE];
+        let mut index = 0;
+        while index < size {
+            let (block, offset, _) = Zone::locate_index(index);
+            let length = if size - index >= Size::Block.len() {
+                Size::Block
+            } else {
+                Size::Word
+            };
+            let response = self.read_config(length, block, offset)?;
+            bytes[index..index + length.len()].copy_from_slice(response.as_ref());
+            index += length.len();
+        }
+        let zone = ConfigZone::from(bytes);
+        self.atca.latched_keys = Slot::keys()
+            .filter(|slot| zone.key_config(*slot).persistent_disable())
+            .fold(0x0000, |keys, slot| keys | 1 << slot as u16);
+        for slot in Slot::keys() {
+            let key_config = zone.key_config(slot);
+            self.atca.required_auth[slot as usize] =
+                Some(key_config.auth_key()).filter(|_| key_config.req_auth());
+            self.atca.capabilities[slot as usize] = Some(zone.slot_capabilities(slot));
+        }
+        Ok(zone)
+    }
+
+    // Write the configuration described by `provisioner`, verify it by
+    // reading the zone back and, given a lock token, lock the config zone
+    // against the verified image. Returns the resulting zone.
+    pub fn provision(
+        &mut self,
+        provisioner: &Provisioner,
+        lock: Option<LockToken>,
+    ) -> Result<ConfigZone, Error> {
+        let current = self.config_zone()?;
+        if current.is_config_locked() {
+            return Err(ErrorKind::ConfigZoneLocked.into());
+        }
+
+        let mut expected = current;
+        provisioner.apply(&mut expected)?;
+        // SlotConfig, SlotLocked with ChipOptions, and KeyConfig. Other
+        // words are left as they are.
+        let words = (Self::SLOT_CONFIG_INDEX..Self::SLOT_CONFIG_INDEX + 0x20)
+            .chain(Self::CHIP_OPTIONS_INDEX - 2..Self::CHIP_OPTIONS_INDEX + 2)
+            .chain(Self::KEY_CONFIG_INDEX..CONFIG_ZONE_SIZE)
+            .step_by(Size::Word.len());
+        for index in words {
+            let word = &expected.as_bytes()[index..index + Size::Word.len()];
+            if word != &current.as_bytes()[index..index + Size::Word.len()] {
+                let (block, offset, _) = Zone::locate_index(index);
+                self.write_config(Size::Word, block, offset, word)?;
+            }
+        }
+
+        let written = self.config_zone()?;
+        if written != expected {
+            return Err(ErrorKind::AssertFailure.into());
+        }
+        if let Some(token) = lock {
+            self.lock_image(Zone::Config, written, token)?;
+        }
+        Ok(written)
+    }
+
+    // Export the config zone, the OTP zone and every slot readable in the
+    // clear, for `apply_device_profile` on other parts. The data zone has to
+    // be locked for slots to be readable.
+    pub fn export_device_profile(&mut self) -> Result<DeviceProfile, Error> {
+        let config = self.config_zone()?;
+        if !config.is_data_locked() {
+            return Err(ErrorKind::DataNotLocked.into());
+        }
+        let mut otp = [0x00; OTP_ZONE_SIZE];
+        for (block, chunk) in otp.chunks_mut(Size::Block.len()).enumerate() {
+            let packet = command::Read::new(self.atca.packet_builder()).read(
+                Zone::Otp,
+                Size::Block,
+                block as u8,
+                0,
+            )?;
+            chunk.copy_from_slice(Block::try_from(self.atca.execute(packet)?.as_ref())?.as_ref());
+        }
+        let mut data = DataZoneImage::default();
+        let mut slots = 0x0000;
+        for slot in Slot::keys().filter(|slot| config.slot_capabilities(*slot).clear_read) {
+            self.read_slot_into(slot, data.slot_mut(slot))?;
+            slots |= 1 << slot as u16;
+        }
+        Ok(DeviceProfile {
+            config,
+            otp,
+            data,
+            slots,
+        })
+    }
+
+    // Program a fresh part from `profile`: write, verify and lock the config
+    // zone, then write the OTP zone and the exported slots. A config zone
+    // already locked is accepted if it matches. The data zone is left
+    // unlocked, for per-device keys to be created before locking it. A new
+    // I2C address takes effect once the device has slept.
+    pub fn apply_device_profile(
+        &mut self,
+        profile: &DeviceProfile,
+        token: LockToken,
+    ) -> Result<(), Error> {
+        let current = self.config_zone()?;
+        if current.is_config_locked() {
+            if !profile.config.diff(&current).is_empty() {
+                return Err(ErrorKind::ConfigZoneLocked.into());
+            }
+        } else {
+            let words = (Self::I2C_ADDRESS_INDEX..CONFIG_ZONE_SIZE)
+                .step_by(Size::Word.len())
+                .filter(|index| *index != Self::USER_EXTRA_INDEX);
+            for index in words {
+                let word = &profile.config.as_bytes()[index..index + Size::Word.len()];
+                if word != &current.as_bytes()[index..index + Size::Word.len()] {
+                    let (block, offset, _) = Zone::locate_index(index);
+                    self.write_config(Size::Word, block, offset, word)?;
+                }
+            }
+            let written = self.config_zone()?;
+            if !profile.config.diff(&written).is_empty() {
+                return Err(ErrorKind::AssertFailure.into());
+            }
+            self.lock_image(Zone::Config, written, token)?;
+        }
+        if current.is_data_locked() {
+            return Err(ErrorKind::DataZoneLocked.into());
+        }
+
+        for (block, chunk) in profile.otp.chunks(Size::Block.len()).enumerate() {
+            let packet = command::Write::new(self.atca.packet_builder()).write(
+                Zone::Otp,
+                Size::Block,
+                block as u8,
+                0,
+                chunk,
+            )?;
+            self.atca.execute(packet)?;
+        }
+        for slot in Slot::keys().filter(|slot| profile.has_slot(*slot)) {
+            self.write_slot_blocks(slot, profile.data.slot(slot))?;
+        }
+        Ok(())
+    }
+
+    // Change the I2C address (7-bit) the device answers at. The new address
+    // takes effect once the config zone is locked and the device has slept,
+    // after which the client has to be rebuilt `with_address`.
+    pub fn set_i2c_address(&mut self, address: u8) -> Result<(), Error> {
+        self.write_config_field(ConfigField::I2cAddress, &[address << 1])
+    }
+
+    // Read a single field of the config zone, reading only the words it
+    // spans.
+    pub fn read_config_field(
+        &mut self,
+        field: ConfigField,
+    ) -> Result<Vec<u8, CONFIG_FIELD_MAX_SIZE>, Error> {
+        let range = field.range().ok_or(ErrorKind::BadParam)?;
+        let mut value = Vec::new();
+        for (index, span) in config_words(&range) {
+            let (block, offset, _) = Zone::locate_index(index);
+            let word = self.read_config(Size::Word, block, offset)?;
+            value
+                .extend_from_slice(&word.as_ref()[span])
+                .map_err(|()| Error::from(ErrorKind::InvalidSize))?;
+        }
+        Ok(value)
+    }
+
+    // Overwrite a field of the unlocked config zone. Other bytes sharing
+    // its words are read and written back unchanged.
+    pub fn write_config_field(&mut self, field: ConfigField, value: &[u8]) -> Result<(), Error> {
+        let range = field.range().ok_or(ErrorKind::BadParam)?;
+        if !field.is_writable() {
+            return Err(ErrorKind::BadParam.into());
+        }
+        if value.len() != range.len() {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        if self.is_locked(Zone::Config)? {
+            return Err(ErrorKind::ConfigZoneLocked.into());
+        }
+
+        let mut value = value;
+        for (index, span) in config_words(&range) {
+            let (block, offset, _) = Zone::locate_index(index);
+            let mut word = Word::try_from(self.read_config(Size::Word, block, offset)?.as_ref())?;
+            let (head, tail) = value.split_at(span.len());
+            word.as_mut()[span].copy_from_slice(head);
+            value = tail;
+            self.write_config(Size::Word, block, offset, word)?;
+        }
+        Ok(())
+    }
+
+    // Read the config zone back and report every field that differs from
+    // `expected`. An empty list means the device matches.
+    pub fn verify_config(
+        &mut self,
+        expected: &ConfigZone,
+    ) -> Result<Vec<ConfigDiff, CONFIG_FIELDS_MAX>, Error> {
+        self.config_zone().map(|actual| expected.diff(&actual))
+    }
+
+    // TODO: Testing purpose only.
+    pub fn read_config(
         &mut self,
         size: Size,
         block: u8,
@@ -396,115 +2075,373 @@ where
     }
 }
 
+// Split the first `length` bytes of a slot into the block and word accesses
+// covering them, as (block, word offset, size).
+fn slot_chunks(length: usize) -> impl Iterator<Item = (u8, u8, Size)> {
+    let blocks = length / Size::Block.len();
+    let words = length % Size::Block.len() / Size::Word.len();
+    let full = (0..blocks).map(|block| (block as u8, 0, Size::Block));
+    let tail = (0..words).map(move |offset| (blocks as u8, offset as u8, Size::Word));
+    full.chain(tail)
+}
+
+// Start index of each config word overlapping `range`, with the part of the
+// word inside the range.
+fn config_words(range: &Range<usize>) -> impl Iterator<Item = (usize, Range<usize>)> + '_ {
+    let word = Size::Word.len();
+    (range.start / word * word..range.end)
+        .step_by(word)
+        .map(move |index| {
+            let start = range.start.max(index) - index;
+            let end = range.end.min(index + word) - index;
+            (index, start..end)
+        })
+}
+
 // Method signature is taken from cipher::block::BlockCipher.
 // AES
-pub struct Aes<'a, PHY, D> {
-    atca: &'a mut AtCaClient<PHY, D>,
+pub struct Aes<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
     key_id: Slot,
+    key_block: u8,
+}
+
+impl<'a, PHY, D, const N: usize> Aes<'a, PHY, D, N> {
+    // Use the key at `key_block` (0 to 3) of the slot, each block holding
+    // a 16-byte key.
+    pub fn key_block(mut self, key_block: u8) -> Self {
+        self.key_block = key_block;
+        self
+    }
 }
 
-impl<'a, PHY, D> Aes<'a, PHY, D>
+// Buffers are processed in ECB mode one 16-byte block at a time, and must
+// be a multiple of 16 bytes long.
+impl<'a, PHY, D, const N: usize> Aes<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     pub fn encrypt(&mut self, plaintext: &[u8], ciphertext: &mut [u8]) -> Result<(), Error> {
-        use command::Aes as AesCmd;
-
         if plaintext.len() != ciphertext.len() {
             return Err(ErrorKind::BadParam.into());
         }
+        ciphertext.copy_from_slice(plaintext);
+        self.encrypt_in_place(ciphertext)
+    }
 
-        for (plain, cipher) in plaintext
-            .chunks(AesCmd::DATA_SIZE)
-            .zip(ciphertext.chunks_mut(AesCmd::DATA_SIZE))
-        {
-            // Input length should be exactly 16 bytes. Otherwise the device
-            // couldn't recognize the command properly. If the length is not
-            // enough, sufficient number of 0s are padded.
-            let packet = AesCmd::new(self.atca.packet_builder()).encrypt(self.key_id, plain)?;
-
-            // Encrypt plain bytes and write the result to cipher.
-            let response = self.atca.execute(packet)?;
-            if response.as_ref().len() != AesCmd::DATA_SIZE {
-                return Err(ErrorKind::InvalidSize.into());
-            }
-            cipher.copy_from_slice(response.as_ref());
+    pub fn decrypt(&mut self, ciphertext: &[u8], plaintext: &mut [u8]) -> Result<(), Error> {
+        if ciphertext.len() != plaintext.len() {
+            return Err(ErrorKind::BadParam.into());
         }
-        Ok(())
+        plaintext.copy_from_slice(ciphertext);
+        self.decrypt_in_place(plaintext)
     }
 
-    pub fn decrypt(&mut self, ciphertext: &[u8], plaintext: &mut [u8]) -> Result<(), Error> {
+    // Multiply `input` by `h` in GF(2^128) on the device, for GHASH.
+    pub fn gfm(&mut self, h: &[u8; 16], input: &[u8; 16]) -> Result<[u8; 16], Error> {
+        self.atca.wiping(|atca| {
+            let packet = command::Aes::new(atca.packet_builder()).gfm(h, input)?;
+            atca.execute(packet)?
+                .as_ref()
+                .try_into()
+                .map_err(|_| ErrorKind::InvalidSize.into())
+        })
+    }
+
+    pub fn encrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.process(buffer, |aes, key_id, key_block, block| {
+            aes.encrypt(key_id, key_block, block)
+        })
+    }
+
+    pub fn decrypt_in_place(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.process(buffer, |aes, key_id, key_block, block| {
+            aes.decrypt(key_id, key_block, block)
+        })
+    }
+
+    fn process(
+        &mut self,
+        buffer: &mut [u8],
+        build: impl Fn(&mut command::Aes<'_>, Slot, u8, &[u8]) -> Result<Packet, Error>,
+    ) -> Result<(), Error> {
         use command::Aes as AesCmd;
 
-        if ciphertext.len() != plaintext.len() {
-            return Err(ErrorKind::BadParam.into());
+        if !buffer.len().is_multiple_of(AesCmd::DATA_SIZE) {
+            return Err(ErrorKind::InvalidSize.into());
         }
 
-        for (cipher, plain) in ciphertext
-            .chunks(AesCmd::DATA_SIZE)
-            .zip(plaintext.chunks_mut(AesCmd::DATA_SIZE))
-        {
-            // Input length should be exactly 16 bytes. Otherwise the device
-            // couldn't recognize the command properly. If the length is not
-            // enough, sufficient number of 0s are padded.
-            let packet = AesCmd::new(self.atca.packet_builder()).decrypt(self.key_id, cipher)?;
+        let (key_id, key_block) = (self.key_id, self.key_block);
+        self.atca
+            .require_capability(key_id, OpCode::Aes, |caps| caps.aes)?;
+        self.atca.require_key_usable(key_id)?;
+        self.atca.wiping(|atca| {
+            for block in buffer.chunks_mut(AesCmd::DATA_SIZE) {
+                let packet = build(
+                    &mut AesCmd::new(atca.packet_builder()),
+                    key_id,
+                    key_block,
+                    block,
+                )?;
+                let response = atca.execute(packet)?;
+                if response.as_ref().len() != AesCmd::DATA_SIZE {
+                    return Err(ErrorKind::InvalidSize.into());
+                }
+                block.copy_from_slice(response.as_ref());
+            }
+            Ok(())
+        })
+    }
+}
 
-            // Decrypt cipher bytes and write the result to plain.
-            let response = self.atca.execute(packet)?;
-            if response.as_ref().len() != AesCmd::DATA_SIZE {
-                return Err(ErrorKind::InvalidSize.into());
+// KDF
+// Keys derived into TempKey, the alternate key buffer or a slot never leave
+// the device. Only the `Output` target yields bytes, which are returned.
+pub struct Kdf<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
+    io_key: Option<Block>,
+}
+
+impl<'a, PHY, D, const N: usize> Kdf<'a, PHY, D, N> {
+    // Key to decrypt output derived to `KdfTarget::OutputEncrypted`, if not
+    // the one of the client. It must match the I/O protection key slot set
+    // in the config zone.
+    pub fn io_protection(mut self, io_key: &Block) -> Self {
+        self.io_key = Some(*io_key);
+        self
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Kdf<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    // TLS 1.2 PRF (P_SHA256). `key_len` is the length of the source key in
+    // bytes; the output is 32 or 64 bytes long.
+    pub fn prf(
+        &mut self,
+        source: KdfSource,
+        target: KdfTarget,
+        key_len: usize,
+        output_64: bool,
+        message: &[u8],
+    ) -> Result<Vec<u8, 64>, Error> {
+        self.require_source(source)?;
+        let packet = command::Kdf::new(self.atca.packet_builder())
+            .prf(source, target, key_len, output_64, message)?;
+        self.execute(packet, target)
+    }
+
+    // HKDF-Extract with an all-zero salt: PRK = HMAC(0, IKM), where the IKM
+    // is typically an ECDH premaster secret kept in TempKey.
+    pub fn hkdf_extract(
+        &mut self,
+        ikm: HkdfMessage,
+        target: KdfTarget,
+    ) -> Result<Vec<u8, 64>, Error> {
+        if ikm == HkdfMessage::TempKey {
+            self.atca.tempkey.require_valid()?;
+        }
+        let packet = command::Kdf::new(self.atca.packet_builder()).hkdf(None, target, ikm)?;
+        self.execute(packet, target)
+    }
+
+    // HKDF-Expand for a single block of output: OKM = HMAC(PRK, info || 0x01).
+    pub fn hkdf_expand(
+        &mut self,
+        prk: KdfSource,
+        target: KdfTarget,
+        info: &[u8],
+    ) -> Result<Vec<u8, 64>, Error> {
+        self.require_source(prk)?;
+        let mut message = Vec::<u8, { command::Kdf::MESSAGE_SIZE_MAX }>::new();
+        message
+            .extend_from_slice(info)
+            .and_then(|()| message.push(0x01).map_err(drop))
+            .map_err(|()| Error::from(ErrorKind::InvalidSize))?;
+        let packet = command::Kdf::new(self.atca.packet_builder()).hkdf(
+            Some(prk),
+            target,
+            HkdfMessage::Input(&message),
+        )?;
+        self.execute(packet, target)
+    }
+
+    // Encrypt a 16-byte message with the AES key at `key_block` of the source.
+    pub fn aes(
+        &mut self,
+        source: KdfSource,
+        target: KdfTarget,
+        key_block: u8,
+        message: &[u8],
+    ) -> Result<Vec<u8, 64>, Error> {
+        self.require_source(source)?;
+        let packet = command::Kdf::new(self.atca.packet_builder())
+            .aes(source, target, key_block, message)?;
+        self.execute(packet, target)
+    }
+
+    fn require_source(&self, source: KdfSource) -> Result<(), Error> {
+        match source {
+            KdfSource::TempKey | KdfSource::TempKeyUpper => {
+                self.atca.tempkey.require_valid().map(drop)
             }
-            plain.copy_from_slice(response.as_ref());
+            _ => Ok(()),
         }
-        Ok(())
+    }
+
+    fn execute(&mut self, packet: Packet, target: KdfTarget) -> Result<Vec<u8, 64>, Error> {
+        let io_key = match (target, self.io_key) {
+            (KdfTarget::OutputEncrypted, None) => return Err(ErrorKind::BadParam.into()),
+            (_, io_key) => io_key,
+        };
+        let output = self.atca.wiping(|atca| {
+            let response = atca.execute(packet)?;
+            let mut output = Vec::new();
+            match (target, io_key) {
+                (KdfTarget::Output, _) => output
+                    .extend_from_slice(response.as_ref())
+                    .map_err(|()| Error::from(ErrorKind::InvalidSize))?,
+                (KdfTarget::OutputEncrypted, Some(io_key)) => {
+                    output = io_decrypt(&io_key, response.as_ref())?;
+                }
+                _ => {}
+            }
+            Ok(output)
+        })?;
+        if matches!(target, KdfTarget::TempKey | KdfTarget::TempKeyUpper) {
+            self.atca.tempkey.load(TempKeySource::Input);
+        }
+        Ok(output)
     }
 }
 
 // SHA
-pub struct Sha<'a, PHY, D> {
-    atca: &'a mut AtCaClient<PHY, D>,
+pub struct Sha<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
     remaining_bytes: Vec<u8, 64>,
 }
 
-impl<'a, PHY, D> Sha<'a, PHY, D>
+impl<'a, PHY, D, const N: usize> Sha<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     pub fn init(&mut self) -> Result<(), Error> {
+        self.remaining_bytes.clear();
         let packet = command::Sha::new(self.atca.packet_builder()).start()?;
         self.atca.execute(packet).map(drop)
     }
 
-    // See digest::Update
+    // See digest::Update. Input of any length is accepted; bytes short of a
+    // full 64-byte block are buffered until more data or `finalize`.
     pub fn update(&mut self, data: impl AsRef<[u8]>) -> Result<(), Error> {
-        let capacity = 0x40;
-        let length = data.as_ref().len();
-
-        // Store remainging bytes for later processing
-        let remainder_length = data.as_ref().len() % capacity;
-        let (bytes, remainder) = data.as_ref().split_at(length - remainder_length);
-        self.remaining_bytes.extend_from_slice(remainder).ok();
-
-        // Execute update command
-        bytes.chunks(capacity).try_for_each(|chunk| {
-            let packet = command::Sha::new(self.atca.packet_builder()).update(chunk)?;
-            self.atca.execute(packet).map(drop)
-        })
+        sha_update(self.atca, &mut self.remaining_bytes, data.as_ref())
     }
 
-    pub fn chain(&mut self, data: impl AsRef<[u8]>) -> Result<&mut Self, Error> {
-        if self.remaining_bytes.len() != 0 {
-            // TODO: Concatinate remaining bytes and input data.
-        }
+    // Suspend the computation, for instance to sign something in between.
+    // The context can be resumed with `restore` on any later Sha handle.
+    pub fn save(&mut self) -> Result<Sha256Context, Error> {
+        let device = self.atca.device_type()?;
+        let packet = command::Sha::new(self.atca.packet_builder()).read_context(device)?;
+        let response = self.atca.execute(packet)?;
+        let mut context = Sha256Context::default();
+        context
+            .device
+            .extend_from_slice(response.as_ref())
+            .map_err(|()| Error::from(ErrorKind::InvalidSize))?;
+        context.remaining_bytes = self.remaining_bytes.clone();
+        Ok(context)
+    }
 
+    pub fn restore(&mut self, context: &Sha256Context) -> Result<(), Error> {
+        let device = self.atca.device_type()?;
+        let packet =
+            command::Sha::new(self.atca.packet_builder()).write_context(device, &context.device)?;
+        self.atca.execute(packet)?;
+        self.remaining_bytes = context.remaining_bytes.clone();
+        Ok(())
+    }
+
+    pub fn chain(&mut self, data: impl AsRef<[u8]>) -> Result<&mut Self, Error> {
         self.update(data)?;
         Ok(self)
     }
 
     pub fn finalize(&mut self) -> Result<Digest, Error> {
         let packet = command::Sha::new(self.atca.packet_builder()).end(&self.remaining_bytes)?;
+        self.remaining_bytes.clear();
+        self.atca.execute(packet)?.as_ref().try_into()
+    }
+
+    pub fn digest(&mut self, data: &[u8]) -> Result<Digest, Error> {
+        self.init()?;
+        self.update(data)?;
+        self.finalize()
+    }
+}
+
+// Feed `data` to a running SHA or HMAC computation in 64-byte blocks,
+// keeping the bytes of an incomplete block in `remaining_bytes`.
+fn sha_update<PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    remaining_bytes: &mut Vec<u8, 64>,
+    mut data: &[u8],
+) -> Result<(), Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    let capacity = remaining_bytes.capacity();
+    while !data.is_empty() {
+        let length = (capacity - remaining_bytes.len()).min(data.len());
+        let (head, tail) = data.split_at(length);
+        remaining_bytes
+            .extend_from_slice(head)
+            .unwrap_or_else(|()| unreachable!("Length is bounded by the capacity."));
+        data = tail;
+
+        if remaining_bytes.len() == capacity {
+            let packet = command::Sha::new(atca.packet_builder()).update(&*remaining_bytes)?;
+            atca.execute(packet)?;
+            remaining_bytes.clear();
+        }
+    }
+    Ok(())
+}
+
+// Method names follow crypto_mac::Mac. Message bytes are buffered until a
+// full 64-byte block can be sent to the device.
+// HMAC
+pub struct Hmac<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
+    key_id: Slot,
+    remaining_bytes: Vec<u8, 64>,
+}
+
+impl<'a, PHY, D, const N: usize> Hmac<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    pub fn init(&mut self) -> Result<(), Error> {
+        self.atca.require_key_usable(self.key_id)?;
+        self.remaining_bytes.clear();
+        let packet = command::Sha::new(self.atca.packet_builder()).hmac_start(self.key_id)?;
+        self.atca.execute(packet).map(drop)
+    }
+
+    pub fn update(&mut self, data: impl AsRef<[u8]>) -> Result<(), Error> {
+        sha_update(self.atca, &mut self.remaining_bytes, data.as_ref())
+    }
+
+    pub fn finalize(&mut self) -> Result<Digest, Error> {
+        let device = self.atca.device_type()?;
+        let packet = command::Sha::new(self.atca.packet_builder())
+            .hmac_end(device, &self.remaining_bytes)?;
+        self.remaining_bytes.clear();
         self.atca.execute(packet)?.as_ref().try_into()
     }
 
@@ -517,37 +2454,31 @@ where
 
 // Method signatures are taken from signature::DigestSigner.
 // Sign
-pub struct Sign<'a, PHY, D> {
-    atca: &'a mut AtCaClient<PHY, D>,
+pub struct Sign<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
     key_id: Slot,
 }
 
-impl<'a, PHY, D> Sign<'a, PHY, D>
+impl<'a, PHY, D, const N: usize> Sign<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     // Takes a 32-byte message to be signed, typically the SHA256 hash of the
     // full message.
     pub fn sign_digest(&mut self, digest: &Digest) -> Result<Signature, Error> {
-        // 1. Random value generation
-        self.atca.random()?;
-        // 2. Nonce load
-        self.atca.write_message_digest_buffer(digest)?;
-        // 3. Sign
-        let packet = command::Sign::new(self.atca.packet_builder()).external(self.key_id)?;
-        self.atca.execute(packet)?.as_ref().try_into()
+        self.atca.sign(self.key_id, digest)
     }
 }
 
-pub struct Verify<'a, PHY, D> {
-    atca: &'a mut AtCaClient<PHY, D>,
+pub struct Verify<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
     key_id: Slot,
 }
 
-impl<'a, PHY, D> Verify<'a, PHY, D>
+impl<'a, PHY, D, const N: usize> Verify<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     // Takes a 32-byte message to be signed, typically the SHA256 hash of the
@@ -558,11 +2489,693 @@ where
         signature: &Signature,
         public_key: &PublicKey,
     ) -> Result<(), Error> {
-        // 1. Nonce load
-        self.atca.write_message_digest_buffer(digest)?;
-        // 2. Verify
-        let packet =
-            command::Verify::new(self.atca.packet_builder()).external(signature, public_key)?;
-        self.atca.execute(packet).map(drop)
+        if self
+            .atca
+            .verify_external_digest(digest, signature, public_key)?
+        {
+            Ok(())
+        } else {
+            Err(Status::CheckmacVerifyFailed.into())
+        }
+    }
+}
+
+// Random numbers are drawn from the device 32 bytes at a time and handed out
+// until the pool is exhausted. With the rand_core feature, DeviceRng is an
+// RngCore and CryptoRng for seeding host side crypto libraries.
+pub struct DeviceRng<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
+    pool: Block,
+    remaining: usize,
+}
+
+impl<'a, PHY, D, const N: usize> DeviceRng<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    pub fn fill(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        for byte in dest.iter_mut() {
+            if self.remaining == 0 {
+                self.pool = self.atca.random()?;
+                self.remaining = self.pool.as_ref().len();
+            }
+            self.remaining -= 1;
+            *byte = self.pool.as_ref()[self.remaining];
+            // Do not keep handed out bytes around.
+            self.pool.as_mut()[self.remaining] = 0x00;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl<'a, PHY, D, const N: usize> rand_core::RngCore for DeviceRng<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn next_u32(&mut self) -> u32 {
+        rand_core::impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        rand_core::impls::next_u64_via_fill(self)
+    }
+
+    // RngCore leaves no way to report an error here. Use try_fill_bytes
+    // where a bus error has to be handled.
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        if let Err(error) = self.fill(dest) {
+            panic!("random number generation failed: {}", error);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill(dest).map_err(rand_core::Error::from)
+    }
+}
+
+#[cfg(feature = "rand_core")]
+impl<'a, PHY, D, const N: usize> rand_core::CryptoRng for DeviceRng<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+}
+
+// Translate a miscompare reported by the device into a negative result.
+pub(crate) fn verification(result: Result<(), Error>) -> Result<bool, Error> {
+    match result {
+        Ok(()) => Ok(true),
+        Err(e) if e.status() == Some(Status::CheckmacVerifyFailed) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+    use crate::error::ExecutionStatus;
+
+    #[test]
+    fn serial_number() {
+        let mut block = [0x00; 32];
+        block[0..4].copy_from_slice(&[0x01, 0x23, 0x45, 0x67]);
+        block[8..13].copy_from_slice(&[0x89, 0xab, 0xcd, 0xef, 0xee]);
+        let mut device = Device::new(0).respond(&block);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let serial = atca.serial_number().unwrap();
+        assert_eq!(
+            serial.as_ref(),
+            [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xee]
+        );
+    }
+
+    #[test]
+    fn buffer_size() {
+        let mut device = Device::new(0).respond(&[0x00, 0x00, 0x60, 0x03]);
+        let mut atca = AtCaClient::<_, _, BUFFER_SIZE_MIN>::with_buffer_size(&mut device, NoDelay);
+        assert_eq!(atca.device_type().unwrap(), DeviceType::Atecc608b);
+        assert_eq!(atca.buffer.capacity(), BUFFER_SIZE_MIN);
+    }
+
+    #[test]
+    fn sign_internal_requires_gen_dig() {
+        let mut device = Device::new(0).respond(&[0x00; 64]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca.sign_internal(Slot::PrivateKey00, false).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::TempKeyInvalid));
+        atca.tempkey.load(TempKeySource::Random);
+        let error = atca.sign_internal(Slot::PrivateKey00, true).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::TempKeySourceMismatch));
+        assert_eq!(atca.release().0.commands, 0);
+    }
+
+    #[test]
+    fn gen_key_digest() {
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca
+            .gen_key_digest(Slot::PrivateKey01, &[0x00; 3])
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::TempKeyInvalid));
+        atca.tempkey.load(TempKeySource::Input);
+        atca.gen_key_digest(Slot::PrivateKey01, &[0x00; 3]).unwrap();
+        assert_eq!(atca.tempkey.gen_key_slot(), Some(Slot::PrivateKey01));
+        assert_eq!(atca.tempkey.gen_dig_key(), None);
+    }
+
+    #[test]
+    fn validate_public_key() {
+        let mut device = Device::new(0).respond(&[0x01]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let signature = Signature::default();
+        atca.tempkey.load(TempKeySource::Random);
+        let error = atca
+            .validate_public_key(Slot::Certificate09, &signature, &[0x00; 19])
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::TempKeySourceMismatch));
+
+        // The device answers the signature check with a mismatch.
+        atca.tempkey.gen_key(Slot::Certificate09);
+        let valid = atca
+            .invalidate_public_key(Slot::Certificate09, &signature, &[0x00; 19])
+            .unwrap();
+        assert!(!valid);
+    }
+
+    #[test]
+    fn poll_response() {
+        let mut device = Device::new(2).respond(&[0x04; 64]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca.poll_response::<PublicKey>().unwrap_err();
+        assert!(matches!(error, nb::Error::Other(e) if e.kind() == Some(ErrorKind::BadParam)));
+
+        atca.start_generate_key(Slot::PrivateKey01).unwrap();
+        assert!(matches!(
+            atca.poll_response::<PublicKey>(),
+            Err(nb::Error::WouldBlock)
+        ));
+        let public_key = crate::block!(atca.poll_response::<PublicKey>()).unwrap();
+        assert_eq!(public_key.as_ref(), [0x04; 64]);
+        assert_eq!(atca.release().0.commands, 1);
+    }
+
+    #[test]
+    fn ecdh_target() {
+        let mut device = Device::new(0).respond(&[0x00]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let public_key = PublicKey::default();
+        let error = atca
+            .ecdh(Slot::PrivateKey02, &public_key, EcdhTarget::EncryptedOutput)
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::BadParam));
+
+        let secret = atca
+            .ecdh(Slot::PrivateKey02, &public_key, EcdhTarget::TempKey)
+            .unwrap();
+        assert!(secret.is_none());
+        assert!(atca.tempkey.is_valid());
+        assert_eq!(atca.release().0.commands, 1);
+    }
+
+    #[test]
+    fn ecdh_encrypted_output() {
+        let io_key = Block::try_from([0x11; 32].as_ref()).unwrap();
+        let mut response = [0x5a; 64];
+        response[32..].iter_mut().for_each(|v| *v = 0x22);
+        let mut device = Device::new(0).respond(&response);
+        let mut atca = AtCaClient::new(&mut device, NoDelay).with_io_protection_key(&io_key);
+        let secret = atca
+            .ecdh(
+                Slot::PrivateKey02,
+                &PublicKey::default(),
+                EcdhTarget::EncryptedOutput,
+            )
+            .unwrap()
+            .unwrap();
+        let key = host::Sha256::new()
+            .update(io_key)
+            .update([0x22; 16])
+            .finalize();
+        let expected = key.as_ref().iter().map(|k| k ^ 0x5a);
+        assert!(secret.as_ref().iter().copied().eq(expected));
+        assert_eq!(atca.release().0.commands, 1);
+    }
+
+    // A success that does not carry the MAC of the I/O protection key is
+    // rejected rather than taken for a valid signature.
+    #[test]
+    fn verify_mac() {
+        let io_key = Block::try_from([0x11; 32].as_ref()).unwrap();
+        let mut device = Device::new(0).respond(&[0x5a; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay).with_io_protection_key(&io_key);
+        let digest = Digest::default();
+        let error = atca
+            .verify_stored_digest(Slot::Certificate0b, &digest, &Signature::default())
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::AuthenticationFailed));
+        // Random for the system nonce, Nonce and Verify.
+        assert_eq!(atca.release().0.commands, 3);
+
+        let system_nonce = Block::try_from([0x5a; 32].as_ref()).unwrap();
+        let mac = host::verify_mac(
+            &io_key,
+            &digest,
+            &system_nonce,
+            &Signature::default(),
+            Some(Slot::Certificate0b),
+        );
+        let result = check_verify_mac(
+            Some((io_key, system_nonce)),
+            mac.as_ref(),
+            &digest,
+            &Signature::default(),
+            Some(Slot::Certificate0b),
+        );
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn execute_raw() {
+        let mut device = Device::new(0).respond(&[0x5a; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        atca.tempkey.load(TempKeySource::Input);
+        let mut response = [0x00; 40];
+        let length = atca
+            .execute_raw(OpCode::Random as u8, 0x00, 0x0000, &[], &mut response)
+            .unwrap();
+        assert_eq!(response[..length], [0x5a; 32]);
+        assert!(!atca.tempkey().is_valid());
+
+        let result = atca.execute_raw(OpCode::Random as u8, 0x00, 0x0000, &[], &mut [0x00; 16]);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::SmallBuffer));
+        // Unknown op-codes go out as given, even to a known device type.
+        atca.device.replace(DeviceType::Atecc608b);
+        let length = atca
+            .execute_raw(0x99, 0x00, 0x0000, &[], &mut response)
+            .unwrap();
+        assert_eq!(response[..length], [0x5a; 32]);
+        // Known ones are still checked against the device type.
+        let result = atca.execute_raw(OpCode::HMac as u8, 0x00, 0x0000, &[], &mut response);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            Some(ErrorKind::UnsupportedByDevice)
+        );
+        drop(atca);
+        assert_eq!(device.commands, 3);
+    }
+
+    #[test]
+    fn error_context() {
+        let mut device = Device::new(0).respond(&[0x03]);
+        let error = AtCaClient::new(&mut device, NoDelay)
+            .with_retry_policy(RetryPolicy::none())
+            .info()
+            .unwrap_err();
+        assert_eq!(error.status(), Some(Status::Parse));
+        assert_eq!(error.execution_status(), Some(ExecutionStatus::ParseError));
+        assert_eq!(error.phase(), Some(Phase::Parse));
+        assert_eq!(error.opcode(), Some(OpCode::Info as u8));
+    }
+
+    #[test]
+    fn watchdog_about_to_expire() {
+        let mut device = Device::new(0).respond(&[0xEE]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay).with_retry_policy(RetryPolicy::none());
+        atca.wake().unwrap();
+        let error = atca.info().unwrap_err();
+        assert_eq!(error.status(), Some(Status::WatchdogAboutToExpire));
+        assert_eq!(
+            error.execution_status(),
+            Some(ExecutionStatus::WatchdogExpire)
+        );
+        assert_eq!(atca.power_state(), PowerState::Awake);
+        drop(atca);
+        // Sent once more after cycling through idle.
+        assert_eq!(device.commands, 2);
+        assert_eq!(device.wakes, 2);
+    }
+
+    #[test]
+    fn persistent_latch() {
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        atca.latched_keys = 1 << Slot::PrivateKey02 as u16;
+        atca.persistent_latch_clear().unwrap();
+        let error = atca
+            .sign(Slot::PrivateKey02, &Digest::default())
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::PersistentLatchClear));
+        assert!(atca.require_key_usable(Slot::PrivateKey03).is_ok());
+        atca.persistent_latch_set().unwrap();
+        assert!(atca.require_key_usable(Slot::PrivateKey02).is_ok());
+        drop(atca);
+        assert_eq!(device.commands, 2);
+    }
+
+    #[test]
+    fn required_auth() {
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        atca.required_auth[Slot::PrivateKey02 as usize] = Some(0x05);
+        let error = atca
+            .sign(Slot::PrivateKey02, &Digest::default())
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::AuthorizationRequired));
+        atca.authorized.replace(Slot::PrivateKey04);
+        assert!(atca.require_key_usable(Slot::PrivateKey02).is_err());
+        atca.authorized.replace(Slot::PrivateKey05);
+        assert!(atca.require_key_usable(Slot::PrivateKey02).is_ok());
+        atca.sleep().unwrap();
+        assert!(atca.require_key_usable(Slot::PrivateKey02).is_err());
+    }
+
+    #[test]
+    fn slot_capabilities() {
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        atca.capabilities[Slot::PrivateKey02 as usize] = Some(SlotCaps {
+            ecdh: true,
+            ..SlotCaps::default()
+        });
+        let error = atca
+            .sign(Slot::PrivateKey02, &Digest::default())
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::KeyNotPermitted));
+        assert_eq!(error.opcode(), Some(OpCode::Sign as u8));
+        atca.tempkey.load(TempKeySource::Random);
+        let error = atca.sign_internal(Slot::PrivateKey02, false).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::KeyNotPermitted));
+        let error = atca
+            .aes(Slot::PrivateKey02)
+            .encrypt_in_place(&mut [0x00; 16])
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::KeyNotPermitted));
+        drop(atca);
+        assert_eq!(device.commands, 0);
+    }
+
+    #[test]
+    fn probe() {
+        let mut device = Device::new(0).respond(&[0x00, 0x00, 0x60, 0x02]);
+        device.address.replace(0x35);
+        let found = AtCaClient::probe(&mut device, NoDelay);
+        assert_eq!(found, Some((0x35, DeviceType::Atecc608a)));
+
+        device.address.replace(0x10);
+        assert_eq!(AtCaClient::probe(&mut device, NoDelay), None);
+    }
+
+    #[test]
+    fn read_slot_encrypted_block() {
+        let key = Block::default();
+        let mut device = Device::new(0).respond(&[0x5a; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut memory = atca.memory();
+        memory
+            .read_slot_encrypted(Slot::Certificate09, 2, Slot::Data08, &key)
+            .unwrap();
+        let error = memory
+            .read_slot_encrypted(Slot::Certificate09, 3, Slot::Data08, &key)
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::BadParam));
+        drop(atca);
+        // Serial number, Nonce, GenDig and Read. Out of range blocks are
+        // refused before anything is sent.
+        assert_eq!(device.commands, 4);
+    }
+
+    #[test]
+    fn write_slot_encrypted_block() {
+        let key = Block::default();
+        let mut device = Device::new(0).respond(&[0x5a; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut memory = atca.memory();
+        memory
+            .write_slot_encrypted(Slot::Certificate09, 2, &key, Slot::Data08, &key)
+            .unwrap();
+        let error = memory
+            .write_slot_encrypted(Slot::Certificate09, 3, &key, Slot::Data08, &key)
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::BadParam));
+        drop(atca);
+        assert_eq!(device.commands, 4);
+    }
+
+    #[test]
+    fn sha_context() {
+        let mut device = Device::new(0).respond(&[0x5a; 12]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        atca.device.replace(DeviceType::Atecc608a);
+        let mut sha = atca.sha();
+        sha.update(b"abc").unwrap();
+        let context = sha.save().unwrap();
+        assert_eq!(context.device, [0x5a; 12]);
+        assert_eq!(context.remaining_bytes, *b"abc");
+
+        let mut sha = atca.sha();
+        sha.restore(&context).unwrap();
+        assert_eq!(sha.remaining_bytes, *b"abc");
+        drop(atca);
+        assert_eq!(device.commands, 2);
+
+        let mut device = Device::new(0).respond(&[0x00, 0x00, 0x50, 0x00]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca.sha().save().unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::UnsupportedByDevice));
+        drop(atca);
+        assert_eq!(device.commands, 1);
+    }
+
+    #[test]
+    fn rng_health_check() {
+        let mut pattern = [0x00; 32];
+        pattern.chunks_mut(4).for_each(|word| word[..2].fill(0xff));
+        let mut device = Device::new(0).respond(&pattern);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let result = atca.rng_health_check();
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::ConfigNotLocked));
+
+        let mut device = Device::new(0).respond(&[0xa5; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        atca.rng_health_check().unwrap();
+    }
+
+    #[test]
+    fn remaining_signatures() {
+        let mut device = Device::new(0).respond(&[0x00, 0x00, 0x00, 0x00]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        assert_eq!(atca.remaining_signatures().unwrap(), None);
+        drop(atca);
+        assert_eq!(device.commands, 1);
+
+        // CountMatch byte 0x81 names slot 8; the same word reads back as the
+        // count-match value and Counter 0.
+        let mut device = Device::new(0).respond(&[0x00, 0x00, 0x81, 0x00]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        assert_eq!(atca.remaining_signatures().unwrap(), Some(0));
+        drop(atca);
+        assert_eq!(device.commands, 4);
+    }
+
+    #[test]
+    fn config_field() {
+        let mut device = Device::new(0).respond(&[0x01, 0x02, 0x03, 0x04]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let value = atca.memory().read_config_field(ConfigField::CountMatch);
+        assert_eq!(value.unwrap(), [0x03]);
+        let value = atca.memory().read_config_field(ConfigField::Counter0);
+        assert_eq!(
+            value.unwrap(),
+            [0x01, 0x02, 0x03, 0x04, 0x01, 0x02, 0x03, 0x04]
+        );
+
+        let mut memory = atca.memory();
+        let result = memory.write_config_field(ConfigField::AesEnable, &[0x01]);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::BadParam));
+        let result = memory.write_config_field(ConfigField::SecureBoot, &[0x01]);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::InvalidSize));
+        drop(atca);
+        assert_eq!(device.commands, 3);
+    }
+
+    #[test]
+    fn slot_chunks() {
+        let mut chunks = super::slot_chunks(Slot::Certificate09.size());
+        assert_eq!(chunks.next(), Some((0, 0, Size::Block)));
+        assert_eq!(chunks.next(), Some((1, 0, Size::Block)));
+        assert_eq!(chunks.next(), Some((2, 0, Size::Word)));
+        assert_eq!(chunks.next(), Some((2, 1, Size::Word)));
+        assert_eq!(chunks.next(), None);
+        assert_eq!(super::slot_chunks(Slot::Data08.size()).count(), 13);
+        assert_eq!(super::slot_chunks(Slot::PrivateKey00.size()).count(), 2);
+    }
+
+    #[test]
+    fn read_slot() {
+        let mut device = Device::new(0).respond(&[0x5a; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut memory = atca.memory();
+        let data = memory.read_slot(Slot::Data08).unwrap();
+        assert_eq!(data[..], [0x5a; 416][..]);
+
+        let mut buffer = [0x00; 420];
+        assert_eq!(
+            memory.read_slot_into(Slot::Data08, &mut buffer).unwrap(),
+            416
+        );
+        assert_eq!(buffer[..416], data[..]);
+        let result = memory.read_slot_into(Slot::Data08, &mut buffer[..415]);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::SmallBuffer));
+        drop(atca);
+        assert_eq!(device.commands, 26);
+    }
+
+    #[test]
+    fn write_slot() {
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut memory = atca.memory();
+        memory.write_slot(Slot::PrivateKey03, &[0x11; 36]).unwrap();
+        let result = memory.write_slot(Slot::PrivateKey03, &[0x11; 40]);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::InvalidSize));
+        let result = memory.write_slot(Slot::Data08, &[0x11; 6]);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::InvalidSize));
+        drop(atca);
+        assert_eq!(device.commands, 2);
+    }
+
+    #[test]
+    fn write_pubkey() {
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut memory = atca.memory();
+        let public_key = PublicKey::default();
+        memory
+            .write_pubkey(Slot::Certificate0a, &public_key)
+            .unwrap();
+        let result = memory.write_pubkey(Slot::PrivateKey03, &public_key);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::InvalidSize));
+        drop(atca);
+        // Two blocks and two words.
+        assert_eq!(device.commands, 4);
+    }
+
+    #[test]
+    fn write_data_image() {
+        // Every config block reads the same: only Data08 is unlocked, none is
+        // a private key, and the data zone is locked.
+        let mut response = [0x00; 32];
+        response[25] = 0x01;
+        let mut device = Device::new(0).respond(&response);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut image = DataZoneImage::default();
+        image
+            .slot_mut(Slot::Data08)
+            .chunks_mut(32)
+            .for_each(|block| block.copy_from_slice(&response));
+        let written = atca.memory().write_data_image(&image).unwrap();
+        assert_eq!(written, [Slot::Data08]);
+
+        image.set_slot(Slot::Data08, &[0xff; 4]).unwrap();
+        let result = atca.memory().write_data_image(&image);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::AssertFailure));
+        drop(atca);
+        // Config zone, then 13 blocks written and read back, twice.
+        assert_eq!(device.commands, 60);
+    }
+
+    #[test]
+    fn apply_device_profile() {
+        // Config zone locked, data zone not.
+        let mut response = [0x00; 32];
+        response[22] = 0x55;
+        let mut device = Device::new(0).respond(&response);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut config = [0x00; CONFIG_ZONE_SIZE];
+        config
+            .chunks_mut(32)
+            .for_each(|block| block.copy_from_slice(&response));
+        let mut profile = DeviceProfile {
+            config: ConfigZone::from(config),
+            otp: [0x00; OTP_ZONE_SIZE],
+            data: DataZoneImage::default(),
+            slots: 1 << Slot::Certificate0a as u16,
+        };
+        let token = LockToken::i_understand_this_is_irreversible();
+        atca.memory().apply_device_profile(&profile, token).unwrap();
+
+        profile.config.set_i2c_address(0x35);
+        let token = LockToken::i_understand_this_is_irreversible();
+        let result = atca.memory().apply_device_profile(&profile, token);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            Some(ErrorKind::ConfigZoneLocked)
+        );
+        drop(atca);
+        // Config zone, OTP blocks and one 72-byte slot, then the config zone.
+        assert_eq!(device.commands, 14);
+    }
+
+    #[test]
+    fn config_words() {
+        let mut words = super::config_words(&(70..76));
+        assert_eq!(words.next(), Some((68, 2..4)));
+        assert_eq!(words.next(), Some((72, 0..4)));
+        assert_eq!(words.next(), None);
+    }
+
+    #[test]
+    fn lock_state() {
+        let mut device = Device::new(0).respond(&[0x55; 4]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        assert!(!atca.memory().is_config_locked().unwrap());
+        assert!(!atca.memory().is_data_locked().unwrap());
+        let token = LockToken::i_understand_this_is_irreversible();
+        let error = atca.memory().lock(Zone::Data, token).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::ConfigNotLocked));
+        assert_eq!(atca.release().0.commands, 3);
+
+        let mut device = Device::new(0).respond(&[0x55; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca.memory().export_device_profile().unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::DataNotLocked));
+    }
+
+    #[test]
+    fn device_rng() {
+        let mut device = Device::new(0).respond(&[0xa5; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut bytes = [0x00; 40];
+        atca.rng().fill(&mut bytes).unwrap();
+        assert_eq!(bytes, [0xa5; 40]);
+        assert_eq!(atca.release().0.commands, 2);
+
+        let mut device = Device::new(0).respond(&[0x0f]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca.rng().fill(&mut bytes).unwrap_err();
+        assert_eq!(error.status(), Some(Status::Execution));
+    }
+
+    #[cfg(feature = "rand_core")]
+    #[test]
+    fn device_rng_core() {
+        use rand_core::RngCore;
+        let mut device = Device::new(0).respond(&[0xa5; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        assert_eq!(atca.rng().next_u32(), 0xa5a5_a5a5);
+
+        let mut device = Device::new(0).respond(&[0x0f]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca.rng().try_fill_bytes(&mut [0x00; 4]).unwrap_err();
+        assert_eq!(
+            error.code().map(u32::from),
+            Some(rand_core::Error::CUSTOM_START + 0x10f)
+        );
+    }
+
+    #[test]
+    fn wipe_after_random() {
+        let mut device = Device::new(0).respond(&[0xa5; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        assert_eq!(atca.random().unwrap().as_ref(), [0xa5; 32]);
+        assert!(atca.buffer.iter().all(|byte| *byte == 0x00));
+    }
+
+    #[test]
+    fn sha_update() {
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let mut sha = atca.sha();
+        sha.update([0x01; 3]).unwrap();
+        assert_eq!(sha.remaining_bytes.len(), 3);
+        // 3 buffered and 130 new bytes make two blocks with 5 bytes left.
+        sha.update([0x02; 130]).unwrap();
+        assert_eq!(sha.remaining_bytes, [0x02; 5]);
+        drop(atca);
+        assert_eq!(device.commands, 2);
     }
 }
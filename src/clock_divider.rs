@@ -12,6 +12,7 @@ const EXEC_TIME_KDF: [u32; 3] = [165, 165, 165];
 const EXEC_TIME_LOCK: [u32; 3] = [35, 35, 35];
 const EXEC_TIME_MAC: [u32; 3] = [55, 55, 55];
 const EXEC_TIME_NONCE: [u32; 3] = [20, 20, 20];
+const EXEC_TIME_PAUSE: [u32; 3] = [3, 3, 3];
 const EXEC_TIME_PRIVWRITE: [u32; 3] = [50, 50, 50];
 const EXEC_TIME_RANDOM: [u32; 3] = [23, 23, 23];
 const EXEC_TIME_READ: [u32; 3] = [5, 5, 5];
@@ -23,6 +24,35 @@ const EXEC_TIME_UPDATE_EXTRA: [u32; 3] = [10, 10, 10];
 const EXEC_TIME_VERIFY: [u32; 3] = [105, 295, 1085];
 const EXEC_TIME_WRITE: [u32; 3] = [45, 45, 45];
 
+/// Default watchdog timeout in ms. The device falls asleep after this long.
+pub(crate) const WATCHDOG_MS: u32 = 1300;
+
+/// Execution time of a command in ms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct ExecTime {
+    /// Time to wait before the first poll.
+    pub(crate) typical: u32,
+    /// Time after which polling gives up.
+    pub(crate) max: u32,
+}
+
+impl ExecTime {
+    pub(crate) const fn new(typical: u32, max: u32) -> Self {
+        Self { typical, max }
+    }
+}
+
+impl Default for ExecTime {
+    // For commands without an entry, poll right away up to the watchdog.
+    fn default() -> Self {
+        Self {
+            typical: 1,
+            max: WATCHDOG_MS,
+        }
+    }
+}
+
 /// ChipMode clock divider {M0, M1, M2}
 #[derive(Clone, Copy, Debug)]
 pub(crate) enum ClockDivider {
@@ -34,8 +64,14 @@ pub(crate) enum ClockDivider {
 }
 
 impl ClockDivider {
-    /// Get the typical execution time for the given command.
-    pub(crate) fn execution_time(&self, opcode: &OpCode) -> Option<u32> {
+    /// Get the execution time for the given command. The ATECC608 datasheet
+    /// lists maximum times only, so polling starts after a millisecond.
+    pub(crate) fn execution_time(&self, opcode: &OpCode) -> Option<ExecTime> {
+        self.max_execution_time(opcode)
+            .map(|max| ExecTime::new(1, max))
+    }
+
+    fn max_execution_time(&self, opcode: &OpCode) -> Option<u32> {
         use OpCode::*;
         let index = *self as usize;
         match opcode {
@@ -51,6 +87,7 @@ impl ClockDivider {
             Lock => EXEC_TIME_LOCK[index].into(),
             Mac => EXEC_TIME_MAC[index].into(),
             Nonce => EXEC_TIME_NONCE[index].into(),
+            Pause => EXEC_TIME_PAUSE[index].into(),
             PrivWrite => EXEC_TIME_PRIVWRITE[index].into(),
             Random => EXEC_TIME_RANDOM[index].into(),
             Read => EXEC_TIME_READ[index].into(),
@@ -61,7 +98,7 @@ impl ClockDivider {
             UpdateExtra => EXEC_TIME_UPDATE_EXTRA[index].into(),
             Verify => EXEC_TIME_VERIFY[index].into(),
             Write => EXEC_TIME_WRITE[index].into(),
-            HMac | Pause => None,
+            HMac => None,
         }
     }
 }
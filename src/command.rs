@@ -1,11 +1,16 @@
 // Command definitions
 // Overall structure is modeled after https://github.com/tokio-rs/mini-redis/blob/master/src/cmd/mod.rs
+use super::device::DeviceType;
 use super::error::{Error, ErrorKind};
 use super::memory::{Size, Slot, Zone};
 use super::packet::{Packet, PacketBuilder};
+use super::tempkey::TempKeySource;
 use core::convert::TryFrom;
 use generic_array::typenum::{U32, U4, U64, U9};
 use generic_array::GenericArray;
+use heapless::Vec;
+#[cfg(feature = "zeroize")]
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 // Encapsulates raw 4 bytes. When it is a return value of `info`, it contains
 // the device's revision number.
@@ -39,6 +44,13 @@ impl AsMut<[u8]> for Word {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Zeroize for Word {
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
 // Encapsulates raw 32 bytes.
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Block {
@@ -70,8 +82,15 @@ impl AsMut<[u8]> for Block {
     }
 }
 
+#[cfg(feature = "zeroize")]
+impl Zeroize for Block {
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
 // Represents a serial number consisting of 9 bytes. Its uniqueness is
-// guaranteed. A return type of API `read_serial`.
+// guaranteed. A return type of API `serial_number`.
 #[derive(Clone, Copy, Debug, Default)]
 pub struct Serial {
     value: GenericArray<u8, U9>,
@@ -93,6 +112,16 @@ impl TryFrom<&[u8]> for Serial {
     }
 }
 
+// A serial number obtained elsewhere, e.g. sent by an accessory to the host
+// that authenticates it.
+impl From<[u8; 9]> for Serial {
+    fn from(value: [u8; 9]) -> Self {
+        Self {
+            value: value.into(),
+        }
+    }
+}
+
 impl AsRef<[u8]> for Serial {
     fn as_ref(&self) -> &[u8] {
         self.value.as_ref()
@@ -137,12 +166,124 @@ impl TryFrom<&[u8]> for Signature {
     }
 }
 
+impl Signature {
+    // Upper bound of DER encoded P256 signature length. Each of two INTEGERs
+    // takes at most 33 bytes plus tag and length.
+    pub const DER_MAX_LEN: usize = 72;
+
+    /// Encode the signature as ASN.1 DER `SEQUENCE { r INTEGER, s INTEGER }`,
+    /// which is understood by `p256::ecdsa::Signature::from_der`, OpenSSL and
+    /// X.509 structures.
+    pub fn to_der(&self) -> Vec<u8, { Self::DER_MAX_LEN }> {
+        let (r, s) = self.as_ref().split_at(32);
+        let mut integers = Vec::<u8, { Self::DER_MAX_LEN }>::new();
+        for scalar in [r, s].iter() {
+            // Strip leading zeros, keeping at least one byte.
+            let start = scalar
+                .iter()
+                .position(|&b| b != 0x00)
+                .unwrap_or(scalar.len() - 1);
+            let scalar = &scalar[start..];
+            // Prepend a zero if the most significant bit is set, otherwise the
+            // integer is interpreted as negative.
+            let pad = scalar[0] & 0x80 != 0x00;
+            integers.push(0x02).ok();
+            integers.push(scalar.len() as u8 + pad as u8).ok();
+            if pad {
+                integers.push(0x00).ok();
+            }
+            integers.extend_from_slice(scalar).ok();
+        }
+
+        let mut der = Vec::new();
+        der.push(0x30).ok();
+        der.push(integers.len() as u8).ok();
+        der.extend_from_slice(&integers).ok();
+        der
+    }
+
+    /// Decode an ASN.1 DER encoded signature.
+    pub fn from_der(der: &[u8]) -> Result<Self, Error> {
+        let body = match der {
+            [0x30, len, body @ ..] if *len as usize == body.len() => body,
+            _ => return Err(ErrorKind::BadParam.into()),
+        };
+
+        let mut signature = Self::default();
+        let mut rest = body;
+        for dst in signature.as_mut().chunks_mut(32) {
+            let (integer, remainder) = match rest {
+                [0x02, len, tail @ ..] if *len as usize <= tail.len() => {
+                    tail.split_at(*len as usize)
+                }
+                _ => return Err(ErrorKind::BadParam.into()),
+            };
+            // Drop the sign padding and left pad to 32 bytes.
+            let integer = match integer {
+                [0x00, tail @ ..] if !tail.is_empty() => tail,
+                _ => integer,
+            };
+            if integer.is_empty() || integer.len() > dst.len() {
+                return Err(ErrorKind::BadParam.into());
+            }
+            let offset = dst.len() - integer.len();
+            dst[offset..].copy_from_slice(integer);
+            rest = remainder;
+        }
+
+        if !rest.is_empty() {
+            return Err(ErrorKind::BadParam.into());
+        }
+        Ok(signature)
+    }
+}
+
 impl signature::Signature for Signature {
     fn from_bytes(bytes: &[u8]) -> Result<Self, signature::Error> {
         Self::try_from(bytes).map_err(|_| signature::Error::new())
     }
 }
 
+// Fails if R or S is zero or not below the curve order.
+#[cfg(feature = "p256")]
+impl TryFrom<Signature> for p256::ecdsa::Signature {
+    type Error = Error;
+    fn try_from(signature: Signature) -> Result<Self, Self::Error> {
+        Self::try_from(signature.as_ref()).map_err(|_| ErrorKind::BadParam.into())
+    }
+}
+
+#[cfg(feature = "p256")]
+impl From<p256::ecdsa::Signature> for Signature {
+    fn from(signature: p256::ecdsa::Signature) -> Self {
+        Self::try_from(signature.as_ref()).unwrap_or_else(|_| unreachable!())
+    }
+}
+
+/// ASN.1 DER encoded signature, the form TLS stacks exchange.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerSignature(Vec<u8, { Signature::DER_MAX_LEN }>);
+
+impl From<Signature> for DerSignature {
+    fn from(signature: Signature) -> Self {
+        Self(signature.to_der())
+    }
+}
+
+impl AsRef<[u8]> for DerSignature {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl signature::Signature for DerSignature {
+    fn from_bytes(bytes: &[u8]) -> Result<Self, signature::Error> {
+        Signature::from_der(bytes)
+            .map(Self::from)
+            .map_err(|_| signature::Error::new())
+    }
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct PublicKey {
     value: GenericArray<u8, U64>,
@@ -160,6 +301,54 @@ impl AsMut<[u8]> for PublicKey {
     }
 }
 
+impl PublicKey {
+    // Length of an uncompressed SEC1 point, `0x04 || X || Y`.
+    pub const SEC1_LEN: usize = 65;
+    const SEC1_UNCOMPRESSED: u8 = 0x04;
+
+    /// Encode as an uncompressed SEC1 point, accepted by
+    /// `p256::PublicKey::from_sec1_bytes` and used in SubjectPublicKeyInfo.
+    pub fn to_sec1_bytes(&self) -> [u8; Self::SEC1_LEN] {
+        let mut bytes = [0x00; Self::SEC1_LEN];
+        bytes[0] = Self::SEC1_UNCOMPRESSED;
+        bytes[1..].copy_from_slice(self.as_ref());
+        bytes
+    }
+
+    /// Decode an uncompressed SEC1 point. Compressed points are not
+    /// supported as the device only handles the raw X and Y coordinates.
+    pub fn from_sec1_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes {
+            [Self::SEC1_UNCOMPRESSED, point @ ..] => Self::try_from(point),
+            _ => Err(ErrorKind::BadParam.into()),
+        }
+    }
+
+    // Length of a public key as stored in a slot.
+    pub const SLOT_FORMAT_LEN: usize = 72;
+
+    /// Encode as stored in a slot, X and Y each after 4 zero pad bytes. This
+    /// is what Verify and GenKey expect to find there.
+    pub fn to_slot_format(&self) -> [u8; Self::SLOT_FORMAT_LEN] {
+        let mut bytes = [0x00; Self::SLOT_FORMAT_LEN];
+        let (x, y) = self.as_ref().split_at(0x20);
+        bytes[0x04..0x24].copy_from_slice(x);
+        bytes[0x28..0x48].copy_from_slice(y);
+        bytes
+    }
+
+    /// Decode a public key as stored in a slot. The pad bytes are ignored.
+    pub fn from_slot_format(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != Self::SLOT_FORMAT_LEN {
+            return Err(ErrorKind::BadParam.into());
+        }
+        let mut value = Self::default();
+        value.as_mut()[..0x20].copy_from_slice(&bytes[0x04..0x24]);
+        value.as_mut()[0x20..].copy_from_slice(&bytes[0x28..0x48]);
+        Ok(value)
+    }
+}
+
 impl TryFrom<&[u8]> for PublicKey {
     type Error = Error;
     fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
@@ -173,24 +362,62 @@ impl TryFrom<&[u8]> for PublicKey {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-pub struct SharedSecret {
+// Fails if the point is not on the curve.
+#[cfg(feature = "p256")]
+impl TryFrom<PublicKey> for p256::PublicKey {
+    type Error = Error;
+    fn try_from(public_key: PublicKey) -> Result<Self, Self::Error> {
+        Self::from_sec1_bytes(&public_key.to_sec1_bytes()).map_err(|_| ErrorKind::BadParam.into())
+    }
+}
+
+#[cfg(feature = "p256")]
+impl From<p256::PublicKey> for PublicKey {
+    fn from(public_key: p256::PublicKey) -> Self {
+        use p256::elliptic_curve::sec1::ToEncodedPoint;
+        let point = public_key.to_encoded_point(false);
+        Self::from_sec1_bytes(point.as_bytes()).unwrap_or_else(|_| unreachable!())
+    }
+}
+
+// A shared secret computed by ECDH. Only produced when the command is asked to
+// return the result through the output buffer. Not `Copy`, so that with the
+// zeroize feature no copy outlives the value it was made from.
+#[derive(Clone, Debug, Default)]
+pub struct PremasterSecret {
     value: GenericArray<u8, U32>,
 }
 
-impl AsRef<[u8]> for SharedSecret {
+impl AsRef<[u8]> for PremasterSecret {
     fn as_ref(&self) -> &[u8] {
         self.value.as_ref()
     }
 }
 
-impl AsMut<[u8]> for SharedSecret {
+impl AsMut<[u8]> for PremasterSecret {
     fn as_mut(&mut self) -> &mut [u8] {
         self.value.as_mut()
     }
 }
 
-impl TryFrom<&[u8]> for SharedSecret {
+#[cfg(feature = "zeroize")]
+impl Zeroize for PremasterSecret {
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for PremasterSecret {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for PremasterSecret {}
+
+impl TryFrom<&[u8]> for PremasterSecret {
     type Error = Error;
     fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
         if buffer.len() != 32 {
@@ -203,6 +430,472 @@ impl TryFrom<&[u8]> for SharedSecret {
     }
 }
 
+/// Identifies one of the two monotonic counters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CounterId {
+    Zero = 0x00,
+    One = 0x01,
+}
+
+/// Selects what UpdateExtra writes to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UpdateExtraMode {
+    /// Configuration byte 84, UserExtra.
+    UserExtra = 0x00,
+    /// Configuration byte 85, UserExtraAdd. Usable as an alternate I2C address.
+    UserExtraAdd = 0x01,
+    /// Decrement the limited use counter of the slot given as value.
+    DecrementLimitedUse = 0x02,
+}
+
+/// Destination of a pass-through nonce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NonceTarget {
+    TempKey,
+    /// Message digest buffer, consumed by Sign and Verify.
+    MsgDigBuf,
+    /// Alternate key buffer, usable as a KDF or AES key source.
+    AltKeyBuf,
+}
+
+impl NonceTarget {
+    fn mode(&self) -> u8 {
+        match self {
+            Self::TempKey => NonceCtx::MODE_TARGET_TEMPKEY,
+            Self::MsgDigBuf => NonceCtx::MODE_TARGET_MSGDIGBUF,
+            Self::AltKeyBuf => NonceCtx::MODE_TARGET_ALTKEYBUF,
+        }
+    }
+}
+
+/// Where ECDH puts the premaster secret.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EcdhTarget {
+    /// The slot after the private key's, n + 1.
+    NextSlot,
+    /// TempKey, as a KDF or GenDig source.
+    TempKey,
+    /// Returned in the clear.
+    Output,
+    /// Returned encrypted with the I/O protection key.
+    EncryptedOutput,
+}
+
+impl EcdhTarget {
+    fn mode(&self) -> u8 {
+        match self {
+            Self::NextSlot => Ecdh::MODE_COPY_EEPROM_SLOT,
+            Self::TempKey => Ecdh::MODE_COPY_TEMP_KEY,
+            Self::Output => Ecdh::MODE_OUTPUT_CLEAR | Ecdh::MODE_COPY_OUTPUT_BUFFER,
+            Self::EncryptedOutput => Ecdh::MODE_OUTPUT_ENC | Ecdh::MODE_COPY_OUTPUT_BUFFER,
+        }
+    }
+
+    /// Whether the premaster secret is returned to the host.
+    pub fn is_output(&self) -> bool {
+        matches!(self, Self::Output | Self::EncryptedOutput)
+    }
+}
+
+/// Value of a monotonic counter. Counters count up to `CounterValue::MAX`
+/// and never decrease.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CounterValue(pub u32);
+
+impl CounterValue {
+    pub const MAX: u32 = 2_097_151;
+}
+
+/// Counter 0 value at which keys with LimitedUse stop working, once
+/// CountMatch is enabled. Stored twice, as two little-endian words at the
+/// start of the count-match slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CountMatch(u32);
+
+impl CountMatch {
+    /// Count-match values are multiples of 32.
+    pub const GRANULARITY: u32 = 32;
+
+    pub fn new(value: u32) -> Result<Self, Error> {
+        if value > CounterValue::MAX || !value.is_multiple_of(Self::GRANULARITY) {
+            return Err(ErrorKind::BadParam.into());
+        }
+        Ok(Self(value))
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+
+    // Uses of Counter 0 left before it reaches this value.
+    pub fn remaining(&self, counter: CounterValue) -> u32 {
+        self.0.saturating_sub(counter.0)
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0x00; 8];
+        bytes[..4].copy_from_slice(&self.0.to_le_bytes());
+        bytes[4..].copy_from_slice(&self.0.to_le_bytes());
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for CountMatch {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        if buffer.len() != 8 {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        let (first, second) = buffer.split_at(4);
+        if first != second {
+            return Err(ErrorKind::BadParam.into());
+        }
+        CounterValue::try_from(first).map(|value| Self(value.0))
+    }
+}
+
+impl TryFrom<&[u8]> for CounterValue {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        let word = Word::try_from(buffer)?;
+        let value = [
+            word.as_ref()[0],
+            word.as_ref()[1],
+            word.as_ref()[2],
+            word.as_ref()[3],
+        ];
+        Ok(Self(u32::from_le_bytes(value)))
+    }
+}
+
+/// Where the KDF command takes its input key from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KdfSource {
+    TempKey,
+    /// Upper 32 bytes of the 64-byte TempKey
+    TempKeyUpper,
+    Slot(Slot),
+    AltKeyBuf,
+}
+
+impl KdfSource {
+    fn mode(&self) -> u8 {
+        match self {
+            Self::TempKey => 0x00,
+            Self::TempKeyUpper => 0x01,
+            Self::Slot(_) => 0x02,
+            Self::AltKeyBuf => 0x03,
+        }
+    }
+
+    fn slot(&self) -> u16 {
+        match self {
+            Self::Slot(slot) => *slot as u16,
+            _ => 0x0000,
+        }
+    }
+}
+
+/// What GenDig combines into TempKey.
+#[derive(Clone, Copy, Debug)]
+pub enum GenDigSource {
+    /// Key stored in a data slot
+    Data(Slot),
+    /// Key stored in a data slot with `KeyConfig.NoMac` set. The 4 bytes of
+    /// OtherData replace the op-code and parameters in the digested message.
+    DataNoMac(Slot, [u8; 4]),
+    /// One of the two 32-byte blocks of the OTP zone
+    Otp(u8),
+    /// Value of a monotonic counter
+    Counter(CounterId),
+    /// SlotConfig and KeyConfig of a slot
+    KeyConfig(Slot),
+    /// 32 bytes passed in with the command, digested ahead of TempKey
+    SharedNonce(Block),
+}
+
+impl GenDigSource {
+    fn zone(&self) -> u8 {
+        match self {
+            Self::Data(_) | Self::DataNoMac(..) => GenDig::ZONE_DATA,
+            Self::Otp(_) => GenDig::ZONE_OTP,
+            Self::Counter(_) => GenDig::ZONE_COUNTER,
+            Self::KeyConfig(_) => GenDig::ZONE_KEY_CONFIG,
+            Self::SharedNonce(_) => GenDig::ZONE_SHARED_NONCE,
+        }
+    }
+
+    fn param2(&self) -> u16 {
+        match self {
+            Self::Data(slot) | Self::DataNoMac(slot, _) | Self::KeyConfig(slot) => *slot as u16,
+            Self::Otp(block) => u16::from(*block),
+            Self::Counter(counter_id) => *counter_id as u16,
+            Self::SharedNonce(_) => 0x0000,
+        }
+    }
+
+    /// Slot whose key ends up in TempKey.
+    pub(crate) fn key_id(&self) -> Option<Slot> {
+        match self {
+            Self::Data(slot) | Self::DataNoMac(slot, _) => Some(*slot),
+            _ => None,
+        }
+    }
+}
+
+/// Where the KDF command puts the derived key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KdfTarget {
+    TempKey,
+    /// Upper 32 bytes of the 64-byte TempKey
+    TempKeyUpper,
+    Slot(Slot),
+    AltKeyBuf,
+    /// Derived key is returned in the clear
+    Output,
+    /// Derived key is returned encrypted with the I/O protection key
+    OutputEncrypted,
+}
+
+impl KdfTarget {
+    fn mode(&self) -> u8 {
+        match self {
+            Self::TempKey => 0x00,
+            Self::TempKeyUpper => 0x04,
+            Self::Slot(_) => 0x08,
+            Self::AltKeyBuf => 0x0c,
+            Self::Output => 0x10,
+            Self::OutputEncrypted => 0x14,
+        }
+    }
+
+    fn slot(&self) -> u16 {
+        match self {
+            Self::Slot(slot) => (*slot as u16) << 8,
+            _ => 0x0000,
+        }
+    }
+}
+
+/// Where the HKDF message is taken from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HkdfMessage<'a> {
+    /// First 32 bytes of TempKey
+    TempKey,
+    /// Message passed in the command input
+    Input(&'a [u8]),
+}
+
+/// Selects the algorithms exercised by the SelfTest command.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestMode(u8);
+
+impl SelfTestMode {
+    const RNG: u8 = 0x01;
+    const ECDSA_SIGN_VERIFY: u8 = 0x02;
+    const ECDH: u8 = 0x08;
+    const AES: u8 = 0x10;
+    const SHA: u8 = 0x20;
+    pub(crate) const ALL: u8 =
+        Self::RNG | Self::ECDSA_SIGN_VERIFY | Self::ECDH | Self::AES | Self::SHA;
+
+    pub fn none() -> Self {
+        Self(0x00)
+    }
+
+    pub fn all() -> Self {
+        Self(Self::ALL)
+    }
+
+    pub fn rng(self) -> Self {
+        Self(self.0 | Self::RNG)
+    }
+
+    pub fn ecdsa(self) -> Self {
+        Self(self.0 | Self::ECDSA_SIGN_VERIFY)
+    }
+
+    pub fn ecdh(self) -> Self {
+        Self(self.0 | Self::ECDH)
+    }
+
+    pub fn aes(self) -> Self {
+        Self(self.0 | Self::AES)
+    }
+
+    pub fn sha(self) -> Self {
+        Self(self.0 | Self::SHA)
+    }
+}
+
+/// Outcome of SelfTest. A test passes unless its bit is set; tests that were
+/// not selected are reported as passed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SelfTestResult(u8);
+
+impl SelfTestResult {
+    pub fn is_success(&self) -> bool {
+        self.0 == 0x00
+    }
+
+    pub fn rng_passed(&self) -> bool {
+        self.0 & SelfTestMode::RNG == 0x00
+    }
+
+    pub fn ecdsa_passed(&self) -> bool {
+        self.0 & SelfTestMode::ECDSA_SIGN_VERIFY == 0x00
+    }
+
+    pub fn ecdh_passed(&self) -> bool {
+        self.0 & SelfTestMode::ECDH == 0x00
+    }
+
+    pub fn aes_passed(&self) -> bool {
+        self.0 & SelfTestMode::AES == 0x00
+    }
+
+    pub fn sha_passed(&self) -> bool {
+        self.0 & SelfTestMode::SHA == 0x00
+    }
+
+    /// Raw bitmap of failed tests.
+    pub fn failures(&self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<&[u8]> for SelfTestResult {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        match buffer {
+            [value] => Ok(Self(*value)),
+            _ => Err(ErrorKind::BadParam.into()),
+        }
+    }
+}
+
+/// Device state reported by Info, covering TempKey and authorization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceState(u16);
+
+impl DeviceState {
+    const TEMPKEY_KEY_ID: u16 = 0x000F;
+    const TEMPKEY_SOURCE_INPUT: u16 = 0x0010;
+    const TEMPKEY_GEN_DIG: u16 = 0x0020;
+    const TEMPKEY_GEN_KEY: u16 = 0x0040;
+    const TEMPKEY_NO_MAC: u16 = 0x0080;
+    const AUTH_VALID: u16 = 0x0400;
+    const AUTH_KEY_SHIFT: u16 = 11;
+    const TEMPKEY_VALID: u16 = 0x8000;
+
+    pub fn tempkey_valid(&self) -> bool {
+        self.0 & Self::TEMPKEY_VALID != 0
+    }
+
+    /// How TempKey was loaded, if it is valid.
+    pub fn tempkey_source(&self) -> Option<TempKeySource> {
+        match self.0 & Self::TEMPKEY_SOURCE_INPUT {
+            _ if !self.tempkey_valid() => None,
+            0 => Some(TempKeySource::Random),
+            _ => Some(TempKeySource::Input),
+        }
+    }
+
+    /// Slot used by the last GenDig or GenKey that wrote TempKey.
+    pub fn tempkey_key_id(&self) -> u8 {
+        (self.0 & Self::TEMPKEY_KEY_ID) as u8
+    }
+
+    /// TempKey holds the output of GenDig.
+    pub fn tempkey_gen_dig(&self) -> bool {
+        self.0 & Self::TEMPKEY_GEN_DIG != 0
+    }
+
+    /// TempKey holds the output of GenKey.
+    pub fn tempkey_gen_key(&self) -> bool {
+        self.0 & Self::TEMPKEY_GEN_KEY != 0
+    }
+
+    /// TempKey derives from a key with NoMac set.
+    pub fn tempkey_no_mac(&self) -> bool {
+        self.0 & Self::TEMPKEY_NO_MAC != 0
+    }
+
+    /// Slot authorized by the last CheckMac, if the authorization holds.
+    pub fn auth_key(&self) -> Option<u8> {
+        if self.0 & Self::AUTH_VALID == 0 {
+            return None;
+        }
+        Some((self.0 >> Self::AUTH_KEY_SHIFT & 0x0F) as u8)
+    }
+}
+
+impl TryFrom<&[u8]> for DeviceState {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        match buffer {
+            [lsb, msb, _, _] => Ok(Self(u16::from_le_bytes([*lsb, *msb]))),
+            _ => Err(ErrorKind::BadParam.into()),
+        }
+    }
+}
+
+/// Whether a slot holds a valid ECC key, as reported by Info. A private key
+/// slot that was never written by GenKey or PrivWrite is not valid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyValid(bool);
+
+impl KeyValid {
+    pub fn is_valid(&self) -> bool {
+        self.0
+    }
+}
+
+impl TryFrom<&[u8]> for KeyValid {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        match buffer {
+            [flag, _, _, _] => Ok(Self(*flag == 0x01)),
+            _ => Err(ErrorKind::BadParam.into()),
+        }
+    }
+}
+
+/// State of the volatile key permit, or persistent, latch. Slots configured
+/// with `KeyConfig.PersistentDisable` can only be used while it is set. It
+/// survives sleep and is cleared at power-up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VolatileKeyPermit(bool);
+
+impl VolatileKeyPermit {
+    pub fn is_permitted(&self) -> bool {
+        self.0
+    }
+}
+
+impl TryFrom<&[u8]> for VolatileKeyPermit {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        match buffer {
+            [flag, _, _, _] => Ok(Self(*flag == 0x01)),
+            _ => Err(ErrorKind::BadParam.into()),
+        }
+    }
+}
+
 // A digest yielded from cryptographic hash functions. Merely a wrapper around
 // `GenericArray<u8, 32>` of `digest` crate.
 #[derive(Clone, Copy, Debug, Default)]
@@ -235,18 +928,159 @@ impl AsMut<[u8]> for Digest {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) enum OpCode {
-    /// CheckMac command op-code
-    #[allow(dead_code)]
-    CheckMac = 0x28,
-    /// DeriveKey command op-code
-    #[allow(dead_code)]
-    DeriveKey = 0x1C,
-    /// Info command op-code
-    Info = 0x30,
-    /// GenDig command op-code
-    GenDig = 0x15,
+#[cfg(feature = "zeroize")]
+impl Zeroize for Digest {
+    fn zeroize(&mut self) {
+        self.as_mut().zeroize();
+    }
+}
+
+// Suspended SHA-256 computation. The device context holds the intermediate
+// state and message length; bytes not yet making up a full 64-byte block are
+// buffered on the host and travel along.
+#[derive(Clone, Debug, Default)]
+pub struct Sha256Context {
+    pub(crate) device: Vec<u8, { Sha::CONTEXT_MAX_SIZE }>,
+    pub(crate) remaining_bytes: Vec<u8, 64>,
+}
+
+#[cfg(feature = "zeroize")]
+impl Zeroize for Sha256Context {
+    fn zeroize(&mut self) {
+        self.device[..].zeroize();
+        self.remaining_bytes[..].zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl Drop for Sha256Context {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl ZeroizeOnDrop for Sha256Context {}
+
+// Byte strings are logged in hex. Block and PremasterSecret are left out as
+// they may hold key material.
+#[cfg(feature = "defmt")]
+macro_rules! format_bytes {
+    ($($t:ty),*) => {
+        $(impl defmt::Format for $t {
+            fn format(&self, f: defmt::Formatter<'_>) {
+                defmt::write!(f, "{=[u8]:x}", self.as_ref())
+            }
+        })*
+    };
+}
+
+#[cfg(feature = "defmt")]
+format_bytes!(Word, Serial, Signature, DerSignature, PublicKey, Digest);
+
+/// Mode parameter shared by the MAC family of commands. It selects where the
+/// two 32-byte blocks of the SHA-256 message come from and which optional
+/// device values are included. The default takes the key from the slot and
+/// the challenge from the command input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MacMode(u8);
+
+impl MacMode {
+    /// Second 32 bytes of the message are taken from TempKey
+    const BLOCK2_TEMPKEY: u8 = 0x01;
+    /// First 32 bytes of the message are taken from TempKey
+    const BLOCK1_TEMPKEY: u8 = 0x02;
+    /// TempKey.SourceFlag is expected to be 1 (pass-through nonce)
+    const SOURCE_FLAG_MATCH: u8 = 0x04;
+    /// Include the first 88 OTP bits in the message
+    const INCLUDE_OTP_88: u8 = 0x10;
+    /// Include the first 64 OTP bits in the message
+    const INCLUDE_OTP_64: u8 = 0x20;
+    /// Include SN[2:3] and SN[4:7] in the message
+    const INCLUDE_SN: u8 = 0x40;
+
+    /// Use TempKey in place of the challenge input.
+    pub fn challenge_from_tempkey(self) -> Self {
+        Self(self.0 | Self::BLOCK2_TEMPKEY)
+    }
+
+    /// Use TempKey in place of the slot key.
+    pub fn key_from_tempkey(self) -> Self {
+        Self(self.0 | Self::BLOCK1_TEMPKEY)
+    }
+
+    /// Require TempKey to have been loaded by a pass-through nonce rather than
+    /// a random one. Only meaningful along with TempKey sources.
+    pub fn passthrough_tempkey(self) -> Self {
+        Self(self.0 | Self::SOURCE_FLAG_MATCH)
+    }
+
+    pub fn include_otp_88(self) -> Self {
+        Self(self.0 | Self::INCLUDE_OTP_88)
+    }
+
+    pub fn include_otp_64(self) -> Self {
+        Self(self.0 | Self::INCLUDE_OTP_64)
+    }
+
+    pub fn include_serial(self) -> Self {
+        Self(self.0 | Self::INCLUDE_SN)
+    }
+
+    pub(crate) fn is_challenge_from_tempkey(&self) -> bool {
+        self.0 & Self::BLOCK2_TEMPKEY != 0x00
+    }
+
+    pub(crate) fn uses_tempkey(&self) -> bool {
+        self.0 & (Self::BLOCK1_TEMPKEY | Self::BLOCK2_TEMPKEY) != 0x00
+    }
+
+    // Source of TempKey the device expects, given by the SourceFlag match bit.
+    pub(crate) fn tempkey_source(&self) -> TempKeySource {
+        if self.0 & Self::SOURCE_FLAG_MATCH != 0x00 {
+            TempKeySource::Input
+        } else {
+            TempKeySource::Random
+        }
+    }
+
+    pub(crate) fn has_otp_88(&self) -> bool {
+        self.0 & Self::INCLUDE_OTP_88 != 0x00
+    }
+
+    pub(crate) fn has_otp_64(&self) -> bool {
+        self.0 & Self::INCLUDE_OTP_64 != 0x00
+    }
+
+    pub(crate) fn has_serial(&self) -> bool {
+        self.0 & Self::INCLUDE_SN != 0x00
+    }
+}
+
+impl From<MacMode> for u8 {
+    fn from(mode: MacMode) -> Self {
+        mode.0
+    }
+}
+
+impl From<u8> for MacMode {
+    fn from(mode: u8) -> Self {
+        Self(mode)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum OpCode {
+    /// CheckMac command op-code
+    CheckMac = 0x28,
+    /// DeriveKey command op-code
+    DeriveKey = 0x1C,
+    /// Info command op-code
+    Info = 0x30,
+    /// GenDig command op-code
+    GenDig = 0x15,
     /// GenKey command op-code
     GenKey = 0x40,
     /// HMAC command op-code
@@ -255,12 +1089,10 @@ pub(crate) enum OpCode {
     /// Lock command op-code
     Lock = 0x17,
     /// MAC command op-code
-    #[allow(dead_code)]
     Mac = 0x08,
     /// Nonce command op-code
     Nonce = 0x16,
     /// Pause command op-code
-    #[allow(dead_code)]
     Pause = 0x01,
     /// PrivWrite command op-code
     PrivWrite = 0x46,
@@ -271,40 +1103,67 @@ pub(crate) enum OpCode {
     /// Sign command op-code
     Sign = 0x41,
     /// UpdateExtra command op-code
-    #[allow(dead_code)]
     UpdateExtra = 0x20,
     /// Verify command op-code
     Verify = 0x45,
     /// Write command op-code
     Write = 0x12,
     /// ECDH command op-code
-    #[allow(dead_code)]
     Ecdh = 0x43,
     /// Counter command op-code
-    #[allow(dead_code)]
     Counter = 0x24,
     /// SHA command op-code
     Sha = 0x47,
     /// AES command op-code
     Aes = 0x51,
     /// KDF command op-code
-    #[allow(dead_code)]
     Kdf = 0x56,
     /// Secure Boot command op-code
-    #[allow(dead_code)]
     SecureBoot = 0x80,
     /// Self test command op-code
-    #[allow(dead_code)]
     SelfTest = 0x77,
 }
 
-#[allow(dead_code)]
+impl TryFrom<u8> for OpCode {
+    type Error = Error;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use OpCode::*;
+        [
+            CheckMac,
+            DeriveKey,
+            Info,
+            GenDig,
+            GenKey,
+            HMac,
+            Lock,
+            Mac,
+            Nonce,
+            Pause,
+            PrivWrite,
+            Random,
+            Read,
+            Sign,
+            UpdateExtra,
+            Verify,
+            Write,
+            Ecdh,
+            Counter,
+            Sha,
+            Aes,
+            Kdf,
+            SecureBoot,
+            SelfTest,
+        ]
+        .iter()
+        .copied()
+        .find(|opcode| *opcode as u8 == value)
+        .ok_or_else(|| ErrorKind::BadOpcode.into())
+    }
+}
+
 pub(crate) struct CheckMac<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct Counter<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct DeriveKey<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct Ecdh<'a>(PacketBuilder<'a>);
 pub(crate) struct GenDig<'a>(PacketBuilder<'a>);
 pub(crate) struct GenKey<'a>(PacketBuilder<'a>);
@@ -312,14 +1171,12 @@ pub(crate) struct GenKey<'a>(PacketBuilder<'a>);
 pub(crate) struct HMac<'a>(PacketBuilder<'a>);
 pub(crate) struct Info<'a>(PacketBuilder<'a>);
 pub(crate) struct Lock<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct Mac<'a>(PacketBuilder<'a>);
 pub(crate) struct NonceCtx<'a> {
     builder: PacketBuilder<'a>,
     #[allow(dead_code)]
     counter: u32,
 }
-#[allow(dead_code)]
 pub(crate) struct Pause<'a>(PacketBuilder<'a>);
 
 // For best security, it is recommended that the `PrivWrite` command not be
@@ -329,35 +1186,317 @@ pub(crate) struct PrivWrite<'a>(PacketBuilder<'a>);
 pub(crate) struct Random<'a>(PacketBuilder<'a>);
 pub(crate) struct Read<'a>(PacketBuilder<'a>);
 pub(crate) struct Sign<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct UpdateExtra<'a>(PacketBuilder<'a>);
 pub(crate) struct Verify<'a>(PacketBuilder<'a>);
 pub(crate) struct Write<'a>(PacketBuilder<'a>);
 pub(crate) struct Sha<'a>(PacketBuilder<'a>);
 pub(crate) struct Aes<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct Kdf<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct SecureBoot<'a>(PacketBuilder<'a>);
-#[allow(dead_code)]
 pub(crate) struct SelfTest<'a>(PacketBuilder<'a>);
+pub(crate) struct Raw<'a>(PacketBuilder<'a>);
 
-#[allow(dead_code)]
+/// CheckMac
+impl<'a> CheckMac<'a> {
+    // Length of OtherData field.
+    pub(crate) const OTHER_DATA_SIZE: usize = 13;
+
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    // Compare a response computed by the host with the digest the device
+    // calculates from its own copy of the key. Only the CheckMac-supported
+    // mode bits are taken into account.
+    pub(crate) fn checkmac(
+        &mut self,
+        mode: MacMode,
+        key_id: Slot,
+        challenge: &Block,
+        response: &Block,
+        other_data: &[u8; 13],
+    ) -> Result<Packet, Error> {
+        let mode = u8::from(mode);
+        if mode & (MacMode::INCLUDE_OTP_88 | MacMode::INCLUDE_SN) != 0x00 {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        // Load PDU data: ClientChal, ClientResp and OtherData.
+        let pdu_buffer = self.0.pdu_buffer();
+        let (chal_buf, pdu_buffer) = pdu_buffer.split_at_mut(Size::Block.len());
+        chal_buf.copy_from_slice(challenge.as_ref());
+        let (resp_buf, pdu_buffer) = pdu_buffer.split_at_mut(Size::Block.len());
+        resp_buf.copy_from_slice(response.as_ref());
+        pdu_buffer[..Self::OTHER_DATA_SIZE].copy_from_slice(other_data);
+
+        let packet = self
+            .0
+            .opcode(OpCode::CheckMac)
+            .mode(mode)
+            .param2(key_id as u16)
+            .pdu_length(Size::Block.len() * 2 + Self::OTHER_DATA_SIZE)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// Counter
+impl<'a> Counter<'a> {
+    const MODE_READ: u8 = 0x00;
+    const MODE_INCREMENT: u8 = 0x01;
+
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    /// Command execution will return the current value of the counter.
+    pub(crate) fn read(&mut self, counter_id: CounterId) -> Result<Packet, Error> {
+        let packet = self
+            .0
+            .opcode(OpCode::Counter)
+            .mode(Self::MODE_READ)
+            .param2(counter_id as u16)
+            .build()?;
+        Ok(packet)
+    }
+
+    /// Command execution will return the counter value after increment.
+    pub(crate) fn increment(&mut self, counter_id: CounterId) -> Result<Packet, Error> {
+        let packet = self
+            .0
+            .opcode(OpCode::Counter)
+            .mode(Self::MODE_INCREMENT)
+            .param2(counter_id as u16)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// DeriveKey
+impl<'a> DeriveKey<'a> {
+    // TempKey is expected to be loaded by a random nonce.
+    pub(crate) const MODE_RANDOM_NONCE: u8 = 0x00;
+
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    // Combine the current key in the target slot (Roll) or its parent key
+    // (Create) with TempKey and write the result into the target slot. The
+    // MAC is required when the target slot's write configuration asks for an
+    // authorizing MAC.
+    pub(crate) fn derive_key(
+        &mut self,
+        target: Slot,
+        mac: Option<&Block>,
+    ) -> Result<Packet, Error> {
+        if let Some(mac) = mac {
+            self.0.pdu_data(mac);
+        }
+
+        let packet = self
+            .0
+            .opcode(OpCode::DeriveKey)
+            .mode(Self::MODE_RANDOM_NONCE)
+            .param2(target as u16)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// KDF
+impl<'a> Kdf<'a> {
+    pub(crate) const MESSAGE_SIZE_MAX: usize = 128;
+    const DETAILS_SIZE: usize = 4;
+    /// KDF algorithms
+    const MODE_ALG_PRF: u8 = 0x00;
+    const MODE_ALG_AES: u8 = 0x20;
+    const MODE_ALG_HKDF: u8 = 0x40;
+    /// PRF details: output length of 64 bytes
+    const DETAILS_PRF_TARGET_LEN_64: u32 = 0x0000_0100;
+    /// HKDF details: message location
+    const DETAILS_HKDF_MSG_LOC_TEMPKEY: u32 = 0x0000_0001;
+    const DETAILS_HKDF_MSG_LOC_INPUT: u32 = 0x0000_0002;
+    /// HKDF details: use a key of all zeros
+    const DETAILS_HKDF_ZERO_KEY: u32 = 0x0000_0004;
+
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    // TLS 1.2 PRF. `key_len` is the length of the source key in bytes, one of
+    // 16, 32, 48 or 64. `message` is label || seed.
+    pub(crate) fn prf(
+        &mut self,
+        source: KdfSource,
+        target: KdfTarget,
+        key_len: usize,
+        output_64: bool,
+        message: &[u8],
+    ) -> Result<Packet, Error> {
+        let key_len = match key_len {
+            16 => 0x00,
+            32 => 0x01,
+            48 => 0x02,
+            64 => 0x03,
+            _ => return Err(ErrorKind::BadParam.into()),
+        };
+        let target_len = if output_64 {
+            Self::DETAILS_PRF_TARGET_LEN_64
+        } else {
+            0x00
+        };
+        let details = key_len | target_len | Self::message_length(message)?;
+        self.kdf(Self::MODE_ALG_PRF, source, target, details, message)
+    }
+
+    // HMAC-SHA256 keyed by the source key, or by zeros if no source is given.
+    pub(crate) fn hkdf(
+        &mut self,
+        source: Option<KdfSource>,
+        target: KdfTarget,
+        message: HkdfMessage,
+    ) -> Result<Packet, Error> {
+        let (location, input) = match message {
+            HkdfMessage::TempKey => (Self::DETAILS_HKDF_MSG_LOC_TEMPKEY, &[][..]),
+            HkdfMessage::Input(input) => (Self::DETAILS_HKDF_MSG_LOC_INPUT, input),
+        };
+        let (source, zero_key) = match source {
+            Some(source) => (source, 0x00),
+            None => (KdfSource::TempKey, Self::DETAILS_HKDF_ZERO_KEY),
+        };
+        let details = location | zero_key | Self::message_length(input)?;
+        self.kdf(Self::MODE_ALG_HKDF, source, target, details, input)
+    }
+
+    // AES-128 ECB encryption of a 16-byte message with the key at
+    // `key_block` (0 to 3) within the source.
+    pub(crate) fn aes(
+        &mut self,
+        source: KdfSource,
+        target: KdfTarget,
+        key_block: u8,
+        message: &[u8],
+    ) -> Result<Packet, Error> {
+        if key_block > 0x03 || message.len() != Aes::DATA_SIZE {
+            return Err(ErrorKind::BadParam.into());
+        }
+        self.kdf(
+            Self::MODE_ALG_AES,
+            source,
+            target,
+            key_block as u32,
+            message,
+        )
+    }
+
+    fn message_length(message: &[u8]) -> Result<u32, Error> {
+        if message.len() > Self::MESSAGE_SIZE_MAX {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        Ok((message.len() as u32) << 24)
+    }
+
+    fn kdf(
+        &mut self,
+        algorithm: u8,
+        source: KdfSource,
+        target: KdfTarget,
+        details: u32,
+        message: &[u8],
+    ) -> Result<Packet, Error> {
+        let mode = algorithm | source.mode() | target.mode();
+        let key_id = source.slot() | target.slot();
+
+        // Load PDU data: details followed by the message.
+        let (details_buf, message_buf) = self.0.pdu_buffer().split_at_mut(Self::DETAILS_SIZE);
+        details_buf.copy_from_slice(&details.to_le_bytes());
+        message_buf[..message.len()].copy_from_slice(message);
+
+        let packet = self
+            .0
+            .opcode(OpCode::Kdf)
+            .mode(mode)
+            .param2(key_id)
+            .pdu_length(Self::DETAILS_SIZE + message.len())
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// ECDH
 impl<'a> Ecdh<'a> {
+    /// Private key is taken from the slot designated by param2
+    const MODE_SOURCE_EEPROM_SLOT: u8 = 0x00;
+    /// Private key is taken from TempKey, as generated by GenKey
+    const MODE_SOURCE_TEMPKEY: u8 = 0x01;
+    /// Output the premaster secret in the clear
+    const MODE_OUTPUT_CLEAR: u8 = 0x00;
+    /// Output the premaster secret encrypted with the I/O protection key
+    const MODE_OUTPUT_ENC: u8 = 0x02;
+    /// Premaster secret is stored in the slot after the private key's
+    const MODE_COPY_EEPROM_SLOT: u8 = 0x04;
+    /// Premaster secret is stored in TempKey
+    const MODE_COPY_TEMP_KEY: u8 = 0x08;
+    /// Premaster secret is returned through the output buffer
+    const MODE_COPY_OUTPUT_BUFFER: u8 = 0x0c;
+
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
     }
 
+    // Compute a premaster secret and put it where `target` says. Only
+    // output targets yield data.
     pub(crate) fn diffie_hellman(
         &mut self,
         private_key_id: Slot,
-        public_key: PublicKey,
+        public_key: &PublicKey,
+        target: EcdhTarget,
+    ) -> Result<Packet, Error> {
+        let mode = Self::MODE_SOURCE_EEPROM_SLOT | target.mode();
+        self.build(mode, private_key_id, public_key)
+    }
+
+    // Compute a premaster secret with the ephemeral private key in TempKey
+    // and return it through the output buffer, encrypted with the I/O
+    // protection key if `encrypted`.
+    pub(crate) fn diffie_hellman_ephemeral(
+        &mut self,
+        public_key: &PublicKey,
+        encrypted: bool,
+    ) -> Result<Packet, Error> {
+        let output = if encrypted {
+            Self::MODE_OUTPUT_ENC
+        } else {
+            Self::MODE_OUTPUT_CLEAR
+        };
+        let mode = Self::MODE_SOURCE_TEMPKEY | output | Self::MODE_COPY_OUTPUT_BUFFER;
+        self.build_packet(mode, 0x0000, public_key)
+    }
+
+    fn build(
+        &mut self,
+        mode: u8,
+        private_key_id: Slot,
+        public_key: &PublicKey,
+    ) -> Result<Packet, Error> {
+        if !private_key_id.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+        self.build_packet(mode, private_key_id as u16, public_key)
+    }
+
+    fn build_packet(
+        &mut self,
+        mode: u8,
+        param2: u16,
+        public_key: &PublicKey,
     ) -> Result<Packet, Error> {
         let packet = self
             .0
             .opcode(OpCode::Ecdh)
-            .mode(0x00)
-            .param2(private_key_id as u16)
+            .mode(mode)
+            .param2(param2)
             .pdu_data(public_key)
             .build()?;
         Ok(packet)
@@ -367,18 +1506,38 @@ impl<'a> Ecdh<'a> {
 // Used when signing an internally stored digest. The GenDig command uses
 // SHA-256 to combine a stored value with the contents of TempKey, which must
 // have been valid prior to the execution of this command.
-#[allow(dead_code)]
 impl<'a> GenDig<'a> {
+    // Zone encoding in param1.
+    pub(crate) const ZONE_OTP: u8 = 0x01;
+    pub(crate) const ZONE_DATA: u8 = 0x02;
+    pub(crate) const ZONE_SHARED_NONCE: u8 = 0x03;
+    pub(crate) const ZONE_COUNTER: u8 = 0x04;
+    pub(crate) const ZONE_KEY_CONFIG: u8 = 0x05;
+    // The OTP zone is digested one 32-byte block at a time.
+    const OTP_BLOCKS: u8 = 2;
+
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
     }
 
-    pub(crate) fn gendig(&mut self, key_id: Slot) -> Result<Packet, Error> {
+    pub(crate) fn source(&mut self, source: GenDigSource) -> Result<Packet, Error> {
+        if let GenDigSource::Otp(block) = source {
+            if block >= Self::OTP_BLOCKS {
+                return Err(ErrorKind::BadParam.into());
+            }
+        }
+
         let packet = self
             .0
             .opcode(OpCode::GenDig)
-            .param2(key_id as u16)
-            .build()?;
+            .mode(source.zone())
+            .param2(source.param2());
+        let packet = match &source {
+            GenDigSource::DataNoMac(_, other_data) => packet.pdu_data(other_data),
+            GenDigSource::SharedNonce(data) => packet.pdu_data(data),
+            _ => packet,
+        }
+        .build()?;
         Ok(packet)
     }
 }
@@ -391,14 +1550,20 @@ impl<'a> GenKey<'a> {
     const MODE_PUBLIC: u8 = 0x00; // Public key calculation
     #[allow(dead_code)]
     const MODE_DIGEST: u8 = 0x08; // PubKey digest will be created after the public key is calculated
-    #[allow(dead_code)]
     const MODE_PUBKEY_DIGEST: u8 = 0x10; // Calculate a digest on the public key
 
+    // KeyID of TempKey, ATECC608 only.
+    const KEY_ID_TEMPKEY: u16 = 0xffff;
+
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
     }
 
     pub(crate) fn private_key(&mut self, key_id: Slot) -> Result<Packet, Error> {
+        if !key_id.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+
         let packet = self
             .0
             .opcode(OpCode::GenKey)
@@ -408,7 +1573,22 @@ impl<'a> GenKey<'a> {
         Ok(packet)
     }
 
+    // Generate an ephemeral private key in TempKey, usable only by ECDH.
+    pub(crate) fn private_key_tempkey(&mut self) -> Result<Packet, Error> {
+        let packet = self
+            .0
+            .opcode(OpCode::GenKey)
+            .mode(Self::MODE_PRIVATE)
+            .param2(Self::KEY_ID_TEMPKEY)
+            .build()?;
+        Ok(packet)
+    }
+
     pub(crate) fn public_key(&mut self, key_id: Slot) -> Result<Packet, Error> {
+        if !key_id.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+
         let packet = self
             .0
             .opcode(OpCode::GenKey)
@@ -417,11 +1597,34 @@ impl<'a> GenKey<'a> {
             .build()?;
         Ok(packet)
     }
+
+    // Digest the public key stored in `key_id`, or computed from the private
+    // key there, with OtherData into TempKey. See `host::gen_key_digest`.
+    pub(crate) fn public_key_digest(
+        &mut self,
+        key_id: Slot,
+        other_data: &[u8; 3],
+    ) -> Result<Packet, Error> {
+        let packet = self
+            .0
+            .opcode(OpCode::GenKey)
+            .mode(Self::MODE_PUBKEY_DIGEST)
+            .param2(key_id as u16)
+            .pdu_data(other_data)
+            .build()?;
+        Ok(packet)
+    }
 }
 
 impl<'a> Info<'a> {
     // Info mode Revision
     const MODE_REVISION: u8 = 0x00;
+    const MODE_KEY_VALID: u8 = 0x01;
+    const MODE_STATE: u8 = 0x02;
+    const MODE_VOLATILE_KEY_PERMIT: u8 = 0x04;
+    // Param2 bits of the VolatileKeyPermit mode.
+    const PERMIT_SET: u16 = 0x0002;
+    const PERMIT_ENABLE: u16 = 0x0001;
 
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
@@ -429,10 +1632,38 @@ impl<'a> Info<'a> {
 
     /// Command execution will return a word containing the revision.
     pub(crate) fn revision(&mut self) -> Result<Packet, Error> {
+        self.build(Self::MODE_REVISION, 0x0000)
+    }
+
+    /// Whether the ECC key in `key_id` is valid.
+    pub(crate) fn key_valid(&mut self, key_id: Slot) -> Result<Packet, Error> {
+        self.build(Self::MODE_KEY_VALID, key_id as u16)
+    }
+
+    /// TempKey flags and authorization state.
+    pub(crate) fn state(&mut self) -> Result<Packet, Error> {
+        self.build(Self::MODE_STATE, 0x0000)
+    }
+
+    pub(crate) fn volatile_key_permit(&mut self) -> Result<Packet, Error> {
+        self.build(Self::MODE_VOLATILE_KEY_PERMIT, 0x0000)
+    }
+
+    pub(crate) fn set_volatile_key_permit(&mut self, enable: bool) -> Result<Packet, Error> {
+        let param2 = if enable {
+            Self::PERMIT_SET | Self::PERMIT_ENABLE
+        } else {
+            Self::PERMIT_SET
+        };
+        self.build(Self::MODE_VOLATILE_KEY_PERMIT, param2)
+    }
+
+    fn build(&mut self, mode: u8, param2: u16) -> Result<Packet, Error> {
         let packet = self
             .0
             .opcode(OpCode::Info)
-            .mode(Self::MODE_REVISION)
+            .mode(mode)
+            .param2(param2)
             .build()?;
         Ok(packet)
     }
@@ -440,6 +1671,7 @@ impl<'a> Info<'a> {
 
 impl<'a> Lock<'a> {
     const LOCK_ZONE_NO_CRC: u8 = 0x80;
+    const LOCK_SLOT: u8 = 0x02;
 
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
@@ -467,35 +1699,63 @@ impl<'a> Lock<'a> {
         Ok(packet)
     }
 
-    pub(crate) fn slot(&mut self, key_id: Slot) -> Result<Packet, Error> {
-        let mode = (key_id as u8) << 2 | 0x02 | Self::LOCK_ZONE_NO_CRC;
-        let packet = self.0.opcode(OpCode::Lock).mode(mode).build()?;
+    // With a CRC, the device refuses to lock unless it matches the CRC-16
+    // over the current slot contents.
+    pub(crate) fn slot(&mut self, key_id: Slot, crc: Option<u16>) -> Result<Packet, Error> {
+        let mode = (key_id as u8) << 2 | Self::LOCK_SLOT;
+        let packet = match crc {
+            None => self
+                .0
+                .opcode(OpCode::Lock)
+                .mode(mode | Self::LOCK_ZONE_NO_CRC)
+                .build()?,
+            Some(crc) => self.0.opcode(OpCode::Lock).mode(mode).param2(crc).build()?,
+        };
         Ok(packet)
     }
 }
 
-/// Nonce
-impl<'a> NonceCtx<'a> {
-    #[allow(dead_code)]
-    const MODE_MASK: u8 = 0x03; // Nonce mode bits 2 to 7 are 0.
-    #[allow(dead_code)]
-    const MODE_SEED_UPDATE: u8 = 0x00; // Nonce mode: update seed
-    #[allow(dead_code)]
-    const MODE_NO_SEED_UPDATE: u8 = 0x01; // Nonce mode: do not update seed
-    #[allow(dead_code)]
-    const MODE_INVALID: u8 = 0x02; // Nonce mode 2 is invalid.
+/// MAC
+impl<'a> Mac<'a> {
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    // Compute a SHA-256 digest over the key and challenge. When the challenge
+    // is taken from TempKey, the command carries no data.
+    pub(crate) fn mac(
+        &mut self,
+        mode: MacMode,
+        key_id: Slot,
+        challenge: Option<&Block>,
+    ) -> Result<Packet, Error> {
+        match (mode.is_challenge_from_tempkey(), challenge) {
+            (true, None) => {}
+            (false, Some(challenge)) => {
+                self.0.pdu_data(challenge);
+            }
+            _ => return Err(ErrorKind::BadParam.into()),
+        }
+
+        let packet = self
+            .0
+            .opcode(OpCode::Mac)
+            .mode(mode.into())
+            .param2(key_id as u16)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// Nonce
+impl<'a> NonceCtx<'a> {
+    pub(crate) const MODE_SEED_UPDATE: u8 = 0x00; // Nonce mode: update seed
+    const MODE_NO_SEED_UPDATE: u8 = 0x01; // Nonce mode: do not update seed
     const MODE_PASSTHROUGH: u8 = 0x03; // Nonce mode: pass-through
-    #[allow(dead_code)]
-    const MODE_INPUT_LEN_MASK: u8 = 0x20; // Nonce mode: input size mask
-    #[allow(dead_code)]
     const MODE_INPUT_LEN_32: u8 = 0x00; // Nonce mode: input size is 32 bytes
-    #[allow(dead_code)]
     const MODE_INPUT_LEN_64: u8 = 0x20; // Nonce mode: input size is 64 bytes
-    const MODE_TARGET_MASK: u8 = 0xc0; // Nonce mode: target mask
-    #[allow(dead_code)]
     const MODE_TARGET_TEMPKEY: u8 = 0x00; // Nonce mode: target is TempKey
     const MODE_TARGET_MSGDIGBUF: u8 = 0x40; // Nonce mode: target is Message Digest Buffer
-    #[allow(dead_code)]
     const MODE_TARGET_ALTKEYBUF: u8 = 0x80; // Nonce mode: target is Alternate Key Buffer
 
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
@@ -503,43 +1763,71 @@ impl<'a> NonceCtx<'a> {
         Self { builder, counter }
     }
 
-    // TODO: Usage of Nonce, especially its correct timing is not clear. In
-    // `test/api_atcab/atca_tests_aes.c`, AES encryption/decryption assumes
-    // nonce value is loaded to TempKey in advance.
     pub(crate) fn message_digest_buffer(&mut self, msg: &Digest) -> Result<Packet, Error> {
-        let mode = Self::MODE_PASSTHROUGH | (Self::MODE_TARGET_MSGDIGBUF & Self::MODE_TARGET_MASK);
+        self.passthrough(NonceTarget::MsgDigBuf, msg.as_ref())
+    }
+
+    // Load `data` as is into the target buffer. Input is either 32 or 64
+    // bytes long; the latter fills TempKey and the following buffer.
+    pub(crate) fn passthrough(
+        &mut self,
+        target: NonceTarget,
+        data: &[u8],
+    ) -> Result<Packet, Error> {
+        let input_len = match data.len() {
+            0x20 => Self::MODE_INPUT_LEN_32,
+            0x40 => Self::MODE_INPUT_LEN_64,
+            _ => return Err(ErrorKind::BadParam.into()),
+        };
+        let mode = Self::MODE_PASSTHROUGH | input_len | target.mode();
         let packet = self
             .builder
             .opcode(OpCode::Nonce)
             .mode(mode)
-            .pdu_data(msg)
+            .pdu_data(data)
             .build()?;
         Ok(packet)
     }
 
-    #[allow(dead_code)]
-    fn load(&mut self) -> Self {
-        unimplemented!()
-    }
-
-    #[allow(dead_code)]
-    fn rand(&mut self) -> Self {
-        unimplemented!()
+    // Combine a random number generated on the device with `num_in` and
+    // load the digest to TempKey. Command execution will return the random
+    // number so that the host can calculate the same TempKey. Skipping the
+    // seed update saves EEPROM wear but requires a recent seed update.
+    pub(crate) fn random(&mut self, num_in: &[u8; 20], seed_update: bool) -> Result<Packet, Error> {
+        let mode = if seed_update {
+            Self::MODE_SEED_UPDATE
+        } else {
+            Self::MODE_NO_SEED_UPDATE
+        };
+        let packet = self
+            .builder
+            .opcode(OpCode::Nonce)
+            .mode(mode | Self::MODE_TARGET_TEMPKEY)
+            .pdu_data(num_in)
+            .build()?;
+        Ok(packet)
     }
+}
 
-    #[allow(dead_code)]
-    fn challenge(&mut self) -> Self {
-        unimplemented!()
+/// Pause
+impl<'a> Pause<'a> {
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
     }
 
-    #[allow(dead_code)]
-    fn challenge_seed_update(&mut self) -> Self {
-        unimplemented!()
+    // Every device whose Selector configuration byte differs from `selector`
+    // enters the idle state and ignores the bus until the next wake.
+    pub(crate) fn pause(&mut self, selector: u8) -> Result<Packet, Error> {
+        let packet = self.0.opcode(OpCode::Pause).mode(selector).build()?;
+        Ok(packet)
     }
 }
 
 /// PrivWrite
 impl<'a> PrivWrite<'a> {
+    pub(crate) const MODE_ENCRYPTED: u8 = 0x40;
+    pub(crate) const VALUE_SIZE: usize = 36;
+
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
     }
@@ -567,6 +1855,32 @@ impl<'a> PrivWrite<'a> {
             .build()?;
         Ok(packet)
     }
+
+    // Value is the padded private key XORed with the session key produced by
+    // GenDig, followed by the input MAC. See `host::priv_write`.
+    pub(crate) fn write_private_key_encrypted(
+        &mut self,
+        key_id: Slot,
+        value: &[u8; 36],
+        mac: &Digest,
+    ) -> Result<Packet, Error> {
+        if !key_id.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        let mac_range = Self::VALUE_SIZE..Self::VALUE_SIZE + Size::Block.len();
+        let mac_length = mac_range.end;
+        self.0.pdu_buffer()[..Self::VALUE_SIZE].copy_from_slice(value);
+        self.0.pdu_buffer()[mac_range].copy_from_slice(mac.as_ref());
+        let packet = self
+            .0
+            .pdu_length(mac_length)
+            .opcode(OpCode::PrivWrite)
+            .mode(Self::MODE_ENCRYPTED)
+            .param2(key_id as u16)
+            .build()?;
+        Ok(packet)
+    }
 }
 
 impl<'a> Sha<'a> {
@@ -579,6 +1893,19 @@ impl<'a> Sha<'a> {
     /// Add 64 byte ECC public key in the slot to the SHA context
     #[allow(dead_code)]
     const MODE_SHA256_PUBLIC: u8 = 0x03;
+    /// Initialization for HMAC calculation, keyed by the slot in param2
+    const MODE_HMAC_START: u8 = 0x04;
+    /// Complete the HMAC calculation on the ATECC508A. The ATECC608 ends
+    /// HMAC with MODE_SHA256_END instead and rejects this mode.
+    const MODE_HMAC_END: u8 = 0x05;
+    /// Read the SHA context, ATECC608 only
+    const MODE_READ_CONTEXT: u8 = 0x06;
+    /// Write a previously read SHA context back, ATECC608 only
+    const MODE_WRITE_CONTEXT: u8 = 0x07;
+    /// Digest is returned in the output buffer only, TempKey is left intact
+    const MODE_TARGET_OUT_ONLY: u8 = 0xc0;
+    /// Largest context the device reads out
+    pub(crate) const CONTEXT_MAX_SIZE: usize = 109;
 
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
@@ -593,6 +1920,41 @@ impl<'a> Sha<'a> {
         Ok(packet)
     }
 
+    pub(crate) fn hmac_start(&mut self, key_id: Slot) -> Result<Packet, Error> {
+        let packet = self
+            .0
+            .opcode(OpCode::Sha)
+            .mode(Self::MODE_HMAC_START)
+            .param2(key_id as u16)
+            .build()?;
+        Ok(packet)
+    }
+
+    /// Command execution will return the HMAC digest of Block size.
+    pub(crate) fn hmac_end(
+        &mut self,
+        device: DeviceType,
+        data: impl AsRef<[u8]>,
+    ) -> Result<Packet, Error> {
+        let length = data.as_ref().len();
+        if length > 64 {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        let mode = match device {
+            DeviceType::Atecc508a => Self::MODE_HMAC_END,
+            _ => Self::MODE_SHA256_END | Self::MODE_TARGET_OUT_ONLY,
+        };
+        let packet = self
+            .0
+            .opcode(OpCode::Sha)
+            .mode(mode)
+            .param2(length as u16)
+            .pdu_data(data)
+            .build()?;
+        Ok(packet)
+    }
+
     /// Data length should be exactly 64 bytes.
     pub(crate) fn update(&mut self, data: impl AsRef<[u8]>) -> Result<Packet, Error> {
         let length = data.as_ref().len();
@@ -610,6 +1972,44 @@ impl<'a> Sha<'a> {
         Ok(packet)
     }
 
+    /// Command execution will return the current SHA context.
+    pub(crate) fn read_context(&mut self, device: DeviceType) -> Result<Packet, Error> {
+        if !device.has_sha_context() {
+            return Err(ErrorKind::UnsupportedByDevice.into());
+        }
+
+        let packet = self
+            .0
+            .opcode(OpCode::Sha)
+            .mode(Self::MODE_READ_CONTEXT)
+            .build()?;
+        Ok(packet)
+    }
+
+    pub(crate) fn write_context(
+        &mut self,
+        device: DeviceType,
+        context: impl AsRef<[u8]>,
+    ) -> Result<Packet, Error> {
+        if !device.has_sha_context() {
+            return Err(ErrorKind::UnsupportedByDevice.into());
+        }
+
+        let length = context.as_ref().len();
+        if length > Self::CONTEXT_MAX_SIZE {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        let packet = self
+            .0
+            .opcode(OpCode::Sha)
+            .mode(Self::MODE_WRITE_CONTEXT)
+            .param2(length as u16)
+            .pdu_data(context)
+            .build()?;
+        Ok(packet)
+    }
+
     /// Command execution will return a digest of Block size.
     pub(crate) fn end(&mut self, data: impl AsRef<[u8]>) -> Result<Packet, Error> {
         let length = data.as_ref().len();
@@ -635,51 +2035,213 @@ impl<'a> Aes<'a> {
     const MODE_ENCRYPT: u8 = 0x00;
     /// AES mode: Decrypt
     const MODE_DECRYPT: u8 = 0x01;
+    /// AES mode: Galois field multiply
+    const MODE_GFM: u8 = 0x03;
+    /// AES mode: Key block within the slot
+    const MODE_KEY_BLOCK_SHIFT: u8 = 6;
+    const KEY_BLOCK_MAX: u8 = 0x03;
 
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
     }
 
     /// Plain text has length of 16 bytes.
-    pub(crate) fn encrypt(&mut self, slot: Slot, plaintext: &[u8]) -> Result<Packet, Error> {
-        if !slot.is_private_key() {
-            return Err(ErrorKind::BadParam.into());
-        }
+    pub(crate) fn encrypt(
+        &mut self,
+        slot: Slot,
+        key_block: u8,
+        plaintext: &[u8],
+    ) -> Result<Packet, Error> {
+        self.build(Self::MODE_ENCRYPT, slot, key_block, plaintext)
+    }
 
-        // Input length should be exactly 16 bytes. Otherwise the device
-        // couldn't recognize the command properly.
-        if plaintext.len() != Self::DATA_SIZE {
+    /// Cipher text has length of 16 bytes.
+    pub(crate) fn decrypt(
+        &mut self,
+        slot: Slot,
+        key_block: u8,
+        ciphertext: &[u8],
+    ) -> Result<Packet, Error> {
+        self.build(Self::MODE_DECRYPT, slot, key_block, ciphertext)
+    }
+
+    /// Multiply `input` by `h` in GF(2^128), the GHASH step of GCM. No key
+    /// is involved.
+    pub(crate) fn gfm(&mut self, h: &[u8], input: &[u8]) -> Result<Packet, Error> {
+        if h.len() != Self::DATA_SIZE || input.len() != Self::DATA_SIZE {
             return Err(ErrorKind::InvalidSize.into());
         }
 
+        let pdu_buffer = self.0.pdu_buffer();
+        pdu_buffer[..Self::DATA_SIZE].copy_from_slice(h);
+        pdu_buffer[Self::DATA_SIZE..Self::DATA_SIZE * 2].copy_from_slice(input);
         let packet = self
             .0
             .opcode(OpCode::Aes)
-            .mode(Self::MODE_ENCRYPT)
-            .param2(slot as u16)
-            .pdu_data(plaintext)
+            .mode(Self::MODE_GFM)
+            .param2(0x0000)
+            .pdu_length(Self::DATA_SIZE * 2)
             .build()?;
         Ok(packet)
     }
 
-    /// Cipher text has length of 16 bytes.
-    pub(crate) fn decrypt(&mut self, slot: Slot, ciphertext: &[u8]) -> Result<Packet, Error> {
-        if !slot.is_private_key() {
+    // A slot holds up to four 16-byte keys, `key_block` selecting one of
+    // them through bits 6 and 7 of the mode.
+    fn build(&mut self, mode: u8, slot: Slot, key_block: u8, data: &[u8]) -> Result<Packet, Error> {
+        if key_block > Self::KEY_BLOCK_MAX {
             return Err(ErrorKind::BadParam.into());
         }
 
         // Input length should be exactly 16 bytes. Otherwise the device
         // couldn't recognize the command properly.
-        if ciphertext.len() != Self::DATA_SIZE {
+        if data.len() != Self::DATA_SIZE {
             return Err(ErrorKind::InvalidSize.into());
         }
 
         let packet = self
             .0
             .opcode(OpCode::Aes)
-            .mode(Self::MODE_DECRYPT)
+            .mode(mode | key_block << Self::MODE_KEY_BLOCK_SHIFT)
             .param2(slot as u16)
-            .pdu_data(ciphertext)
+            .pdu_data(data)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// SecureBoot
+impl<'a> SecureBoot<'a> {
+    /// Input digest is encrypted and the response carries a MAC
+    const MODE_ENC_MAC_FLAG: u8 = 0x80;
+
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    // Validate the firmware digest against the signature with the secure
+    // boot public key. `encrypted` tells that the digest has been encrypted
+    // with the I/O protection key, in which case the device returns a MAC.
+    pub(crate) fn full(
+        &mut self,
+        mode: u8,
+        digest: &Digest,
+        signature: &Signature,
+        encrypted: bool,
+    ) -> Result<Packet, Error> {
+        self.build(mode, digest, Some(signature), encrypted)
+    }
+
+    // Compare the digest with the one stored by FullStore, without a
+    // signature. Only accepted when the device is configured for stored
+    // digests.
+    pub(crate) fn stored_digest(
+        &mut self,
+        mode: u8,
+        digest: &Digest,
+        encrypted: bool,
+    ) -> Result<Packet, Error> {
+        self.build(mode, digest, None, encrypted)
+    }
+
+    fn build(
+        &mut self,
+        mode: u8,
+        digest: &Digest,
+        signature: Option<&Signature>,
+        encrypted: bool,
+    ) -> Result<Packet, Error> {
+        let mode = if encrypted {
+            mode | Self::MODE_ENC_MAC_FLAG
+        } else {
+            mode
+        };
+
+        // Load PDU data
+        let digest_length = digest.as_ref().len();
+        let (digest_buf, pdu_buffer) = self.0.pdu_buffer().split_at_mut(digest_length);
+        digest_buf.copy_from_slice(digest.as_ref());
+        let sig_length = signature.map_or(0, |signature| signature.as_ref().len());
+        if let Some(signature) = signature {
+            pdu_buffer[..sig_length].copy_from_slice(signature.as_ref());
+        }
+
+        let packet = self
+            .0
+            .opcode(OpCode::SecureBoot)
+            .mode(mode)
+            .pdu_length(digest_length + sig_length)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// Raw
+impl<'a> Raw<'a> {
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    // Any command, with parameters and data as given.
+    pub(crate) fn command(
+        &mut self,
+        opcode: u8,
+        mode: u8,
+        param2: u16,
+        data: &[u8],
+    ) -> Result<Packet, Error> {
+        let packet = self
+            .0
+            .try_pdu_data(data)?
+            .raw_opcode(opcode)
+            .mode(mode)
+            .param2(param2)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// SelfTest
+impl<'a> SelfTest<'a> {
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    /// Command execution will return a bitmap of failed tests.
+    pub(crate) fn self_test(&mut self, mode: SelfTestMode) -> Result<Packet, Error> {
+        if mode.0 == 0x00 {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        let packet = self.0.opcode(OpCode::SelfTest).mode(mode.0).build()?;
+        Ok(packet)
+    }
+}
+
+/// UpdateExtra
+impl<'a> UpdateExtra<'a> {
+    pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
+        Self(builder)
+    }
+
+    // Configuration bytes can only be updated once, while they are still
+    // zero. `value` carries the new byte or the slot to be decremented.
+    pub(crate) fn update_extra(
+        &mut self,
+        mode: UpdateExtraMode,
+        value: u16,
+    ) -> Result<Packet, Error> {
+        if mode == UpdateExtraMode::DecrementLimitedUse && value > Slot::Certificate0f as u16 {
+            return Err(ErrorKind::BadParam.into());
+        }
+        if mode != UpdateExtraMode::DecrementLimitedUse && value > 0xff {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        let packet = self
+            .0
+            .opcode(OpCode::UpdateExtra)
+            .mode(mode as u8)
+            .param2(value)
             .build()?;
         Ok(packet)
     }
@@ -688,17 +2250,23 @@ impl<'a> Aes<'a> {
 /// Random
 impl<'a> Random<'a> {
     const MODE_SEED_UPDATE: u8 = 0x00;
+    // Skip updating the EEPROM seed, which wears with every update.
+    const MODE_NO_SEED_UPDATE: u8 = 0x01;
 
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
     }
 
     pub(crate) fn random(&mut self) -> Result<Packet, Error> {
-        let packet = self
-            .0
-            .opcode(OpCode::Random)
-            .mode(Self::MODE_SEED_UPDATE)
-            .build()?;
+        self.build(Self::MODE_SEED_UPDATE)
+    }
+
+    pub(crate) fn random_no_seed_update(&mut self) -> Result<Packet, Error> {
+        self.build(Self::MODE_NO_SEED_UPDATE)
+    }
+
+    fn build(&mut self, mode: u8) -> Result<Packet, Error> {
+        let packet = self.0.opcode(OpCode::Random).mode(mode).build()?;
         Ok(packet)
     }
 }
@@ -721,6 +2289,23 @@ impl<'a> Read<'a> {
         Ok(packet)
     }
 
+    // Read a word of a slot, for the tail of slots not ending on a block
+    // boundary.
+    pub(crate) fn slot_word(&mut self, slot: Slot, block: u8, offset: u8) -> Result<Packet, Error> {
+        if offset >= 0x08 {
+            return Err(ErrorKind::BadParam.into());
+        }
+        let addr = Zone::Data.get_slot_addr(slot, block)? | offset as u16;
+        let mode = Zone::Data.encode(Size::Word);
+        let packet = self
+            .0
+            .opcode(OpCode::Read)
+            .mode(mode)
+            .param2(addr)
+            .build()?;
+        Ok(packet)
+    }
+
     pub(crate) fn read(
         &mut self,
         zone: Zone,
@@ -742,6 +2327,8 @@ impl<'a> Read<'a> {
 
 /// Sign
 impl<'a> Sign<'a> {
+    const MODE_INTERNAL: u8 = 0x00;
+    const MODE_INCLUDE_SN: u8 = 0x40;
     const MODE_SOURCE_MSGDIGBUF: u8 = 0x20;
     const MODE_EXTERNAL: u8 = 0x80;
 
@@ -752,6 +2339,10 @@ impl<'a> Sign<'a> {
     // Sign a 32-byte external message using the private key in the specified
     // slot.
     pub(crate) fn external(&mut self, key_id: Slot) -> Result<Packet, Error> {
+        if !key_id.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+
         let mode = Self::MODE_EXTERNAL | Self::MODE_SOURCE_MSGDIGBUF;
         let packet = self
             .0
@@ -761,13 +2352,47 @@ impl<'a> Sign<'a> {
             .build()?;
         Ok(packet)
     }
+
+    pub(crate) fn internal_mode(include_full_sn: bool) -> u8 {
+        match include_full_sn {
+            true => Self::MODE_INTERNAL | Self::MODE_INCLUDE_SN,
+            false => Self::MODE_INTERNAL,
+        }
+    }
+
+    // Sign a message the device builds from TempKey, after GenDig, and its
+    // own state. See `host::SignInternalMessage`.
+    pub(crate) fn internal(
+        &mut self,
+        key_id: Slot,
+        include_full_sn: bool,
+    ) -> Result<Packet, Error> {
+        if !key_id.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+
+        let packet = self
+            .0
+            .opcode(OpCode::Sign)
+            .mode(Self::internal_mode(include_full_sn))
+            .param2(key_id as u16)
+            .build()?;
+        Ok(packet)
+    }
 }
 
 /// Verify
 impl<'a> Verify<'a> {
-    const MODE_SOURCE_MSGDIGBUF: u8 = 0x20;
-    const MODE_EXTERNAL: u8 = 0x02;
-    const KEY_P256: u16 = 0x0004;
+    pub(crate) const MODE_SOURCE_MSGDIGBUF: u8 = 0x20;
+    pub(crate) const MODE_STORED: u8 = 0x00;
+    pub(crate) const MODE_EXTERNAL: u8 = 0x02;
+    const MODE_VALIDATE: u8 = 0x03;
+    const MODE_INVALIDATE: u8 = 0x07;
+    // Output a MAC over the result under the I/O protection key.
+    pub(crate) const MODE_MAC: u8 = 0x80;
+    pub(crate) const KEY_P256: u16 = 0x0004;
+    // Length of OtherData field in Validate and Invalidate modes.
+    pub(crate) const OTHER_DATA_SIZE: usize = 19;
 
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
@@ -780,7 +2405,27 @@ impl<'a> Verify<'a> {
         signature: &Signature,
         public_key: &PublicKey,
     ) -> Result<Packet, Error> {
-        let mode = Self::MODE_EXTERNAL | Self::MODE_SOURCE_MSGDIGBUF;
+        self.external_mode(0x00, signature, public_key)
+    }
+
+    // Same as `external`, the device answering with a MAC. The system nonce
+    // follows the message in the message digest buffer. See
+    // `host::verify_mac`.
+    pub(crate) fn external_mac(
+        &mut self,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<Packet, Error> {
+        self.external_mode(Self::MODE_MAC, signature, public_key)
+    }
+
+    fn external_mode(
+        &mut self,
+        flags: u8,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> Result<Packet, Error> {
+        let mode = Self::MODE_EXTERNAL | Self::MODE_SOURCE_MSGDIGBUF | flags;
 
         // Load PDU data
         let sig_length = signature.as_ref().len();
@@ -799,14 +2444,108 @@ impl<'a> Verify<'a> {
             .build()?;
         Ok(packet)
     }
-}
 
-/// Write
+    // Verify a 32-byte external message using the public key stored in the
+    // specified slot. If the key requires validation and it has not been
+    // validated, the device reports an execution error.
+    pub(crate) fn stored(&mut self, key_id: Slot, signature: &Signature) -> Result<Packet, Error> {
+        self.stored_mode(0x00, key_id, signature)
+    }
+
+    // Same as `stored`, the device answering with a MAC. See
+    // `external_mac`.
+    pub(crate) fn stored_mac(
+        &mut self,
+        key_id: Slot,
+        signature: &Signature,
+    ) -> Result<Packet, Error> {
+        self.stored_mode(Self::MODE_MAC, key_id, signature)
+    }
+
+    fn stored_mode(
+        &mut self,
+        flags: u8,
+        key_id: Slot,
+        signature: &Signature,
+    ) -> Result<Packet, Error> {
+        let mode = Self::MODE_STORED | Self::MODE_SOURCE_MSGDIGBUF | flags;
+        let packet = self
+            .0
+            .opcode(OpCode::Verify)
+            .mode(mode)
+            .param2(key_id as u16)
+            .pdu_data(signature)
+            .build()?;
+        Ok(packet)
+    }
+
+    // Mark the public key stored in `key_id` valid, or invalid, if
+    // `signature` verifies over the message built from TempKey and
+    // OtherData. See `host::validation_digest`.
+    pub(crate) fn validate(
+        &mut self,
+        key_id: Slot,
+        signature: &Signature,
+        other_data: &[u8; 19],
+        valid: bool,
+    ) -> Result<Packet, Error> {
+        let mode = if valid {
+            Self::MODE_VALIDATE
+        } else {
+            Self::MODE_INVALIDATE
+        };
+
+        // Load PDU data: Signature and OtherData.
+        let sig_length = signature.as_ref().len();
+        let (sig_buf, pdu_buffer) = self.0.pdu_buffer().split_at_mut(sig_length);
+        sig_buf.copy_from_slice(signature.as_ref());
+        pdu_buffer[..Self::OTHER_DATA_SIZE].copy_from_slice(other_data);
+
+        let packet = self
+            .0
+            .opcode(OpCode::Verify)
+            .mode(mode)
+            .param2(key_id as u16)
+            .pdu_length(sig_length + Self::OTHER_DATA_SIZE)
+            .build()?;
+        Ok(packet)
+    }
+}
+
+/// Write
 impl<'a> Write<'a> {
+    pub(crate) const MODE_ENCRYPTED: u8 = 0x40;
+
     pub(crate) fn new(builder: PacketBuilder<'a>) -> Self {
         Self(builder)
     }
 
+    // Data is XORed with the GenDig session key and authenticated by the
+    // input MAC. See `host::write_mac`.
+    pub(crate) fn slot_encrypted(
+        &mut self,
+        slot: Slot,
+        block: u8,
+        ciphertext: &Block,
+        mac: &Digest,
+    ) -> Result<Packet, Error> {
+        let addr = Zone::Data.get_slot_addr(slot, block)?;
+        let mode = Zone::Data.encode(Size::Block) | Self::MODE_ENCRYPTED;
+        let data_range = 0..Size::Block.len();
+        let mac_range = data_range.end..data_range.end + Size::Block.len();
+        let mac_length = mac_range.end;
+        self.0.pdu_buffer()[data_range].copy_from_slice(ciphertext.as_ref());
+        self.0.pdu_buffer()[mac_range].copy_from_slice(mac.as_ref());
+        let packet = self
+            .0
+            .pdu_length(mac_length)
+            .opcode(OpCode::Write)
+            .mode(mode)
+            .param2(addr)
+            .build()?;
+        Ok(packet)
+    }
+
     pub(crate) fn slot(&mut self, slot: Slot, block: u8, data: &Block) -> Result<Packet, Error> {
         let addr = Zone::Data.get_slot_addr(slot, block)?;
         let mode = Zone::Data.encode(Size::Block);
@@ -820,6 +2559,30 @@ impl<'a> Write<'a> {
         Ok(packet)
     }
 
+    // Write a word of a slot, for the tail of slots not ending on a block
+    // boundary.
+    pub(crate) fn slot_word(
+        &mut self,
+        slot: Slot,
+        block: u8,
+        offset: u8,
+        data: &Word,
+    ) -> Result<Packet, Error> {
+        if offset >= 0x08 {
+            return Err(ErrorKind::BadParam.into());
+        }
+        let addr = Zone::Data.get_slot_addr(slot, block)? | offset as u16;
+        let mode = Zone::Data.encode(Size::Word);
+        let packet = self
+            .0
+            .opcode(OpCode::Write)
+            .mode(mode)
+            .param2(addr)
+            .pdu_data(data)
+            .build()?;
+        Ok(packet)
+    }
+
     pub(crate) fn write(
         &mut self,
         zone: Zone,
@@ -849,6 +2612,40 @@ impl<'a> Write<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn count_match() {
+        let limit = CountMatch::new(0x0400).unwrap();
+        let bytes = limit.to_bytes();
+        assert_eq!(bytes, [0x00, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00]);
+        assert_eq!(CountMatch::try_from(bytes.as_ref()).unwrap(), limit);
+        assert_eq!(limit.remaining(CounterValue(0x03f0)), 0x10);
+        assert_eq!(limit.remaining(CounterValue(0x0500)), 0);
+        assert!(CountMatch::new(0x0401).is_err());
+        assert!(CountMatch::new(CounterValue::MAX + 1).is_err());
+        assert!(
+            CountMatch::try_from([0x00, 0x04, 0x00, 0x00, 0x20, 0x04, 0x00, 0x00].as_ref())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn public_key_slot_format() {
+        let mut public_key = PublicKey::default();
+        public_key
+            .as_mut()
+            .iter_mut()
+            .zip(1..)
+            .for_each(|(v, i)| *v = i);
+        let bytes = public_key.to_slot_format();
+        assert_eq!(bytes[..4], [0x00; 4]);
+        assert_eq!(bytes[4..36], public_key.as_ref()[..32]);
+        assert_eq!(bytes[36..40], [0x00; 4]);
+        assert_eq!(bytes[40..], public_key.as_ref()[32..]);
+        let decoded = PublicKey::from_slot_format(&bytes).unwrap();
+        assert_eq!(decoded.as_ref(), public_key.as_ref());
+        assert!(PublicKey::from_slot_format(&bytes[..64]).is_err());
+    }
+
     #[test]
     fn sha() {
         let buf = &mut [0x00u8; 0xff];
@@ -862,6 +2659,144 @@ mod tests {
         assert_eq!(packet[0x04..0x06], [0x00, 0x00]);
     }
 
+    #[test]
+    fn sha_context() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Sha::new(PacketBuilder::new(buf.as_mut()))
+            .read_context(DeviceType::Atecc608a)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x03], 0x06);
+
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Sha::new(PacketBuilder::new(buf.as_mut()))
+            .write_context(DeviceType::Atecc608a, [0xaa; 40])
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07 + 40);
+        assert_eq!(packet[0x03], 0x07);
+        assert_eq!(packet[0x04..0x06], [40, 0x00]);
+        assert_eq!(packet[0x06..0x2e], [0xaa; 40]);
+
+        let buf = &mut [0x00u8; 0xff];
+        assert!(Sha::new(PacketBuilder::new(buf.as_mut()))
+            .write_context(DeviceType::Atecc608a, [0xaa; 110])
+            .is_err());
+
+        for device in [DeviceType::Atecc508a, DeviceType::Atecc108a] {
+            let buf = &mut [0x00u8; 0xff];
+            let result = Sha::new(PacketBuilder::new(buf.as_mut())).read_context(device);
+            let error = result.map(drop).unwrap_err();
+            assert_eq!(error.kind(), Some(ErrorKind::UnsupportedByDevice));
+            let result =
+                Sha::new(PacketBuilder::new(buf.as_mut())).write_context(device, [0xaa; 40]);
+            let error = result.map(drop).unwrap_err();
+            assert_eq!(error.kind(), Some(ErrorKind::UnsupportedByDevice));
+        }
+    }
+
+    #[test]
+    fn random() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Random::new(PacketBuilder::new(buf.as_mut()))
+            .random_no_seed_update()
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::Random as u8);
+        assert_eq!(packet[0x03], 0x01);
+    }
+
+    #[test]
+    fn gendig() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = GenDig::new(PacketBuilder::new(buf.as_mut()))
+            .source(GenDigSource::DataNoMac(
+                Slot::Data08,
+                [0x01, 0x02, 0x03, 0x04],
+            ))
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x0b);
+        assert_eq!(packet[0x03], 0x02);
+        assert_eq!(packet[0x04..0x06], [0x08, 0x00]);
+        assert_eq!(packet[0x06..0x0a], [0x01, 0x02, 0x03, 0x04]);
+
+        let packet = GenDig::new(PacketBuilder::new(buf.as_mut()))
+            .source(GenDigSource::Counter(CounterId::One))
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x03], 0x04);
+        assert_eq!(packet[0x04..0x06], [0x01, 0x00]);
+
+        let packet = GenDig::new(PacketBuilder::new(buf.as_mut()))
+            .source(GenDigSource::SharedNonce(Block::default()))
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x27);
+        assert_eq!(packet[0x03], 0x03);
+
+        let result = GenDig::new(PacketBuilder::new(buf.as_mut())).source(GenDigSource::Otp(2));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn info() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Info::new(PacketBuilder::new(buf.as_mut()))
+            .key_valid(Slot::PrivateKey02)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x01);
+        assert_eq!(packet[0x04..0x06], [0x02, 0x00]);
+
+        let packet = Info::new(PacketBuilder::new(buf.as_mut()))
+            .set_volatile_key_permit(true)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x04);
+        assert_eq!(packet[0x04..0x06], [0x03, 0x00]);
+
+        let state = DeviceState::try_from([0x13, 0xa4, 0x00, 0x00].as_ref()).unwrap();
+        assert_eq!(state.tempkey_source(), Some(TempKeySource::Input));
+        assert_eq!(state.tempkey_key_id(), 0x03);
+        assert_eq!(state.auth_key(), Some(0x04));
+        let state = DeviceState::try_from([0x13, 0x00, 0x00, 0x00].as_ref()).unwrap();
+        assert_eq!(state.tempkey_source(), None);
+        assert_eq!(state.auth_key(), None);
+    }
+
+    #[test]
+    fn hmac() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Sha::new(PacketBuilder::new(buf.as_mut()))
+            .hmac_start(Slot::Data08)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x04);
+        assert_eq!(packet[0x04..0x06], [0x08, 0x00]);
+
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Sha::new(PacketBuilder::new(buf.as_mut()))
+            .hmac_end(DeviceType::Atecc608a, b"abc")
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x0a);
+        assert_eq!(packet[0x03], 0xc2);
+        assert_eq!(packet[0x04..0x06], [0x03, 0x00]);
+        assert_eq!(packet[0x06..0x09], *b"abc");
+
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Sha::new(PacketBuilder::new(buf.as_mut()))
+            .hmac_end(DeviceType::Atecc508a, b"abc")
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x05);
+        assert_eq!(packet[0x06..0x09], *b"abc");
+    }
+
     #[test]
     fn lock() {
         let buf = &mut [0x00u8; 0xff];
@@ -889,6 +2824,86 @@ mod tests {
         assert_eq!(packet[0x04..0x06], crc.to_le_bytes());
     }
 
+    #[test]
+    fn aes_key_block() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Aes::new(PacketBuilder::new(buf.as_mut()))
+            .decrypt(Slot::Certificate09, 2, &[0x00; 16])
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x02], OpCode::Aes as u8);
+        assert_eq!(packet[0x03], 0x81);
+        assert_eq!(packet[0x04..0x06], [0x09, 0x00]);
+
+        assert!(Aes::new(PacketBuilder::new(buf.as_mut()))
+            .encrypt(Slot::Certificate09, 4, &[0x00; 16])
+            .is_err());
+    }
+
+    #[test]
+    fn aes_gfm() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Aes::new(PacketBuilder::new(buf.as_mut()))
+            .gfm(&[0x11; 16], &[0x22; 16])
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07 + 0x20);
+        assert_eq!(packet[0x03], 0x03);
+        assert_eq!(packet[0x06..0x16], [0x11; 16]);
+        assert_eq!(packet[0x16..0x26], [0x22; 16]);
+    }
+
+    #[test]
+    fn read_slot_word() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Read::new(PacketBuilder::new(buf.as_mut()))
+            .slot_word(Slot::PrivateKey02, 1, 0)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::Read as u8);
+        assert_eq!(packet[0x03], Zone::Data.encode(Size::Word));
+        assert_eq!(packet[0x04..0x06], [0x10, 0x01]);
+    }
+
+    #[test]
+    fn write_slot_word() {
+        let buf = &mut [0x00u8; 0xff];
+        let word = Word::try_from([0xaa; 4].as_ref()).unwrap();
+        let packet = Write::new(PacketBuilder::new(buf.as_mut()))
+            .slot_word(Slot::Certificate0a, 2, 1, &word)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x02], OpCode::Write as u8);
+        assert_eq!(packet[0x03], Zone::Data.encode(Size::Word));
+        assert_eq!(packet[0x04..0x06], [0x51, 0x02]);
+        assert_eq!(packet[0x06..0x0a], [0xaa; 4]);
+
+        assert!(Write::new(PacketBuilder::new(buf.as_mut()))
+            .slot_word(Slot::Certificate0a, 2, 8, &word)
+            .is_err());
+    }
+
+    #[test]
+    fn lock_slot_crc() {
+        let buf = &mut [0x00u8; 0xff];
+        let crc = 0xBEEF;
+        let packet = Lock::new(PacketBuilder::new(buf.as_mut()))
+            .slot(Slot::Data08, Some(crc))
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x02], OpCode::Lock as u8);
+        assert_eq!(packet[0x03], 0x22);
+        assert_eq!(packet[0x04..0x06], crc.to_le_bytes());
+
+        let packet = Lock::new(PacketBuilder::new(buf.as_mut()))
+            .slot(Slot::Data08, None)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0xa2);
+        assert_eq!(packet[0x04..0x06], [0x00, 0x00]);
+    }
+
     #[test]
     fn genkey() {
         let buf = &mut [0x00u8; 0xff];
@@ -902,6 +2917,24 @@ mod tests {
         assert_eq!(packet[0x04..0x06], [0x01, 0x00]);
     }
 
+    #[test]
+    fn genkey_public() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = GenKey::new(PacketBuilder::new(buf.as_mut()))
+            .public_key(Slot::PrivateKey03)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::GenKey as u8);
+        assert_eq!(packet[0x03], 0x00);
+        assert_eq!(packet[0x04..0x06], [0x03, 0x00]);
+
+        let buf = &mut [0x00u8; 0xff];
+        assert!(GenKey::new(PacketBuilder::new(buf.as_mut()))
+            .private_key(Slot::Data08)
+            .is_err());
+    }
+
     #[test]
     fn privwrite() {
         let buf = &mut [0x00u8; 0xff];
@@ -922,6 +2955,36 @@ mod tests {
         assert_eq!(packet[0x0a..0x2a].as_ref(), data.as_ref());
     }
 
+    #[test]
+    fn privwrite_encrypted() {
+        let buf = &mut [0x00u8; 0xff];
+        let value = [0x11u8; 36];
+        let mac = Digest::try_from([0x22u8; 32].as_ref()).unwrap();
+        let packet = PrivWrite::new(PacketBuilder::new(buf.as_mut()))
+            .write_private_key_encrypted(Slot::PrivateKey02, &value, &mac)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x4b);
+        assert_eq!(packet[0x02], OpCode::PrivWrite as u8);
+        assert_eq!(packet[0x03], 0x40);
+        assert_eq!(packet[0x04..0x06], [0x02, 0x00]);
+        assert_eq!(packet[0x06..0x2a], value);
+        assert_eq!(packet[0x2a..0x4a], [0x22; 0x20]);
+    }
+
+    #[test]
+    fn sign() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Sign::new(PacketBuilder::new(buf.as_mut()))
+            .external(Slot::PrivateKey00)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::Sign as u8);
+        assert_eq!(packet[0x03], 0xa0);
+        assert_eq!(packet[0x04..0x06], [0x00, 0x00]);
+    }
+
     #[test]
     fn verify() {
         let buf = &mut [0x00u8; 0xff];
@@ -945,5 +3008,401 @@ mod tests {
         assert_eq!(packet[0x04..0x06], [0x04, 0x00]);
         assert_eq!(packet[0x06..0x46].as_ref(), signature.as_ref());
         assert_eq!(packet[0x46..0x86].as_ref(), public_key.as_ref());
+
+        let packet = Verify::new(PacketBuilder::new(buf.as_mut()))
+            .external_mac(&signature, &public_key)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0xa2);
+        assert_eq!(packet[0x04..0x06], [0x04, 0x00]);
+    }
+
+    #[test]
+    fn ecdh() {
+        let buf = &mut [0x00u8; 0xff];
+        let mut public_key = PublicKey::default();
+        public_key
+            .as_mut()
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = i as u8);
+        let packet = Ecdh::new(PacketBuilder::new(buf.as_mut()))
+            .diffie_hellman(Slot::PrivateKey02, &public_key, EcdhTarget::Output)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x47);
+        assert_eq!(packet[0x02], OpCode::Ecdh as u8);
+        assert_eq!(packet[0x03], 0x0c);
+        assert_eq!(packet[0x04..0x06], [0x02, 0x00]);
+        assert_eq!(packet[0x06..0x46].as_ref(), public_key.as_ref());
+
+        let packet = Ecdh::new(PacketBuilder::new(buf.as_mut()))
+            .diffie_hellman(Slot::PrivateKey02, &public_key, EcdhTarget::NextSlot)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x04);
+        assert_eq!(packet[0x04..0x06], [0x02, 0x00]);
+
+        let packet = Ecdh::new(PacketBuilder::new(buf.as_mut()))
+            .diffie_hellman_ephemeral(&public_key, true)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x0f);
+        assert_eq!(packet[0x04..0x06], [0x00, 0x00]);
+
+        let packet = GenKey::new(PacketBuilder::new(buf.as_mut()))
+            .private_key_tempkey()
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x04);
+        assert_eq!(packet[0x04..0x06], [0xff, 0xff]);
+    }
+
+    #[test]
+    fn verify_stored() {
+        let buf = &mut [0x00u8; 0xff];
+        let mut signature = Signature::default();
+        signature.as_mut().iter_mut().for_each(|v| *v = 0x5a);
+        let packet = Verify::new(PacketBuilder::new(buf.as_mut()))
+            .stored(Slot::Certificate0b, &signature)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x47);
+        assert_eq!(packet[0x02], OpCode::Verify as u8);
+        assert_eq!(packet[0x03], 0x20);
+        assert_eq!(packet[0x04..0x06], [0x0b, 0x00]);
+        assert_eq!(packet[0x06..0x46].as_ref(), signature.as_ref());
+
+        let packet = Verify::new(PacketBuilder::new(buf.as_mut()))
+            .stored_mac(Slot::Certificate0b, &signature)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0xa0);
+        assert_eq!(packet[0x04..0x06], [0x0b, 0x00]);
+    }
+
+    #[test]
+    fn signature_der() {
+        let mut signature = Signature::default();
+        let (r, s) = signature.as_mut().split_at_mut(32);
+        r.iter_mut().for_each(|v| *v = 0x81);
+        s[1..].iter_mut().for_each(|v| *v = 0x11);
+
+        let der = signature.to_der();
+        assert_eq!(der[..4], [0x30, 0x44, 0x02, 0x21]);
+        assert_eq!(der[4], 0x00);
+        assert_eq!(der[0x25..0x27], [0x02, 0x1f]);
+        assert_eq!(der.len(), 0x46);
+
+        let decoded = Signature::from_der(&der).unwrap();
+        assert_eq!(decoded.as_ref(), signature.as_ref());
+        assert!(Signature::from_der(&der[..der.len() - 1]).is_err());
+
+        let der = DerSignature::from(signature);
+        let decoded: DerSignature = signature::Signature::from_bytes(der.as_ref()).unwrap();
+        assert_eq!(decoded, der);
+    }
+
+    #[test]
+    fn public_key_sec1() {
+        let mut public_key = PublicKey::default();
+        public_key.as_mut().iter_mut().for_each(|v| *v = 0x42);
+        let bytes = public_key.to_sec1_bytes();
+        assert_eq!(bytes[0], 0x04);
+        let decoded = PublicKey::from_sec1_bytes(&bytes).unwrap();
+        assert_eq!(decoded.as_ref(), public_key.as_ref());
+        assert!(PublicKey::from_sec1_bytes(&bytes[1..]).is_err());
+    }
+
+    #[cfg(feature = "zeroize")]
+    #[test]
+    fn premaster_secret_zeroize() {
+        fn zeroized_on_drop<T: ZeroizeOnDrop>(_: &T) {}
+        let mut secret = PremasterSecret::try_from(&[0x5a; 32][..]).unwrap();
+        zeroized_on_drop(&secret);
+        secret.zeroize();
+        assert_eq!(secret.as_ref(), [0x00; 32]);
+    }
+
+    #[cfg(feature = "p256")]
+    #[test]
+    fn p256_interop() {
+        let signature = Signature::try_from(&[0x11; 64][..]).unwrap();
+        let converted = p256::ecdsa::Signature::try_from(signature).unwrap();
+        assert_eq!(converted.as_ref(), signature.as_ref());
+        assert_eq!(Signature::from(converted).as_ref(), signature.as_ref());
+        let result = p256::ecdsa::Signature::try_from(Signature::default());
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::BadParam));
+
+        let generator = p256::PublicKey::from_affine(p256::AffinePoint::GENERATOR).unwrap();
+        let public_key = PublicKey::from(generator);
+        assert_eq!(public_key.as_ref()[..2], [0x6b, 0x17]);
+        assert!(p256::PublicKey::try_from(public_key).unwrap() == generator);
+        let result = p256::PublicKey::try_from(PublicKey::default());
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::BadParam));
+    }
+
+    #[test]
+    fn checkmac() {
+        let buf = &mut [0x00u8; 0xff];
+        let mut challenge = Block::default();
+        challenge.as_mut().iter_mut().for_each(|v| *v = 0x01);
+        let mut response = Block::default();
+        response.as_mut().iter_mut().for_each(|v| *v = 0x02);
+        let other_data = [0x03; CheckMac::OTHER_DATA_SIZE];
+        let mode = MacMode::default().challenge_from_tempkey();
+        let packet = CheckMac::new(PacketBuilder::new(buf.as_mut()))
+            .checkmac(mode, Slot::Data08, &challenge, &response, &other_data)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x54);
+        assert_eq!(packet[0x02], OpCode::CheckMac as u8);
+        assert_eq!(packet[0x03], 0x01);
+        assert_eq!(packet[0x04..0x06], [0x08, 0x00]);
+        assert_eq!(packet[0x06..0x26].as_ref(), challenge.as_ref());
+        assert_eq!(packet[0x26..0x46].as_ref(), response.as_ref());
+        assert_eq!(packet[0x46..0x53], other_data);
+    }
+
+    #[test]
+    fn mac() {
+        let buf = &mut [0x00u8; 0xff];
+        let mut challenge = Block::default();
+        challenge.as_mut().iter_mut().for_each(|v| *v = 0x01);
+        let packet = Mac::new(PacketBuilder::new(buf.as_mut()))
+            .mac(MacMode::default(), Slot::Data08, Some(&challenge))
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x27);
+        assert_eq!(packet[0x02], OpCode::Mac as u8);
+        assert_eq!(packet[0x03], 0x00);
+        assert_eq!(packet[0x04..0x06], [0x08, 0x00]);
+        assert_eq!(packet[0x06..0x26].as_ref(), challenge.as_ref());
+
+        let buf = &mut [0x00u8; 0xff];
+        let mode = MacMode::default().challenge_from_tempkey();
+        let packet = Mac::new(PacketBuilder::new(buf.as_mut()))
+            .mac(mode, Slot::Data08, None)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x03], 0x01);
+    }
+
+    #[test]
+    fn counter() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Counter::new(PacketBuilder::new(buf.as_mut()))
+            .increment(CounterId::One)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::Counter as u8);
+        assert_eq!(packet[0x03], 0x01);
+        assert_eq!(packet[0x04..0x06], [0x01, 0x00]);
+
+        let value = CounterValue::try_from([0x78, 0x56, 0x34, 0x12].as_ref()).unwrap();
+        assert_eq!(value, CounterValue(0x12345678));
+    }
+
+    #[test]
+    fn derive_key() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = DeriveKey::new(PacketBuilder::new(buf.as_mut()))
+            .derive_key(Slot::PrivateKey05, None)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::DeriveKey as u8);
+        assert_eq!(packet[0x04..0x06], [0x05, 0x00]);
+
+        let buf = &mut [0x00u8; 0xff];
+        let mut mac = Block::default();
+        mac.as_mut().iter_mut().for_each(|v| *v = 0x33);
+        let packet = DeriveKey::new(PacketBuilder::new(buf.as_mut()))
+            .derive_key(Slot::PrivateKey05, Some(&mac))
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x27);
+        assert_eq!(packet[0x06..0x26].as_ref(), mac.as_ref());
+    }
+
+    #[test]
+    fn kdf() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Kdf::new(PacketBuilder::new(buf.as_mut()))
+            .hkdf(
+                Some(KdfSource::Slot(Slot::Data08)),
+                KdfTarget::Output,
+                HkdfMessage::Input(b"info"),
+            )
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x0f);
+        assert_eq!(packet[0x02], OpCode::Kdf as u8);
+        assert_eq!(packet[0x03], 0x52);
+        assert_eq!(packet[0x04..0x06], [0x08, 0x00]);
+        assert_eq!(packet[0x06..0x0a], [0x02, 0x00, 0x00, 0x04]);
+        assert_eq!(packet[0x0a..0x0e], *b"info");
+
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Kdf::new(PacketBuilder::new(buf.as_mut()))
+            .prf(
+                KdfSource::TempKey,
+                KdfTarget::Slot(Slot::Certificate09),
+                32,
+                true,
+                b"label",
+            )
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x03], 0x08);
+        assert_eq!(packet[0x04..0x06], [0x00, 0x09]);
+        assert_eq!(packet[0x06..0x0a], [0x01, 0x01, 0x00, 0x05]);
+    }
+
+    #[test]
+    fn nonce_random() {
+        let buf = &mut [0x00u8; 0xff];
+        let num_in = [0x0a; 20];
+        let packet = NonceCtx::new(PacketBuilder::new(buf.as_mut()))
+            .random(&num_in, true)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x1b);
+        assert_eq!(packet[0x02], OpCode::Nonce as u8);
+        assert_eq!(packet[0x03], 0x00);
+        assert_eq!(packet[0x06..0x1a], num_in);
+    }
+
+    #[test]
+    fn nonce_passthrough() {
+        let buf = &mut [0x00u8; 0xff];
+        let data = [0x5a; 64];
+        let packet = NonceCtx::new(PacketBuilder::new(buf.as_mut()))
+            .passthrough(NonceTarget::AltKeyBuf, &data)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x47);
+        assert_eq!(packet[0x02], OpCode::Nonce as u8);
+        assert_eq!(packet[0x03], 0xa3);
+        assert_eq!(packet[0x06..0x46], data);
+
+        let mut builder = NonceCtx::new(PacketBuilder::new(buf.as_mut()));
+        assert!(builder
+            .passthrough(NonceTarget::TempKey, &data[..20])
+            .is_err());
+    }
+
+    #[test]
+    fn secure_boot() {
+        let buf = &mut [0x00u8; 0xff];
+        let digest = Digest::default();
+        let signature = Signature::default();
+        let packet = SecureBoot::new(PacketBuilder::new(buf.as_mut()))
+            .full(0x06, &digest, &signature, true)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x67);
+        assert_eq!(packet[0x02], OpCode::SecureBoot as u8);
+        assert_eq!(packet[0x03], 0x86);
+        assert_eq!(packet[0x04..0x06], [0x00, 0x00]);
+
+        let packet = SecureBoot::new(PacketBuilder::new(buf.as_mut()))
+            .stored_digest(0x05, &digest, true)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x27);
+        assert_eq!(packet[0x03], 0x85);
+    }
+
+    #[test]
+    fn raw() {
+        let buf = &mut [0x00u8; 0xff];
+        let opcode = OpCode::try_from(0x1b).unwrap();
+        assert_eq!(opcode, OpCode::Random);
+        assert!(OpCode::try_from(0x99).is_err());
+        let packet = Raw::new(PacketBuilder::new(buf.as_mut()))
+            .command(0x1b, 0x00, 0x0000, &[0xaa; 4])
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01..0x06], [0x0b, 0x1b, 0x00, 0x00, 0x00]);
+        assert_eq!(packet[0x06..0x0a], [0xaa; 4]);
+        // Unknown op-codes are written as given.
+        let packet = Raw::new(PacketBuilder::new(buf.as_mut()))
+            .command(0x99, 0x00, 0x0000, &[])
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01..0x03], [0x07, 0x99]);
+        let result =
+            Raw::new(PacketBuilder::new(buf.as_mut())).command(0x1b, 0x00, 0x0000, &[0x00; 0xf9]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn self_test() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = SelfTest::new(PacketBuilder::new(buf.as_mut()))
+            .self_test(SelfTestMode::none().rng().sha())
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::SelfTest as u8);
+        assert_eq!(packet[0x03], 0x21);
+
+        let result = SelfTestResult::try_from([0x08].as_ref()).unwrap();
+        assert!(!result.is_success());
+        assert!(result.rng_passed());
+        assert!(!result.ecdh_passed());
+    }
+
+    #[test]
+    fn update_extra() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = UpdateExtra::new(PacketBuilder::new(buf.as_mut()))
+            .update_extra(
+                UpdateExtraMode::DecrementLimitedUse,
+                Slot::PrivateKey05 as u16,
+            )
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::UpdateExtra as u8);
+        assert_eq!(packet[0x03], 0x02);
+        assert_eq!(packet[0x04..0x06], [0x05, 0x00]);
+
+        let mut builder = UpdateExtra::new(PacketBuilder::new(buf.as_mut()));
+        assert!(builder
+            .update_extra(UpdateExtraMode::UserExtra, 0x100)
+            .is_err());
+    }
+
+    #[test]
+    fn pause() {
+        let buf = &mut [0x00u8; 0xff];
+        let packet = Pause::new(PacketBuilder::new(buf.as_mut()))
+            .pause(0xa5)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x07);
+        assert_eq!(packet[0x02], OpCode::Pause as u8);
+        assert_eq!(packet[0x03], 0xa5);
+        assert_eq!(packet[0x04..0x06], [0x00, 0x00]);
+    }
+
+    #[test]
+    fn write_encrypted() {
+        let buf = &mut [0x00u8; 0xff];
+        let data = Block::try_from([0x33u8; 32].as_ref()).unwrap();
+        let mac = Digest::try_from([0x44u8; 32].as_ref()).unwrap();
+        let packet = Write::new(PacketBuilder::new(buf.as_mut()))
+            .slot_encrypted(Slot::PrivateKey03, 0, &data, &mac)
+            .unwrap()
+            .buffer(buf.as_ref());
+        assert_eq!(packet[0x01], 0x47);
+        assert_eq!(packet[0x02], OpCode::Write as u8);
+        assert_eq!(packet[0x03] & Write::MODE_ENCRYPTED, Write::MODE_ENCRYPTED);
+        assert_eq!(packet[0x06..0x26], [0x33; 0x20]);
+        assert_eq!(packet[0x26..0x46], [0x44; 0x20]);
     }
 }
@@ -0,0 +1,944 @@
+// Typed view of the 128-byte configuration zone. Field offsets and bit
+// positions follow the ATECC608 datasheet. Unrecognised bits are kept as is,
+// so a parsed zone always serializes back to the same bytes.
+use super::command::Serial;
+use super::error::{Error, ErrorKind};
+use super::memory::Slot;
+use core::convert::{TryFrom, TryInto};
+use core::ops::Range;
+use heapless::Vec;
+
+pub const CONFIG_ZONE_SIZE: usize = 128;
+
+const REVISION: usize = 4;
+const AES_ENABLE: usize = 13;
+const I2C_ENABLE: usize = 14;
+const I2C_ADDRESS: usize = 16;
+const COUNT_MATCH: usize = 18;
+const CHIP_MODE: usize = 19;
+const SLOT_CONFIG: usize = 20;
+const USER_EXTRA: usize = 84;
+const USER_EXTRA_ADD: usize = 85;
+const LOCK_VALUE: usize = 86;
+const LOCK_CONFIG: usize = 87;
+const SLOT_LOCKED: usize = 88;
+const CHIP_OPTIONS: usize = 90;
+const KEY_CONFIG: usize = 96;
+
+// ChipOptions fields.
+const IO_PROTECTION_ENABLE: u16 = 0x0002;
+const ECDH_PROTECTION_SHIFT: u32 = 8;
+const KDF_PROTECTION_SHIFT: u32 = 10;
+const IO_PROTECTION_KEY_SHIFT: u32 = 12;
+
+// CountMatch fields.
+const COUNT_MATCH_ENABLE: u8 = 0x01;
+const COUNT_MATCH_KEY_SHIFT: u32 = 4;
+
+// ReadKey bits of a private key slot.
+const SIGN_EXTERNAL: u8 = 0x01;
+const SIGN_INTERNAL: u8 = 0x02;
+const ECDH_ALLOWED: u8 = 0x04;
+
+// WriteConfig bits that select encrypted writes.
+const WRITE_MODE: u8 = 0x0c;
+const WRITE_ENCRYPT: u8 = 0x04;
+
+// Lock bytes read 0x55 while the zone is unlocked.
+const UNLOCKED: u8 = 0x55;
+
+/// Upper bound of differences `ConfigZone::diff` reports, one per field.
+pub const CONFIG_FIELDS_MAX: usize = 64;
+
+/// Size of the largest field with a single range, the counters.
+pub const CONFIG_FIELD_MAX_SIZE: usize = 8;
+
+/// How ECDH and KDF output may leave the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputProtection {
+    /// Output may be returned in the clear.
+    Clear = 0x00,
+    /// Output must be encrypted with the I/O protection key.
+    Encrypted = 0x01,
+    /// Output may only be kept in TempKey or a slot.
+    Internal = 0x02,
+}
+
+/// A named region of the configuration zone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigField {
+    SerialNumber,
+    Revision,
+    AesEnable,
+    I2cEnable,
+    I2cAddress,
+    CountMatch,
+    ChipMode,
+    SlotConfig(Slot),
+    Counter0,
+    Counter1,
+    UseLock,
+    VolatileKeyPermission,
+    SecureBoot,
+    KdfIvLoc,
+    KdfIvStr,
+    UserExtra,
+    UserExtraAdd,
+    LockValue,
+    LockConfig,
+    SlotLocked,
+    ChipOptions,
+    X509Format,
+    KeyConfig(Slot),
+    Reserved,
+}
+
+// Fields other than per-slot words, in zone order.
+const FIELDS: [(ConfigField, Range<usize>); 25] = [
+    (ConfigField::SerialNumber, 0..4),
+    (ConfigField::Revision, 4..8),
+    (ConfigField::SerialNumber, 8..13),
+    (ConfigField::AesEnable, 13..14),
+    (ConfigField::I2cEnable, 14..15),
+    (ConfigField::Reserved, 15..16),
+    (ConfigField::I2cAddress, 16..17),
+    (ConfigField::Reserved, 17..18),
+    (ConfigField::CountMatch, 18..19),
+    (ConfigField::ChipMode, 19..20),
+    (ConfigField::Counter0, 52..60),
+    (ConfigField::Counter1, 60..68),
+    (ConfigField::UseLock, 68..69),
+    (ConfigField::VolatileKeyPermission, 69..70),
+    (ConfigField::SecureBoot, 70..72),
+    (ConfigField::KdfIvLoc, 72..73),
+    (ConfigField::KdfIvStr, 73..75),
+    (ConfigField::Reserved, 75..84),
+    (ConfigField::UserExtra, 84..85),
+    (ConfigField::UserExtraAdd, 85..86),
+    (ConfigField::LockValue, 86..87),
+    (ConfigField::LockConfig, 87..88),
+    (ConfigField::SlotLocked, 88..90),
+    (ConfigField::ChipOptions, 90..92),
+    (ConfigField::X509Format, 92..96),
+];
+
+impl ConfigField {
+    /// Bytes the field occupies in the zone. The serial number and reserved
+    /// bytes are split over several ranges and have none.
+    pub fn range(&self) -> Option<Range<usize>> {
+        match self {
+            Self::SlotConfig(slot) => {
+                let index = SLOT_CONFIG + *slot as usize * 2;
+                Some(index..index + 2)
+            }
+            Self::KeyConfig(slot) => {
+                let index = KEY_CONFIG + *slot as usize * 2;
+                Some(index..index + 2)
+            }
+            Self::SerialNumber | Self::Reserved => None,
+            field => FIELDS
+                .iter()
+                .find(|(other, _)| other == field)
+                .map(|(_, range)| range.clone()),
+        }
+    }
+
+    /// Whether the Write command can change the field before the zone is
+    /// locked. The first 16 bytes are read-only; UserExtra and the lock
+    /// bytes are changed by UpdateExtra and Lock.
+    pub fn is_writable(&self) -> bool {
+        match self {
+            Self::UserExtra | Self::UserExtraAdd | Self::LockValue | Self::LockConfig => false,
+            field => field
+                .range()
+                .is_some_and(|range| range.start >= I2C_ADDRESS),
+        }
+    }
+}
+
+/// A field whose bytes differ between two zones, at `range` of both.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigDiff {
+    pub field: ConfigField,
+    pub range: Range<usize>,
+}
+
+/// Kind of key a slot holds, as encoded in KeyConfig.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum KeyType {
+    P256 = 0x04,
+    Aes = 0x06,
+    /// SHA key or arbitrary data.
+    ShaOrText = 0x07,
+}
+
+impl TryFrom<u8> for KeyType {
+    type Error = Error;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x04 => Ok(Self::P256),
+            0x06 => Ok(Self::Aes),
+            0x07 => Ok(Self::ShaOrText),
+            _ => Err(ErrorKind::BadParam.into()),
+        }
+    }
+}
+
+/// Access policy of a slot, two bytes per slot starting at offset 20.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "SlotConfigFields", into = "SlotConfigFields")
+)]
+pub struct SlotConfig(u16);
+
+impl SlotConfig {
+    const READ_KEY: u16 = 0x000f;
+    const NO_MAC: u16 = 0x0010;
+    const LIMITED_USE: u16 = 0x0020;
+    const ENCRYPT_READ: u16 = 0x0040;
+    const IS_SECRET: u16 = 0x0080;
+    const WRITE_KEY: u16 = 0x0f00;
+    const WRITE_CONFIG: u16 = 0xf000;
+
+    /// Slot whose key encrypts reads, or the ECDH/sign permissions of a
+    /// private key slot.
+    pub fn read_key(&self) -> u8 {
+        (self.0 & Self::READ_KEY) as u8
+    }
+
+    pub fn no_mac(&self) -> bool {
+        self.0 & Self::NO_MAC != 0x00
+    }
+
+    pub fn limited_use(&self) -> bool {
+        self.0 & Self::LIMITED_USE != 0x00
+    }
+
+    pub fn encrypt_read(&self) -> bool {
+        self.0 & Self::ENCRYPT_READ != 0x00
+    }
+
+    pub fn is_secret(&self) -> bool {
+        self.0 & Self::IS_SECRET != 0x00
+    }
+
+    pub fn write_key(&self) -> u8 {
+        ((self.0 & Self::WRITE_KEY) >> 8) as u8
+    }
+
+    pub fn write_config(&self) -> u8 {
+        ((self.0 & Self::WRITE_CONFIG) >> 12) as u8
+    }
+
+    pub fn with_read_key(self, key: u8) -> Self {
+        Self(self.0 & !Self::READ_KEY | (key as u16 & Self::READ_KEY))
+    }
+
+    pub fn with_no_mac(self, value: bool) -> Self {
+        self.with_flag(Self::NO_MAC, value)
+    }
+
+    pub fn with_limited_use(self, value: bool) -> Self {
+        self.with_flag(Self::LIMITED_USE, value)
+    }
+
+    pub fn with_encrypt_read(self, value: bool) -> Self {
+        self.with_flag(Self::ENCRYPT_READ, value)
+    }
+
+    pub fn with_is_secret(self, value: bool) -> Self {
+        self.with_flag(Self::IS_SECRET, value)
+    }
+
+    pub fn with_write_key(self, key: u8) -> Self {
+        Self(self.0 & !Self::WRITE_KEY | ((key as u16) << 8 & Self::WRITE_KEY))
+    }
+
+    pub fn with_write_config(self, config: u8) -> Self {
+        Self(self.0 & !Self::WRITE_CONFIG | ((config as u16) << 12 & Self::WRITE_CONFIG))
+    }
+
+    fn with_flag(self, flag: u16, value: bool) -> Self {
+        if value {
+            Self(self.0 | flag)
+        } else {
+            Self(self.0 & !flag)
+        }
+    }
+}
+
+impl From<u16> for SlotConfig {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<SlotConfig> for u16 {
+    fn from(config: SlotConfig) -> Self {
+        config.0
+    }
+}
+
+/// Key properties of a slot, two bytes per slot starting at offset 96.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "KeyConfigFields", into = "KeyConfigFields")
+)]
+pub struct KeyConfig(u16);
+
+impl KeyConfig {
+    const PRIVATE: u16 = 0x0001;
+    const PUB_INFO: u16 = 0x0002;
+    const KEY_TYPE: u16 = 0x001c;
+    const LOCKABLE: u16 = 0x0020;
+    const REQ_RANDOM: u16 = 0x0040;
+    const REQ_AUTH: u16 = 0x0080;
+    const AUTH_KEY: u16 = 0x0f00;
+    const PERSISTENT_DISABLE: u16 = 0x1000;
+    const X509_ID: u16 = 0xc000;
+    #[cfg(feature = "serde")]
+    const RESERVED: u16 = 0x2000;
+
+    pub fn private(&self) -> bool {
+        self.0 & Self::PRIVATE != 0x00
+    }
+
+    pub fn pub_info(&self) -> bool {
+        self.0 & Self::PUB_INFO != 0x00
+    }
+
+    /// `None` if the field holds a value reserved by the datasheet.
+    pub fn key_type(&self) -> Option<KeyType> {
+        KeyType::try_from(((self.0 & Self::KEY_TYPE) >> 2) as u8).ok()
+    }
+
+    pub fn lockable(&self) -> bool {
+        self.0 & Self::LOCKABLE != 0x00
+    }
+
+    pub fn req_random(&self) -> bool {
+        self.0 & Self::REQ_RANDOM != 0x00
+    }
+
+    pub fn req_auth(&self) -> bool {
+        self.0 & Self::REQ_AUTH != 0x00
+    }
+
+    pub fn auth_key(&self) -> u8 {
+        ((self.0 & Self::AUTH_KEY) >> 8) as u8
+    }
+
+    pub fn persistent_disable(&self) -> bool {
+        self.0 & Self::PERSISTENT_DISABLE != 0x00
+    }
+
+    pub fn x509_id(&self) -> u8 {
+        ((self.0 & Self::X509_ID) >> 14) as u8
+    }
+
+    pub fn with_private(self, value: bool) -> Self {
+        self.with_flag(Self::PRIVATE, value)
+    }
+
+    pub fn with_pub_info(self, value: bool) -> Self {
+        self.with_flag(Self::PUB_INFO, value)
+    }
+
+    pub fn with_key_type(self, key_type: KeyType) -> Self {
+        Self(self.0 & !Self::KEY_TYPE | (key_type as u16) << 2)
+    }
+
+    pub fn with_lockable(self, value: bool) -> Self {
+        self.with_flag(Self::LOCKABLE, value)
+    }
+
+    pub fn with_req_random(self, value: bool) -> Self {
+        self.with_flag(Self::REQ_RANDOM, value)
+    }
+
+    pub fn with_req_auth(self, value: bool) -> Self {
+        self.with_flag(Self::REQ_AUTH, value)
+    }
+
+    pub fn with_auth_key(self, key: u8) -> Self {
+        Self(self.0 & !Self::AUTH_KEY | ((key as u16) << 8 & Self::AUTH_KEY))
+    }
+
+    pub fn with_persistent_disable(self, value: bool) -> Self {
+        self.with_flag(Self::PERSISTENT_DISABLE, value)
+    }
+
+    pub fn with_x509_id(self, id: u8) -> Self {
+        Self(self.0 & !Self::X509_ID | ((id as u16) << 14 & Self::X509_ID))
+    }
+
+    fn with_flag(self, flag: u16, value: bool) -> Self {
+        if value {
+            Self(self.0 | flag)
+        } else {
+            Self(self.0 & !flag)
+        }
+    }
+}
+
+impl From<u16> for KeyConfig {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<KeyConfig> for u16 {
+    fn from(config: KeyConfig) -> Self {
+        config.0
+    }
+}
+
+// SlotConfig and KeyConfig are serialized field by field, so stored
+// configurations read like the datasheet tables instead of raw words.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SlotConfigFields {
+    read_key: u8,
+    no_mac: bool,
+    limited_use: bool,
+    encrypt_read: bool,
+    is_secret: bool,
+    write_key: u8,
+    write_config: u8,
+}
+
+#[cfg(feature = "serde")]
+impl From<SlotConfig> for SlotConfigFields {
+    fn from(config: SlotConfig) -> Self {
+        Self {
+            read_key: config.read_key(),
+            no_mac: config.no_mac(),
+            limited_use: config.limited_use(),
+            encrypt_read: config.encrypt_read(),
+            is_secret: config.is_secret(),
+            write_key: config.write_key(),
+            write_config: config.write_config(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SlotConfigFields> for SlotConfig {
+    fn from(fields: SlotConfigFields) -> Self {
+        Self::default()
+            .with_read_key(fields.read_key)
+            .with_no_mac(fields.no_mac)
+            .with_limited_use(fields.limited_use)
+            .with_encrypt_read(fields.encrypt_read)
+            .with_is_secret(fields.is_secret)
+            .with_write_key(fields.write_key)
+            .with_write_config(fields.write_config)
+    }
+}
+
+// The key type is kept as a number, since reserved values have no KeyType.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct KeyConfigFields {
+    private: bool,
+    pub_info: bool,
+    key_type: u8,
+    lockable: bool,
+    req_random: bool,
+    req_auth: bool,
+    auth_key: u8,
+    persistent_disable: bool,
+    x509_id: u8,
+    // Bit 13, which the datasheet requires to be zero.
+    #[serde(default, skip_serializing_if = "is_false")]
+    reserved: bool,
+}
+
+#[cfg(feature = "serde")]
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(feature = "serde")]
+impl From<KeyConfig> for KeyConfigFields {
+    fn from(config: KeyConfig) -> Self {
+        Self {
+            private: config.private(),
+            pub_info: config.pub_info(),
+            key_type: ((config.0 & KeyConfig::KEY_TYPE) >> 2) as u8,
+            lockable: config.lockable(),
+            req_random: config.req_random(),
+            req_auth: config.req_auth(),
+            auth_key: config.auth_key(),
+            persistent_disable: config.persistent_disable(),
+            x509_id: config.x509_id(),
+            reserved: config.0 & KeyConfig::RESERVED != 0x00,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<KeyConfigFields> for KeyConfig {
+    fn from(fields: KeyConfigFields) -> Self {
+        let key_type = (fields.key_type as u16) << 2 & Self::KEY_TYPE;
+        Self(key_type)
+            .with_flag(Self::RESERVED, fields.reserved)
+            .with_private(fields.private)
+            .with_pub_info(fields.pub_info)
+            .with_lockable(fields.lockable)
+            .with_req_random(fields.req_random)
+            .with_req_auth(fields.req_auth)
+            .with_auth_key(fields.auth_key)
+            .with_persistent_disable(fields.persistent_disable)
+            .with_x509_id(fields.x509_id)
+    }
+}
+
+/// What the configuration of a slot allows, as derived from its SlotConfig
+/// and KeyConfig.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SlotCaps {
+    /// Sign arbitrary digests with the private key.
+    pub sign_external: bool,
+    /// Sign messages built by the device after GenDig or GenKey.
+    pub sign_internal: bool,
+    pub ecdh: bool,
+    /// Holds keys for the AES command. Requires AES to be enabled.
+    pub aes: bool,
+    pub clear_read: bool,
+    pub encrypted_read: bool,
+    pub encrypted_write: bool,
+    pub lockable: bool,
+}
+
+/// Contents of the configuration zone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConfigZone([u8; CONFIG_ZONE_SIZE]);
+
+impl ConfigZone {
+    pub fn serial_number(&self) -> Serial {
+        // Serial is parsed from the first block, which interleaves the two
+        // halves of the serial number with the revision.
+        Serial::try_from(&self.0[..0x20]).unwrap_or_else(|_| unreachable!())
+    }
+
+    pub fn revision(&self) -> [u8; 4] {
+        self.0[REVISION..REVISION + 4]
+            .try_into()
+            .unwrap_or_else(|_| unreachable!())
+    }
+
+    pub fn aes_enabled(&self) -> bool {
+        self.0[AES_ENABLE] & 0x01 != 0x00
+    }
+
+    pub fn i2c_enabled(&self) -> bool {
+        self.0[I2C_ENABLE] & 0x01 != 0x00
+    }
+
+    /// I2C address byte, which holds the 7-bit address shifted left by one.
+    pub fn i2c_address(&self) -> u8 {
+        self.0[I2C_ADDRESS]
+    }
+
+    pub fn count_match(&self) -> u8 {
+        self.0[COUNT_MATCH]
+    }
+
+    /// Slot holding the count-match value, if CountMatch is enabled.
+    pub fn count_match_key(&self) -> Option<Slot> {
+        count_match_key(self.count_match())
+    }
+
+    /// Enable CountMatch with the value stored in `slot`, or disable it.
+    pub fn set_count_match(&mut self, slot: Option<Slot>) -> &mut Self {
+        self.0[COUNT_MATCH] = count_match_byte(slot);
+        self
+    }
+
+    pub fn chip_mode(&self) -> u8 {
+        self.0[CHIP_MODE]
+    }
+
+    pub fn slot_config(&self, slot: Slot) -> SlotConfig {
+        self.u16_at(SLOT_CONFIG + slot as usize * 2).into()
+    }
+
+    pub fn set_slot_config(&mut self, slot: Slot, config: SlotConfig) -> &mut Self {
+        self.set_u16_at(SLOT_CONFIG + slot as usize * 2, config.into())
+    }
+
+    pub fn key_config(&self, slot: Slot) -> KeyConfig {
+        self.u16_at(KEY_CONFIG + slot as usize * 2).into()
+    }
+
+    pub fn set_key_config(&mut self, slot: Slot, config: KeyConfig) -> &mut Self {
+        self.set_u16_at(KEY_CONFIG + slot as usize * 2, config.into())
+    }
+
+    pub fn slot_capabilities(&self, slot: Slot) -> SlotCaps {
+        let slot_config = self.slot_config(slot);
+        let key_config = self.key_config(slot);
+        let key_type = key_config.key_type();
+        let private_key = key_config.private() && key_type == Some(KeyType::P256);
+        let permits = |bit| private_key && slot_config.read_key() & bit != 0x00;
+        SlotCaps {
+            sign_external: permits(SIGN_EXTERNAL),
+            sign_internal: permits(SIGN_INTERNAL),
+            ecdh: permits(ECDH_ALLOWED),
+            aes: self.aes_enabled() && key_type == Some(KeyType::Aes),
+            clear_read: !key_config.private() && !slot_config.is_secret(),
+            encrypted_read: !key_config.private()
+                && slot_config.is_secret()
+                && slot_config.encrypt_read(),
+            encrypted_write: slot_config.write_config() & WRITE_MODE == WRITE_ENCRYPT,
+            lockable: key_config.lockable(),
+        }
+    }
+
+    pub fn user_extra(&self) -> u8 {
+        self.0[USER_EXTRA]
+    }
+
+    pub fn user_extra_add(&self) -> u8 {
+        self.0[USER_EXTRA_ADD]
+    }
+
+    pub fn is_data_locked(&self) -> bool {
+        self.0[LOCK_VALUE] != UNLOCKED
+    }
+
+    pub fn is_config_locked(&self) -> bool {
+        self.0[LOCK_CONFIG] != UNLOCKED
+    }
+
+    pub fn is_slot_locked(&self, slot: Slot) -> bool {
+        self.u16_at(SLOT_LOCKED) & (0x01 << slot as u32) == 0x00
+    }
+
+    pub fn chip_options(&self) -> u16 {
+        self.u16_at(CHIP_OPTIONS)
+    }
+
+    /// Set the I2C address byte from a 7-bit address.
+    pub fn set_i2c_address(&mut self, address: u8) -> &mut Self {
+        self.0[I2C_ADDRESS] = address << 1;
+        self
+    }
+
+    pub fn set_chip_options(&mut self, options: u16) -> &mut Self {
+        self.set_u16_at(CHIP_OPTIONS, options)
+    }
+
+    /// Slot of the I/O protection key, if I/O protection is enabled.
+    pub fn io_protection_key(&self) -> Option<Slot> {
+        let options = self.chip_options();
+        if options & IO_PROTECTION_ENABLE == 0x00 {
+            return None;
+        }
+        Slot::keys().nth((options >> IO_PROTECTION_KEY_SHIFT) as usize)
+    }
+
+    /// Enable I/O protection with the key in `key_id` and set how ECDH and
+    /// KDF output is to be protected.
+    pub fn set_io_protection(
+        &mut self,
+        key_id: Slot,
+        ecdh: OutputProtection,
+        kdf: OutputProtection,
+    ) -> &mut Self {
+        let options = self.chip_options() & 0x00ff
+            | IO_PROTECTION_ENABLE
+            | (ecdh as u16) << ECDH_PROTECTION_SHIFT
+            | (kdf as u16) << KDF_PROTECTION_SHIFT
+            | (key_id as u16) << IO_PROTECTION_KEY_SHIFT;
+        self.set_chip_options(options)
+    }
+
+    /// List the fields of `other` that differ from this zone. Only fields
+    /// the Write command can change are compared, so a template matches any
+    /// device regardless of serial number, revision or lock state.
+    pub fn diff(&self, other: &Self) -> Vec<ConfigDiff, CONFIG_FIELDS_MAX> {
+        let per_slot = Slot::keys().flat_map(|slot| {
+            let slot_config = SLOT_CONFIG + slot as usize * 2;
+            let key_config = KEY_CONFIG + slot as usize * 2;
+            [
+                (ConfigField::SlotConfig(slot), slot_config..slot_config + 2),
+                (ConfigField::KeyConfig(slot), key_config..key_config + 2),
+            ]
+        });
+        FIELDS
+            .iter()
+            .cloned()
+            .chain(per_slot)
+            .filter(|(field, _)| field.is_writable())
+            .filter(|(_, range)| self.0[range.clone()] != other.0[range.clone()])
+            .map(|(field, range)| ConfigDiff { field, range })
+            .collect()
+    }
+
+    pub fn as_bytes(&self) -> &[u8; CONFIG_ZONE_SIZE] {
+        &self.0
+    }
+
+    fn u16_at(&self, index: usize) -> u16 {
+        u16::from_le_bytes([self.0[index], self.0[index + 1]])
+    }
+
+    fn set_u16_at(&mut self, index: usize, value: u16) -> &mut Self {
+        self.0[index..index + 2].copy_from_slice(&value.to_le_bytes());
+        self
+    }
+}
+
+pub(crate) fn count_match_key(count_match: u8) -> Option<Slot> {
+    if count_match & COUNT_MATCH_ENABLE == 0x00 {
+        return None;
+    }
+    Slot::keys().nth((count_match >> COUNT_MATCH_KEY_SHIFT) as usize)
+}
+
+pub(crate) fn count_match_byte(slot: Option<Slot>) -> u8 {
+    slot.map_or(0x00, |slot| {
+        (slot as u8) << COUNT_MATCH_KEY_SHIFT | COUNT_MATCH_ENABLE
+    })
+}
+
+impl From<[u8; CONFIG_ZONE_SIZE]> for ConfigZone {
+    fn from(bytes: [u8; CONFIG_ZONE_SIZE]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for ConfigZone {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        buffer
+            .try_into()
+            .map(Self)
+            .map_err(|_| ErrorKind::InvalidSize.into())
+    }
+}
+
+impl AsRef<[u8]> for ConfigZone {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+// Serde derives stop at arrays of 32 elements, so the zone is written as a
+// byte string by hand. Formats without one, such as JSON, get a sequence.
+#[cfg(feature = "serde")]
+impl serde::Serialize for ConfigZone {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ConfigZone {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ZoneVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for ZoneVisitor {
+            type Value = ConfigZone;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                write!(f, "{} bytes", CONFIG_ZONE_SIZE)
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Self::Value, E> {
+                ConfigZone::try_from(bytes).map_err(|_| E::invalid_length(bytes.len(), &self))
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut bytes = [0x00; CONFIG_ZONE_SIZE];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(serde::de::Error::invalid_length(
+                        CONFIG_ZONE_SIZE + 1,
+                        &self,
+                    ));
+                }
+                Ok(ConfigZone(bytes))
+            }
+        }
+
+        deserializer.deserialize_bytes(ZoneVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tng_tls_config() -> ConfigZone {
+        let mut bytes = [0x00; CONFIG_ZONE_SIZE];
+        bytes[..4].copy_from_slice(&[0x01, 0x23, 0xaa, 0xbb]);
+        bytes[8..13].copy_from_slice(&[0xcc, 0xdd, 0xee, 0xff, 0x01]);
+        bytes[I2C_ADDRESS] = 0x6a;
+        bytes[LOCK_VALUE] = 0x00;
+        bytes[LOCK_CONFIG] = 0x00;
+        bytes[SLOT_LOCKED..SLOT_LOCKED + 2].copy_from_slice(&[0xfe, 0xff]);
+        // Slot 0: primary private key. Slot 6: I/O protection key.
+        bytes[SLOT_CONFIG..SLOT_CONFIG + 2].copy_from_slice(&[0x85, 0x00]);
+        bytes[SLOT_CONFIG + 12..SLOT_CONFIG + 14].copy_from_slice(&[0x8f, 0x0f]);
+        bytes[KEY_CONFIG..KEY_CONFIG + 2].copy_from_slice(&[0x53, 0x00]);
+        bytes[KEY_CONFIG + 18..KEY_CONFIG + 20].copy_from_slice(&[0x18, 0x00]);
+        ConfigZone::from(bytes)
+    }
+
+    #[test]
+    fn parse() {
+        let mut config = tng_tls_config();
+        assert_eq!(
+            config.serial_number().as_ref(),
+            [0x01, 0x23, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff, 0x01]
+        );
+        assert_eq!(config.i2c_address(), 0x6a);
+        config.set_i2c_address(0x60);
+        assert_eq!(config.i2c_address(), 0xc0);
+        config.set_io_protection(
+            Slot::PrivateKey06,
+            OutputProtection::Encrypted,
+            OutputProtection::Internal,
+        );
+        assert_eq!(config.chip_options() & 0xff02, 0x6902);
+        assert_eq!(config.io_protection_key(), Some(Slot::PrivateKey06));
+        assert!(config.is_config_locked());
+        assert!(config.is_slot_locked(Slot::PrivateKey00));
+        assert!(!config.is_slot_locked(Slot::PrivateKey01));
+
+        let slot0 = config.slot_config(Slot::PrivateKey00);
+        assert!(slot0.is_secret());
+        assert!(!slot0.encrypt_read());
+        assert_eq!(slot0.read_key(), 0x05);
+        assert_eq!(slot0.write_config(), 0x00);
+
+        let slot6 = config.slot_config(Slot::PrivateKey06);
+        assert_eq!(slot6.write_key(), 0x0f);
+
+        let key0 = config.key_config(Slot::PrivateKey00);
+        assert!(key0.private());
+        assert!(key0.pub_info());
+        assert_eq!(key0.key_type(), Some(KeyType::P256));
+        assert!(key0.req_random());
+        assert!(!key0.req_auth());
+        assert_eq!(
+            config.key_config(Slot::Certificate09).key_type(),
+            Some(KeyType::Aes)
+        );
+    }
+
+    #[test]
+    fn slot_capabilities() {
+        let mut config = tng_tls_config();
+        let key0 = config.slot_capabilities(Slot::PrivateKey00);
+        assert!(key0.sign_external && key0.ecdh);
+        assert!(!key0.sign_internal && !key0.clear_read && !key0.aes);
+
+        let aes = config.slot_capabilities(Slot::Certificate09);
+        assert!(!aes.aes && aes.clear_read);
+        config.0[AES_ENABLE] = 0x01;
+        assert!(config.slot_capabilities(Slot::Certificate09).aes);
+
+        config.set_slot_config(
+            Slot::Data08,
+            SlotConfig::default()
+                .with_is_secret(true)
+                .with_encrypt_read(true)
+                .with_write_config(0x04),
+        );
+        let data = config.slot_capabilities(Slot::Data08);
+        assert!(data.encrypted_read && data.encrypted_write);
+        assert!(!data.clear_read && !data.sign_external && !data.lockable);
+    }
+
+    #[test]
+    fn count_match() {
+        let mut config = tng_tls_config();
+        assert_eq!(config.count_match_key(), None);
+        config.set_count_match(Some(Slot::Data08));
+        assert_eq!(config.count_match(), 0x81);
+        assert_eq!(config.count_match_key(), Some(Slot::Data08));
+        config.set_count_match(None);
+        assert_eq!(config.count_match(), 0x00);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut config = tng_tls_config();
+        let slot = SlotConfig::default()
+            .with_is_secret(true)
+            .with_encrypt_read(true)
+            .with_read_key(0x06)
+            .with_write_config(0x04)
+            .with_write_key(0x06);
+        let key = KeyConfig::default()
+            .with_key_type(KeyType::ShaOrText)
+            .with_lockable(true);
+        config
+            .set_slot_config(Slot::Data08, slot)
+            .set_key_config(Slot::Data08, key);
+
+        let parsed = ConfigZone::try_from(config.as_ref()).unwrap();
+        assert_eq!(parsed, config);
+        assert_eq!(u16::from(parsed.slot_config(Slot::Data08)), 0x46c6);
+        assert_eq!(u16::from(parsed.key_config(Slot::Data08)), 0x003c);
+        assert!(ConfigZone::try_from([0x00; 64].as_ref()).is_err());
+    }
+
+    #[test]
+    fn field_range() {
+        assert_eq!(ConfigField::I2cAddress.range(), Some(16..17));
+        assert_eq!(ConfigField::Counter1.range(), Some(60..68));
+        assert_eq!(ConfigField::SlotConfig(Slot::Data08).range(), Some(36..38));
+        assert_eq!(ConfigField::KeyConfig(Slot::Data08).range(), Some(112..114));
+        assert_eq!(ConfigField::SerialNumber.range(), None);
+        assert!(ConfigField::ChipMode.is_writable());
+        assert!(!ConfigField::AesEnable.is_writable());
+        assert!(!ConfigField::LockConfig.is_writable());
+    }
+
+    #[test]
+    fn diff() {
+        let expected = tng_tls_config();
+        let mut actual = expected;
+        assert!(expected.diff(&actual).is_empty());
+
+        // Another device, already locked, still matches the template.
+        let mut bytes = *actual.as_bytes();
+        bytes[0..4].copy_from_slice(&[0x01, 0x23, 0x6b, 0x1f]);
+        bytes[4..8].copy_from_slice(&[0x00, 0x00, 0x60, 0x03]);
+        bytes[15] ^= 0xff;
+        bytes[84..88].copy_from_slice(&[0x5a, 0x00, 0x00, 0x00]);
+        actual = ConfigZone::from(bytes);
+        assert!(expected.diff(&actual).is_empty());
+
+        actual
+            .set_slot_config(Slot::PrivateKey06, SlotConfig::default())
+            .set_chip_options(0x0e60);
+        let diff = expected.diff(&actual);
+        assert_eq!(diff.len(), 2);
+        assert_eq!(diff[0].field, ConfigField::ChipOptions);
+        assert_eq!(diff[0].range, 90..92);
+        assert_eq!(diff[1].field, ConfigField::SlotConfig(Slot::PrivateKey06));
+        assert_eq!(diff[1].range, 32..34);
+    }
+}
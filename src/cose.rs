@@ -0,0 +1,142 @@
+// COSE_Sign1 messages (RFC 8152) signed with ES256 by a slot key. The
+// Sig_structure is streamed through the device SHA engine rather than
+// assembled in memory, and the raw r || s signature the device returns is
+// the COSE encoding of an ECDSA signature as is.
+use super::client::AtCaClient;
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind};
+use super::memory::Slot;
+use embedded_hal::delay::DelayNs;
+use heapless::Vec;
+
+pub const COSE_SIZE_MAX: usize = 1024;
+
+pub type CoseSign1 = Vec<u8, COSE_SIZE_MAX>;
+
+// CBOR major types.
+const BYTES: u8 = 0x40;
+const TEXT: u8 = 0x60;
+const ARRAY: u8 = 0x80;
+const MAP: u8 = 0xa0;
+
+// Tag 18, COSE_Sign1.
+const COSE_SIGN1_TAG: u8 = 0xd2;
+// Protected header {1: -7}, alg ES256.
+const PROTECTED: [u8; 3] = [0xa1, 0x01, 0x26];
+// Label of the kid header parameter.
+const KID: u8 = 0x04;
+const CONTEXT: &str = "Signature1";
+
+/// Sign `payload` with the private key in `key_id` and return the tagged
+/// COSE_Sign1 message. `external_aad` is covered by the signature without
+/// being carried in the message. A `kid` goes to the unprotected header.
+pub fn sign1<PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    key_id: Slot,
+    payload: &[u8],
+    external_aad: &[u8],
+    kid: Option<&[u8]>,
+) -> Result<CoseSign1, Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    let digest = {
+        let mut sha = atca.sha();
+        sha.init()?;
+        sig_structure(payload, external_aad, |bytes| sha.update(bytes))?;
+        sha.finalize()?
+    };
+    let signature = atca.sign(key_id, &digest)?;
+
+    let mut message = CoseSign1::new();
+    let mut write = |bytes: &[u8]| {
+        message
+            .extend_from_slice(bytes)
+            .map_err(|()| Error::from(ErrorKind::SmallBuffer))
+    };
+    write(&[COSE_SIGN1_TAG])?;
+    write(&head(ARRAY, 4))?;
+    byte_string(&PROTECTED, &mut write)?;
+    match kid {
+        Some(kid) => {
+            write(&head(MAP, 1))?;
+            write(&[KID])?;
+            byte_string(kid, &mut write)?;
+        }
+        None => write(&head(MAP, 0))?,
+    }
+    byte_string(payload, &mut write)?;
+    byte_string(signature.as_ref(), &mut write)?;
+    Ok(message)
+}
+
+// Sig_structure = ["Signature1", protected, external_aad, payload], fed to
+// `sink` piece by piece.
+fn sig_structure(
+    payload: &[u8],
+    external_aad: &[u8],
+    mut sink: impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    sink(&head(ARRAY, 4))?;
+    sink(&head(TEXT, CONTEXT.len()))?;
+    sink(CONTEXT.as_bytes())?;
+    byte_string(&PROTECTED, &mut sink)?;
+    byte_string(external_aad, &mut sink)?;
+    byte_string(payload, &mut sink)
+}
+
+fn byte_string(
+    bytes: &[u8],
+    sink: &mut impl FnMut(&[u8]) -> Result<(), Error>,
+) -> Result<(), Error> {
+    sink(&head(BYTES, bytes.len()))?;
+    sink(bytes)
+}
+
+// Initial byte of a data item and its length argument.
+fn head(major: u8, length: usize) -> Vec<u8, 9> {
+    let length = length as u64;
+    let mut head = Vec::new();
+    let (info, argument) = match length {
+        0..=23 => (length as u8, &[][..]),
+        0x18..=0xff => (24, &length.to_be_bytes()[7..]),
+        0x100..=0xffff => (25, &length.to_be_bytes()[6..]),
+        0x1_0000..=0xffff_ffff => (26, &length.to_be_bytes()[4..]),
+        _ => (27, &length.to_be_bytes()[..]),
+    };
+    head.push(major | info).ok();
+    head.extend_from_slice(argument).ok();
+    head
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head() {
+        assert_eq!(super::head(BYTES, 0), [0x40]);
+        assert_eq!(super::head(BYTES, 23), [0x57]);
+        assert_eq!(super::head(BYTES, 64), [0x58, 0x40]);
+        assert_eq!(super::head(TEXT, 0x1234), [0x79, 0x12, 0x34]);
+        assert_eq!(super::head(ARRAY, 0x10000), [0x9a, 0x00, 0x01, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn sig_structure() {
+        let mut encoded = Vec::<u8, 32>::new();
+        super::sig_structure(b"hi", &[], |bytes| {
+            encoded.extend_from_slice(bytes).ok();
+            Ok(())
+        })
+        .unwrap();
+        let mut expected = Vec::<u8, 32>::new();
+        expected.extend_from_slice(&[0x84, 0x6a]).unwrap();
+        expected.extend_from_slice(b"Signature1").unwrap();
+        expected
+            .extend_from_slice(&[0x43, 0xa1, 0x01, 0x26, 0x40, 0x42, b'h', b'i'])
+            .unwrap();
+        assert_eq!(encoded, expected);
+    }
+}
@@ -1,7 +1,15 @@
 // for this implementation of I2C with CryptoAuth chips, txdata is assumed to
 // have ATCAPacket format Devices such as ATECCx08A require a word address value
 // pre-pended to the packet txdata[0] is using _reserved byte of the ATCAPacket
-use super::error::{Error, ErrorKind};
+#[cfg(feature = "std-linux")]
+pub mod linux;
+pub mod remote;
+pub mod shared;
+pub mod swi;
+pub mod trace;
+
+use super::clock_divider::{ExecTime, WATCHDOG_MS};
+use super::error::{Error, ErrorKind, Phase};
 use super::packet::{Packet, Response};
 use core::fmt::Debug;
 use core::iter::from_fn;
@@ -15,12 +23,15 @@ const WAKE_SELFTEST_FAILED: &[u8] = &[0x04, 0x07, 0xC4, 0x40];
 const ADDRESS: u8 = 0xc0 >> 1;
 /// Default time in us that takes for ATECC608 device to wake up.
 const DELAY_US: u32 = 1500;
+/// Interval in us between polls while a command is executing.
+const POLL_INTERVAL_US: u32 = 500;
+/// How many times a response with a bad CRC is read again.
+const CRC_REREAD: usize = 2;
 
 // By default, wake up sequence is repeated up to 20 times until it succeeds.
 // Multiply by 2, otherwise you see RxFail on wake up. It happens when you try
 // to write a word to the config zone on Raspberry Pi's I2C. This behavior might
-// be specific to linux HAL. What's worse, GenKey command from Raspberry Pi
-// needs to retry 20 * 15 times until it succeeds.
+// be specific to linux HAL.
 #[cfg(target_os = "none")]
 const RETRY: usize = 20;
 #[cfg(not(target_os = "none"))]
@@ -37,37 +48,269 @@ pub(crate) enum Transaction {
     Reserved = 0xff,
 }
 
+/// Physical layer carrying frames to and from the device. Implemented for
+/// every `embedded_hal` I2C bus; implement it directly for other transports
+/// such as a USB bridge or a kernel driver. Frames are those of the I2C
+/// interface: each write starts with a word address (0x00 reset, 0x01 sleep,
+/// 0x02 idle, 0x03 command) and a read returns bytes of the response. The
+/// datalink drives timing, polling and retries on top.
+pub trait Interface {
+    /// Send the wake token. The device does not answer it, so there is no
+    /// result; the datalink reads the wake response afterwards.
+    fn wake(&mut self, address: u8);
+    /// Write `bytes` to the device. An error while a command executes is
+    /// taken as the device being busy.
+    fn send(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error>;
+    /// Read exactly `buffer.len()` bytes from the device.
+    fn receive(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error>;
+
+    /// Put the device in the idle state.
+    fn idle(&mut self, address: u8) -> Result<(), Error> {
+        self.send(address, &[Transaction::Idle as u8])
+    }
+
+    /// Put the device to sleep.
+    fn sleep(&mut self, address: u8) -> Result<(), Error> {
+        self.send(address, &[Transaction::Sleep as u8])
+    }
+}
+
+impl<T> Interface for T
+where
+    T: i2c::I2c,
+{
+    fn wake(&mut self, address: u8) {
+        // Send a single null byte to an absent address.
+        //
+        // Ignore errors as this will error if the device is not awake yet.
+        self.write(address, from_ref(&0x00)).ok();
+    }
+
+    fn send(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.write(address, bytes)
+            .map_err(|_| ErrorKind::TxFail.into())
+    }
+
+    fn receive(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.read(address, buffer)
+            .map_err(|_| ErrorKind::RxFail.into())
+    }
+}
+
+/// Power state of the device as far as the host can tell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PowerState {
+    /// Asleep. Volatile state such as TempKey is lost.
+    Sleep,
+    /// Idle. Volatile state is retained and the watchdog is stopped.
+    Idle,
+    /// Awake. The watchdog puts the device to sleep when it expires.
+    Awake,
+}
+
 pub(crate) struct I2c<PHY, D> {
     phy: PHY,
     delay: D,
+    address: u8,
+    state: PowerState,
+    // Time spent awake in us, counted from the delays issued by the driver.
+    awake_us: u32,
+    // Set when the device turned out to have fallen asleep on its own.
+    watchdog_expired: bool,
+    // Command sent by `start` whose response is yet to be received.
+    pending: Option<Pending>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Pending {
+    hold: bool,
+    exec_time: ExecTime,
 }
 
 impl<PHY, D> I2c<PHY, D> {
     pub(crate) fn new(phy: PHY, delay: D) -> Self {
-        Self { phy, delay }
+        Self {
+            phy,
+            delay,
+            address: ADDRESS,
+            state: PowerState::Sleep,
+            awake_us: 0,
+            watchdog_expired: false,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn address(&self) -> u8 {
+        self.address
+    }
+
+    pub(crate) fn set_address(&mut self, address: u8) {
+        self.address = address;
+    }
+
+    pub(crate) fn release(self) -> (PHY, D) {
+        (self.phy, self.delay)
+    }
+
+    pub(crate) fn power_state(&self) -> PowerState {
+        self.state
+    }
+
+    /// Time in ms the device has been awake since the last wake.
+    pub(crate) fn awake_ms(&self) -> u32 {
+        match self.state {
+            PowerState::Awake => self.awake_us / 1000,
+            _ => 0,
+        }
+    }
+
+    /// Whether the watchdog put the device to sleep since the last call.
+    pub(crate) fn take_watchdog_expired(&mut self) -> bool {
+        core::mem::take(&mut self.watchdog_expired)
     }
 }
 
 impl<PHY, D> I2c<PHY, D>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     /// Wakes up device, sends the packet, waits for command completion,
-    /// receives response, and puts the device into the idle state.
-    pub(crate) fn execute<'a>(
+    /// receives response, and puts the device into the idle state. A device
+    /// woken explicitly with `wake` is left awake. The response is left
+    /// unchecked at the start of `buffer` and its length returned.
+    pub(crate) fn transfer(
         &mut self,
-        buffer: &'a mut [u8],
+        buffer: &mut [u8],
         packet: Packet,
-        exec_time: Option<u32>,
-    ) -> Result<Response<'a>, Error> {
-        self.wake()?;
-        self.send(&packet.buffer(buffer))?;
-        // Wait for the device to finish its job.
-        self.delay.delay_us(exec_time.unwrap_or(1) * 1000);
-        let response_buffer = self.receive(buffer)?;
+        exec_time: ExecTime,
+    ) -> Result<usize, Error> {
+        let hold = self.state == PowerState::Awake;
+        let result = self
+            .wake_and_send(packet.buffer(buffer), hold, exec_time)
+            .and_then(|()| {
+                // Wait for the device to finish its job in the usual case,
+                // then keep polling until the worst case has passed.
+                self.delay_us(exec_time.typical * 1000);
+                self.poll(exec_time.max.saturating_sub(exec_time.typical) * 1000)
+                    .map_err(|e| e.in_phase(Phase::Poll))?;
+                self.receive_checked(buffer)
+                    .map_err(|e| e.in_phase(Phase::Receive))
+            });
+        if !hold {
+            // Do not leave the device awake after a failure either. Should
+            // idle fail, the device stays awake; the command has run all the
+            // same, so its response is kept.
+            self.idle().ok();
+        }
+        result
+    }
+
+    /// First half of `transfer`, returning once the packet is sent. The
+    /// response is collected with `try_receive`.
+    pub(crate) fn start(
+        &mut self,
+        buffer: &[u8],
+        packet: Packet,
+        exec_time: ExecTime,
+    ) -> Result<(), Error> {
+        let hold = self.state == PowerState::Awake;
+        match self.wake_and_send(packet.buffer(buffer), hold, exec_time) {
+            Ok(()) => {
+                self.pending.replace(Pending { hold, exec_time });
+                Ok(())
+            }
+            Err(e) => {
+                if !hold {
+                    self.idle().ok();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Check once on the command sent by `start`: `None` while the device is
+    /// still executing it, the length of the response otherwise. No time is
+    /// counted in between, so the command is taken to have run for its
+    /// maximum time as far as the watchdog is concerned.
+    pub(crate) fn try_receive(&mut self, buffer: &mut [u8]) -> Option<Result<usize, Error>> {
+        let pending = match self.pending {
+            Some(pending) => pending,
+            None => return Some(Err(ErrorKind::BadParam.into())),
+        };
+        let word_address = Transaction::Reset as u8;
+        if self
+            .phy
+            .send(self.address, from_ref(&word_address))
+            .is_err()
+        {
+            return None;
+        }
+        self.pending = None;
+        if self.state == PowerState::Awake {
+            self.awake_us = self.awake_us.saturating_add(pending.exec_time.max * 1000);
+        }
+        let result = self
+            .receive_checked(buffer)
+            .map_err(|e| e.in_phase(Phase::Receive));
+        if !pending.hold {
+            self.idle().ok();
+        }
+        Some(result)
+    }
+
+    pub(crate) fn abandon(&mut self) {
+        self.pending = None;
+    }
+
+    fn wake_and_send(
+        &mut self,
+        packet: &[u8],
+        hold: bool,
+        exec_time: ExecTime,
+    ) -> Result<(), Error> {
+        self.ensure_awake(exec_time.max)
+            .map_err(|e| e.in_phase(Phase::Wake))?;
+        match self.send(&packet) {
+            // The device no longer acknowledges, most likely because the
+            // watchdog expired while the host was busy elsewhere.
+            Err(_) if hold => {
+                self.state = PowerState::Sleep;
+                self.watchdog_expired = true;
+                self.wake().map_err(|e| e.in_phase(Phase::Wake))?;
+                self.send(&packet)
+            }
+            result => result,
+        }
+    }
+
+    // Wake the device unless it is already awake with enough of the watchdog
+    // window left to run a command taking up to `max_ms`. Going through idle
+    // restarts the watchdog without losing TempKey.
+    fn ensure_awake(&mut self, max_ms: u32) -> Result<(), Error> {
+        if self.state == PowerState::Awake {
+            let remaining_us = (WATCHDOG_MS * 1000).saturating_sub(self.awake_us);
+            if max_ms * 1000 < remaining_us {
+                return Ok(());
+            }
+            self.idle()?;
+        }
+        self.wake()
+    }
+
+    /// Restart the watchdog of an awake device. Going through idle keeps
+    /// TempKey.
+    pub(crate) fn restart_watchdog(&mut self) -> Result<(), Error> {
         self.idle()?;
-        Response::new(response_buffer)
+        self.wake()
+    }
+
+    pub(crate) fn delay_us(&mut self, us: u32) {
+        self.delay.delay_us(us);
+        if self.state == PowerState::Awake {
+            self.awake_us = self.awake_us.saturating_add(us);
+        }
     }
 
     fn send<T>(&mut self, bytes: &T) -> Result<(), Error>
@@ -75,23 +318,53 @@ where
         T: AsRef<[u8]>,
     {
         self.phy
-            .write(ADDRESS, bytes.as_ref())
-            .map_err(|_| ErrorKind::TxFail.into())
+            .send(self.address, bytes.as_ref())
+            .map_err(|e| e.in_phase(Phase::Send))
     }
 
-    /// Returns response buffer for later processing.
-    fn receive<'a>(&mut self, buffer: &'a mut [u8]) -> Result<&'a mut [u8], Error> {
+    /// Waits until the device acknowledges its address, which it does not
+    /// while a command is still executing. Gives up after `timeout_us`.
+    fn poll(&mut self, timeout_us: u32) -> Result<(), Error> {
         // Reset indicates the beginning of transaction.
         let word_address = Transaction::Reset as u8;
-        from_fn(|| self.phy.write(ADDRESS, from_ref(&word_address)).into())
-            .take(RETRY)
-            .find_map(Result::<_, _>::ok)
-            .ok_or_else(|| Error::from(ErrorKind::TxFail))?;
+        let mut elapsed = 0;
+        loop {
+            if self.phy.send(self.address, from_ref(&word_address)).is_ok() {
+                return Ok(());
+            }
+            if elapsed >= timeout_us {
+                return Err(ErrorKind::Timeout.into());
+            }
+            self.delay_us(POLL_INTERVAL_US);
+            elapsed += POLL_INTERVAL_US;
+        }
+    }
 
+    /// Receives the response and checks its CRC. The device keeps the
+    /// response in its I/O buffer, so a frame corrupted on the bus is read
+    /// again from the start instead of running the command once more.
+    fn receive_checked(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+        let mut length = self.receive(buffer)?.len();
+        for _ in 0..CRC_REREAD {
+            match Response::check_crc(&buffer[..length]) {
+                Err(e) if e.kind() == Some(ErrorKind::RxCrcError) => {
+                    // Reset rewinds the I/O buffer address counter.
+                    self.poll(0)?;
+                    length = self.receive(buffer)?.len();
+                }
+                _ => break,
+            }
+        }
+        Ok(length)
+    }
+
+    /// Returns response buffer for later processing.
+    fn receive<'a>(&mut self, buffer: &'a mut [u8]) -> Result<&'a mut [u8], Error> {
         let min_resp_size = 4;
-        self.phy
-            .read(ADDRESS, &mut buffer[0..2])
-            .map_err(|_| Error::from(ErrorKind::RxFail))?;
+        let header = buffer
+            .get_mut(0..2)
+            .ok_or_else(|| Error::from(ErrorKind::SmallBuffer))?;
+        self.phy.receive(self.address, header)?;
 
         let length_to_read = match buffer[0] {
             // A single byte has already read.
@@ -104,46 +377,316 @@ where
         };
 
         self.phy
-            .read(ADDRESS, buffer[2..length_to_read].as_mut())
+            .receive(self.address, buffer[2..length_to_read].as_mut())
             .map(move |()| buffer[..length_to_read].as_mut())
-            .map_err(|_| ErrorKind::RxFail.into())
     }
 
-    fn wake(&mut self) -> Result<(), Error> {
-        // Send a single null byte to an absent address.
-        //
-        // Ignore errors as this will error if the device is not awake yet.
-        self.phy.write(ADDRESS, from_ref(&0x00)).ok();
+    pub(crate) fn wake(&mut self) -> Result<(), Error> {
+        self.phy.wake(self.address);
 
         // Wait for the device to wake up.
         self.delay.delay_us(DELAY_US);
 
         let buffer = &mut [0x00, 0x00, 0x00, 0x00];
-        from_fn(|| self.phy.read(ADDRESS, buffer.as_mut()).into())
+        from_fn(|| self.phy.receive(self.address, buffer.as_mut()).into())
             .take(RETRY)
             .find_map(Result::<_, _>::ok)
             .ok_or_else(|| Error::from(ErrorKind::RxFail))?;
 
         match buffer.as_ref() {
-            WAKE_RESPONSE_EXPECTED => Ok(()),
+            WAKE_RESPONSE_EXPECTED => {
+                self.state = PowerState::Awake;
+                self.awake_us = 0;
+                Ok(())
+            }
             WAKE_SELFTEST_FAILED => Err(ErrorKind::WakeFailed.into()),
             _ => Err(ErrorKind::WakeFailed.into()),
         }
     }
 
-    fn idle(&mut self) -> Result<(), Error> {
-        let word_address = Transaction::Idle as u8;
-        self.phy
-            .write(ADDRESS, from_ref(&word_address))
-            .map_err(|_| ErrorKind::TxFail.into())
+    pub(crate) fn idle(&mut self) -> Result<(), Error> {
+        if self.state != PowerState::Awake {
+            return Ok(());
+        }
+        self.phy.idle(self.address)?;
+        self.state = PowerState::Idle;
+        Ok(())
     }
 
     pub(crate) fn sleep(&mut self) -> Result<(), Error> {
-        let word_address = Transaction::Sleep as u8;
+        match self.state {
+            PowerState::Sleep => return Ok(()),
+            // An idle device ignores everything but the wake token.
+            PowerState::Idle => self.wake()?,
+            PowerState::Awake => {}
+        }
         // Wait for the I2C bus to be ready.
         self.delay.delay_us(30);
-        self.phy
-            .write(ADDRESS, from_ref(&word_address))
-            .map_err(|_| ErrorKind::TxFail.into())
+        self.phy.sleep(self.address)?;
+        self.state = PowerState::Sleep;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::command::Info;
+    use crate::packet::{PacketBuilder, CRC16};
+    use core::cell::RefCell;
+    use i2c::Operation;
+    use shared::SharedI2c;
+
+    // Just enough of the device to exercise power state handling. Replies
+    // to every command with a success status after `busy` polls.
+    pub(crate) struct Device {
+        pub(crate) state: PowerState,
+        woken: bool,
+        busy: usize,
+        response: heapless::Vec<u8, 72>,
+        read: usize,
+        // Address the device answers at. Any if not set.
+        pub(crate) address: Option<u8>,
+        // Number of responses to corrupt on the bus.
+        pub(crate) corrupt: usize,
+        // Whether the idle transaction goes unacknowledged.
+        pub(crate) refuse_idle: bool,
+        pub(crate) commands: usize,
+        pub(crate) wakes: usize,
+    }
+
+    impl Device {
+        pub(crate) fn new(busy: usize) -> Self {
+            Self {
+                state: PowerState::Sleep,
+                woken: false,
+                busy,
+                response: heapless::Vec::new(),
+                read: 0,
+                address: None,
+                corrupt: 0,
+                refuse_idle: false,
+                commands: 0,
+                wakes: 0,
+            }
+            .respond(&[0x00])
+        }
+
+        // Reply to every command with `data`.
+        pub(crate) fn respond(mut self, data: &[u8]) -> Self {
+            self.response.clear();
+            self.response.push(data.len() as u8 + 3).ok();
+            self.response.extend_from_slice(data).ok();
+            let crc = CRC16.checksum(&self.response).to_le_bytes();
+            self.response.extend_from_slice(&crc).ok();
+            self
+        }
+
+        fn write(&mut self, bytes: &[u8]) -> Result<(), i2c::ErrorKind> {
+            let nack = i2c::ErrorKind::NoAcknowledge(i2c::NoAcknowledgeSource::Address);
+            if self.state != PowerState::Awake {
+                self.woken = bytes == [0x00];
+                return Err(nack);
+            }
+            match bytes[0] {
+                0x00 if self.busy > 0 => {
+                    self.busy -= 1;
+                    return Err(nack);
+                }
+                0x00 => self.read = 0,
+                0x01 => self.state = PowerState::Sleep,
+                0x02 if self.refuse_idle => return Err(nack),
+                0x02 => self.state = PowerState::Idle,
+                0x03 => {
+                    self.commands += 1;
+                    self.read = 0;
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn read(&mut self, bytes: &mut [u8]) -> Result<(), i2c::ErrorKind> {
+            if self.woken {
+                self.woken = false;
+                self.wakes += 1;
+                self.state = PowerState::Awake;
+                bytes.copy_from_slice(WAKE_RESPONSE_EXPECTED);
+                return Ok(());
+            }
+            let end = self.read + bytes.len();
+            bytes.copy_from_slice(&self.response[self.read..end]);
+            self.read = end;
+            if end == self.response.len() && self.corrupt > 0 {
+                self.corrupt -= 1;
+                bytes[bytes.len() - 1] ^= 0xff;
+            }
+            Ok(())
+        }
+    }
+
+    impl i2c::ErrorType for Device {
+        type Error = i2c::ErrorKind;
+    }
+
+    impl i2c::I2c for Device {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if self.address.is_some_and(|own| own != address) {
+                return Err(i2c::ErrorKind::NoAcknowledge(
+                    i2c::NoAcknowledgeSource::Address,
+                ));
+            }
+            operations.iter_mut().try_for_each(|op| match op {
+                Operation::Write(bytes) => self.write(bytes),
+                Operation::Read(bytes) => self.read(bytes),
+            })
+        }
+    }
+
+    pub(crate) struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn info(i2c: &mut I2c<Device, NoDelay>, exec_time: ExecTime) -> Result<(), Error> {
+        let buffer = &mut [0x00u8; 0xff];
+        let packet = Info::new(PacketBuilder::new(buffer.as_mut())).revision()?;
+        let length = i2c.transfer(buffer.as_mut(), packet, exec_time)?;
+        Response::new(&buffer[..length], packet.opcode()).map(drop)
+    }
+
+    #[test]
+    fn idle_after_command() {
+        let mut i2c = I2c::new(Device::new(3), NoDelay);
+        info(&mut i2c, ExecTime::new(1, 5)).unwrap();
+        assert_eq!(i2c.power_state(), PowerState::Idle);
+        assert_eq!(i2c.phy.state, PowerState::Idle);
+
+        i2c.sleep().unwrap();
+        assert_eq!(i2c.phy.state, PowerState::Sleep);
+        assert_eq!(i2c.phy.wakes, 2);
+    }
+
+    #[test]
+    fn held_awake() {
+        let mut i2c = I2c::new(Device::new(0), NoDelay);
+        i2c.wake().unwrap();
+        info(&mut i2c, ExecTime::new(1, 5)).unwrap();
+        info(&mut i2c, ExecTime::new(1, 5)).unwrap();
+        assert_eq!(i2c.power_state(), PowerState::Awake);
+        assert_eq!(i2c.phy.wakes, 1);
+
+        // Not enough of the watchdog window left. Cycle through idle.
+        info(&mut i2c, ExecTime::new(1, WATCHDOG_MS)).unwrap();
+        assert_eq!(i2c.phy.wakes, 2);
+        assert!(!i2c.take_watchdog_expired());
+
+        // The device fell asleep behind the host's back.
+        i2c.phy.state = PowerState::Sleep;
+        info(&mut i2c, ExecTime::new(1, 5)).unwrap();
+        assert_eq!(i2c.phy.commands, 4);
+        assert!(i2c.take_watchdog_expired());
+    }
+
+    #[test]
+    fn idle_refused() {
+        let mut i2c = I2c::new(Device::new(0), NoDelay);
+        i2c.phy.refuse_idle = true;
+        info(&mut i2c, ExecTime::new(1, 5)).unwrap();
+        assert_eq!(i2c.phy.commands, 1);
+        assert_eq!(i2c.power_state(), PowerState::Awake);
+    }
+
+    #[test]
+    fn crc_reread() {
+        let mut i2c = I2c::new(Device::new(0), NoDelay);
+        i2c.phy.corrupt = CRC_REREAD;
+        info(&mut i2c, ExecTime::new(1, 5)).unwrap();
+        assert_eq!(i2c.phy.commands, 1);
+
+        i2c.phy.corrupt = CRC_REREAD + 1;
+        let result = info(&mut i2c, ExecTime::new(1, 5));
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::RxCrcError));
+    }
+
+    // Another driver using the bus whenever the datalink waits.
+    struct OtherDriver<'a>(&'a RefCell<Device>);
+
+    impl<'a> DelayNs for OtherDriver<'a> {
+        fn delay_ns(&mut self, _ns: u32) {
+            assert!(self.0.try_borrow_mut().is_ok());
+        }
+    }
+
+    #[test]
+    fn shared_bus() {
+        let bus = RefCell::new(Device::new(3));
+        let mut i2c = I2c::new(SharedI2c::new(&bus), OtherDriver(&bus));
+        let buffer = &mut [0x00u8; 0xff];
+        let packet = Info::new(PacketBuilder::new(buffer.as_mut()))
+            .revision()
+            .unwrap();
+        i2c.transfer(buffer.as_mut(), packet, ExecTime::new(1, 5))
+            .unwrap();
+        assert_eq!(bus.borrow().commands, 1);
+    }
+
+    // A physical layer that is not an I2C bus, handing frames to the device
+    // directly.
+    struct Bridge {
+        device: Device,
+        frames: usize,
+    }
+
+    impl Interface for Bridge {
+        fn wake(&mut self, _address: u8) {
+            self.device.write(&[0x00]).ok();
+        }
+
+        fn send(&mut self, _address: u8, bytes: &[u8]) -> Result<(), Error> {
+            self.frames += 1;
+            self.device
+                .write(bytes)
+                .map_err(|_| ErrorKind::TxFail.into())
+        }
+
+        fn receive(&mut self, _address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+            self.device
+                .read(buffer)
+                .map_err(|_| ErrorKind::RxFail.into())
+        }
+    }
+
+    #[test]
+    fn custom_interface() {
+        let bridge = Bridge {
+            device: Device::new(1),
+            frames: 0,
+        };
+        let mut i2c = I2c::new(bridge, NoDelay);
+        let buffer = &mut [0x00u8; 0xff];
+        let packet = Info::new(PacketBuilder::new(buffer.as_mut()))
+            .revision()
+            .unwrap();
+        i2c.transfer(buffer.as_mut(), packet, ExecTime::new(1, 5))
+            .unwrap();
+        i2c.sleep().unwrap();
+        assert_eq!(i2c.phy.device.commands, 1);
+        assert_eq!(i2c.phy.device.state, PowerState::Sleep);
+        // Command, two polls, idle, sleep.
+        assert_eq!(i2c.phy.frames, 5);
+    }
+
+    #[test]
+    fn timeout() {
+        let mut i2c = I2c::new(Device::new(usize::MAX), NoDelay);
+        let error = info(&mut i2c, ExecTime::new(1, 5)).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::Timeout));
+        assert_eq!(error.phase(), Some(Phase::Poll));
     }
 }
@@ -0,0 +1,125 @@
+// Linux I2C adapters, such as `linux_embedded_hal::I2cdev`, cannot hold SDA
+// low long enough by writing to the device address: the kernel aborts the
+// transfer on the first NACK. Writing a null byte to the general call address
+// instead keeps the line low for the whole address phase at 100 kHz, which is
+// long enough to wake the device. The write is expected to be NACKed.
+//
+// `LinuxI2c` wraps such an adapter as the datalink's `Interface`, sending
+// that sequence for every wake. Frames are passed through to the device
+// address.
+use super::Interface;
+use crate::error::{Error, ErrorKind};
+use embedded_hal::i2c::{self, SevenBitAddress};
+
+const GENERAL_CALL: SevenBitAddress = 0x00;
+
+pub struct LinuxI2c<I> {
+    i2c: I,
+}
+
+impl<I> LinuxI2c<I>
+where
+    I: i2c::I2c,
+{
+    pub fn new(i2c: I) -> Self {
+        Self { i2c }
+    }
+
+    pub fn release(self) -> I {
+        self.i2c
+    }
+}
+
+impl<I> Interface for LinuxI2c<I>
+where
+    I: i2c::I2c,
+{
+    fn wake(&mut self, _address: u8) {
+        self.i2c.write(GENERAL_CALL, &[0x00]).ok();
+    }
+
+    fn send(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.i2c
+            .write(address, bytes)
+            .map_err(|_| ErrorKind::TxFail.into())
+    }
+
+    fn receive(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        self.i2c
+            .read(address, buffer)
+            .map_err(|_| ErrorKind::RxFail.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock_divider::ExecTime;
+    use crate::command::Info;
+    use crate::datalink::tests::{Device, NoDelay};
+    use crate::datalink::I2c;
+    use crate::packet::{PacketBuilder, Response};
+    use crate::PowerState;
+    use i2c::Operation;
+
+    // Writes seen on the bus, with their addresses.
+    struct Recorder {
+        device: Device,
+        writes: heapless::Vec<(u8, u8), 32>,
+    }
+
+    impl i2c::ErrorType for Recorder {
+        type Error = i2c::ErrorKind;
+    }
+
+    impl i2c::I2c for Recorder {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            for operation in operations.iter() {
+                if let Operation::Write(bytes) = operation {
+                    self.writes.push((address, bytes[0])).ok();
+                }
+            }
+            self.device.transaction(address, operations)
+        }
+    }
+
+    fn info(i2c: &mut I2c<LinuxI2c<Recorder>, NoDelay>) -> Result<(), Error> {
+        let buffer = &mut [0x00u8; 0xff];
+        let packet = Info::new(PacketBuilder::new(buffer.as_mut())).revision()?;
+        let length = i2c.transfer(buffer.as_mut(), packet, ExecTime::new(1, 5))?;
+        Response::new(&buffer[..length], packet.opcode()).map(drop)
+    }
+
+    #[test]
+    fn general_call_wake() {
+        let recorder = Recorder {
+            device: Device::new(0),
+            writes: heapless::Vec::new(),
+        };
+        let mut i2c = I2c::new(LinuxI2c::new(recorder), NoDelay);
+        info(&mut i2c).unwrap();
+        // The watchdog put the device to sleep behind the host's back.
+        i2c.wake().unwrap();
+        i2c.phy.i2c.device.state = PowerState::Sleep;
+        info(&mut i2c).unwrap();
+
+        let recorder = i2c.phy.release();
+        assert_eq!(recorder.device.wakes, 3);
+        let wakes = recorder
+            .writes
+            .iter()
+            .filter(|(address, _)| *address == 0x00);
+        assert_eq!(wakes.count(), 3);
+        // Everything else went to the device address.
+        assert!(recorder
+            .writes
+            .iter()
+            .filter(|(address, _)| *address != 0x00)
+            .all(|(address, _)| *address == 0x60));
+        assert_eq!(recorder.device.commands, 2);
+    }
+}
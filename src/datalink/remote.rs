@@ -0,0 +1,307 @@
+// Pass-through of the physical layer over a byte link such as a debug UART
+// or a USB CDC channel. `Remote` runs on the host and implements `Interface`
+// by sending every operation as a request frame. `Responder` runs in the
+// firmware of the target board and replays the requests on its own bus, so a
+// provisioning tool drives the device as if it were attached locally.
+//
+// Request: kind, address, length (u16 LE), payload, CRC16 (LE).
+// Reply: status, length (u16 LE), payload, CRC16 (LE).
+//
+// A send request carries the bytes to write, a receive request the number of
+// bytes to read (u16 LE). The CRC is the one of device packets and covers the
+// whole frame before it.
+use super::Interface;
+use crate::error::{Error, ErrorKind};
+use crate::packet::CRC16;
+use core::convert::TryFrom;
+use heapless::Vec;
+
+/// Largest payload of a frame: a word address followed by a packet.
+pub const PAYLOAD_SIZE_MAX: usize = 0x100;
+
+const HEAD_SIZE_MAX: usize = 4;
+const CRC_SIZE: usize = 2;
+
+type Frame = Vec<u8, { HEAD_SIZE_MAX + PAYLOAD_SIZE_MAX + CRC_SIZE }>;
+
+// Requests
+const REQUEST_WAKE: u8 = 0x01;
+const REQUEST_SEND: u8 = 0x02;
+const REQUEST_RECEIVE: u8 = 0x03;
+
+// Reply status
+const STATUS_OK: u8 = 0x00;
+const STATUS_FAILED: u8 = 0x01;
+
+/// Byte stream between the host and the target board. `read` fills the
+/// whole buffer or fails, timing out if the other side went away.
+pub trait Link {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error>;
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error>;
+}
+
+/// Host side. Pass it to `AtCaClient::new` like an I2C bus.
+pub struct Remote<L> {
+    link: L,
+}
+
+impl<L> Remote<L> {
+    pub fn new(link: L) -> Self {
+        Self { link }
+    }
+
+    pub fn release(self) -> L {
+        self.link
+    }
+}
+
+impl<L> Remote<L>
+where
+    L: Link,
+{
+    // Send a request, then return the payload of the reply if it succeeded.
+    fn request(&mut self, kind: u8, address: u8, payload: &[u8]) -> Result<Option<Frame>, Error> {
+        write_frame(&mut self.link, &[kind, address], payload)?;
+        let mut reply = read_frame(&mut self.link, 1)?;
+        check_crc(&reply)?;
+        if reply[0] != STATUS_OK {
+            return Ok(None);
+        }
+        reply.truncate(reply.len() - CRC_SIZE);
+        Ok(Some(
+            Vec::from_slice(&reply[3..]).unwrap_or_else(|()| unreachable!()),
+        ))
+    }
+}
+
+impl<L> Interface for Remote<L>
+where
+    L: Link,
+{
+    fn wake(&mut self, address: u8) {
+        self.request(REQUEST_WAKE, address, &[]).ok();
+    }
+
+    fn send(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        self.request(REQUEST_SEND, address, bytes)?
+            .map(drop)
+            .ok_or_else(|| ErrorKind::TxFail.into())
+    }
+
+    fn receive(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let count = u16::try_from(buffer.len()).map_err(|_| Error::from(ErrorKind::BadParam))?;
+        let payload = self
+            .request(REQUEST_RECEIVE, address, &count.to_le_bytes())?
+            .ok_or_else(|| Error::from(ErrorKind::RxFail))?;
+        if payload.len() != buffer.len() {
+            return Err(ErrorKind::CommFail.into());
+        }
+        buffer.copy_from_slice(&payload);
+        Ok(())
+    }
+}
+
+/// Target side, forwarding requests to the local bus.
+pub struct Responder<PHY> {
+    phy: PHY,
+}
+
+impl<PHY> Responder<PHY> {
+    pub fn new(phy: PHY) -> Self {
+        Self { phy }
+    }
+
+    pub fn release(self) -> PHY {
+        self.phy
+    }
+}
+
+impl<PHY> Responder<PHY>
+where
+    PHY: Interface,
+{
+    /// Serve a single request from `link`. Device errors are reported to the
+    /// host in the reply; only link errors and corrupted requests, which are
+    /// answered with a failure, are returned.
+    pub fn serve<L>(&mut self, link: &mut L) -> Result<(), Error>
+    where
+        L: Link,
+    {
+        let request = read_frame(link, 2)?;
+        if let Err(e) = check_crc(&request) {
+            write_frame(link, &[STATUS_FAILED], &[])?;
+            return Err(e);
+        }
+        let (kind, address) = (request[0], request[1]);
+        let payload = &request[HEAD_SIZE_MAX..request.len() - CRC_SIZE];
+
+        let mut data = [0x00u8; PAYLOAD_SIZE_MAX];
+        let result = match (kind, payload) {
+            (REQUEST_WAKE, _) => {
+                self.phy.wake(address);
+                Ok(0)
+            }
+            (REQUEST_SEND, bytes) => self.phy.send(address, bytes).map(|()| 0),
+            (REQUEST_RECEIVE, &[lsb, msb]) => {
+                let count = usize::from(u16::from_le_bytes([lsb, msb]));
+                data.get_mut(..count)
+                    .ok_or_else(|| ErrorKind::BadParam.into())
+                    .and_then(|buffer| self.phy.receive(address, buffer))
+                    .map(|()| count)
+            }
+            _ => Err(ErrorKind::BadParam.into()),
+        };
+        match result {
+            Ok(count) => write_frame(link, &[STATUS_OK], &data[..count]),
+            Err(_) => write_frame(link, &[STATUS_FAILED], &[]),
+        }
+    }
+}
+
+fn write_frame<L>(link: &mut L, head: &[u8], payload: &[u8]) -> Result<(), Error>
+where
+    L: Link,
+{
+    let length = u16::try_from(payload.len())
+        .ok()
+        .filter(|_| payload.len() <= PAYLOAD_SIZE_MAX)
+        .ok_or_else(|| Error::from(ErrorKind::InvalidSize))?;
+    let mut frame = Frame::new();
+    frame.extend_from_slice(head).ok();
+    frame.extend_from_slice(&length.to_le_bytes()).ok();
+    frame.extend_from_slice(payload).ok();
+    let crc = CRC16.checksum(&frame).to_le_bytes();
+    frame.extend_from_slice(&crc).ok();
+    link.write(&frame)
+}
+
+// Read a frame whose length field follows `head_size` bytes. The CRC is kept
+// at the end, unchecked.
+fn read_frame<L>(link: &mut L, head_size: usize) -> Result<Frame, Error>
+where
+    L: Link,
+{
+    let mut frame = Frame::new();
+    frame.resize(head_size + 2, 0x00).ok();
+    link.read(&mut frame)?;
+    let length = usize::from(u16::from_le_bytes([frame[head_size], frame[head_size + 1]]));
+    if length > PAYLOAD_SIZE_MAX {
+        return Err(ErrorKind::InvalidSize.into());
+    }
+    let start = frame.len();
+    frame.resize(start + length + CRC_SIZE, 0x00).ok();
+    link.read(&mut frame[start..])?;
+    Ok(frame)
+}
+
+fn check_crc(frame: &[u8]) -> Result<(), Error> {
+    let (body, crc) = frame.split_at(frame.len() - CRC_SIZE);
+    if CRC16.checksum(body).to_le_bytes() != crc {
+        return Err(ErrorKind::RxCrcError.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock_divider::ExecTime;
+    use crate::command::Info;
+    use crate::datalink::tests::{Device, NoDelay};
+    use crate::datalink::{I2c, PowerState};
+    use crate::packet::PacketBuilder;
+
+    type Queue = Vec<u8, 512>;
+
+    fn take(queue: &mut Queue, buffer: &mut [u8]) -> Result<(), Error> {
+        if queue.len() < buffer.len() {
+            return Err(ErrorKind::Timeout.into());
+        }
+        buffer.copy_from_slice(&queue[..buffer.len()]);
+        *queue = Vec::from_slice(&queue[buffer.len()..]).unwrap();
+        Ok(())
+    }
+
+    // Target end of the link.
+    struct Target<'a> {
+        requests: &'a mut Queue,
+        replies: &'a mut Queue,
+    }
+
+    impl<'a> Link for Target<'a> {
+        fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+            self.replies.extend_from_slice(bytes).unwrap();
+            Ok(())
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+            take(self.requests, buffer)
+        }
+    }
+
+    // Host end of the link, with the target serving each request as soon as
+    // it is written.
+    struct Loopback {
+        responder: Responder<Device>,
+        requests: Queue,
+        replies: Queue,
+    }
+
+    impl Link for Loopback {
+        fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+            self.requests.extend_from_slice(bytes).unwrap();
+            self.responder.serve(&mut Target {
+                requests: &mut self.requests,
+                replies: &mut self.replies,
+            })
+        }
+
+        fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+            take(&mut self.replies, buffer)
+        }
+    }
+
+    #[test]
+    fn pass_through() {
+        let link = Loopback {
+            responder: Responder::new(Device::new(2)),
+            requests: Queue::new(),
+            replies: Queue::new(),
+        };
+        let mut i2c = I2c::new(Remote::new(link), NoDelay);
+        let buffer = &mut [0x00u8; 0xff];
+        let packet = Info::new(PacketBuilder::new(buffer.as_mut()))
+            .revision()
+            .unwrap();
+        i2c.transfer(buffer.as_mut(), packet, ExecTime::new(1, 5))
+            .unwrap();
+        i2c.sleep().unwrap();
+
+        let link = i2c.phy.release();
+        assert!(link.requests.is_empty());
+        assert!(link.replies.is_empty());
+        let device = link.responder.release();
+        assert_eq!(device.commands, 1);
+        assert_eq!(device.state, PowerState::Sleep);
+    }
+
+    #[test]
+    fn corrupted_request() {
+        let mut requests = Queue::new();
+        let mut replies = Queue::new();
+        let mut target = Target {
+            requests: &mut requests,
+            replies: &mut replies,
+        };
+        write_frame(&mut target, &[REQUEST_SEND, 0x60], &[0x02]).unwrap();
+        let mut frame = Queue::from_slice(target.replies).unwrap();
+        frame[4] ^= 0xff;
+        target.replies.clear();
+        target.requests.extend_from_slice(&frame).unwrap();
+
+        let mut responder = Responder::new(Device::new(0));
+        let error = responder.serve(&mut target).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::RxCrcError));
+        assert_eq!(target.replies[..3], [STATUS_FAILED, 0x00, 0x00]);
+    }
+}
@@ -0,0 +1,36 @@
+// I2C bus shared with other drivers through a `RefCell`. The bus is borrowed
+// for one transaction at a time, so it is free while the device executes a
+// command. Works the same way as `RefCellDevice` from embedded-hal-bus, which
+// can be passed to `AtCaClient::new` directly as well.
+use core::cell::RefCell;
+use embedded_hal::i2c::{self, ErrorType, Operation, SevenBitAddress};
+
+pub struct SharedI2c<'a, I> {
+    bus: &'a RefCell<I>,
+}
+
+impl<'a, I> SharedI2c<'a, I> {
+    pub fn new(bus: &'a RefCell<I>) -> Self {
+        Self { bus }
+    }
+}
+
+impl<'a, I> ErrorType for SharedI2c<'a, I>
+where
+    I: i2c::I2c,
+{
+    type Error = I::Error;
+}
+
+impl<'a, I> i2c::I2c<SevenBitAddress> for SharedI2c<'a, I>
+where
+    I: i2c::I2c,
+{
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.bus.borrow_mut().transaction(address, operations)
+    }
+}
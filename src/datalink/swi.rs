@@ -0,0 +1,227 @@
+// Single-Wire Interface. Every bit on the wire is a token, which a UART at
+// 230400 baud sends and receives as one 7-bit frame: 0x7F for a one and 0x7D
+// for a zero, least significant bit first. Each transfer starts with a flag
+// byte telling the device what follows.
+//
+// `Swi` speaks SWI on one side and `embedded_hal::i2c::I2c` on the other, so
+// the client drives a device on either interface the same way.
+use core::fmt::Debug;
+use embedded_hal::i2c::{self, ErrorType, NoAcknowledgeSource, Operation, SevenBitAddress};
+use heapless::Vec;
+
+// Flags
+const FLAG_COMMAND: u8 = 0x77;
+const FLAG_TRANSMIT: u8 = 0x88;
+const FLAG_IDLE: u8 = 0xBB;
+const FLAG_SLEEP: u8 = 0xCC;
+
+// Tokens as seen by the UART.
+const TOKEN_ONE: u8 = 0x7F;
+const TOKEN_ZERO: u8 = 0x7D;
+
+// Word addresses written by the I2C datalink.
+const WORD_RESET: u8 = 0x00;
+const WORD_SLEEP: u8 = 0x01;
+const WORD_IDLE: u8 = 0x02;
+const WORD_COMMAND: u8 = 0x03;
+
+/// UART, or a bit-banged pin behaving like one, wired to the SDA pin of
+/// the device. Transmitted frames must not be echoed back by `read`.
+pub trait SwiUart {
+    type Error: Debug;
+
+    /// Switch to the slow rate used for the wake pulse, where a single 0x00
+    /// frame holds the line low for at least 60 us, or back to 230400 baud.
+    fn set_wake_baud(&mut self, wake: bool) -> Result<(), Self::Error>;
+
+    fn write(&mut self, frames: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read up to `frames.len()` frames. Returns how many arrived before the
+    /// line went quiet, 0 if the device did not answer at all.
+    fn read(&mut self, frames: &mut [u8]) -> Result<usize, Self::Error>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwiError<E> {
+    Uart(E),
+    /// The device did not answer the transmit flag, usually because it is
+    /// still busy.
+    NoResponse,
+    /// The response was cut short or does not fit in the buffer.
+    Malformed,
+}
+
+impl<E: Debug> i2c::Error for SwiError<E> {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            Self::Uart(_) => i2c::ErrorKind::Other,
+            Self::NoResponse => i2c::ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address),
+            Self::Malformed => i2c::ErrorKind::Bus,
+        }
+    }
+}
+
+/// SWI transport. The I2C address is ignored, as the bus has one device.
+pub struct Swi<P> {
+    uart: P,
+    awake: bool,
+    response: Vec<u8, 192>,
+    read: usize,
+}
+
+impl<P> Swi<P>
+where
+    P: SwiUart,
+{
+    pub fn new(uart: P) -> Self {
+        Self {
+            uart,
+            awake: false,
+            response: Vec::new(),
+            read: 0,
+        }
+    }
+
+    pub fn release(self) -> P {
+        self.uart
+    }
+
+    fn write_word(&mut self, bytes: &[u8]) -> Result<(), SwiError<P::Error>> {
+        match bytes {
+            [WORD_RESET] if !self.awake => self.wake(),
+            // The datalink writes Reset to poll. Over SWI, polling means
+            // asking for the response and seeing if one comes.
+            [WORD_RESET] => self.receive(),
+            [WORD_SLEEP] => self.flag(FLAG_SLEEP).map(|()| self.awake = false),
+            [WORD_IDLE] => self.flag(FLAG_IDLE).map(|()| self.awake = false),
+            [WORD_COMMAND, packet @ ..] => {
+                self.response.clear();
+                self.flag(FLAG_COMMAND)?;
+                packet.iter().try_for_each(|byte| self.send(*byte))
+            }
+            _ => Err(SwiError::Malformed),
+        }
+    }
+
+    fn read_response(&mut self, bytes: &mut [u8]) -> Result<(), SwiError<P::Error>> {
+        // Nothing was polled for, as after wake. Ask for it now.
+        if self.response.is_empty() {
+            self.receive()?;
+        }
+        let end = self.read + bytes.len();
+        let src = self
+            .response
+            .get(self.read..end)
+            .ok_or(SwiError::Malformed)?;
+        bytes.copy_from_slice(src);
+        self.read = end;
+        Ok(())
+    }
+
+    fn wake(&mut self) -> Result<(), SwiError<P::Error>> {
+        self.uart.set_wake_baud(true).map_err(SwiError::Uart)?;
+        let result = self.uart.write(&[0x00]).map_err(SwiError::Uart);
+        self.uart.set_wake_baud(false).map_err(SwiError::Uart)?;
+        self.response.clear();
+        self.awake = true;
+        result
+    }
+
+    fn flag(&mut self, flag: u8) -> Result<(), SwiError<P::Error>> {
+        self.send(flag)
+    }
+
+    fn send(&mut self, byte: u8) -> Result<(), SwiError<P::Error>> {
+        self.uart.write(&encode(byte)).map_err(SwiError::Uart)
+    }
+
+    // Send the transmit flag and buffer the whole response, count first.
+    fn receive(&mut self) -> Result<(), SwiError<P::Error>> {
+        self.response.clear();
+        self.read = 0;
+        self.flag(FLAG_TRANSMIT)?;
+        let count = self.receive_byte()?;
+        if count == 0 || self.response.capacity() < count as usize {
+            return Err(SwiError::Malformed);
+        }
+        self.response.push(count).ok();
+        for _ in 1..count {
+            let byte = self.receive_byte()?;
+            self.response.push(byte).ok();
+        }
+        Ok(())
+    }
+
+    fn receive_byte(&mut self) -> Result<u8, SwiError<P::Error>> {
+        let mut frames = [0x00; 8];
+        match self.uart.read(&mut frames).map_err(SwiError::Uart)? {
+            0 => Err(SwiError::NoResponse),
+            8 => Ok(decode(&frames)),
+            _ => Err(SwiError::Malformed),
+        }
+    }
+}
+
+// One frame per bit, least significant bit first.
+fn encode(byte: u8) -> [u8; 8] {
+    let mut frames = [TOKEN_ZERO; 8];
+    frames
+        .iter_mut()
+        .enumerate()
+        .filter(|(i, _)| byte & (1 << i) != 0)
+        .for_each(|(_, frame)| *frame = TOKEN_ONE);
+    frames
+}
+
+// Anything but a clean one token reads as zero.
+fn decode(frames: &[u8; 8]) -> u8 {
+    frames
+        .iter()
+        .enumerate()
+        .filter(|(_, frame)| **frame == TOKEN_ONE)
+        .fold(0x00, |byte, (i, _)| byte | (1 << i))
+}
+
+impl<P> ErrorType for Swi<P>
+where
+    P: SwiUart,
+{
+    type Error = SwiError<P::Error>;
+}
+
+impl<P> i2c::I2c<SevenBitAddress> for Swi<P>
+where
+    P: SwiUart,
+{
+    fn transaction(
+        &mut self,
+        _address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        operations
+            .iter_mut()
+            .try_for_each(|operation| match operation {
+                Operation::Write(bytes) => self.write_word(bytes),
+                Operation::Read(bytes) => self.read_response(bytes),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        assert_eq!(
+            encode(FLAG_COMMAND),
+            [
+                TOKEN_ONE, TOKEN_ONE, TOKEN_ONE, TOKEN_ZERO, TOKEN_ONE, TOKEN_ONE, TOKEN_ONE,
+                TOKEN_ZERO
+            ]
+        );
+        (0..=0xff).for_each(|byte| assert_eq!(decode(&encode(byte)), byte));
+        // A zero token may be sampled as 0x7E depending on the edge.
+        assert_eq!(decode(&[0x7E; 8]), 0x00);
+    }
+}
@@ -0,0 +1,234 @@
+// Wire traffic capture. `Recorder` wraps a physical layer and logs every
+// exchange with the device; `Replay` plays such a log back as the device,
+// so a trace attached to a bug report or kept as a regression test runs
+// without hardware.
+//
+// Each exchange is one entry: kind, address, result (0x00 success), length
+// (u16 LE), then the bytes. Those are the bytes written for a send, and the
+// bytes read for a successful receive, where the length is the one asked
+// for either way. A wake carries no bytes.
+use super::Interface;
+use crate::error::{Error, ErrorKind};
+use core::convert::TryFrom;
+
+const WAKE: u8 = 0x01;
+const SEND: u8 = 0x02;
+const RECEIVE: u8 = 0x03;
+
+const RESULT_OK: u8 = 0x00;
+const RESULT_FAILED: u8 = 0x01;
+
+const HEADER_SIZE: usize = 5;
+
+/// Destination of recorded entries.
+pub trait TraceLog {
+    /// Append bytes to the log. Fails when it is full.
+    fn append(&mut self, bytes: &[u8]) -> Result<(), Error>;
+}
+
+impl<const N: usize> TraceLog for heapless::Vec<u8, N> {
+    fn append(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(bytes)
+            .map_err(|()| ErrorKind::SmallBuffer.into())
+    }
+}
+
+pub struct Recorder<PHY, L> {
+    phy: PHY,
+    log: L,
+    complete: bool,
+}
+
+impl<PHY, L> Recorder<PHY, L> {
+    pub fn new(phy: PHY, log: L) -> Self {
+        Self {
+            phy,
+            log,
+            complete: true,
+        }
+    }
+
+    // False once an entry did not fit in the log. Entries after it are
+    // dropped, so the log stays replayable up to that point.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    pub fn release(self) -> (PHY, L) {
+        (self.phy, self.log)
+    }
+}
+
+impl<PHY, L> Recorder<PHY, L>
+where
+    L: TraceLog,
+{
+    fn record(&mut self, kind: u8, address: u8, ok: bool, length: usize, bytes: &[u8]) {
+        let result = if ok { RESULT_OK } else { RESULT_FAILED };
+        let length = u16::try_from(length).unwrap_or(u16::MAX).to_le_bytes();
+        let header = [kind, address, result, length[0], length[1]];
+        if self.complete {
+            self.complete = self.log.append(&header).is_ok() && self.log.append(bytes).is_ok();
+        }
+    }
+}
+
+impl<PHY, L> Interface for Recorder<PHY, L>
+where
+    PHY: Interface,
+    L: TraceLog,
+{
+    fn wake(&mut self, address: u8) {
+        self.phy.wake(address);
+        self.record(WAKE, address, true, 0, &[]);
+    }
+
+    fn send(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        let result = self.phy.send(address, bytes);
+        self.record(SEND, address, result.is_ok(), bytes.len(), bytes);
+        result
+    }
+
+    fn receive(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let result = self.phy.receive(address, buffer);
+        let bytes = if result.is_ok() { &buffer[..] } else { &[] };
+        self.record(RECEIVE, address, result.is_ok(), buffer.len(), bytes);
+        result
+    }
+}
+
+/// Device played back from a log. Every exchange must match the next entry;
+/// the first one that does not fails with `CommFail`, as does running past
+/// the end of the log.
+pub struct Replay<'a> {
+    log: &'a [u8],
+    position: usize,
+    divergence: Option<usize>,
+}
+
+impl<'a> Replay<'a> {
+    pub fn new(log: &'a [u8]) -> Self {
+        Self {
+            log,
+            position: 0,
+            divergence: None,
+        }
+    }
+
+    // Offset in the log of the entry the traffic departed from.
+    pub fn divergence(&self) -> Option<usize> {
+        self.divergence
+    }
+
+    // Whether every entry was played.
+    pub fn is_finished(&self) -> bool {
+        self.position == self.log.len()
+    }
+
+    // Take the next entry if it is of `kind` for `address`, returning its
+    // result, length and bytes.
+    fn next(&mut self, kind: u8, address: u8) -> Result<(bool, usize, &'a [u8]), Error> {
+        let log = self.log;
+        let entry = log.get(self.position..).unwrap_or_default();
+        let parsed = match entry {
+            [k, a, result, lsb, msb, rest @ ..] if *k == kind && *a == address => {
+                let length = usize::from(u16::from_le_bytes([*lsb, *msb]));
+                let ok = *result == RESULT_OK;
+                let size = match kind {
+                    RECEIVE if !ok => 0,
+                    _ => length,
+                };
+                rest.get(..size).map(|bytes| (ok, length, bytes))
+            }
+            _ => None,
+        };
+        match parsed {
+            Some((ok, length, bytes)) => {
+                self.position += HEADER_SIZE + bytes.len();
+                Ok((ok, length, bytes))
+            }
+            None => Err(self.diverge()),
+        }
+    }
+
+    fn diverge(&mut self) -> Error {
+        self.divergence.get_or_insert(self.position);
+        ErrorKind::CommFail.into()
+    }
+}
+
+impl<'a> Interface for Replay<'a> {
+    fn wake(&mut self, address: u8) {
+        self.next(WAKE, address).ok();
+    }
+
+    fn send(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        let position = self.position;
+        match self.next(SEND, address)? {
+            (_, _, recorded) if recorded != bytes => {
+                self.position = position;
+                Err(self.diverge())
+            }
+            (true, _, _) => Ok(()),
+            (false, _, _) => Err(ErrorKind::TxFail.into()),
+        }
+    }
+
+    fn receive(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let position = self.position;
+        match self.next(RECEIVE, address)? {
+            (_, length, _) if length != buffer.len() => {
+                self.position = position;
+                Err(self.diverge())
+            }
+            (true, _, recorded) => {
+                buffer.copy_from_slice(recorded);
+                Ok(())
+            }
+            (false, _, _) => Err(ErrorKind::RxFail.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::AtCaClient;
+    use crate::datalink::tests::{Device, NoDelay};
+
+    type Log = heapless::Vec<u8, 1024>;
+
+    #[test]
+    fn record_and_replay() {
+        let response = [0x00, 0x00, 0x60, 0x03];
+        let mut device = Device::new(2).respond(&response);
+        let recorder = Recorder::new(&mut device, Log::new());
+        let mut atca = AtCaClient::new(recorder, NoDelay);
+        let revision = atca.info().unwrap();
+        atca.sleep().unwrap();
+        let (recorder, _) = atca.release();
+        let (_, log) = recorder.release();
+        assert_eq!(revision.as_ref(), response);
+
+        let mut atca = AtCaClient::new(Replay::new(&log), NoDelay);
+        assert_eq!(atca.info().unwrap().as_ref(), response);
+        atca.sleep().unwrap();
+        let (replay, _) = atca.release();
+        assert!(replay.is_finished());
+        assert_eq!(replay.divergence(), None);
+
+        // Different traffic departs from the trace at the command.
+        let mut atca = AtCaClient::new(Replay::new(&log), NoDelay);
+        assert!(atca.random().is_err());
+        assert!(atca.release().0.divergence().is_some());
+    }
+
+    #[test]
+    fn full_log() {
+        let mut device = Device::new(0).respond(&[0x00, 0x00, 0x60, 0x03]);
+        let recorder = Recorder::new(&mut device, heapless::Vec::<u8, 16>::new());
+        let mut atca = AtCaClient::new(recorder, NoDelay);
+        atca.info().unwrap();
+        assert!(!atca.release().0.is_complete());
+    }
+}
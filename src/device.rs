@@ -0,0 +1,292 @@
+// Device family detection from the Info revision word, and the command set
+// each family implements.
+use super::clock_divider::{ClockDivider, ExecTime};
+use super::command::OpCode;
+use super::error::{Error, ErrorKind};
+use super::memory::Slot;
+use core::convert::{TryFrom, TryInto};
+
+// Devices before ATECC608 have no clock divider. Typical and maximum
+// execution times in ms are taken from the datasheets; typical times below
+// a millisecond are rounded up. Maximum times follow cryptoauthlib where it
+// allows more than the datasheet.
+const ATSHA204A_EXEC_TIME: [(OpCode, ExecTime); 14] = [
+    (OpCode::CheckMac, ExecTime::new(12, 38)),
+    (OpCode::DeriveKey, ExecTime::new(14, 62)),
+    (OpCode::GenDig, ExecTime::new(11, 43)),
+    (OpCode::HMac, ExecTime::new(27, 69)),
+    (OpCode::Info, ExecTime::new(1, 2)),
+    (OpCode::Lock, ExecTime::new(5, 24)),
+    (OpCode::Mac, ExecTime::new(12, 35)),
+    (OpCode::Nonce, ExecTime::new(22, 60)),
+    (OpCode::Pause, ExecTime::new(1, 2)),
+    (OpCode::Random, ExecTime::new(11, 50)),
+    (OpCode::Read, ExecTime::new(1, 5)),
+    (OpCode::Sha, ExecTime::new(11, 22)),
+    (OpCode::UpdateExtra, ExecTime::new(8, 12)),
+    (OpCode::Write, ExecTime::new(4, 42)),
+];
+
+const ATECC108A_EXEC_TIME: [(OpCode, ExecTime); 19] = [
+    (OpCode::CheckMac, ExecTime::new(5, 13)),
+    (OpCode::Counter, ExecTime::new(5, 20)),
+    (OpCode::DeriveKey, ExecTime::new(2, 50)),
+    (OpCode::GenDig, ExecTime::new(5, 11)),
+    (OpCode::GenKey, ExecTime::new(11, 115)),
+    (OpCode::HMac, ExecTime::new(13, 23)),
+    (OpCode::Info, ExecTime::new(1, 2)),
+    (OpCode::Lock, ExecTime::new(8, 32)),
+    (OpCode::Mac, ExecTime::new(5, 14)),
+    (OpCode::Nonce, ExecTime::new(1, 7)),
+    (OpCode::Pause, ExecTime::new(1, 3)),
+    (OpCode::PrivWrite, ExecTime::new(1, 48)),
+    (OpCode::Random, ExecTime::new(1, 21)),
+    (OpCode::Read, ExecTime::new(1, 1)),
+    (OpCode::Sha, ExecTime::new(7, 9)),
+    (OpCode::Sign, ExecTime::new(50, 60)),
+    (OpCode::UpdateExtra, ExecTime::new(8, 10)),
+    (OpCode::Verify, ExecTime::new(58, 72)),
+    (OpCode::Write, ExecTime::new(7, 26)),
+];
+
+const ATECC508A_EXEC_TIME: [(OpCode, ExecTime); 20] = [
+    (OpCode::CheckMac, ExecTime::new(5, 13)),
+    (OpCode::Counter, ExecTime::new(5, 20)),
+    (OpCode::DeriveKey, ExecTime::new(2, 50)),
+    (OpCode::Ecdh, ExecTime::new(38, 58)),
+    (OpCode::GenDig, ExecTime::new(5, 11)),
+    (OpCode::GenKey, ExecTime::new(11, 115)),
+    (OpCode::HMac, ExecTime::new(13, 23)),
+    (OpCode::Info, ExecTime::new(1, 2)),
+    (OpCode::Lock, ExecTime::new(8, 32)),
+    (OpCode::Mac, ExecTime::new(5, 14)),
+    (OpCode::Nonce, ExecTime::new(1, 29)),
+    (OpCode::Pause, ExecTime::new(1, 3)),
+    (OpCode::PrivWrite, ExecTime::new(1, 48)),
+    (OpCode::Random, ExecTime::new(1, 23)),
+    (OpCode::Read, ExecTime::new(1, 5)),
+    (OpCode::Sha, ExecTime::new(7, 80)),
+    (OpCode::Sign, ExecTime::new(42, 60)),
+    (OpCode::UpdateExtra, ExecTime::new(8, 10)),
+    (OpCode::Verify, ExecTime::new(38, 72)),
+    (OpCode::Write, ExecTime::new(7, 26)),
+];
+
+/// Member of the CryptoAuthentication family, as reported by Info.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DeviceType {
+    Atsha204a,
+    Atecc108a,
+    Atecc508a,
+    Atecc608a,
+    Atecc608b,
+    /// Revision word not recognised. Every command is let through.
+    Unknown([u8; 4]),
+}
+
+impl DeviceType {
+    /// Whether the device implements the command.
+    pub(crate) fn supports(&self, opcode: &OpCode) -> bool {
+        use OpCode::*;
+        match self {
+            Self::Atecc608a | Self::Atecc608b => !matches!(opcode, HMac),
+            Self::Atecc508a => !matches!(opcode, Aes | Kdf | SecureBoot | SelfTest),
+            Self::Atecc108a => !matches!(opcode, Aes | Kdf | SecureBoot | SelfTest | Ecdh),
+            Self::Atsha204a => !matches!(
+                opcode,
+                Aes | Kdf
+                    | SecureBoot
+                    | SelfTest
+                    | Ecdh
+                    | GenKey
+                    | Sign
+                    | Verify
+                    | PrivWrite
+                    | Counter
+            ),
+            Self::Unknown(_) => true,
+        }
+    }
+
+    /// Whether the Sha command can read out and restore its context, modes
+    /// 0x06 and 0x07. Only the ATECC608 implements them.
+    pub(crate) fn has_sha_context(&self) -> bool {
+        matches!(self, Self::Atecc608a | Self::Atecc608b | Self::Unknown(_))
+    }
+
+    /// Size of the configuration zone in bytes.
+    pub fn config_zone_size(&self) -> usize {
+        match self {
+            Self::Atsha204a => 88,
+            _ => 128,
+        }
+    }
+
+    /// Size of a data slot in bytes.
+    pub fn slot_size(&self, slot: Slot) -> usize {
+        match self {
+            Self::Atsha204a => 32,
+            _ if slot.is_private_key() => 36,
+            _ if slot == Slot::Data08 => 416,
+            _ => 72,
+        }
+    }
+
+    pub(crate) fn execution_time(
+        &self,
+        clock_divider: &ClockDivider,
+        opcode: &OpCode,
+    ) -> Option<ExecTime> {
+        let table: &[(OpCode, ExecTime)] = match self {
+            Self::Atsha204a => &ATSHA204A_EXEC_TIME,
+            Self::Atecc108a => &ATECC108A_EXEC_TIME,
+            Self::Atecc508a => &ATECC508A_EXEC_TIME,
+            _ => return clock_divider.execution_time(opcode),
+        };
+        table
+            .iter()
+            .find(|(op, _)| op == opcode)
+            .map(|(_, time)| *time)
+    }
+}
+
+/// Revision word returned by Info. Byte 2 identifies the device family and
+/// byte 3 the silicon revision within it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Revision([u8; 4]);
+
+impl Revision {
+    pub fn device_type(&self) -> DeviceType {
+        DeviceType::from(self)
+    }
+
+    pub fn silicon_revision(&self) -> u8 {
+        self.0[3]
+    }
+
+    pub fn is_ecc608a(&self) -> bool {
+        self.device_type() == DeviceType::Atecc608a
+    }
+
+    pub fn is_ecc608b(&self) -> bool {
+        self.device_type() == DeviceType::Atecc608b
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 4] {
+        &self.0
+    }
+}
+
+impl From<[u8; 4]> for Revision {
+    fn from(bytes: [u8; 4]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for Revision {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        buffer
+            .try_into()
+            .map(Self)
+            .map_err(|_| ErrorKind::InvalidSize.into())
+    }
+}
+
+impl AsRef<[u8]> for Revision {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<&Revision> for DeviceType {
+    fn from(revision: &Revision) -> Self {
+        match (revision.0[2], revision.0[3]) {
+            (0x00, _) | (0x02, _) => Self::Atsha204a,
+            (0x10, _) => Self::Atecc108a,
+            (0x50, _) => Self::Atecc508a,
+            (0x60, 0x00..=0x02) => Self::Atecc608a,
+            (0x60, _) => Self::Atecc608b,
+            _ => Self::Unknown(revision.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(revision: [u8; 4]) -> DeviceType {
+        Revision::try_from(revision.as_ref()).unwrap().device_type()
+    }
+
+    #[test]
+    fn revision() {
+        assert_eq!(detect([0x00, 0x00, 0x60, 0x02]), DeviceType::Atecc608a);
+        assert_eq!(detect([0x00, 0x00, 0x60, 0x03]), DeviceType::Atecc608b);
+        assert_eq!(detect([0x00, 0x00, 0x50, 0x00]), DeviceType::Atecc508a);
+        assert_eq!(detect([0x00, 0x02, 0x00, 0x09]), DeviceType::Atsha204a);
+        assert_eq!(
+            detect([0x00, 0x00, 0x70, 0x00]),
+            DeviceType::Unknown([0x00, 0x00, 0x70, 0x00])
+        );
+
+        let revision = Revision::from([0x00, 0x00, 0x60, 0x03]);
+        assert!(revision.is_ecc608b() && !revision.is_ecc608a());
+        assert_eq!(revision.silicon_revision(), 0x03);
+        assert!(Revision::try_from([0x00; 5].as_ref()).is_err());
+    }
+
+    #[test]
+    fn supports() {
+        assert!(DeviceType::Atecc608a.supports(&OpCode::Kdf));
+        assert!(!DeviceType::Atecc508a.supports(&OpCode::Aes));
+        assert!(DeviceType::Atecc508a.supports(&OpCode::Ecdh));
+        assert!(!DeviceType::Atsha204a.supports(&OpCode::Sign));
+        assert!(DeviceType::Atsha204a.supports(&OpCode::Mac));
+    }
+
+    #[test]
+    fn atsha204a_profile() {
+        let device = DeviceType::Atsha204a;
+        assert_eq!(device.config_zone_size(), 88);
+        assert_eq!(device.slot_size(Slot::Data08), 32);
+        assert_eq!(
+            device.execution_time(&ClockDivider::Zero, &OpCode::Mac),
+            Some(ExecTime::new(12, 35))
+        );
+        assert_eq!(
+            DeviceType::Atecc608a
+                .execution_time(&ClockDivider::Zero, &OpCode::Mac)
+                .map(|t| t.max),
+            Some(55)
+        );
+    }
+
+    #[test]
+    fn atecc108a_profile() {
+        let device = DeviceType::Atecc108a;
+        assert!(!device.supports(&OpCode::Ecdh));
+        assert!(!device.supports(&OpCode::Kdf));
+        assert_eq!(
+            device.execution_time(&ClockDivider::Zero, &OpCode::Sign),
+            Some(ExecTime::new(50, 60))
+        );
+        assert_eq!(
+            device.execution_time(&ClockDivider::Zero, &OpCode::Aes),
+            None
+        );
+    }
+
+    #[test]
+    fn execution_time_polling_window() {
+        let time = DeviceType::Atecc608a
+            .execution_time(&ClockDivider::Zero, &OpCode::GenKey)
+            .unwrap();
+        assert!(time.typical < time.max);
+        assert_eq!(time.max, 115);
+        assert!(ExecTime::default().max >= time.max);
+    }
+}
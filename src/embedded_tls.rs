@@ -0,0 +1,126 @@
+// Crypto provider for embedded-tls, for TLS 1.3 client authentication with
+// the private key kept in a slot. CertificateVerify is hashed on the device
+// and signed with the slot key, and the random numbers of the handshake come
+// from the device RNG. Server certificates are checked by the verifier given
+// with `with_verifier`, or not at all.
+//
+//     let provider = TlsProvider::<Aes128GcmSha256, _, _>::new(&mut atca, Slot::PrivateKey00)
+//         .with_cert(certificate_der);
+//     connection.open(TlsContext::new(&config, provider))?;
+use super::client::{AtCaClient, SlotSigner, BUFFER_SIZE};
+use super::command::DerSignature;
+use super::datalink::Interface;
+use super::memory::Slot;
+use core::marker::PhantomData;
+use embedded_hal::delay::DelayNs;
+use embedded_tls::{
+    Certificate, CryptoProvider, CryptoRngCore, NoVerify, SignatureScheme, TlsCipherSuite,
+    TlsError, TlsVerifier,
+};
+
+// embedded-tls takes signers of signature 2.x.
+impl<'a, PHY, D, const N: usize> signature2::Signer<DerSignature> for SlotSigner<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn try_sign(&self, msg: &[u8]) -> Result<DerSignature, signature2::Error> {
+        self.sign_der(msg).map_err(|_| signature2::Error::new())
+    }
+}
+
+/// Slot key, client certificate and device RNG as an embedded-tls
+/// `CryptoProvider`.
+pub struct TlsProvider<'a, CipherSuite, PHY, D, V = NoVerify, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
+    key_id: Slot,
+    cert: Option<&'a [u8]>,
+    verifier: V,
+    cipher_suite: PhantomData<CipherSuite>,
+}
+
+impl<'a, CipherSuite, PHY, D, const N: usize> TlsProvider<'a, CipherSuite, PHY, D, NoVerify, N> {
+    pub fn new(atca: &'a mut AtCaClient<PHY, D, N>, key_id: Slot) -> Self {
+        Self {
+            atca,
+            key_id,
+            cert: None,
+            verifier: NoVerify,
+            cipher_suite: PhantomData,
+        }
+    }
+}
+
+impl<'a, CipherSuite, PHY, D, V, const N: usize> TlsProvider<'a, CipherSuite, PHY, D, V, N> {
+    /// DER encoded X.509 certificate of the slot key, sent when the server
+    /// asks for client authentication.
+    pub fn with_cert(mut self, cert: &'a [u8]) -> Self {
+        self.cert.replace(cert);
+        self
+    }
+
+    pub fn with_verifier<W>(self, verifier: W) -> TlsProvider<'a, CipherSuite, PHY, D, W, N> {
+        TlsProvider {
+            atca: self.atca,
+            key_id: self.key_id,
+            cert: self.cert,
+            verifier,
+            cipher_suite: PhantomData,
+        }
+    }
+}
+
+impl<'a, CipherSuite, PHY, D, V, const N: usize> CryptoProvider
+    for TlsProvider<'a, CipherSuite, PHY, D, V, N>
+where
+    CipherSuite: TlsCipherSuite,
+    PHY: Interface,
+    D: DelayNs,
+    V: TlsVerifier<CipherSuite>,
+{
+    type CipherSuite = CipherSuite;
+    type Signature = DerSignature;
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        self.atca.rng()
+    }
+
+    fn verifier(&mut self) -> Result<&mut impl TlsVerifier<CipherSuite>, TlsError> {
+        Ok(&mut self.verifier)
+    }
+
+    fn signer(
+        &mut self,
+    ) -> Result<(impl signature2::SignerMut<DerSignature>, SignatureScheme), TlsError> {
+        let signer = self.atca.signer(self.key_id);
+        Ok((signer, SignatureScheme::EcdsaSecp256r1Sha256))
+    }
+
+    fn client_cert(&mut self) -> Option<Certificate<impl AsRef<[u8]>>> {
+        self.cert.map(Certificate::X509)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+    use embedded_tls::Aes128GcmSha256;
+    use rand_core::RngCore;
+
+    #[test]
+    fn provider() {
+        let mut device = Device::new(0).respond(&[0xa5; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let cert = [0x30, 0x00];
+        let mut provider = TlsProvider::<Aes128GcmSha256, _, _>::new(&mut atca, Slot::PrivateKey00)
+            .with_cert(&cert);
+        assert_eq!(provider.rng().next_u32(), 0xa5a5_a5a5);
+        assert!(matches!(
+            provider.client_cert(),
+            Some(Certificate::X509(der)) if der.as_ref() == cert
+        ));
+        let (_, scheme) = provider.signer().unwrap();
+        assert!(scheme == SignatureScheme::EcdsaSecp256r1Sha256);
+    }
+}
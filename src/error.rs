@@ -2,20 +2,88 @@ use core::convert::TryFrom;
 
 /// An error type representing ATECC608's erroneous conditions.
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub struct Error {
     repr: Repr,
+    // Where the error came up, if it happened while running a command.
+    opcode: Option<u8>,
+    phase: Option<Phase>,
+}
+
+/// Stage of a command exchange with the device.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Phase {
+    /// Waking the device up
+    Wake,
+    /// Sending the command packet
+    Send,
+    /// Waiting for the command to complete
+    Poll,
+    /// Reading the response
+    Receive,
+    /// Checking the response and its status byte
+    Parse,
 }
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 enum Repr {
     Device(Status),
     Simple(ErrorKind),
 }
 
+impl Error {
+    /// Status byte reported by the device, if the error originates from it.
+    pub fn status(&self) -> Option<Status> {
+        match self.repr {
+            Repr::Device(status) => Some(status),
+            Repr::Simple(_) => None,
+        }
+    }
+
+    /// Host side error kind, if the error did not come from the device.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self.repr {
+            Repr::Device(_) => None,
+            Repr::Simple(kind) => Some(kind),
+        }
+    }
+
+    /// Device status byte of the failure, for counting failure modes.
+    /// `None` for host side errors and unrecognised status bytes.
+    pub fn execution_status(&self) -> Option<ExecutionStatus> {
+        self.status().and_then(ExecutionStatus::from_status)
+    }
+
+    /// Op-code of the command that failed.
+    pub fn opcode(&self) -> Option<u8> {
+        self.opcode
+    }
+
+    /// Stage of the command exchange that failed.
+    pub fn phase(&self) -> Option<Phase> {
+        self.phase
+    }
+
+    // The phase first recorded is kept, being the closest to the cause.
+    pub(crate) fn in_phase(mut self, phase: Phase) -> Self {
+        self.phase.get_or_insert(phase);
+        self
+    }
+
+    pub(crate) fn with_opcode(mut self, opcode: u8) -> Self {
+        self.opcode.get_or_insert(opcode);
+        self
+    }
+}
+
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
         Error {
             repr: Repr::Simple(kind),
+            opcode: None,
+            phase: None,
         }
     }
 }
@@ -24,20 +92,57 @@ impl From<Status> for Error {
     fn from(status: Status) -> Error {
         Error {
             repr: Repr::Device(status),
+            opcode: None,
+            phase: None,
         }
     }
 }
 
+// The error code is the device status byte with bit 8 set, or the host
+// side error kind, offset by rand_core's custom range.
+#[cfg(feature = "rand_core")]
+impl From<Error> for rand_core::Error {
+    fn from(error: Error) -> Self {
+        let code = match error.repr {
+            Repr::Device(status) => 0x100 | status as u32,
+            Repr::Simple(kind) => kind as u32,
+        };
+        core::num::NonZeroU32::new(Self::CUSTOM_START + code)
+            .map(Self::from)
+            .unwrap_or_else(|| unreachable!())
+    }
+}
+
 impl core::fmt::Display for Error {
     fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match &self.repr {
-            Repr::Device(status) => write!(fmt, "{}", status),
-            Repr::Simple(kind) => write!(fmt, "{}", kind),
+            Repr::Device(status) => write!(fmt, "{}", status)?,
+            Repr::Simple(kind) => write!(fmt, "{}", kind)?,
+        }
+        if let Some(phase) = self.phase {
+            write!(fmt, " ({})", phase)?;
+        }
+        if let Some(opcode) = self.opcode {
+            write!(fmt, " in command {:#04x}", opcode)?;
         }
+        Ok(())
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+impl core::fmt::Display for Phase {
+    fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Wake => write!(fmt, "waking the device"),
+            Self::Send => write!(fmt, "sending the command"),
+            Self::Poll => write!(fmt, "waiting for completion"),
+            Self::Receive => write!(fmt, "receiving the response"),
+            Self::Parse => write!(fmt, "parsing the response"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Status {
     /// response status byte indicates CheckMac failure (status byte = 0x01)
     CheckmacVerifyFailed = 0x01,
@@ -58,6 +163,8 @@ pub enum Status {
     SelfTest = 0x07,
     /// random number generator health test error
     HealthTest = 0x08,
+    /// watchdog about to expire, command not run (status byte = 0xEE)
+    WatchdogAboutToExpire = 0xEE,
 }
 
 impl TryFrom<u8> for Status {
@@ -73,6 +180,7 @@ impl TryFrom<u8> for Status {
             0x05 => Ok(Self::Ecc),
             0x07 => Ok(Self::SelfTest),
             0x08 => Ok(Self::HealthTest),
+            0xEE => Ok(Self::WatchdogAboutToExpire),
             _ => Ok(Self::Unknown),
         }
     }
@@ -98,15 +206,67 @@ impl core::fmt::Display for Status {
             ),
             Self::SelfTest => write!(fmt, "chip is in self test failure mode"),
             Self::Unknown => write!(fmt, "response contains unknown non-zero status byte"),
+            Self::WatchdogAboutToExpire => {
+                write!(fmt, "watchdog about to expire, command not executed")
+            }
+        }
+    }
+}
+
+/// Status byte of a one-byte device response, successful or not.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ExecutionStatus {
+    Success = 0x00,
+    /// CheckMac or Verify miscompare
+    Miscompare = 0x01,
+    ParseError = 0x03,
+    EccFault = 0x05,
+    SelfTestError = 0x07,
+    HealthTestError = 0x08,
+    ExecutionError = 0x0F,
+    /// Received after a wake, before the first command
+    WakeToken = 0x11,
+    WatchdogExpire = 0xEE,
+    /// CRC or other communication error
+    CommError = 0xFF,
+}
+
+impl ExecutionStatus {
+    pub fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(Self::Success),
+            0x01 => Some(Self::Miscompare),
+            0x03 => Some(Self::ParseError),
+            0x05 => Some(Self::EccFault),
+            0x07 => Some(Self::SelfTestError),
+            0x08 => Some(Self::HealthTestError),
+            0x0F => Some(Self::ExecutionError),
+            0x11 => Some(Self::WakeToken),
+            0xEE => Some(Self::WatchdogExpire),
+            0xFF => Some(Self::CommError),
+            _ => None,
+        }
+    }
+
+    fn from_status(status: Status) -> Option<Self> {
+        match status {
+            Status::Unknown => None,
+            status => Self::from_byte(status as u8),
         }
     }
 }
 
 /// A list of specific error causes. Each kind is converted into `Error` type.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum ErrorKind {
     /// Code failed run-time consistency check
     AssertFailure = 0xF6,
+    /// Authentication tag of a decrypted message did not match
+    AuthenticationFailed = 0xD6,
+    /// Key requires an authorization session that is not in place
+    AuthorizationRequired = 0xD9,
     /// opcode is not supported by the device
     BadOpcode = 0xF2,
     /// bad argument (out of range, null pointer, etc.)
@@ -114,6 +274,10 @@ pub enum ErrorKind {
     /// Communication with device failed. Same as in hardware dependent modules.
     CommFail = 0xF0,
     ConfigZoneLocked = 0x01,
+    /// Config zone has to be locked first
+    ConfigNotLocked = 0xDC,
+    /// Data zone has to be locked first
+    DataNotLocked = 0xDD,
     DataZoneLocked = 0x04,
     /// Function could not execute due to incorrect condition / state.
     FuncFail = 0xE0,
@@ -121,16 +285,26 @@ pub enum ErrorKind {
     InvalidId = 0xE3,
     /// Count value is out of range or greater than buffer size.
     InvalidSize = 0xE4,
+    /// Slot configuration does not permit the key to be used this way
+    KeyNotPermitted = 0xDB,
     /// required zone was not locked
     NotLocked = 0xF8,
+    /// Key requires the persistent latch, which is clear
+    PersistentLatchClear = 0xD8,
     /// Re-synchronization succeeded, but only after generating a Wake-up
     ResyncWithWakeup = 0xE8,
+    /// Shared client was left in the middle of a command sequence
+    Poisoned = 0xDA,
     /// Crc error in data received from device
     RxCrcError = 0xE5,
     /// Timed out while waiting for response. Number of bytes received is > 0.
     RxFail = 0xE6,
     /// Supplied buffer is too small for data required
     SmallBuffer = 0xED,
+    /// TempKey is required but has not been loaded or is no longer valid
+    TempKeyInvalid = 0xD1,
+    /// TempKey was not loaded the way the command requires
+    TempKeySourceMismatch = 0xD2,
     /// Timed out while waiting for response. Number of bytes received is 0.
     Timeout = 0xF1,
     /// Device did not respond too many times during a transmission. Could
@@ -138,18 +312,26 @@ pub enum ErrorKind {
     TooManyCommRetries = 0xEC,
     /// Failed to write
     TxFail = 0xF7,
+    /// Command is not implemented by the detected device type
+    UnsupportedByDevice = 0xD3,
     /// Function or some element of it hasn't been implemented yet
     Unimplemented = 0xF5,
     /// Use flags on the device indicates its consumed fully
     UseFlagsConsumed = 0xFC,
+    /// Image version is older than the anti-rollback counter allows
+    VersionRollback = 0xD7,
     /// Device did not respond to wake call as expected
     WakeFailed = 0xD0,
+    /// Command sequence cannot complete before the watchdog expires
+    WatchdogExceeded = 0xD4,
 }
 
 impl core::fmt::Display for ErrorKind {
     fn fmt(&self, fmt: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Self::AssertFailure => write!(fmt, "failed run-time consistency check"),
+            Self::AuthenticationFailed => write!(fmt, "authentication tag did not match"),
+            Self::AuthorizationRequired => write!(fmt, "key requires prior authorization"),
             Self::BadOpcode => write!(fmt, "opcode is not supported by the device"),
             Self::BadParam => write!(fmt, "bad argument (out of range, null pointer, etc.)"),
             Self::CommFail => write!(fmt, "communication with device failed"),
@@ -164,7 +346,22 @@ impl core::fmt::Display for ErrorKind {
                 fmt,
                 "count value is out of range or greater than buffer size"
             ),
+            Self::KeyNotPermitted => {
+                write!(
+                    fmt,
+                    "slot configuration does not permit this use of the key"
+                )
+            }
             Self::NotLocked => write!(fmt, "required zone was not locked"),
+            Self::ConfigNotLocked => write!(fmt, "config zone is not locked"),
+            Self::DataNotLocked => write!(fmt, "data zone is not locked"),
+            Self::PersistentLatchClear => {
+                write!(
+                    fmt,
+                    "key cannot be used while the persistent latch is clear"
+                )
+            }
+            Self::Poisoned => write!(fmt, "shared client was left mid-sequence"),
             Self::ResyncWithWakeup => write!(
                 fmt,
                 "re-synchronization succeeded, but only after generating a Wake-up"
@@ -175,6 +372,10 @@ impl core::fmt::Display for ErrorKind {
                 "timed out while waiting for response. Number of bytes received is > 0"
             ),
             Self::SmallBuffer => write!(fmt, "supplied buffer is too small for data required"),
+            Self::TempKeyInvalid => write!(fmt, "tempkey is not loaded or no longer valid"),
+            Self::TempKeySourceMismatch => {
+                write!(fmt, "tempkey was not loaded the way the command requires")
+            }
             Self::Timeout => write!(fmt, "timed out while waiting for response"),
             Self::TooManyCommRetries => {
                 write!(
@@ -183,6 +384,9 @@ impl core::fmt::Display for ErrorKind {
                 )
             }
             Self::TxFail => write!(fmt, "failed to write"),
+            Self::UnsupportedByDevice => {
+                write!(fmt, "command is not supported by the detected device")
+            }
             Self::Unimplemented => write!(
                 fmt,
                 "function or some element of it hasn't been implemented yet"
@@ -190,7 +394,11 @@ impl core::fmt::Display for ErrorKind {
             Self::UseFlagsConsumed => {
                 write!(fmt, "use flags on the device indicates its consumed fully")
             }
+            Self::VersionRollback => write!(fmt, "image version is older than the minimum"),
             Self::WakeFailed => write!(fmt, "device did not respond to wake call as expected"),
+            Self::WatchdogExceeded => {
+                write!(fmt, "command sequence cannot complete within the watchdog")
+            }
         }
     }
 }
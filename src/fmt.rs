@@ -245,3 +245,25 @@ impl<T, E> Try for Result<T, E> {
         self
     }
 }
+
+// Unit tests run on the host, where nothing collects defmt frames. A logger
+// that drops them lets the test binary link.
+#[cfg(all(test, feature = "defmt"))]
+mod test_logger {
+    #[defmt::global_logger]
+    struct Discard;
+
+    unsafe impl defmt::Logger for Discard {
+        fn acquire() {}
+        unsafe fn flush() {}
+        unsafe fn release() {}
+        unsafe fn write(_bytes: &[u8]) {}
+    }
+
+    defmt::timestamp!("");
+
+    #[defmt::panic_handler]
+    fn panic() -> ! {
+        core::panic!("defmt panic")
+    }
+}
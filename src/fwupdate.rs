@@ -0,0 +1,166 @@
+// Firmware update verification. The image is hashed on the device as it
+// streams in, its signature is checked against a public key stored in a
+// slot, and a monotonic counter holding the minimum acceptable version
+// guards against rollback to an older, possibly vulnerable, image.
+//
+// The signed digest covers the image followed by its version as 4
+// little-endian bytes, so the version cannot be changed without
+// invalidating the signature.
+//
+// Versions are small sequential integers, 1, 2, 3 and so on, not encoded
+// semantic versions: the counter only counts up by one per command, so
+// reaching a version takes as many increments as its distance from the
+// counter, each wearing the counter a little.
+use super::client::AtCaClient;
+use super::command::{CounterId, CounterValue, Digest, Signature};
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind, Status};
+use super::memory::Slot;
+use embedded_hal::delay::DelayNs;
+
+/// Largest step a single update may raise the version by. A larger gap is
+/// more likely a version encoded some other way than that many skipped
+/// releases, and would spend as many counter increments.
+pub const MAX_VERSION_STEP: u32 = 16;
+
+/// Hash `blocks` with `version`, verify `signature` with the public key in
+/// `pubkey_slot`, then raise the counter `min_version_counter` to
+/// `version`. The counter only moves once the signature has been verified
+/// and an image older than the counter is rejected before anything else.
+/// `version` is a small sequential integer; one more than
+/// [`MAX_VERSION_STEP`] above the counter is rejected with `BadParam`.
+/// Returns the new counter value.
+pub fn verify_image<'b, PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    blocks: impl IntoIterator<Item = &'b [u8]>,
+    signature: &Signature,
+    pubkey_slot: Slot,
+    version: u32,
+    min_version_counter: CounterId,
+) -> Result<CounterValue, Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    if version > CounterValue::MAX {
+        return Err(ErrorKind::BadParam.into());
+    }
+    check_version(atca.counter_read(min_version_counter)?, version)?;
+
+    let digest = image_digest(atca, blocks, version)?;
+    if !atca.verify_stored_digest(pubkey_slot, &digest, signature)? {
+        return Err(Status::CheckmacVerifyFailed.into());
+    }
+
+    // Counter only counts up by one. Read it back every step so that an
+    // update interrupted halfway resumes where it stopped.
+    let mut counter = atca.counter_read(min_version_counter)?;
+    while counter.0 < version {
+        counter = atca.counter_increment(min_version_counter)?;
+    }
+    Ok(counter)
+}
+
+fn check_version(counter: CounterValue, version: u32) -> Result<(), Error> {
+    if version < counter.0 {
+        return Err(ErrorKind::VersionRollback.into());
+    }
+    if version - counter.0 > MAX_VERSION_STEP {
+        return Err(ErrorKind::BadParam.into());
+    }
+    Ok(())
+}
+
+fn image_digest<'b, PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    blocks: impl IntoIterator<Item = &'b [u8]>,
+    version: u32,
+) -> Result<Digest, Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    let mut sha = atca.sha();
+    sha.init()?;
+    for block in blocks {
+        sha.update(block)?;
+    }
+    sha.update(version.to_le_bytes())?;
+    sha.finalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_version() {
+        assert!(super::check_version(CounterValue(3), 3).is_ok());
+        assert!(super::check_version(CounterValue(3), 4).is_ok());
+        assert_eq!(
+            super::check_version(CounterValue(3), 2).unwrap_err().kind(),
+            Some(ErrorKind::VersionRollback)
+        );
+        assert!(super::check_version(CounterValue(3), 3 + MAX_VERSION_STEP).is_ok());
+        assert_eq!(
+            super::check_version(CounterValue(3), 4 + MAX_VERSION_STEP)
+                .unwrap_err()
+                .kind(),
+            Some(ErrorKind::BadParam)
+        );
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn verify_image() {
+        use crate::command::PublicKey;
+        use crate::datalink::tests::NoDelay;
+        use crate::host::Sha256;
+        use crate::mock::MockDevice;
+        use p256::ecdsa::SigningKey;
+        use signature::hazmat::PrehashSigner;
+
+        let image: [&[u8]; 2] = [&[0x5a; 100], &[0xa5; 28]];
+        let signing_key = SigningKey::from_bytes(&[0x11; 32]).unwrap();
+        let sign = |version: u32| {
+            let mut sha = Sha256::new();
+            for block in image {
+                sha.update(block);
+            }
+            let digest = sha.update(version.to_le_bytes()).finalize();
+            let signature: p256::ecdsa::Signature =
+                signing_key.sign_prehash(digest.as_ref()).unwrap();
+            Signature::from(signature)
+        };
+        let public_key = PublicKey::from(p256::PublicKey::from(signing_key.verifying_key()));
+        let device = MockDevice::new().with_slot(Slot::Data08, &public_key.to_slot_format());
+        let mut atca = AtCaClient::new(device, NoDelay);
+        let counter = CounterId::Zero;
+        for _ in 0..3 {
+            atca.counter_increment(counter).unwrap();
+        }
+        let verify = |atca: &mut AtCaClient<_, _>, signature, version| {
+            super::verify_image(atca, image, &signature, Slot::Data08, version, counter)
+        };
+        assert_eq!(
+            verify(&mut atca, sign(2), 2).unwrap_err().kind(),
+            Some(ErrorKind::VersionRollback)
+        );
+        let version = 4 + MAX_VERSION_STEP;
+        assert_eq!(
+            verify(&mut atca, sign(version), version)
+                .unwrap_err()
+                .kind(),
+            Some(ErrorKind::BadParam)
+        );
+        // A signature over another version leaves the counter alone.
+        assert_eq!(
+            verify(&mut atca, sign(5), 6).unwrap_err().status(),
+            Some(Status::CheckmacVerifyFailed)
+        );
+        assert_eq!(atca.counter_read(counter).unwrap(), CounterValue(3));
+
+        assert_eq!(verify(&mut atca, sign(6), 6).unwrap(), CounterValue(6));
+        assert_eq!(atca.counter_read(counter).unwrap(), CounterValue(6));
+    }
+}
@@ -0,0 +1,73 @@
+// The device SHA engine behind the digest 0.9 traits, so generic code taking
+// `Update + FixedOutput` can hash on the device as it would with
+// sha2::Sha256. The traits are infallible, so a failing device command
+// panics.
+use super::client::{Sha, BUFFER_SIZE};
+use super::datalink::Interface;
+use digest::{FixedOutputDirty, Reset, Update};
+use embedded_hal::delay::DelayNs;
+use generic_array::typenum::U32;
+use generic_array::GenericArray;
+
+pub struct Hasher<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    sha: Sha<'a, PHY, D, N>,
+    // Whether the Start command has been sent for the current message.
+    started: bool,
+}
+
+impl<'a, PHY, D, const N: usize> From<Sha<'a, PHY, D, N>> for Hasher<'a, PHY, D, N> {
+    fn from(sha: Sha<'a, PHY, D, N>) -> Self {
+        Self {
+            sha,
+            started: false,
+        }
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Hasher<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn start(&mut self) {
+        if !self.started {
+            self.sha.init().expect("init operation failed");
+            self.started = true;
+        }
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Update for Hasher<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        self.start();
+        self.sha.update(data).expect("update operation failed");
+    }
+}
+
+impl<'a, PHY, D, const N: usize> FixedOutputDirty for Hasher<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    type OutputSize = U32;
+    fn finalize_into_dirty(&mut self, out: &mut GenericArray<u8, Self::OutputSize>) {
+        self.start();
+        let digest = self.sha.finalize().expect("finalize operation failed");
+        out.copy_from_slice(digest.as_ref());
+    }
+}
+
+// The next message starts with a fresh Start command.
+impl<'a, PHY, D, const N: usize> Reset for Hasher<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn reset(&mut self) {
+        self.started = false;
+    }
+}
@@ -0,0 +1,779 @@
+// Host side counterparts of device calculations. They reproduce the messages
+// the device hashes internally, so that firmware can precompute expected
+// responses or check the ones returned by the device.
+use super::command::{
+    Block, DeriveKey, Digest, GenDig, MacMode, NonceCtx, OpCode, PrivWrite, PublicKey, Serial,
+    Signature, Verify, Write,
+};
+use super::memory::{Size, Slot, Zone};
+use core::convert::TryInto;
+
+const SHA256_BLOCK_SIZE: usize = 64;
+
+const SHA256_H: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Software SHA-256. The device calculates digests over messages with a
+/// fixed layout; this is used to reproduce them on the host.
+#[derive(Clone, Debug)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: [u8; SHA256_BLOCK_SIZE],
+    buffered: usize,
+    length: u64,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self {
+            state: SHA256_H,
+            buffer: [0x00; SHA256_BLOCK_SIZE],
+            buffered: 0,
+            length: 0,
+        }
+    }
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: impl AsRef<[u8]>) -> &mut Self {
+        let mut data = data.as_ref();
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let length = (SHA256_BLOCK_SIZE - self.buffered).min(data.len());
+            let (head, tail) = data.split_at(length);
+            self.buffer[self.buffered..self.buffered + length].copy_from_slice(head);
+            self.buffered += length;
+            data = tail;
+            if self.buffered == SHA256_BLOCK_SIZE {
+                let block = self.buffer;
+                self.compress(&block);
+                self.buffered = 0;
+            }
+        }
+        self
+    }
+
+    pub fn finalize(&mut self) -> Digest {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = [0x00; SHA256_BLOCK_SIZE + 8];
+        padding[0] = 0x80;
+        let pad_length = if self.buffered < 56 {
+            56 - self.buffered
+        } else {
+            SHA256_BLOCK_SIZE + 56 - self.buffered
+        };
+        self.update(&padding[..pad_length]);
+        self.update(bit_length.to_be_bytes());
+
+        let mut digest = Digest::default();
+        digest
+            .as_mut()
+            .chunks_mut(4)
+            .zip(self.state.iter())
+            .for_each(|(dst, word)| dst.copy_from_slice(&word.to_be_bytes()));
+        *self = Self::default();
+        digest
+    }
+
+    pub fn digest(data: impl AsRef<[u8]>) -> Digest {
+        Self::new().update(data).finalize()
+    }
+
+    fn compress(&mut self, block: &[u8; SHA256_BLOCK_SIZE]) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in block.chunks(4).enumerate() {
+            w[i] = chunk
+                .try_into()
+                .map(u32::from_be_bytes)
+                .unwrap_or_else(|_| unreachable!());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h].iter()) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+}
+
+const SHA1_H: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+/// Software SHA-1. Only used where a format mandates it, such as X.509 key
+/// identifiers. Not for anything security relevant.
+#[derive(Clone, Debug)]
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: [u8; SHA256_BLOCK_SIZE],
+    buffered: usize,
+    length: u64,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self {
+            state: SHA1_H,
+            buffer: [0x00; SHA256_BLOCK_SIZE],
+            buffered: 0,
+            length: 0,
+        }
+    }
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, data: impl AsRef<[u8]>) -> &mut Self {
+        let mut data = data.as_ref();
+        self.length += data.len() as u64;
+        while !data.is_empty() {
+            let length = (SHA256_BLOCK_SIZE - self.buffered).min(data.len());
+            let (head, tail) = data.split_at(length);
+            self.buffer[self.buffered..self.buffered + length].copy_from_slice(head);
+            self.buffered += length;
+            data = tail;
+            if self.buffered == SHA256_BLOCK_SIZE {
+                let block = self.buffer;
+                self.compress(&block);
+                self.buffered = 0;
+            }
+        }
+        self
+    }
+
+    pub fn finalize(&mut self) -> [u8; 20] {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = [0x00; SHA256_BLOCK_SIZE + 8];
+        padding[0] = 0x80;
+        let pad_length = if self.buffered < 56 {
+            56 - self.buffered
+        } else {
+            SHA256_BLOCK_SIZE + 56 - self.buffered
+        };
+        self.update(&padding[..pad_length]);
+        self.update(bit_length.to_be_bytes());
+
+        let mut digest = [0x00; 20];
+        digest
+            .chunks_mut(4)
+            .zip(self.state.iter())
+            .for_each(|(dst, word)| dst.copy_from_slice(&word.to_be_bytes()));
+        *self = Self::default();
+        digest
+    }
+
+    pub fn digest(data: impl AsRef<[u8]>) -> [u8; 20] {
+        Self::new().update(data).finalize()
+    }
+
+    fn compress(&mut self, block: &[u8; SHA256_BLOCK_SIZE]) {
+        let mut w = [0u32; 80];
+        for (i, chunk) in block.chunks(4).enumerate() {
+            w[i] = chunk
+                .try_into()
+                .map(u32::from_be_bytes)
+                .unwrap_or_else(|_| unreachable!());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = self.state;
+        for (i, w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*w);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e].iter()) {
+            *state = state.wrapping_add(*value);
+        }
+    }
+}
+
+// Optional device values included in a MAC calculation.
+fn otp_and_serial(
+    hasher: &mut Sha256,
+    mode: MacMode,
+    serial: &Serial,
+    otp: Option<&[u8; 11]>,
+) -> Result<(), ()> {
+    // OTP[0:10] or OTP[0:7] depending on the mode. Zeros otherwise.
+    let mut otp_bytes = [0x00; 11];
+    if mode.has_otp_88() || mode.has_otp_64() {
+        let otp = otp.ok_or(())?;
+        let length = if mode.has_otp_88() { 11 } else { 8 };
+        otp_bytes[..length].copy_from_slice(&otp[..length]);
+    }
+    hasher.update(otp_bytes);
+
+    let sn = serial.as_ref();
+    hasher.update(&sn[8..9]);
+    if mode.has_serial() {
+        hasher.update(&sn[4..8]);
+    } else {
+        hasher.update([0x00; 4]);
+    }
+    hasher.update(&sn[0..2]);
+    if mode.has_serial() {
+        hasher.update(&sn[2..4]);
+    } else {
+        hasher.update([0x00; 2]);
+    }
+    Ok(())
+}
+
+/// Reproduce the digest returned by the MAC command. `key` is the slot key,
+/// or TempKey if the mode takes the key from TempKey. Likewise `challenge` is
+/// the challenge input or TempKey. `otp` is required when the mode includes
+/// OTP bits.
+pub fn mac(
+    mode: MacMode,
+    key_id: Slot,
+    key: &Block,
+    challenge: &Block,
+    serial: &Serial,
+    otp: Option<&[u8; 11]>,
+) -> Option<Digest> {
+    let mut hasher = Sha256::new();
+    hasher
+        .update(key)
+        .update(challenge)
+        .update([OpCode::Mac as u8, u8::from(mode)])
+        .update((key_id as u16).to_le_bytes());
+    otp_and_serial(&mut hasher, mode, serial, otp).ok()?;
+    Some(hasher.finalize())
+}
+
+/// Reproduce the digest the CheckMac command compares against `response`.
+/// OtherData is typically filled with the op-code, mode and param2 of a MAC
+/// command so that the output of MAC on another device can be checked.
+pub fn check_mac(
+    mode: MacMode,
+    key: &Block,
+    challenge: &Block,
+    other_data: &[u8; 13],
+    serial: &Serial,
+    otp: Option<&[u8; 11]>,
+) -> Option<Digest> {
+    let mut otp_bytes = [0x00; 8];
+    if mode.has_otp_64() {
+        otp_bytes.copy_from_slice(&otp?[..8]);
+    }
+
+    let sn = serial.as_ref();
+    let digest = Sha256::new()
+        .update(key)
+        .update(challenge)
+        .update(&other_data[0..4])
+        .update(otp_bytes)
+        .update(&other_data[4..7])
+        .update(&sn[8..9])
+        .update(&other_data[7..11])
+        .update(&sn[0..2])
+        .update(&other_data[11..13])
+        .finalize();
+    Some(digest)
+}
+
+/// Reproduce TempKey after a random nonce. `rand_out` is the random number
+/// returned by the device and `num_in` the input supplied by the host.
+pub fn nonce(rand_out: &Block, num_in: &[u8; 20]) -> Block {
+    let digest = Sha256::new()
+        .update(rand_out)
+        .update(num_in)
+        .update([OpCode::Nonce as u8, NonceCtx::MODE_SEED_UPDATE, 0x00])
+        .finalize();
+    let mut tempkey = Block::default();
+    tempkey.as_mut().copy_from_slice(digest.as_ref());
+    tempkey
+}
+
+/// Diversify a root key by the device serial number, so that every device
+/// holds a unique key: SHA256(root key || SN[0:8] || 23 zero bytes).
+pub fn diversify_key(root_key: &Block, serial: &Serial) -> Block {
+    let digest = Sha256::new()
+        .update(root_key)
+        .update(serial)
+        .update([0x00; 23])
+        .finalize();
+    let mut key = Block::default();
+    key.as_mut().copy_from_slice(digest.as_ref());
+    key
+}
+
+/// Reproduce the key DeriveKey writes into `target` from the parent key and
+/// the TempKey loaded by a random nonce.
+pub fn derive_key(key: &Block, target: Slot, serial: &Serial, tempkey: &Block) -> Digest {
+    let sn = serial.as_ref();
+    Sha256::new()
+        .update(key)
+        .update([OpCode::DeriveKey as u8, DeriveKey::MODE_RANDOM_NONCE])
+        .update((target as u16).to_le_bytes())
+        .update(&sn[8..9])
+        .update(&sn[0..2])
+        .update([0x00; 25])
+        .update(tempkey)
+        .finalize()
+}
+
+/// Calculate the authorizing MAC of DeriveKey for a target slot that requires
+/// one. `key` is the key designated by the target slot's WriteKey:
+/// SHA256(key || op-code || mode || target || SN[8] || SN[0:1]).
+pub fn derive_key_mac(key: &Block, target: Slot, serial: &Serial) -> Digest {
+    let sn = serial.as_ref();
+    Sha256::new()
+        .update(key)
+        .update([OpCode::DeriveKey as u8, DeriveKey::MODE_RANDOM_NONCE])
+        .update((target as u16).to_le_bytes())
+        .update(&sn[8..9])
+        .update(&sn[0..2])
+        .finalize()
+}
+
+/// Reproduce TempKey after GenDig over the data slot `key_id`. With the
+/// slot's key this yields the session key used to encrypt PrivWrite input.
+pub fn gen_dig(key: &Block, key_id: Slot, serial: &Serial, tempkey: &Block) -> Block {
+    let [lsb, msb] = (key_id as u16).to_le_bytes();
+    let header = [OpCode::GenDig as u8, GenDig::ZONE_DATA, lsb, msb];
+    gen_dig_message(key, &header, serial, tempkey)
+}
+
+/// Same as `gen_dig` for a key with `KeyConfig.NoMac` set, where OtherData
+/// takes the place of the op-code and parameters.
+pub fn gen_dig_no_mac(
+    key: &Block,
+    other_data: &[u8; 4],
+    serial: &Serial,
+    tempkey: &Block,
+) -> Block {
+    gen_dig_message(key, other_data, serial, tempkey)
+}
+
+fn gen_dig_message(key: &Block, header: &[u8; 4], serial: &Serial, tempkey: &Block) -> Block {
+    let sn = serial.as_ref();
+    let digest = Sha256::new()
+        .update(key)
+        .update(header)
+        .update(&sn[8..9])
+        .update(&sn[0..2])
+        .update([0x00; 25])
+        .update(tempkey)
+        .finalize();
+    let mut session_key = Block::default();
+    session_key.as_mut().copy_from_slice(digest.as_ref());
+    session_key
+}
+
+/// Reproduce TempKey after GenKey in public key digest mode:
+/// SHA256(TempKey || op-code || OtherData || SN[8] || SN[0:1] || 25 zero
+/// bytes || public key).
+pub fn gen_key_digest(
+    other_data: &[u8; 3],
+    serial: &Serial,
+    public_key: &PublicKey,
+    tempkey: &Block,
+) -> Block {
+    let sn = serial.as_ref();
+    let digest = Sha256::new()
+        .update(tempkey)
+        .update([OpCode::GenKey as u8])
+        .update(other_data)
+        .update(&sn[8..9])
+        .update(&sn[0..2])
+        .update([0x00; 25])
+        .update(public_key)
+        .finalize();
+    let mut value = Block::default();
+    value.as_mut().copy_from_slice(digest.as_ref());
+    value
+}
+
+/// Digest signed by the attestation of `public_key` with `challenge` and
+/// `other_data`, as returned by `AtCaClient::attest_key` along with
+/// `message`. Verify the signature over it with the attestation public key.
+pub fn attestation_digest(
+    challenge: &Block,
+    other_data: &[u8; 3],
+    serial: &Serial,
+    public_key: &PublicKey,
+    message: &SignInternalMessage,
+) -> Digest {
+    message.digest(&gen_key_digest(other_data, serial, public_key, challenge))
+}
+
+/// Digest whose signature by the parent key validates, or invalidates, a
+/// stored public key: SHA256(TempKey || OtherData[0:9] || SN[8] ||
+/// OtherData[10:13] || SN[0:1] || OtherData[14:18]). TempKey is the public
+/// key digest from `gen_key_digest`; OtherData typically starts with the
+/// GenKey op-code, mode and key id used to compute it.
+pub fn validation_digest(tempkey: &Block, other_data: &[u8; 19], serial: &Serial) -> Digest {
+    let sn = serial.as_ref();
+    Sha256::new()
+        .update(tempkey)
+        .update(&other_data[0..10])
+        .update(&sn[8..9])
+        .update(&other_data[10..14])
+        .update(&sn[0..2])
+        .update(&other_data[14..19])
+        .finalize()
+}
+
+/// Message signed by Sign in Internal mode, apart from TempKey, which leads
+/// it. The device signs SHA256(TempKey || message), so a verifier that
+/// reproduces TempKey, with `nonce`, `gen_dig` or `gen_key_digest`, gets the signed digest
+/// from `digest`.
+///
+/// Layout: op-code, mode, key id (LE), SlotConfig and KeyConfig of the
+/// GenDig or GenKey slot (LE), TempKeyFlags, two zeros, SN[8], SN[4:7],
+/// SN[0:1], SN[2:3], the inverted SlotLocked bit of that slot, PubKey valid and
+/// a zero. SN[4:7] and SN[2:3] are zeros unless the full serial number is
+/// included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SignInternalMessage([u8; Self::SIZE]);
+
+impl SignInternalMessage {
+    pub const SIZE: usize = 23;
+
+    pub fn digest(&self, tempkey: &Block) -> Digest {
+        Sha256::new().update(tempkey).update(self.0).finalize()
+    }
+}
+
+impl From<[u8; SignInternalMessage::SIZE]> for SignInternalMessage {
+    fn from(message: [u8; SignInternalMessage::SIZE]) -> Self {
+        Self(message)
+    }
+}
+
+impl AsRef<[u8]> for SignInternalMessage {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Encrypt a block of slot data with a GenDig session key. Encryption is a
+/// plain XOR, so this also decrypts data returned by an encrypted read.
+pub fn encrypt_block(session_key: &Block, data: &Block) -> Block {
+    let mut output = *data;
+    output
+        .as_mut()
+        .iter_mut()
+        .zip(session_key.as_ref())
+        .for_each(|(v, k)| *v ^= k);
+    output
+}
+
+/// Decrypt output protected by the I/O protection key in place. Each 32-byte
+/// block is XORed with SHA256(io key || 16 bytes of the output nonce).
+pub fn io_decrypt(io_key: &Block, out_nonce: &Block, data: &mut [u8]) {
+    data.chunks_mut(32)
+        .zip(out_nonce.as_ref().chunks(16))
+        .for_each(|(block, nonce)| {
+            let key = Sha256::new().update(io_key).update(nonce).finalize();
+            block
+                .iter_mut()
+                .zip(key.as_ref())
+                .for_each(|(v, k)| *v ^= k);
+        });
+}
+
+/// Reproduce the MAC Verify returns under the I/O protection key when it
+/// succeeds. `message` and `system_nonce` are the two halves of the message
+/// digest buffer. `key_id` is the slot of a stored public key, or `None`
+/// for an external one.
+pub fn verify_mac(
+    io_key: &Block,
+    message: &Digest,
+    system_nonce: &Block,
+    signature: &Signature,
+    key_id: Option<Slot>,
+) -> Digest {
+    let mode = match key_id {
+        Some(_) => Verify::MODE_STORED,
+        None => Verify::MODE_EXTERNAL,
+    } | Verify::MODE_SOURCE_MSGDIGBUF
+        | Verify::MODE_MAC;
+    let param2 = key_id.map_or(Verify::KEY_P256, |key_id| key_id as u16);
+    Sha256::new()
+        .update(io_key)
+        .update(message)
+        .update(system_nonce)
+        .update(signature)
+        .update([OpCode::Verify as u8, mode])
+        .update(param2.to_le_bytes())
+        .finalize()
+}
+
+/// Calculate the input MAC of an encrypted write of `data`, the plaintext,
+/// to `block` of the slot. Returns `None` if the block is out of range.
+pub fn write_mac(
+    session_key: &Block,
+    key_id: Slot,
+    block: u8,
+    serial: &Serial,
+    data: &Block,
+) -> Option<Digest> {
+    let addr = Zone::Data.get_slot_addr(key_id, block).ok()?;
+    let mode = Zone::Data.encode(Size::Block) | Write::MODE_ENCRYPTED;
+    let sn = serial.as_ref();
+    let mac = Sha256::new()
+        .update(session_key)
+        .update([OpCode::Write as u8, mode])
+        .update(addr.to_le_bytes())
+        .update(&sn[8..9])
+        .update(&sn[0..2])
+        .update([0x00; 25])
+        .update(data)
+        .finalize();
+    Some(mac)
+}
+
+/// Encrypt a private key for PrivWrite and calculate its input MAC. Returns
+/// the 36-byte value, 4 bytes of padding followed by the key, and the MAC.
+pub fn priv_write(
+    session_key: &Block,
+    key_id: Slot,
+    serial: &Serial,
+    private_key: &Block,
+) -> ([u8; 36], Digest) {
+    let mut plain = [0x00; 36];
+    plain[4..].copy_from_slice(private_key.as_ref());
+
+    // The first 32 bytes are XORed with the session key, the remaining 4
+    // with the head of its digest.
+    let mut value = plain;
+    let pad = Sha256::digest(session_key);
+    value
+        .iter_mut()
+        .zip(session_key.as_ref().iter().chain(pad.as_ref()))
+        .for_each(|(v, k)| *v ^= k);
+
+    let sn = serial.as_ref();
+    let mac = Sha256::new()
+        .update(session_key)
+        .update([OpCode::PrivWrite as u8, PrivWrite::MODE_ENCRYPTED])
+        .update((key_id as u16).to_le_bytes())
+        .update(&sn[8..9])
+        .update(&sn[0..2])
+        .update([0x00; 21])
+        .update(plain)
+        .finalize();
+    (value, mac)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::TryFrom;
+
+    const DIGEST_OF_ABC: [u8; 0x20] = [
+        0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d, 0xae, 0x22,
+        0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10, 0xff, 0x61, 0xf2, 0x00,
+        0x15, 0xad,
+    ];
+
+    // Taken from the example; 0xbc repeated just short of two blocks.
+    const DIGEST_OF_0XBC: [u8; 0x20] = [
+        0xA9, 0x22, 0x18, 0x56, 0x43, 0x70, 0xA0, 0x57, 0x27, 0x3F, 0xF4, 0x85, 0xA8, 0x07, 0x3F,
+        0x32, 0xFC, 0x1F, 0x14, 0x12, 0xEC, 0xA2, 0xE3, 0x0B, 0x81, 0xA8, 0x87, 0x76, 0x0B, 0x61,
+        0x31, 0x72,
+    ];
+
+    fn serial() -> Serial {
+        let mut block = [0x00; 0x20];
+        block[0..4].copy_from_slice(&[0x01, 0x23, 0xaa, 0xbb]);
+        block[8..13].copy_from_slice(&[0xcc, 0xdd, 0xee, 0xff, 0xee]);
+        Serial::try_from(block.as_ref()).unwrap()
+    }
+
+    #[test]
+    fn sha1() {
+        let digest = [
+            0xa9, 0x99, 0x3e, 0x36, 0x47, 0x06, 0x81, 0x6a, 0xba, 0x3e, 0x25, 0x71, 0x78, 0x50,
+            0xc2, 0x6c, 0x9c, 0xd0, 0xd8, 0x9d,
+        ];
+        assert_eq!(Sha1::digest(b"abc"), digest);
+        let mut hasher = Sha1::new();
+        hasher.update(b"a").update(b"bc");
+        assert_eq!(hasher.finalize(), digest);
+    }
+
+    #[test]
+    fn sha256() {
+        assert_eq!(Sha256::digest(b"abc").as_ref(), DIGEST_OF_ABC);
+        assert_eq!(Sha256::digest([0xbc; 0x7f]).as_ref(), DIGEST_OF_0XBC);
+
+        // Feeding in pieces yields the same digest.
+        let mut hasher = Sha256::new();
+        [0xbc; 0x7f].chunks(5).for_each(|chunk| {
+            hasher.update(chunk);
+        });
+        assert_eq!(hasher.finalize().as_ref(), DIGEST_OF_0XBC);
+    }
+
+    #[test]
+    fn diversify_key() {
+        let mut root_key = Block::default();
+        root_key.as_mut().iter_mut().for_each(|v| *v = 0x11);
+        let serial = serial();
+        let mut message = [0x00; 64];
+        message[..32].copy_from_slice(root_key.as_ref());
+        message[32..41].copy_from_slice(serial.as_ref());
+        let key = super::diversify_key(&root_key, &serial);
+        assert_eq!(key.as_ref(), Sha256::digest(message).as_ref());
+
+        // A serial number built from its bytes is the same.
+        let mut bytes = [0x00; 9];
+        bytes.copy_from_slice(serial.as_ref());
+        let other = super::diversify_key(&root_key, &Serial::from(bytes));
+        assert_eq!(key.as_ref(), other.as_ref());
+        bytes[0] ^= 0x01;
+        let other = super::diversify_key(&root_key, &Serial::from(bytes));
+        assert_ne!(key.as_ref(), other.as_ref());
+    }
+
+    #[test]
+    fn derive_key_mac() {
+        let mut key = Block::default();
+        key.as_mut().iter_mut().for_each(|v| *v = 0x11);
+        let serial = serial();
+        let mut message = [0x00; 39];
+        message[..32].copy_from_slice(key.as_ref());
+        message[32..36].copy_from_slice(&[0x1c, 0x00, 0x0a, 0x00]);
+        message[36..39].copy_from_slice(&[0xee, 0x01, 0x23]);
+        let mac = super::derive_key_mac(&key, Slot::Certificate0a, &serial);
+        assert_eq!(mac.as_ref(), Sha256::digest(message).as_ref());
+
+        // TempKey only enters the derived key, never the MAC.
+        let derived = super::derive_key(&key, Slot::Certificate0a, &serial, &Block::default());
+        assert_ne!(derived.as_ref(), mac.as_ref());
+    }
+
+    #[test]
+    fn mac_matches_check_mac() {
+        let mut key = Block::default();
+        key.as_mut().iter_mut().for_each(|v| *v = 0x11);
+        let mut challenge = Block::default();
+        challenge.as_mut().iter_mut().for_each(|v| *v = 0x22);
+        let mode = MacMode::default();
+        let serial = serial();
+
+        let expected = mac(mode, Slot::Data08, &key, &challenge, &serial, None).unwrap();
+        // OtherData carries op-code, mode, param2 and the optional fields.
+        let mut other_data = [0x00; 13];
+        other_data[0..4].copy_from_slice(&[OpCode::Mac as u8, 0x00, 0x08, 0x00]);
+        let actual = check_mac(mode, &key, &challenge, &other_data, &serial, None).unwrap();
+        assert_eq!(expected.as_ref(), actual.as_ref());
+
+        // OTP is required when the mode includes it.
+        let mode = mode.include_otp_64();
+        assert!(mac(mode, Slot::Data08, &key, &challenge, &serial, None).is_none());
+    }
+
+    #[test]
+    fn attestation_message() {
+        let mut challenge = Block::default();
+        challenge.as_mut().iter_mut().for_each(|v| *v = 0x33);
+        let mut public_key = PublicKey::default();
+        public_key.as_mut().iter_mut().for_each(|v| *v = 0x44);
+        let serial = serial();
+        let sn = serial.as_ref();
+
+        // GenKey digests a full 128-byte message.
+        let mut message = [0x00; 128];
+        message[..32].copy_from_slice(challenge.as_ref());
+        message[32..36].copy_from_slice(&[OpCode::GenKey as u8, 0x01, 0x02, 0x03]);
+        message[36] = sn[8];
+        message[37..39].copy_from_slice(&sn[0..2]);
+        message[64..].copy_from_slice(public_key.as_ref());
+        let tempkey = gen_key_digest(&[0x01, 0x02, 0x03], &serial, &public_key, &challenge);
+        assert_eq!(tempkey.as_ref(), Sha256::digest(message).as_ref());
+
+        let sign_internal = SignInternalMessage::from([0x55; SignInternalMessage::SIZE]);
+        let mut message = [0x55; 32 + SignInternalMessage::SIZE];
+        message[..32].copy_from_slice(tempkey.as_ref());
+        let digest = attestation_digest(
+            &challenge,
+            &[0x01, 0x02, 0x03],
+            &serial,
+            &public_key,
+            &sign_internal,
+        );
+        assert_eq!(digest.as_ref(), Sha256::digest(message).as_ref());
+    }
+
+    #[test]
+    fn io_decrypt_blocks() {
+        let mut io_key = Block::default();
+        io_key.as_mut().iter_mut().for_each(|v| *v = 0x11);
+        let mut nonce = Block::default();
+        nonce.as_mut()[16..].iter_mut().for_each(|v| *v = 0x22);
+
+        let mut data = [0x00; 64];
+        io_decrypt(&io_key, &nonce, &mut data);
+        let key = Sha256::new().update(io_key).update([0x00; 16]).finalize();
+        assert_eq!(&data[..32], key.as_ref());
+        let key = Sha256::new().update(io_key).update([0x22; 16]).finalize();
+        assert_eq!(&data[32..], key.as_ref());
+    }
+}
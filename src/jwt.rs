@@ -0,0 +1,186 @@
+// Compact JWS tokens signed with ES256 by a slot key, as used for token
+// authentication by cloud IoT services. The signing input is hashed on the
+// device and the raw r || s signature it returns is exactly what ES256
+// expects, so no DER conversion is involved.
+use super::client::AtCaClient;
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind};
+use super::memory::Slot;
+use core::fmt::Write;
+use embedded_hal::delay::DelayNs;
+use heapless::String;
+
+pub const JWT_SIZE_MAX: usize = 512;
+pub const CLAIMS_SIZE_MAX: usize = 256;
+
+pub type Jwt = String<JWT_SIZE_MAX>;
+
+// {"alg":"ES256","typ":"JWT"}
+const HEADER: &str = "eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCJ9";
+
+const BASE64URL: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// Registered claims commonly required by IoT token authentication.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Claims<'a> {
+    issuer: Option<&'a str>,
+    subject: Option<&'a str>,
+    audience: Option<&'a str>,
+    issued_at: Option<u64>,
+    expires_at: Option<u64>,
+}
+
+impl<'a> Claims<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn issuer(mut self, issuer: &'a str) -> Self {
+        self.issuer.replace(issuer);
+        self
+    }
+
+    pub fn subject(mut self, subject: &'a str) -> Self {
+        self.subject.replace(subject);
+        self
+    }
+
+    pub fn audience(mut self, audience: &'a str) -> Self {
+        self.audience.replace(audience);
+        self
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn issued_at(mut self, time: u64) -> Self {
+        self.issued_at.replace(time);
+        self
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn expires_at(mut self, time: u64) -> Self {
+        self.expires_at.replace(time);
+        self
+    }
+
+    /// JSON object holding the claims that were set.
+    pub fn to_json(&self) -> Result<String<CLAIMS_SIZE_MAX>, Error> {
+        let mut json = String::new();
+        let mut separator = "{";
+        let strings = [
+            ("iss", self.issuer),
+            ("sub", self.subject),
+            ("aud", self.audience),
+        ];
+        for (name, value) in strings.iter() {
+            if let Some(value) = value {
+                write!(json, "{}\"{}\":", separator, name).map_err(|_| small_buffer())?;
+                json_string(&mut json, value)?;
+                separator = ",";
+            }
+        }
+        let numbers = [("iat", self.issued_at), ("exp", self.expires_at)];
+        for (name, value) in numbers.iter() {
+            if let Some(value) = value {
+                write!(json, "{}\"{}\":{}", separator, name, value).map_err(|_| small_buffer())?;
+                separator = ",";
+            }
+        }
+        if separator == "{" {
+            json.push('{').map_err(|()| small_buffer())?;
+        }
+        json.push('}').map_err(|()| small_buffer())?;
+        Ok(json)
+    }
+}
+
+/// Build `header.payload.signature` for the JSON `claims`, signed by the
+/// private key in `key_id`.
+pub fn create_jwt<PHY, D, const N: usize>(
+    atca: &mut AtCaClient<PHY, D, N>,
+    key_id: Slot,
+    claims: &str,
+) -> Result<Jwt, Error>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    let mut jwt = Jwt::new();
+    jwt.push_str(HEADER).map_err(|()| small_buffer())?;
+    jwt.push('.').map_err(|()| small_buffer())?;
+    base64url(&mut jwt, claims.as_bytes())?;
+    let digest = atca.sha().digest(jwt.as_bytes())?;
+    let signature = atca.sign(key_id, &digest)?;
+    jwt.push('.').map_err(|()| small_buffer())?;
+    base64url(&mut jwt, signature.as_ref())?;
+    Ok(jwt)
+}
+
+// Unpadded base64url, appended to `dst`.
+fn base64url<const N: usize>(dst: &mut String<N>, src: &[u8]) -> Result<(), Error> {
+    for chunk in src.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+            bits | u32::from(*byte) << (16 - 8 * i)
+        });
+        for i in 0..=chunk.len() {
+            let index = (bits >> (18 - 6 * i)) & 0x3f;
+            dst.push(char::from(BASE64URL[index as usize]))
+                .map_err(|()| small_buffer())?;
+        }
+    }
+    Ok(())
+}
+
+// Quoted JSON string with the characters JSON requires escaped.
+fn json_string<const N: usize>(dst: &mut String<N>, value: &str) -> Result<(), Error> {
+    dst.push('"').map_err(|()| small_buffer())?;
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => write!(dst, "\\{}", c),
+            c if c < ' ' => write!(dst, "\\u{:04x}", u32::from(c)),
+            c => write!(dst, "{}", c),
+        }
+        .map_err(|_| small_buffer())?;
+    }
+    dst.push('"').map_err(|()| small_buffer())
+}
+
+fn small_buffer() -> Error {
+    ErrorKind::SmallBuffer.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64url() {
+        let encode = |src: &[u8]| {
+            let mut dst = String::<16>::new();
+            super::base64url(&mut dst, src).unwrap();
+            dst
+        };
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode(b"f"), "Zg");
+        assert_eq!(encode(b"fo"), "Zm8");
+        assert_eq!(encode(b"foo"), "Zm9v");
+        assert_eq!(encode(b"foob"), "Zm9vYg");
+        assert_eq!(encode(&[0xfb, 0xff]), "-_8");
+
+        let mut header = String::<64>::new();
+        super::base64url(&mut header, br#"{"alg":"ES256","typ":"JWT"}"#).unwrap();
+        assert_eq!(header, HEADER);
+    }
+
+    #[test]
+    fn claims() {
+        let claims = Claims::new()
+            .audience("my-\"project\"")
+            .issued_at(1_600_000_000)
+            .expires_at(1_600_003_600);
+        assert_eq!(
+            claims.to_json().unwrap(),
+            r#"{"aud":"my-\"project\"","iat":1600000000,"exp":1600003600}"#
+        );
+        assert_eq!(Claims::new().to_json().unwrap(), "{}");
+    }
+}
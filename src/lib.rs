@@ -1,16 +1,67 @@
 #![no_std]
+#[cfg(feature = "rustls")]
+extern crate std;
+
 mod fmt;
 
+pub mod aes;
+pub mod auth;
+#[cfg(feature = "serde")]
+mod byte_array;
+pub mod cert;
 mod client;
 mod clock_divider;
 mod command;
+pub mod config;
+#[cfg(feature = "cose")]
+pub mod cose;
 mod datalink;
+mod device;
+#[cfg(feature = "embedded-tls")]
+pub mod embedded_tls;
 pub mod error;
+pub mod fwupdate;
+#[cfg(feature = "digest")]
+mod hasher;
+pub mod host;
+#[cfg(feature = "jwt")]
+pub mod jwt;
 pub mod memory;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod nb;
+pub mod onboarding;
 mod packet;
+pub mod profile;
+pub mod provision;
+mod retry;
+#[cfg(feature = "rustls")]
+pub mod rustls;
+pub mod secure_boot;
+pub mod session;
+pub mod sync;
+mod tempkey;
+pub mod tls;
 pub mod tngtls;
+pub mod transaction;
 
-pub use client::{AtCaClient, Memory, Verifier, Verify};
-pub use command::{Block, Digest, PublicKey, Signature};
-pub use packet::CRC16;
+pub use client::{
+    AtCaClient, DeviceRng, Memory, Sign, SlotSigner, Verifier, Verify, BUFFER_SIZE, BUFFER_SIZE_MIN,
+};
+pub use command::{
+    Block, CountMatch, CounterId, CounterValue, DerSignature, DeviceState, Digest, EcdhTarget,
+    GenDigSource, HkdfMessage, KdfSource, KdfTarget, KeyValid, MacMode, NonceTarget,
+    PremasterSecret, PublicKey, SelfTestMode, SelfTestResult, Serial, Sha256Context, Signature,
+    UpdateExtraMode, VolatileKeyPermit,
+};
+#[cfg(feature = "std-linux")]
+pub use datalink::linux;
+pub use datalink::shared::SharedI2c;
+pub use datalink::{remote, swi, trace, Interface, PowerState};
+pub use device::{DeviceType, Revision};
+#[cfg(feature = "digest")]
+pub use hasher::Hasher;
+pub use packet::{crc16, parse_response, CRC16};
+pub use retry::RetryPolicy;
 pub use signature;
+pub use tempkey::{TempKeySource, TempKeyState};
@@ -1,11 +1,12 @@
 use super::error::{Error, ErrorKind};
+use core::convert::{TryFrom, TryInto};
 use core::ops::{Range, RangeInclusive};
-use core::slice::from_ref;
 /// Zone bit 7 set: Access 32 bytes, otherwise 4 bytes.
 const ZONE_READWRITE_32: u8 = 0x80;
 
 /// A unit of data exchange is either 4 or 32 bytes.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Size {
     Word = 0x04,
     Block = 0x20,
@@ -21,12 +22,25 @@ impl Size {
 }
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Zone {
     Config = 0x00,
     Data = 0x01,
     Otp = 0x02,
 }
 
+/// Permission to lock a zone or slot. Locking cannot be undone, and a
+/// device locked with the wrong configuration is unusable, so every lock
+/// consumes a token created by an explicit call.
+#[derive(Debug)]
+pub struct LockToken(());
+
+impl LockToken {
+    pub fn i_understand_this_is_irreversible() -> Self {
+        Self(())
+    }
+}
+
 impl Zone {
     // A helper method to translate a global index into block and offset.
     pub fn locate_index(index: usize) -> (u8, u8, u8) {
@@ -38,8 +52,7 @@ impl Zone {
 
     pub(crate) fn get_slot_addr(&self, slot: Slot, block: u8) -> Result<u16, Error> {
         match self {
-            Self::Data if slot.is_private_key() && block == 0 => Ok((slot as u16) << 3),
-            Self::Data if slot.is_certificate() && block <= 2 => {
+            Self::Data if (block as usize) < slot.blocks() => {
                 Ok((slot as u16) << 3 | (block as u16) << 8)
             }
             _ => Err(ErrorKind::BadParam.into()),
@@ -68,7 +81,17 @@ impl Zone {
     }
 }
 
+/// Size of the largest slot, Data08.
+pub const SLOT_MAX_SIZE: usize = 416;
+
+/// Size of all data slots together.
+pub const DATA_ZONE_SIZE: usize = 1208;
+
+/// Size of the OTP zone.
+pub const OTP_ZONE_SIZE: usize = 64;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum Slot {
     /// PrivateKey0x contains 36 bytes, taking 2 block reads.
     PrivateKey00 = 0x00,
@@ -102,6 +125,20 @@ impl Slot {
         Self::Certificate09 <= *self
     }
 
+    /// Number of bytes the slot holds.
+    pub fn size(&self) -> usize {
+        match self {
+            slot if slot.is_private_key() => 36,
+            Self::Data08 => SLOT_MAX_SIZE,
+            _ => 72,
+        }
+    }
+
+    // Blocks addressable in the slot, the last one possibly partial.
+    pub(crate) fn blocks(&self) -> usize {
+        self.size().div_ceil(Size::Block.len())
+    }
+
     pub fn keys() -> KeysIter {
         KeysIter(0x00..=0x0f)
     }
@@ -135,23 +172,67 @@ impl Iterator for KeysIter {
     }
 }
 
-pub(crate) struct CertificateRepr(RangeInclusive<usize>);
+/// Contents of every data slot, in slot order, as in the image the data
+/// zone lock CRC is computed over.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DataZoneImage(
+    #[cfg_attr(feature = "serde", serde(with = "crate::byte_array"))] [u8; DATA_ZONE_SIZE],
+);
+
+impl DataZoneImage {
+    pub fn slot(&self, slot: Slot) -> &[u8] {
+        &self.0[Self::range(slot)]
+    }
+
+    pub fn slot_mut(&mut self, slot: Slot) -> &mut [u8] {
+        &mut self.0[Self::range(slot)]
+    }
 
-impl CertificateRepr {
-    pub(crate) fn new() -> Self {
-        Self(0..=2)
+    // Fill the start of a slot with `data`.
+    pub fn set_slot(&mut self, slot: Slot, data: &[u8]) -> Result<&mut Self, Error> {
+        self.slot_mut(slot)
+            .get_mut(..data.len())
+            .ok_or_else(|| Error::from(ErrorKind::InvalidSize))?
+            .copy_from_slice(data);
+        Ok(self)
+    }
+
+    fn range(slot: Slot) -> Range<usize> {
+        let start = Slot::keys()
+            .take_while(|key_id| *key_id != slot)
+            .map(|key_id| key_id.size())
+            .sum();
+        start..start + slot.size()
     }
 }
 
-impl Iterator for CertificateRepr {
-    type Item = &'static [Range<usize>];
-    fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().and_then(|i| match i {
-            0 => from_ref(&(0x04..0x20)).into(),
-            1 => [0x00..0x04, 0x08..0x20].as_ref().into(),
-            2 => from_ref(&(0x00..0x08)).into(),
-            _ => None,
-        })
+impl Default for DataZoneImage {
+    fn default() -> Self {
+        Self([0x00; DATA_ZONE_SIZE])
+    }
+}
+
+impl From<[u8; DATA_ZONE_SIZE]> for DataZoneImage {
+    fn from(bytes: [u8; DATA_ZONE_SIZE]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for DataZoneImage {
+    type Error = Error;
+    fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+        buffer
+            .try_into()
+            .map(Self)
+            .map_err(|_| ErrorKind::InvalidSize.into())
+    }
+}
+
+impl AsRef<[u8]> for DataZoneImage {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
     }
 }
 
@@ -159,9 +240,6 @@ impl Iterator for CertificateRepr {
 mod tests {
     use super::*;
     use crate::client::Memory;
-    use core::convert::identity;
-    use core::iter::repeat;
-    use heapless::Vec;
     use Slot::*;
     use Zone::*;
 
@@ -188,6 +266,26 @@ mod tests {
             let result = Data.get_slot_addr(Certificate0f, block).unwrap();
             assert_eq!(addr, result);
         }
+        assert_eq!(0x0138, Data.get_slot_addr(PrivateKey07, 1).unwrap());
+        assert_eq!(0x0c40, Data.get_slot_addr(Data08, 12).unwrap());
+        assert!(Data.get_slot_addr(PrivateKey07, 2).is_err());
+        assert!(Data.get_slot_addr(Certificate0f, 3).is_err());
+        assert!(Data.get_slot_addr(Data08, 13).is_err());
+    }
+
+    #[test]
+    fn data_zone_image() {
+        assert_eq!(
+            Slot::keys().map(|slot| slot.size()).sum::<usize>(),
+            DATA_ZONE_SIZE
+        );
+        let mut image = DataZoneImage::default();
+        image.set_slot(Data08, &[0x5a; 8]).unwrap();
+        image.set_slot(Certificate0f, &[0xa5; 72]).unwrap();
+        assert!(image.set_slot(PrivateKey07, &[0x00; 40]).is_err());
+        assert_eq!(image.as_ref()[288..296], [0x5a; 8]);
+        assert_eq!(image.as_ref()[DATA_ZONE_SIZE - 72..], [0xa5; 72][..]);
+        assert_eq!(image.slot(Data08).len(), SLOT_MAX_SIZE);
     }
 
     #[test]
@@ -196,29 +294,4 @@ mod tests {
         assert_eq!(0x0016, Config.get_addr(2, 6).unwrap());
         assert_eq!(0x0018, Config.get_addr(3, 0).unwrap());
     }
-
-    #[test]
-    fn certificate_representation() {
-        let slot_buffer = repeat(0)
-            .take(4)
-            .chain(0x00..0x20)
-            .chain(repeat(0).take(4))
-            .chain(0x20..0x40)
-            .chain(repeat(0).take(24))
-            .collect::<Vec<u8, 96>>();
-        assert_eq!(Size::Block.len() * 3, slot_buffer.len());
-        slot_buffer
-            .chunks(Size::Block.len())
-            .zip(CertificateRepr::new())
-            .scan(0, |i, (chunk, ranges)| {
-                for range in ranges {
-                    for j in &chunk[range.clone()] {
-                        assert_eq!(i, j);
-                        *i += 1;
-                    }
-                }
-                Some(())
-            })
-            .for_each(identity)
-    }
 }
@@ -0,0 +1,822 @@
+// Software model of an ATECC608B for running code against the client without
+// hardware, in CI for instance. `MockDevice` implements `Interface`, so it is
+// passed to `AtCaClient::new` in place of an I2C bus and answers commands
+// with the status codes the device would.
+//
+// Modelled: config, OTP and data zone storage with lock semantics, Info,
+// Random, Nonce and TempKey, SHA and HMAC, MAC, Counter, and with P-256
+// keys, GenKey, Sign and Verify over an external message, and ECDH with a
+// private key in a slot. The remaining commands and modes, among them
+// internal Sign, Verify with a MAC and ECDH with encrypted output, answer
+// with an execution error, as does anything depending on slot access
+// policies beyond lock state.
+use super::command::{MacMode, OpCode, PublicKey, Serial, Signature};
+use super::config::CONFIG_ZONE_SIZE;
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind, Status};
+use super::host::{self, Sha256};
+use super::memory::{Size, Slot, SLOT_MAX_SIZE};
+use super::packet::CRC16;
+use super::Block;
+use core::convert::TryFrom;
+use core::ops::Range;
+use heapless::Vec;
+use p256::ecdsa::{SigningKey, VerifyingKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::SecretKey;
+use signature::hazmat::{PrehashSigner, PrehashVerifier};
+
+pub const OTP_SIZE: usize = 64;
+
+const WAKE_RESPONSE: [u8; 4] = [0x04, 0x11, 0x33, 0x43];
+const RESPONSE_SIZE_MAX: usize = 3 + 64;
+
+// Config zone offsets.
+const I2C_ADDRESS: usize = 16;
+const USER_EXTRA: usize = 84;
+const LOCK_VALUE: usize = 86;
+const LOCK_CONFIG: usize = 87;
+const SLOT_LOCKED: usize = 88;
+const KEY_CONFIG: usize = 96;
+const UNLOCKED: u8 = 0x55;
+
+// KeyConfig bit telling that the slot holds a private key.
+const KEY_CONFIG_PRIVATE: u8 = 0x01;
+
+const COUNTER_MAX: u32 = 2_097_151;
+
+// Op-codes of the modelled commands.
+const INFO: u8 = OpCode::Info as u8;
+const READ: u8 = OpCode::Read as u8;
+const WRITE: u8 = OpCode::Write as u8;
+const LOCK: u8 = OpCode::Lock as u8;
+const RANDOM: u8 = OpCode::Random as u8;
+const NONCE: u8 = OpCode::Nonce as u8;
+const SHA: u8 = OpCode::Sha as u8;
+const MAC: u8 = OpCode::Mac as u8;
+const COUNTER: u8 = OpCode::Counter as u8;
+const GENKEY: u8 = OpCode::GenKey as u8;
+const SIGN: u8 = OpCode::Sign as u8;
+const VERIFY: u8 = OpCode::Verify as u8;
+const ECDH: u8 = OpCode::Ecdh as u8;
+// Known to the device but not modelled.
+const OTHERS: [OpCode; 11] = [
+    OpCode::CheckMac,
+    OpCode::DeriveKey,
+    OpCode::GenDig,
+    OpCode::HMac,
+    OpCode::Pause,
+    OpCode::PrivWrite,
+    OpCode::UpdateExtra,
+    OpCode::Aes,
+    OpCode::Kdf,
+    OpCode::SecureBoot,
+    OpCode::SelfTest,
+];
+
+// Factory state of a blank device: serial number, revision, I2C enabled at
+// 0x60, everything unlocked.
+const CONFIG_DEFAULT: [u8; 20] = [
+    0x01, 0x23, 0x6b, 0x1f, 0x00, 0x00, 0x60, 0x03, 0x9d, 0x5a, 0x0e, 0x4c, 0xee, 0x01, 0x01, 0x00,
+    0xc0, 0x00, 0x00, 0x00,
+];
+
+type Output = Vec<u8, 64>;
+
+pub struct MockDevice {
+    config: [u8; CONFIG_ZONE_SIZE],
+    otp: [u8; OTP_SIZE],
+    slots: [[u8; SLOT_MAX_SIZE]; 16],
+    counters: [u32; 2],
+    awake: bool,
+    tempkey: Option<Block>,
+    message_digest: Option<Block>,
+    sha: Option<Sha256>,
+    hmac_key: Option<Block>,
+    seed: u64,
+    response: Vec<u8, RESPONSE_SIZE_MAX>,
+    read: usize,
+}
+
+impl Default for MockDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockDevice {
+    pub fn new() -> Self {
+        let mut config = [0x00; CONFIG_ZONE_SIZE];
+        config[..CONFIG_DEFAULT.len()].copy_from_slice(&CONFIG_DEFAULT);
+        config[LOCK_VALUE] = UNLOCKED;
+        config[LOCK_CONFIG] = UNLOCKED;
+        config[SLOT_LOCKED..SLOT_LOCKED + 2].copy_from_slice(&[0xff, 0xff]);
+        Self {
+            config,
+            otp: [0x00; OTP_SIZE],
+            slots: [[0x00; SLOT_MAX_SIZE]; 16],
+            counters: [0; 2],
+            awake: false,
+            tempkey: None,
+            message_digest: None,
+            sha: None,
+            hmac_key: None,
+            seed: 0x2545_f491_4f6c_dd1d,
+            response: Vec::new(),
+            read: 0,
+        }
+    }
+
+    // Seed of the random number generator, for tests depending on the
+    // values it draws.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed | 0x01;
+        self
+    }
+
+    // Preload a slot, bypassing lock state and access policies.
+    pub fn with_slot(mut self, slot: Slot, data: &[u8]) -> Self {
+        self.slots[slot as usize][..data.len()].copy_from_slice(data);
+        self
+    }
+
+    pub fn config_zone(&self) -> &[u8; CONFIG_ZONE_SIZE] {
+        &self.config
+    }
+
+    pub fn otp_zone(&self) -> &[u8; OTP_SIZE] {
+        &self.otp
+    }
+
+    pub fn slot(&self, slot: Slot) -> &[u8] {
+        &self.slots[slot as usize][..slot.size()]
+    }
+
+    fn serial(&self) -> Serial {
+        Serial::try_from(&self.config[..Size::Block.len()]).unwrap_or_else(|_| unreachable!())
+    }
+
+    fn is_config_locked(&self) -> bool {
+        self.config[LOCK_CONFIG] != UNLOCKED
+    }
+
+    fn is_data_locked(&self) -> bool {
+        self.config[LOCK_VALUE] != UNLOCKED
+    }
+
+    fn is_slot_locked(&self, index: usize) -> bool {
+        let locked = u16::from_le_bytes([self.config[SLOT_LOCKED], self.config[SLOT_LOCKED + 1]]);
+        locked & 1 << index == 0
+    }
+
+    fn is_private(&self, index: usize) -> bool {
+        self.config[KEY_CONFIG + 2 * index] & KEY_CONFIG_PRIVATE != 0x00
+    }
+
+    // xorshift64*, plenty for a model.
+    fn random(&mut self) -> Block {
+        let mut block = Block::default();
+        // Before the config zone is locked, the device returns a fixed
+        // pattern instead of random numbers.
+        if !self.is_config_locked() {
+            for (i, byte) in block.as_mut().iter_mut().enumerate() {
+                *byte = if i % 4 < 2 { 0xff } else { 0x00 };
+            }
+            return block;
+        }
+        for chunk in block.as_mut().chunks_mut(8) {
+            self.seed ^= self.seed >> 12;
+            self.seed ^= self.seed << 25;
+            self.seed ^= self.seed >> 27;
+            let value = self.seed.wrapping_mul(0x2545_f491_4f6c_dd1d);
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        block
+    }
+
+    fn respond(&mut self, data: &[u8]) {
+        self.response.clear();
+        self.response.push(data.len() as u8 + 3).ok();
+        self.response.extend_from_slice(data).ok();
+        let crc = CRC16.checksum(&self.response).to_le_bytes();
+        self.response.extend_from_slice(&crc).ok();
+        self.read = 0;
+    }
+
+    fn command(&mut self, packet: &[u8]) {
+        let result = match packet {
+            [count, opcode, mode, lsb, msb, ..] if usize::from(*count) == packet.len() => {
+                let (body, crc) = packet.split_at(packet.len() - 2);
+                if CRC16.checksum(body).to_le_bytes() != crc {
+                    Err(Status::Crc)
+                } else {
+                    let param2 = u16::from_le_bytes([*lsb, *msb]);
+                    self.execute(*opcode, *mode, param2, &body[5..])
+                }
+            }
+            _ => Err(Status::Parse),
+        };
+        match result {
+            Ok(output) if output.is_empty() => self.respond(&[0x00]),
+            Ok(output) => self.respond(&output),
+            Err(status) => self.respond(&[status as u8]),
+        }
+    }
+
+    fn execute(
+        &mut self,
+        opcode: u8,
+        mode: u8,
+        param2: u16,
+        data: &[u8],
+    ) -> Result<Output, Status> {
+        match opcode {
+            INFO => self.info(mode),
+            READ => self.read(mode, param2),
+            WRITE => self.write(mode, param2, data),
+            LOCK => self.lock(mode, param2),
+            RANDOM => output(self.random().as_ref()),
+            NONCE => self.nonce(mode, data),
+            SHA => self.sha(mode, param2, data),
+            MAC => self.mac(mode, param2, data),
+            COUNTER => self.counter(mode, param2),
+            GENKEY => self.gen_key(mode, param2),
+            SIGN => self.sign(mode, param2),
+            VERIFY => self.verify(mode, param2, data),
+            ECDH => self.ecdh(mode, param2, data),
+            opcode if OTHERS.iter().any(|other| *other as u8 == opcode) => Err(Status::Execution),
+            _ => Err(Status::Parse),
+        }
+    }
+
+    fn info(&mut self, mode: u8) -> Result<Output, Status> {
+        match mode {
+            // Revision
+            0x00 => output(&self.config[4..8]),
+            _ => Err(Status::Parse),
+        }
+    }
+
+    // Zone, index of the slot for the data zone, and byte range addressed.
+    fn locate(&self, mode: u8, param2: u16) -> Result<(u8, usize, Range<usize>), Status> {
+        let size = if mode & 0x80 != 0x00 {
+            Size::Block.len()
+        } else {
+            Size::Word.len()
+        };
+        let offset = usize::from(param2 & 0x07) * Size::Word.len();
+        let (zone, index, block, zone_size) = match mode & 0x03 {
+            0x00 => (0x00, 0, param2 >> 3 & 0x03, CONFIG_ZONE_SIZE),
+            0x01 => {
+                let index = usize::from(param2 >> 3 & 0x0f);
+                let slot = Slot::keys().nth(index).ok_or(Status::Parse)?;
+                (0x01, index, param2 >> 8, slot.size())
+            }
+            0x02 => (0x02, 0, param2 >> 3 & 0x03, OTP_SIZE),
+            _ => return Err(Status::Parse),
+        };
+        let start = usize::from(block) * Size::Block.len() + offset;
+        // Blocks are aligned, so only a word offset into a block is allowed
+        // with word access.
+        if size == Size::Block.len() && offset != 0 || start + size > zone_size {
+            return Err(Status::Parse);
+        }
+        Ok((zone, index, start..start + size))
+    }
+
+    fn read(&mut self, mode: u8, param2: u16) -> Result<Output, Status> {
+        let (zone, index, range) = self.locate(mode, param2)?;
+        match zone {
+            0x00 => output(&self.config[range]),
+            0x01 if !self.is_data_locked() || self.is_private(index) => Err(Status::Execution),
+            0x01 => output(&self.slots[index][range]),
+            _ => output(&self.otp[range]),
+        }
+    }
+
+    fn write(&mut self, mode: u8, param2: u16, data: &[u8]) -> Result<Output, Status> {
+        // Encrypted writes are not modelled.
+        if mode & 0x40 != 0x00 {
+            return Err(Status::Execution);
+        }
+        let (zone, index, range) = self.locate(mode, param2)?;
+        if data.len() != range.len() {
+            return Err(Status::Parse);
+        }
+        match zone {
+            0x00 => {
+                // The first 16 bytes are fixed at the factory. UserExtra and
+                // the lock bytes are written by their own commands.
+                let fixed = range.start < 16 || range.start < SLOT_LOCKED && USER_EXTRA < range.end;
+                if self.is_config_locked() || fixed {
+                    return Err(Status::Execution);
+                }
+                self.config[range].copy_from_slice(data);
+            }
+            0x01 => {
+                if !self.is_config_locked() {
+                    return Err(Status::Execution);
+                }
+                let locked = self.is_slot_locked(index);
+                if self.is_data_locked() && (locked || self.is_private(index)) {
+                    return Err(Status::Execution);
+                }
+                self.slots[index][range].copy_from_slice(data);
+            }
+            _ => {
+                if !self.is_config_locked() || self.is_data_locked() {
+                    return Err(Status::Execution);
+                }
+                self.otp[range].copy_from_slice(data);
+            }
+        }
+        Ok(Output::new())
+    }
+
+    fn lock(&mut self, mode: u8, param2: u16) -> Result<Output, Status> {
+        let check = |contents: &[&[u8]]| {
+            let mut digest = CRC16.digest();
+            contents.iter().for_each(|bytes| digest.update(bytes));
+            mode & 0x80 != 0x00 || digest.finalize() == param2
+        };
+        match mode & 0x03 {
+            0x00 if !self.is_config_locked() && check(&[&self.config]) => {
+                self.config[LOCK_CONFIG] = 0x00;
+            }
+            0x01 if self.is_config_locked() && !self.is_data_locked() => {
+                let slots = &self.slots;
+                let mut contents: Vec<&[u8], 17> = Slot::keys()
+                    .map(|slot| &slots[slot as usize][..slot.size()])
+                    .collect();
+                contents.push(&self.otp).ok();
+                if !check(&contents) {
+                    return Err(Status::Execution);
+                }
+                self.config[LOCK_VALUE] = 0x00;
+            }
+            0x02 => {
+                let index = usize::from(mode >> 2 & 0x0f);
+                let slot = Slot::keys().nth(index).ok_or(Status::Parse)?;
+                let contents = &self.slots[index][..slot.size()];
+                if !self.is_data_locked() || self.is_slot_locked(index) || !check(&[contents]) {
+                    return Err(Status::Execution);
+                }
+                let bit = index % 8;
+                self.config[SLOT_LOCKED + index / 8] &= !(1 << bit);
+            }
+            0x03 => return Err(Status::Parse),
+            _ => return Err(Status::Execution),
+        }
+        Ok(Output::new())
+    }
+
+    fn nonce(&mut self, mode: u8, data: &[u8]) -> Result<Output, Status> {
+        match (mode & 0x03, data.len()) {
+            (0x00, 20) | (0x01, 20) => {
+                let rand_out = self.random();
+                let digest = Sha256::new()
+                    .update(rand_out)
+                    .update(data)
+                    .update([OpCode::Nonce as u8, mode, 0x00])
+                    .finalize();
+                self.tempkey = Block::try_from(digest.as_ref()).ok();
+                output(rand_out.as_ref())
+            }
+            (0x03, 32) | (0x03, 64) => {
+                let (first, second) = data.split_at(32);
+                let first = Block::try_from(first).ok();
+                match mode & 0xc0 {
+                    0x00 => self.tempkey = first,
+                    0x40 => self.message_digest = first,
+                    // Alternate key buffer, unused by the model.
+                    0x80 => {}
+                    _ => return Err(Status::Parse),
+                }
+                if !second.is_empty() {
+                    self.message_digest = Block::try_from(second).ok();
+                }
+                Ok(Output::new())
+            }
+            _ => Err(Status::Parse),
+        }
+    }
+
+    fn sha(&mut self, mode: u8, param2: u16, data: &[u8]) -> Result<Output, Status> {
+        match mode & 0x07 {
+            0x00 => {
+                self.sha.replace(Sha256::new());
+                self.hmac_key = None;
+                Ok(Output::new())
+            }
+            0x04 => {
+                let index = usize::from(param2 & 0x0f);
+                let key = Block::try_from(&self.slots[index][..32]).unwrap_or_default();
+                let mut pad = [0x36; 64];
+                pad.iter_mut()
+                    .zip(key.as_ref())
+                    .for_each(|(pad, key)| *pad ^= key);
+                let mut sha = Sha256::new();
+                sha.update(pad);
+                self.sha.replace(sha);
+                self.hmac_key.replace(key);
+                Ok(Output::new())
+            }
+            0x01 if data.len() == 64 && usize::from(param2) == data.len() => {
+                self.sha.as_mut().ok_or(Status::Execution)?.update(data);
+                Ok(Output::new())
+            }
+            0x02 if data.len() <= 64 && usize::from(param2) == data.len() => {
+                let mut sha = self.sha.take().ok_or(Status::Execution)?;
+                let mut digest = sha.update(data).finalize();
+                if let Some(key) = self.hmac_key.take() {
+                    let mut pad = [0x5c; 64];
+                    pad.iter_mut()
+                        .zip(key.as_ref())
+                        .for_each(|(pad, key)| *pad ^= key);
+                    digest = Sha256::new().update(pad).update(digest).finalize();
+                }
+                let block = Block::try_from(digest.as_ref()).ok();
+                match mode & 0xc0 {
+                    0x00 => self.tempkey = block,
+                    0x40 => self.message_digest = block,
+                    _ => {}
+                }
+                output(digest.as_ref())
+            }
+            // Context save and restore are not modelled.
+            0x06 | 0x07 => Err(Status::Execution),
+            _ => Err(Status::Parse),
+        }
+    }
+
+    fn mac(&mut self, mode: u8, param2: u16, data: &[u8]) -> Result<Output, Status> {
+        let mode = MacMode::from(mode);
+        let index = usize::from(param2 & 0x0f);
+        let slot = Slot::keys().nth(index).ok_or(Status::Parse)?;
+        let tempkey = self.tempkey.take();
+        let key = if u8::from(mode) & 0x02 != 0x00 {
+            tempkey.ok_or(Status::Execution)?
+        } else {
+            Block::try_from(&self.slots[index][..32]).unwrap_or_default()
+        };
+        let challenge = if mode.is_challenge_from_tempkey() {
+            tempkey.ok_or(Status::Execution)?
+        } else {
+            Block::try_from(data).map_err(|_| Status::Parse)?
+        };
+        let mut otp = [0x00; 11];
+        otp.copy_from_slice(&self.otp[..11]);
+        let digest = host::mac(mode, slot, &key, &challenge, &self.serial(), Some(&otp))
+            .ok_or(Status::Execution)?;
+        output(digest.as_ref())
+    }
+
+    fn counter(&mut self, mode: u8, param2: u16) -> Result<Output, Status> {
+        let counter = self
+            .counters
+            .get_mut(usize::from(param2))
+            .ok_or(Status::Parse)?;
+        match mode {
+            0x00 => {}
+            0x01 if *counter < COUNTER_MAX => *counter += 1,
+            0x01 => return Err(Status::Execution),
+            _ => return Err(Status::Parse),
+        }
+        output(&counter.to_le_bytes())
+    }
+
+    fn gen_key(&mut self, mode: u8, param2: u16) -> Result<Output, Status> {
+        // TempKey and the digest modes are not modelled.
+        let index = usize::from(param2);
+        self.check_private(index)?;
+        match mode {
+            // Private key
+            0x04 => {
+                if self.is_data_locked() && self.is_slot_locked(index) {
+                    return Err(Status::Execution);
+                }
+                // Draw until the value is a valid scalar, which it all but
+                // always is the first time.
+                let private_key = loop {
+                    let candidate = self.random();
+                    if SecretKey::from_be_bytes(candidate.as_ref()).is_ok() {
+                        break candidate;
+                    }
+                };
+                self.slots[index][..32].copy_from_slice(private_key.as_ref());
+            }
+            // Public key
+            0x00 => {}
+            _ => return Err(Status::Execution),
+        }
+        let public_key = PublicKey::from(self.private_key(index)?.public_key());
+        output(public_key.as_ref())
+    }
+
+    fn sign(&mut self, mode: u8, param2: u16) -> Result<Output, Status> {
+        // Only external messages; internal ones are built from GenDig.
+        if mode & 0x80 == 0x00 {
+            return Err(Status::Execution);
+        }
+        let index = usize::from(param2);
+        self.check_private(index)?;
+        let message = self.message(mode)?;
+        let signing_key = SigningKey::from(self.private_key(index)?);
+        let signature: p256::ecdsa::Signature = signing_key
+            .sign_prehash(message.as_ref())
+            .map_err(|_| Status::Execution)?;
+        output(Signature::from(signature).as_ref())
+    }
+
+    fn verify(&mut self, mode: u8, param2: u16, data: &[u8]) -> Result<Output, Status> {
+        // A MAC over the result needs the I/O protection key, not modelled.
+        if mode & 0x80 != 0x00 {
+            return Err(Status::Execution);
+        }
+        let (signature, public_key) = match (mode & 0x07, data.len()) {
+            // Stored
+            (0x00, 64) => {
+                let index = usize::from(param2);
+                let stored = self.slots.get(index).ok_or(Status::Parse)?;
+                let public_key = PublicKey::from_slot_format(&stored[..PublicKey::SLOT_FORMAT_LEN])
+                    .map_err(|_| Status::Execution)?;
+                (data, public_key)
+            }
+            // External
+            (0x02, 128) => {
+                let (signature, public_key) = data.split_at(64);
+                let public_key = PublicKey::try_from(public_key).map_err(|_| Status::Parse)?;
+                (signature, public_key)
+            }
+            (0x00, _) | (0x02, _) => return Err(Status::Parse),
+            // Validate, Invalidate and the ECC-only modes.
+            _ => return Err(Status::Execution),
+        };
+        let message = self.message(mode)?;
+        let public_key = p256::PublicKey::try_from(public_key).map_err(|_| Status::Execution)?;
+        let signature = Signature::try_from(signature)
+            .and_then(p256::ecdsa::Signature::try_from)
+            .map_err(|_| Status::CheckmacVerifyFailed)?;
+        VerifyingKey::from(public_key)
+            .verify_prehash(message.as_ref(), &signature)
+            .map_err(|_| Status::CheckmacVerifyFailed)?;
+        Ok(Output::new())
+    }
+
+    fn ecdh(&mut self, mode: u8, param2: u16, data: &[u8]) -> Result<Output, Status> {
+        // An ephemeral key in TempKey and encrypted output are not
+        // modelled.
+        if mode & 0x03 != 0x00 {
+            return Err(Status::Execution);
+        }
+        let index = usize::from(param2);
+        self.check_private(index)?;
+        let public_key = PublicKey::try_from(data)
+            .ok()
+            .and_then(|public_key| p256::PublicKey::try_from(public_key).ok())
+            .ok_or(Status::Parse)?;
+        let shared = (public_key.to_projective() * *self.private_key(index)?.to_nonzero_scalar())
+            .to_affine()
+            .to_encoded_point(false);
+        let premaster = shared.x().ok_or(Status::Execution)?;
+        match mode & 0x0c {
+            // Next slot
+            0x04 => {
+                let next = self.slots.get_mut(index + 1).ok_or(Status::Execution)?;
+                next[..32].copy_from_slice(premaster);
+                Ok(Output::new())
+            }
+            // TempKey
+            0x08 => {
+                self.tempkey = Block::try_from(premaster.as_slice()).ok();
+                Ok(Output::new())
+            }
+            // Output buffer
+            0x0c => output(premaster),
+            _ => Err(Status::Execution),
+        }
+    }
+
+    // Slots 0 to 7 configured for a private key, once the config zone is
+    // locked.
+    fn check_private(&self, index: usize) -> Result<(), Status> {
+        if !self.is_config_locked() || index >= 8 || !self.is_private(index) {
+            return Err(Status::Execution);
+        }
+        Ok(())
+    }
+
+    fn private_key(&self, index: usize) -> Result<SecretKey, Status> {
+        SecretKey::from_be_bytes(&self.slots[index][..32]).map_err(|_| Status::Execution)
+    }
+
+    // The 32-byte message of Sign and Verify, from the message digest
+    // buffer or TempKey.
+    fn message(&self, mode: u8) -> Result<Block, Status> {
+        let message = if mode & 0x20 != 0x00 {
+            self.message_digest
+        } else {
+            self.tempkey
+        };
+        message.ok_or(Status::Execution)
+    }
+}
+
+fn output(bytes: &[u8]) -> Result<Output, Status> {
+    Output::from_slice(bytes).map_err(|()| Status::Execution)
+}
+
+impl Interface for MockDevice {
+    fn wake(&mut self, _address: u8) {
+        if !self.awake {
+            self.awake = true;
+            self.response = Vec::from_slice(&WAKE_RESPONSE).unwrap_or_default();
+            self.read = 0;
+        }
+    }
+
+    fn send(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+        if !self.awake || address != self.config[I2C_ADDRESS] >> 1 {
+            return Err(ErrorKind::TxFail.into());
+        }
+        match bytes.split_first() {
+            // Reset the address counter of the output buffer.
+            Some((0x00, _)) => self.read = 0,
+            Some((0x01, _)) => {
+                self.awake = false;
+                self.tempkey = None;
+                self.message_digest = None;
+                self.sha = None;
+            }
+            Some((0x02, _)) => self.awake = false,
+            Some((0x03, packet)) => self.command(packet),
+            _ => return Err(ErrorKind::TxFail.into()),
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let end = self.read + buffer.len();
+        if !self.awake || address != self.config[I2C_ADDRESS] >> 1 || end > self.response.len() {
+            return Err(ErrorKind::RxFail.into());
+        }
+        buffer.copy_from_slice(&self.response[self.read..end]);
+        self.read = end;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::AtCaClient;
+    use crate::command::EcdhTarget;
+    use crate::datalink::tests::NoDelay;
+    use crate::memory::{LockToken, Zone};
+    use crate::provision::{Provisioner, SlotPurpose};
+    use crate::DeviceType;
+
+    #[test]
+    fn provisioning() {
+        let mut atca = AtCaClient::new(MockDevice::new(), NoDelay);
+        assert_eq!(atca.device_type().unwrap(), DeviceType::Atecc608b);
+        let mut memory = atca.memory();
+        let config = memory.config_zone().unwrap();
+        assert!(!config.is_config_locked());
+        assert_eq!(
+            memory.serial_number().unwrap().as_ref(),
+            [0x01, 0x23, 0x6b, 0x1f, 0x9d, 0x5a, 0x0e, 0x4c, 0xee]
+        );
+        // Data slots are written only once the config zone is locked, and
+        // read only once the data zone is.
+        let secret = [0x5a; 32];
+        assert!(memory.write_slot(Slot::Data08, &secret).is_err());
+        let lock = LockToken::i_understand_this_is_irreversible;
+        memory.lock(Zone::Config, lock()).unwrap();
+        memory.write_slot(Slot::Data08, &secret).unwrap();
+        assert!(memory.read_slot(Slot::Data08).is_err());
+        memory.lock(Zone::Data, lock()).unwrap();
+        assert_eq!(memory.read_slot(Slot::Data08).unwrap()[..32], secret);
+        assert!(memory.lock(Zone::Config, lock()).is_err());
+    }
+
+    #[test]
+    fn hashing() {
+        let key = [0x0b; 32];
+        let device = MockDevice::new().with_slot(Slot::Data08, &key);
+        let mut atca = AtCaClient::new(device, NoDelay);
+        let message = [0x61; 100];
+        let digest = atca.sha().digest(&message).unwrap();
+        assert_eq!(
+            digest.as_ref(),
+            Sha256::new().update(message).finalize().as_ref()
+        );
+
+        let mac = atca.hmac(Slot::Data08, b"Hi There").unwrap();
+        let inner = {
+            let mut pad = [0x36; 64];
+            pad[..32].iter_mut().for_each(|byte| *byte ^= 0x0b);
+            Sha256::new().update(pad).update(b"Hi There").finalize()
+        };
+        let mut pad = [0x5c; 64];
+        pad[..32].iter_mut().for_each(|byte| *byte ^= 0x0b);
+        let outer = Sha256::new().update(pad).update(inner).finalize();
+        assert_eq!(mac.as_ref(), outer.as_ref());
+    }
+
+    fn provisioned() -> AtCaClient<MockDevice, NoDelay> {
+        let mut atca = AtCaClient::new(MockDevice::new(), NoDelay);
+        let key = SlotPurpose::PrivateKey {
+            ecdh: true,
+            regenerable: true,
+        };
+        let provisioner = Provisioner::new().slot(Slot::PrivateKey00, key);
+        let token = LockToken::i_understand_this_is_irreversible();
+        atca.memory().provision(&provisioner, Some(token)).unwrap();
+        atca
+    }
+
+    #[test]
+    fn gen_key() {
+        let mut atca = AtCaClient::new(MockDevice::new(), NoDelay);
+        // GenKey fails until the config zone is locked.
+        let error = atca.generate_key(Slot::PrivateKey00).unwrap_err();
+        assert_eq!(error.status(), Some(Status::Execution));
+
+        let mut atca = provisioned();
+        let public_key = atca.generate_key(Slot::PrivateKey00).unwrap();
+        assert!(p256::PublicKey::try_from(public_key).is_ok());
+        assert_eq!(
+            atca.public_key(Slot::PrivateKey00).unwrap().as_ref(),
+            public_key.as_ref()
+        );
+        assert_ne!(
+            atca.generate_key(Slot::PrivateKey00).unwrap().as_ref(),
+            public_key.as_ref()
+        );
+        assert!(atca.generate_key(Slot::PrivateKey01).is_err());
+    }
+
+    #[test]
+    fn sign_verify() {
+        let mut atca = provisioned();
+        let public_key = atca.generate_key(Slot::PrivateKey00).unwrap();
+        let digest = atca.sha().digest(b"message").unwrap();
+        let signature = atca.sign(Slot::PrivateKey00, &digest).unwrap();
+
+        // The signature holds for p256 too.
+        let verifying_key = VerifyingKey::from(p256::PublicKey::try_from(public_key).unwrap());
+        let converted = p256::ecdsa::Signature::try_from(signature).unwrap();
+        assert!(verifying_key
+            .verify_prehash(digest.as_ref(), &converted)
+            .is_ok());
+
+        assert!(atca
+            .verify_external_digest(&digest, &signature, &public_key)
+            .unwrap());
+        let other = atca.sha().digest(b"other").unwrap();
+        assert!(!atca
+            .verify_external_digest(&other, &signature, &public_key)
+            .unwrap());
+
+        let mut atca = AtCaClient::new(
+            MockDevice::new().with_slot(Slot::Data08, &public_key.to_slot_format()),
+            NoDelay,
+        );
+        assert!(atca
+            .verify_stored_digest(Slot::Data08, &digest, &signature)
+            .unwrap());
+        assert!(!atca
+            .verify_stored_digest(Slot::Data08, &other, &signature)
+            .unwrap());
+    }
+
+    #[test]
+    fn ecdh() {
+        let mut atca = provisioned();
+        let public_key = atca.generate_key(Slot::PrivateKey00).unwrap();
+        let peer = SecretKey::from_be_bytes(&[0x11; 32]).unwrap();
+        let premaster = atca
+            .ecdh(
+                Slot::PrivateKey00,
+                &PublicKey::from(peer.public_key()),
+                EcdhTarget::Output,
+            )
+            .unwrap()
+            .unwrap();
+        // Same secret from the peer's side.
+        let public_key = p256::PublicKey::try_from(public_key).unwrap();
+        let shared = (public_key.to_projective() * *peer.to_nonzero_scalar())
+            .to_affine()
+            .to_encoded_point(false);
+        assert_eq!(premaster.as_ref(), shared.x().unwrap().as_slice());
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut atca = provisioned();
+        // The ephemeral key lives in TempKey.
+        let error = atca.ecdhe(&Default::default()).unwrap_err();
+        assert_eq!(error.status(), Some(Status::Execution));
+    }
+}
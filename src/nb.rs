@@ -0,0 +1,34 @@
+// Results of non-blocking operations, shaped after the `nb` crate so that
+// code written against it, `block!` included, carries over unchanged.
+
+/// Error of a non-blocking operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The operation failed.
+    Other(E),
+    /// The operation has not completed yet. Try again later.
+    WouldBlock,
+}
+
+pub type Result<T, E> = core::result::Result<T, Error<E>>;
+
+impl<E> From<E> for Error<E> {
+    fn from(error: E) -> Self {
+        Self::Other(error)
+    }
+}
+
+/// Poll a non-blocking operation until it completes.
+#[macro_export]
+macro_rules! block {
+    ($e:expr) => {
+        loop {
+            match $e {
+                Err($crate::nb::Error::Other(e)) => break Err(e),
+                Err($crate::nb::Error::WouldBlock) => {}
+                Ok(x) => break Ok(x),
+            }
+        }
+    };
+}
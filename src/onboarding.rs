@@ -0,0 +1,235 @@
+// First-boot provisioning as a resumable sequence. Each step leaves the
+// device in a state that can be recognised from its lock bits and config
+// zone alone, so firmware can call `Onboarding::step` or `run` on every boot
+// and carry on after a power loss without keeping progress anywhere else.
+//
+// The data zone cannot be read back before it is locked. The identity key
+// and public data are therefore written again when a step is interrupted,
+// which is harmless since nothing in the data zone is committed until the
+// final lock.
+use super::client::AtCaClient;
+use super::command::PublicKey;
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind};
+use super::memory::{LockToken, Slot, Zone};
+use super::provision::Provisioner;
+use embedded_hal::delay::DelayNs;
+
+/// Where a device stands in the onboarding sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OnboardingStage {
+    /// Config zone unlocked and not yet matching the template.
+    Blank,
+    /// Config zone written with the template, not locked.
+    ConfigWritten,
+    /// Config zone locked. The identity key and public data are written and
+    /// the data zone locked in the next step.
+    ConfigLocked,
+    /// Both zones locked.
+    Complete,
+}
+
+/// First-boot sequence: write `template` to the config zone and lock it,
+/// generate the identity key, write public data and lock the data zone.
+/// Each of the two locks consumes its own token.
+#[derive(Debug)]
+pub struct Onboarding<'a> {
+    template: &'a Provisioner,
+    identity_key: Slot,
+    data: [Option<&'a [u8]>; 16],
+    config_token: Option<LockToken>,
+    data_token: Option<LockToken>,
+}
+
+impl<'a> Onboarding<'a> {
+    pub fn new(
+        template: &'a Provisioner,
+        identity_key: Slot,
+        config_token: LockToken,
+        data_token: LockToken,
+    ) -> Self {
+        Self {
+            template,
+            identity_key,
+            data: [None; 16],
+            config_token: Some(config_token),
+            data_token: Some(data_token),
+        }
+    }
+
+    /// Data written to the start of `slot` before the data zone is locked,
+    /// such as certificates or a signer's public key.
+    pub fn data(mut self, slot: Slot, data: &'a [u8]) -> Self {
+        self.data[slot as usize].replace(data);
+        self
+    }
+
+    /// Read the device's stage. A device whose config zone is locked with a
+    /// different layout is rejected with `ConfigZoneLocked`.
+    pub fn stage<PHY, D, const N: usize>(
+        &self,
+        atca: &mut AtCaClient<PHY, D, N>,
+    ) -> Result<OnboardingStage, Error>
+    where
+        PHY: Interface,
+        D: DelayNs,
+    {
+        let current = atca.memory().config_zone()?;
+        let mut expected = current;
+        self.template.apply(&mut expected)?;
+        let matches = expected == current;
+        match (current.is_config_locked(), current.is_data_locked()) {
+            (false, _) if matches => Ok(OnboardingStage::ConfigWritten),
+            (false, _) => Ok(OnboardingStage::Blank),
+            (true, _) if !matches => Err(ErrorKind::ConfigZoneLocked.into()),
+            (true, false) => Ok(OnboardingStage::ConfigLocked),
+            (true, true) => Ok(OnboardingStage::Complete),
+        }
+    }
+
+    /// Carry out the step following the device's current stage and return
+    /// the stage reached. A lock whose token was spent by an earlier failed
+    /// attempt is rejected with `BadParam`.
+    pub fn step<PHY, D, const N: usize>(
+        &mut self,
+        atca: &mut AtCaClient<PHY, D, N>,
+    ) -> Result<OnboardingStage, Error>
+    where
+        PHY: Interface,
+        D: DelayNs,
+    {
+        if !self.identity_key.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+        match self.stage(atca)? {
+            OnboardingStage::Blank => {
+                atca.memory().provision(self.template, None)?;
+                Ok(OnboardingStage::ConfigWritten)
+            }
+            OnboardingStage::ConfigWritten => {
+                let mut memory = atca.memory();
+                let zone = memory.config_zone()?;
+                let token = Self::take(&mut self.config_token)?;
+                memory.lock_image(Zone::Config, zone, token)?;
+                Ok(OnboardingStage::ConfigLocked)
+            }
+            OnboardingStage::ConfigLocked => {
+                atca.generate_key(self.identity_key)?;
+                let mut memory = atca.memory();
+                for slot in Slot::keys() {
+                    if let Some(data) = self.data[slot as usize] {
+                        memory.write_slot(slot, data)?;
+                    }
+                }
+                let token = Self::take(&mut self.data_token)?;
+                memory.lock(Zone::Data, token)?;
+                Ok(OnboardingStage::Complete)
+            }
+            OnboardingStage::Complete => Ok(OnboardingStage::Complete),
+        }
+    }
+
+    /// Step until the device is onboarded, then return the identity public
+    /// key.
+    pub fn run<PHY, D, const N: usize>(
+        &mut self,
+        atca: &mut AtCaClient<PHY, D, N>,
+    ) -> Result<PublicKey, Error>
+    where
+        PHY: Interface,
+        D: DelayNs,
+    {
+        while self.step(atca)? != OnboardingStage::Complete {}
+        atca.public_key(self.identity_key)
+    }
+
+    fn take(token: &mut Option<LockToken>) -> Result<LockToken, Error> {
+        token.take().ok_or_else(|| ErrorKind::BadParam.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+
+    #[test]
+    fn stage() {
+        let lock = LockToken::i_understand_this_is_irreversible;
+        let template = Provisioner::new();
+        let mut onboarding = Onboarding::new(&template, Slot::PrivateKey00, lock(), lock());
+
+        // Lock bytes read 0x55: both zones unlocked.
+        let mut device = Device::new(0).respond(&[0x55; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let stage = onboarding.stage(&mut atca).unwrap();
+        assert_eq!(stage, OnboardingStage::ConfigWritten);
+
+        let mut device = Device::new(0).respond(&[0x00; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let stage = onboarding.step(&mut atca).unwrap();
+        assert_eq!(stage, OnboardingStage::Complete);
+    }
+
+    #[test]
+    fn locked_with_other_layout() {
+        let lock = LockToken::i_understand_this_is_irreversible;
+        let template = Provisioner::trust_flex();
+        let onboarding = Onboarding::new(&template, Slot::PrivateKey00, lock(), lock());
+        let mut device = Device::new(0).respond(&[0x00; 32]);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = onboarding.stage(&mut atca).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::ConfigZoneLocked));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn run_from_blank() {
+        use crate::mock::MockDevice;
+        let lock = LockToken::i_understand_this_is_irreversible;
+        let template = Provisioner::trust_flex();
+        let certificate = [0x30; 72];
+        let mut onboarding = Onboarding::new(&template, Slot::PrivateKey00, lock(), lock())
+            .data(Slot::Data08, &certificate);
+        let mut atca = AtCaClient::new(MockDevice::new(), NoDelay);
+        assert_eq!(onboarding.stage(&mut atca).unwrap(), OnboardingStage::Blank);
+
+        let public_key = onboarding.run(&mut atca).unwrap();
+        assert_eq!(
+            atca.public_key(Slot::PrivateKey00).unwrap().as_ref(),
+            public_key.as_ref()
+        );
+        let stage = onboarding.stage(&mut atca).unwrap();
+        assert_eq!(stage, OnboardingStage::Complete);
+        let slot = atca.memory().read_slot(Slot::Data08).unwrap();
+        assert_eq!(slot[..certificate.len()], certificate);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn resume_from_config_locked() {
+        use crate::mock::MockDevice;
+        let lock = LockToken::i_understand_this_is_irreversible;
+        let template = Provisioner::trust_flex();
+        let mut onboarding = Onboarding::new(&template, Slot::PrivateKey00, lock(), lock());
+        let mut atca = AtCaClient::new(MockDevice::new(), NoDelay);
+        onboarding.step(&mut atca).unwrap();
+        onboarding.step(&mut atca).unwrap();
+        // Power loss after the identity key was generated, before the data
+        // zone was locked.
+        atca.generate_key(Slot::PrivateKey00).unwrap();
+        let (device, _) = atca.release();
+
+        let mut atca = AtCaClient::new(device, NoDelay);
+        let mut onboarding = Onboarding::new(&template, Slot::PrivateKey00, lock(), lock());
+        let stage = onboarding.stage(&mut atca).unwrap();
+        assert_eq!(stage, OnboardingStage::ConfigLocked);
+        let public_key = onboarding.run(&mut atca).unwrap();
+        assert_eq!(
+            atca.public_key(Slot::PrivateKey00).unwrap().as_ref(),
+            public_key.as_ref()
+        );
+        assert!(atca.memory().config_zone().unwrap().is_data_locked());
+    }
+}
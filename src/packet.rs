@@ -1,5 +1,5 @@
-use super::command::OpCode;
-use super::error::{Error, ErrorKind, Status};
+use super::command::{OpCode, SelfTestMode};
+use super::error::{Error, ErrorKind, Phase, Status};
 use crate::datalink::Transaction;
 use core::convert::{TryFrom, TryInto};
 use core::mem::size_of;
@@ -27,11 +27,20 @@ const CUSTOM_ALG: Algorithm<u16> = Algorithm {
 // CRC memoise table
 pub const CRC16: Crc<u16> = Crc::<u16>::new(&CUSTOM_ALG);
 
+/// CRC-16 as the device computes it over packets and lock images:
+/// polynomial 0x8005, input bits reflected, no final XOR. Table driven.
+///
+/// Lock commands also accept a CRC computed elsewhere, for instance by a
+/// hardware CRC unit, through `Memory::lock_crc` and `lock_slot_crc`.
+pub fn crc16(data: &[u8]) -> u16 {
+    CRC16.checksum(data)
+}
+
 #[derive(Debug)]
 pub(crate) struct PacketBuilder<'a> {
     buffer: &'a mut [u8],
     pdu_length: Option<usize>,
-    opcode: Option<OpCode>,
+    opcode: Option<u8>,
     mode: Option<u8>,
     param2: Option<u16>,
 }
@@ -48,6 +57,11 @@ impl<'a> PacketBuilder<'a> {
     }
 
     pub(crate) fn opcode(&mut self, opcode: OpCode) -> &mut Self {
+        self.raw_opcode(opcode as u8)
+    }
+
+    // Any op-code, including ones the crate does not know.
+    pub(crate) fn raw_opcode(&mut self, opcode: u8) -> &mut Self {
         self.opcode.replace(opcode);
         self
     }
@@ -73,6 +87,16 @@ impl<'a> PacketBuilder<'a> {
         self
     }
 
+    // Same as `pdu_data`, failing on data that does not fit the buffer or a
+    // packet.
+    pub(crate) fn try_pdu_data(&mut self, data: &[u8]) -> Result<&mut Self, Error> {
+        let packet_length = CMD_SIZE_MIN + data.len();
+        if PACKET_OFFSET + packet_length > self.buffer.len() || packet_length > u8::MAX as usize {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        Ok(self.pdu_data(data))
+    }
+
     // Input length cannot exceed the length of underlying buffer. Only use it
     // for packets of fixed length. Also note that `pdu_data` modifies `pdu_length`.
     pub(crate) fn pdu_length(&mut self, length: usize) -> &mut Self {
@@ -101,19 +125,21 @@ impl<'a> PacketBuilder<'a> {
         self.buffer[0] = Transaction::Command as u8;
         let packet = self.packet_buffer();
         packet[0] = packet_length as u8;
-        packet[1] = opcode as u8;
+        packet[1] = opcode;
         packet[2] = mode;
         packet[3..5]
             .as_mut()
             .copy_from_slice(param2.to_le_bytes().as_ref());
 
         let crc_offset = packet_length - size_of::<u16>();
-        let crc = CRC16.checksum(&packet[..crc_offset]);
+        let crc = crc16(&packet[..crc_offset]);
         packet[crc_offset..packet_length]
             .as_mut()
             .copy_from_slice(crc.to_le_bytes().as_ref());
         Ok(Packet {
             opcode,
+            mode,
+            param2,
             range: (..packet_length + PACKET_OFFSET),
         })
     }
@@ -128,13 +154,23 @@ impl<'a> From<&'a mut [u8]> for PacketBuilder<'a> {
 /// Assuming buffer is alocated elsewhere, `Packet` designates subslice in use.
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct Packet {
-    opcode: OpCode,
+    opcode: u8,
+    mode: u8,
+    param2: u16,
     range: RangeTo<usize>,
 }
 
 impl Packet {
-    pub(crate) fn opcode(&self) -> &OpCode {
-        &self.opcode
+    pub(crate) fn opcode(&self) -> u8 {
+        self.opcode
+    }
+
+    pub(crate) fn mode(&self) -> u8 {
+        self.mode
+    }
+
+    pub(crate) fn param2(&self) -> u16 {
+        self.param2
     }
 
     pub(crate) fn buffer(self, buffer: &[u8]) -> &[u8] {
@@ -142,6 +178,20 @@ impl Packet {
     }
 }
 
+// Command metadata only; the data may be secret.
+#[cfg(feature = "defmt")]
+impl defmt::Format for Packet {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(
+            f,
+            "Packet {{ opcode: {=u8:#x}, mode: {=u8:#x}, param2: {=u16:#x} }}",
+            self.opcode,
+            self.mode,
+            self.param2
+        )
+    }
+}
+
 // Is it possible to classify the response into [] | [u8; WORD] | [u8; BLOCK]?
 // TODO: Testing purpose only. Should not be public.
 #[derive(Clone, Copy, Debug)]
@@ -150,10 +200,8 @@ pub struct Response<'a> {
 }
 
 impl<'a> Response<'a> {
-    /// Check if the response indicates an error. The received data is expected
-    /// to be in the form of a CA device response frame.
-    /// Extract PDU.
-    pub(crate) fn new(buffer: &'a [u8]) -> Result<Self, Error> {
+    /// Check that the frame is well-formed and its CRC matches.
+    pub(crate) fn check_crc(buffer: &[u8]) -> Result<(), Error> {
         // Check if buffer is well-formed.
         if buffer.len() < 0x04 {
             // Buffer is too small. Bail out.
@@ -166,20 +214,44 @@ impl<'a> Response<'a> {
             .try_into()
             .map(u16::from_le_bytes)
             .unwrap_or_else(|_| unreachable!());
-        if crc != CRC16.checksum(&payload) {
+        if crc != crc16(payload) {
             return Err(ErrorKind::RxCrcError.into());
         }
+        Ok(())
+    }
 
-        // Check error status. Error packets are always 4 bytes long.
-        let (header, pdu) = payload.split_at(1);
-        if header[0] == 0x04 {
-            if let Ok(status) = Status::try_from(pdu[0]) {
-                return Err(status.into());
-            }
-        }
+    /// Check if the response indicates an error. The received data is expected
+    /// to be in the form of a CA device response frame.
+    /// Extract PDU.
+    pub(crate) fn new(buffer: &'a [u8], opcode: u8) -> Result<Self, Error> {
+        parse_response(buffer, opcode).map(|pdu| Self { pdu })
+    }
+}
+
+/// Validate a response frame as read from the device: the length byte
+/// matches the frame, the CRC matches and a status byte, if the frame is
+/// one, reports success. Returns the data between the length byte and the
+/// CRC. `opcode` is the command answered, which tells a SelfTest result from
+/// a status byte.
+pub fn parse_response(frame: &[u8], opcode: u8) -> Result<&[u8], Error> {
+    Response::check_crc(frame).map_err(|e| e.in_phase(Phase::Parse))?;
+    if usize::from(frame[0]) != frame.len() {
+        return Err(Error::from(ErrorKind::CommFail).in_phase(Phase::Parse));
+    }
+    let pdu = &frame[1..frame.len() - size_of::<u16>()];
 
-        Ok(Self { pdu })
+    // Check error status. Error packets are always 4 bytes long.
+    if frame.len() == 0x04 && !is_result_byte(opcode, pdu[0]) {
+        if let Ok(status) = Status::try_from(pdu[0]) {
+            return Err(Error::from(status).in_phase(Phase::Parse));
+        }
     }
+    Ok(pdu)
+}
+
+// SelfTest reports failed tests as a bitmap in place of the status byte.
+fn is_result_byte(opcode: u8, value: u8) -> bool {
+    opcode == OpCode::SelfTest as u8 && value & !SelfTestMode::ALL == 0x00
 }
 
 impl<'a> AsRef<[u8]> for Response<'a> {
@@ -187,3 +259,99 @@ impl<'a> AsRef<[u8]> for Response<'a> {
         self.pdu
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Info;
+
+    fn frame(data: &[u8]) -> heapless::Vec<u8, 0x100> {
+        let mut frame = heapless::Vec::new();
+        frame.push(data.len() as u8 + 3).unwrap();
+        frame.extend_from_slice(data).unwrap();
+        let crc = CRC16.checksum(&frame).to_le_bytes();
+        frame.extend_from_slice(&crc).unwrap();
+        frame
+    }
+
+    #[test]
+    fn crc16() {
+        assert_eq!(super::crc16(b"123456789"), CUSTOM_ALG.check);
+        // Response of a successful command: count 4, status 0x00.
+        assert_eq!(super::crc16(&[0x04, 0x00]).to_le_bytes(), [0x03, 0x40]);
+    }
+
+    #[test]
+    fn parse_response() {
+        let info = OpCode::Info as u8;
+        assert_eq!(
+            super::parse_response(&frame(&[0x00]), info).unwrap(),
+            [0x00]
+        );
+        let error = super::parse_response(&frame(&[0x0f]), info).unwrap_err();
+        assert_eq!(error.status(), Some(Status::Execution));
+        // A failed test bitmap is a result of SelfTest, not a status.
+        let self_test = OpCode::SelfTest as u8;
+        assert!(super::parse_response(&frame(&[0x01]), self_test).is_ok());
+
+        let mut long = frame(&[0x00, 0x00, 0x60, 0x03]);
+        long[0] += 1;
+        assert!(super::parse_response(&long, info).is_err());
+        assert!(super::parse_response(&[], info).is_err());
+    }
+
+    // Every packet the builder encodes parses back to its fields, since
+    // commands and responses share the framing.
+    #[test]
+    fn round_trip() {
+        let buffer = &mut [0x00u8; 0xff];
+        for length in 0..=64 {
+            for &param2 in [0x0000, 0x0001, 0x1234, 0xffff].iter() {
+                let data: heapless::Vec<u8, 64> = (0..length).map(|i| i as u8 ^ 0xa5).collect();
+                let packet = PacketBuilder::new(buffer.as_mut())
+                    .opcode(OpCode::Sha)
+                    .mode(length as u8)
+                    .param2(param2)
+                    .pdu_data(&data)
+                    .build()
+                    .unwrap();
+                let encoded = &packet.buffer(buffer)[PACKET_OFFSET..];
+                let pdu = super::parse_response(encoded, OpCode::Sha as u8).unwrap();
+                assert_eq!(pdu[0], OpCode::Sha as u8);
+                assert_eq!(pdu[1], length as u8);
+                assert_eq!(pdu[2..4], param2.to_le_bytes());
+                assert_eq!(pdu[4..], data);
+            }
+        }
+    }
+
+    // Truncated, extended and bit-flipped frames are rejected without
+    // panicking.
+    #[test]
+    fn malformed() {
+        let buffer = &mut [0x00u8; 0xff];
+        let packet = Info::new(PacketBuilder::new(buffer.as_mut()))
+            .revision()
+            .unwrap();
+        let encoded = &packet.buffer(buffer)[PACKET_OFFSET..];
+        let info = OpCode::Info as u8;
+        for end in 0..encoded.len() {
+            assert!(super::parse_response(&encoded[..end], info).is_err());
+        }
+        let mut extended = heapless::Vec::<u8, 16>::from_slice(encoded).unwrap();
+        extended.push(0x00).unwrap();
+        assert!(super::parse_response(&extended, info).is_err());
+        for bit in 0..encoded.len() * 8 {
+            let mut flipped = heapless::Vec::<u8, 16>::from_slice(encoded).unwrap();
+            flipped[bit / 8] ^= 1 << (bit % 8);
+            assert!(super::parse_response(&flipped, info).is_err());
+        }
+        for length in 0..=0xff {
+            let mut forged = [length, 0x00, 0x00, 0x00];
+            let crc = CRC16.checksum(&forged[..2]).to_le_bytes();
+            forged[2..].copy_from_slice(&crc);
+            let parsed = super::parse_response(&forged, info);
+            assert_eq!(parsed.is_ok(), length == 0x04);
+        }
+    }
+}
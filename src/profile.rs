@@ -0,0 +1,142 @@
+// Snapshot of a device for cloning its configuration onto fresh parts:
+// the config zone, the OTP zone and the slots readable in the clear.
+// Private keys and secret slots are per-device and never exported; generate
+// or write them on each part before locking the data zone.
+//
+//     let profile = golden.memory().export_device_profile()?;
+//     store(&profile.to_bytes());
+//     ...
+//     let profile = DeviceProfile::from_bytes(&load())?;
+//     let token = LockToken::i_understand_this_is_irreversible();
+//     fresh.memory().apply_device_profile(&profile, token)?;
+//
+// With the serde feature, a profile can be kept as JSON instead.
+use super::config::{ConfigZone, CONFIG_ZONE_SIZE};
+use super::error::{Error, ErrorKind};
+use super::memory::{DataZoneImage, Slot, DATA_ZONE_SIZE, OTP_ZONE_SIZE};
+use core::convert::TryFrom;
+
+// Layout version, the first byte of the serialized form.
+const FORMAT_VERSION: u8 = 0x01;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceProfile {
+    pub(crate) config: ConfigZone,
+    #[cfg_attr(feature = "serde", serde(with = "crate::byte_array"))]
+    pub(crate) otp: [u8; OTP_ZONE_SIZE],
+    pub(crate) data: DataZoneImage,
+    // One bit per slot whose contents were exported.
+    pub(crate) slots: u16,
+}
+
+impl DeviceProfile {
+    /// Length of the serialized form.
+    pub const SIZE: usize = 1 + CONFIG_ZONE_SIZE + OTP_ZONE_SIZE + 2 + DATA_ZONE_SIZE;
+
+    pub fn config(&self) -> &ConfigZone {
+        &self.config
+    }
+
+    pub fn otp(&self) -> &[u8; OTP_ZONE_SIZE] {
+        &self.otp
+    }
+
+    /// Contents of `slot`, if it was exported.
+    pub fn slot(&self, slot: Slot) -> Option<&[u8]> {
+        Some(self.data.slot(slot)).filter(|_| self.has_slot(slot))
+    }
+
+    pub fn has_slot(&self, slot: Slot) -> bool {
+        self.slots & 1 << slot as u16 != 0x00
+    }
+
+    /// Version byte, config zone, OTP zone, the exported slot bitmap (LE)
+    /// and all data slots in order. Slots not exported read as zeros.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0x00; Self::SIZE];
+        let (version, rest) = bytes.split_at_mut(1);
+        let (config, rest) = rest.split_at_mut(CONFIG_ZONE_SIZE);
+        let (otp, rest) = rest.split_at_mut(OTP_ZONE_SIZE);
+        let (slots, data) = rest.split_at_mut(2);
+        version[0] = FORMAT_VERSION;
+        config.copy_from_slice(self.config.as_ref());
+        otp.copy_from_slice(&self.otp);
+        slots.copy_from_slice(&self.slots.to_le_bytes());
+        data.copy_from_slice(self.data.as_ref());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() != Self::SIZE {
+            return Err(ErrorKind::InvalidSize.into());
+        }
+        let (version, rest) = bytes.split_at(1);
+        if version[0] != FORMAT_VERSION {
+            return Err(ErrorKind::BadParam.into());
+        }
+        let (config, rest) = rest.split_at(CONFIG_ZONE_SIZE);
+        let (otp, rest) = rest.split_at(OTP_ZONE_SIZE);
+        let (slots, data) = rest.split_at(2);
+        let mut profile = Self {
+            config: ConfigZone::try_from(config)?,
+            otp: [0x00; OTP_ZONE_SIZE],
+            data: DataZoneImage::try_from(data)?,
+            slots: u16::from_le_bytes([slots[0], slots[1]]),
+        };
+        profile.otp.copy_from_slice(otp);
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialization() {
+        let mut data = DataZoneImage::default();
+        data.set_slot(Slot::Certificate0a, &[0x5a; 72]).unwrap();
+        let profile = DeviceProfile {
+            config: ConfigZone::from([0x11; CONFIG_ZONE_SIZE]),
+            otp: [0x22; OTP_ZONE_SIZE],
+            data,
+            slots: 1 << Slot::Certificate0a as u16,
+        };
+        let bytes = profile.to_bytes();
+        assert_eq!(bytes[..3], [FORMAT_VERSION, 0x11, 0x11]);
+        let parsed = DeviceProfile::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, profile);
+        assert_eq!(parsed.slot(Slot::Certificate0a), Some([0x5a; 72].as_ref()));
+        assert_eq!(parsed.slot(Slot::Data08), None);
+
+        assert!(DeviceProfile::from_bytes(&bytes[1..]).is_err());
+        let mut bytes = bytes;
+        bytes[0] = 0x02;
+        assert!(DeviceProfile::from_bytes(&bytes).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn profile_serde() {
+        let mut data = DataZoneImage::default();
+        data.set_slot(Slot::Certificate0a, &[0x5a; 72]).unwrap();
+        let profile = DeviceProfile {
+            config: ConfigZone::from([0x11; CONFIG_ZONE_SIZE]),
+            otp: [0x22; OTP_ZONE_SIZE],
+            data,
+            slots: 1 << Slot::Certificate0a as u16,
+        };
+        let json = serde_json::to_string(&profile).unwrap();
+        assert!(json.contains(r#""slots":1024"#));
+        assert_eq!(
+            serde_json::from_str::<DeviceProfile>(&json).unwrap(),
+            profile
+        );
+
+        // The OTP zone is exactly 64 bytes.
+        let json = json.replacen("\"otp\":[34,", "\"otp\":[", 1);
+        assert!(serde_json::from_str::<DeviceProfile>(&json).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+// Declarative device configuration. Describe what each slot is for and let
+// the provisioner compute SlotConfig and KeyConfig words. The result is
+// written with `Memory::provision`, which reads the zone back to verify it
+// before the optional lock.
+//
+// A `ProvisioningProfile` holds the words a template resolves to. With the
+// serde feature it can be kept as JSON or TOML next to the firmware and
+// reviewed field by field, then turned back into a `Provisioner`.
+use super::config::{ConfigZone, KeyConfig, KeyType, SlotConfig};
+use super::error::{Error, ErrorKind};
+use super::memory::Slot;
+use super::tngtls::TrustAndGo;
+use core::convert::TryFrom;
+
+// WriteConfig values
+const WRITE_ALWAYS: u8 = 0x00;
+const WRITE_GENKEY: u8 = 0x02;
+const WRITE_ENCRYPT: u8 = 0x04;
+const WRITE_NEVER: u8 = 0x08;
+
+// ReadKey bits of a private key slot.
+const SIGN_EXTERNAL: u8 = 0x01;
+const SIGN_INTERNAL: u8 = 0x02;
+const ECDH_ALLOWED: u8 = 0x04;
+
+// Any ReadKey or WriteKey value no command refers to.
+const NO_KEY: u8 = 0x0f;
+
+/// Intended use of a slot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlotPurpose {
+    /// P-256 private key generated on the device and never exported. Only
+    /// valid for slots 0 to 7. `regenerable` allows GenKey after the data
+    /// zone is locked.
+    PrivateKey { ecdh: bool, regenerable: bool },
+    /// P-256 private key that only signs messages generated internally,
+    /// such as key attestations.
+    InternalSignKey,
+    /// AES key. With `write_key`, writes must be encrypted with that key.
+    AesKey { write_key: Option<Slot> },
+    /// Secret for SHA, HMAC and MAC. With `write_key`, writes must be
+    /// encrypted with that key.
+    Secret { write_key: Option<Slot> },
+    /// Key protecting transfers such as SecureBoot digests and ECDH output.
+    IoProtectionKey,
+    /// P-256 public key, for example a signer's key.
+    PublicKey,
+    /// Certificate or other data readable in the clear and lockable once
+    /// written.
+    Certificate,
+    /// Data that can never be modified after the data zone is locked.
+    ReadOnlyData,
+    /// Raw configuration words for layouts not covered above.
+    Custom(SlotConfig, KeyConfig),
+}
+
+impl SlotPurpose {
+    fn words(&self, slot: Slot) -> Result<(SlotConfig, KeyConfig), Error> {
+        let private_key = KeyConfig::default()
+            .with_private(true)
+            .with_pub_info(true)
+            .with_key_type(KeyType::P256)
+            .with_req_random(true);
+        let secret = SlotConfig::default()
+            .with_is_secret(true)
+            .with_read_key(NO_KEY);
+        let clear = SlotConfig::default()
+            .with_read_key(NO_KEY)
+            .with_write_key(NO_KEY);
+
+        let words = match *self {
+            Self::PrivateKey { ecdh, regenerable } => {
+                let read_key = SIGN_EXTERNAL | if ecdh { ECDH_ALLOWED } else { 0x00 };
+                let write_config = if regenerable {
+                    WRITE_GENKEY
+                } else {
+                    WRITE_ALWAYS
+                };
+                let slot_config = secret
+                    .with_read_key(read_key)
+                    .with_write_config(write_config);
+                (slot_config, private_key.with_lockable(regenerable))
+            }
+            Self::InternalSignKey => (secret.with_read_key(SIGN_INTERNAL), private_key),
+            Self::AesKey { write_key } => (
+                Self::secret_write(secret, write_key),
+                KeyConfig::default()
+                    .with_key_type(KeyType::Aes)
+                    .with_pub_info(true),
+            ),
+            Self::Secret { write_key } => (
+                Self::secret_write(secret, write_key),
+                KeyConfig::default()
+                    .with_key_type(KeyType::ShaOrText)
+                    .with_lockable(true),
+            ),
+            Self::IoProtectionKey => (
+                secret.with_write_key(NO_KEY),
+                KeyConfig::default()
+                    .with_key_type(KeyType::ShaOrText)
+                    .with_lockable(true)
+                    .with_req_random(true),
+            ),
+            Self::PublicKey => (
+                clear.with_write_config(WRITE_NEVER),
+                KeyConfig::default().with_key_type(KeyType::P256),
+            ),
+            Self::Certificate => (
+                clear,
+                KeyConfig::default()
+                    .with_key_type(KeyType::ShaOrText)
+                    .with_lockable(true),
+            ),
+            Self::ReadOnlyData => (
+                clear.with_write_config(WRITE_NEVER),
+                KeyConfig::default().with_key_type(KeyType::ShaOrText),
+            ),
+            Self::Custom(slot_config, key_config) => (slot_config, key_config),
+        };
+
+        if words.1.private() && !slot.is_private_key() {
+            return Err(ErrorKind::BadParam.into());
+        }
+        Ok(words)
+    }
+
+    fn secret_write(config: SlotConfig, write_key: Option<Slot>) -> SlotConfig {
+        match write_key {
+            Some(key_id) => config
+                .with_write_key(key_id as u8)
+                .with_write_config(WRITE_ENCRYPT),
+            None => config.with_write_key(NO_KEY),
+        }
+    }
+}
+
+/// Configuration template. Slots without a purpose keep whatever the device
+/// already holds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Provisioner {
+    slots: [Option<SlotPurpose>; 16],
+    chip_options: Option<u16>,
+}
+
+impl Provisioner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn slot(mut self, slot: Slot, purpose: SlotPurpose) -> Self {
+        self.slots[slot as usize].replace(purpose);
+        self
+    }
+
+    pub fn chip_options(mut self, options: u16) -> Self {
+        self.chip_options.replace(options);
+        self
+    }
+
+    /// Layout of the pre-provisioned TrustAndGo TLS devices, the same one
+    /// `AtCaClient::tng` applies.
+    pub fn trust_and_go() -> Self {
+        let slot_configs = TrustAndGo::<(), ()>::TNG_TLS_SLOT_CONFIG_DATA;
+        let key_configs = TrustAndGo::<(), ()>::TNG_TLS_KEY_CONFIG_DATA;
+        let chip_options = TrustAndGo::<(), ()>::TNG_TLS_CHIP_OPTIONS;
+        Slot::keys()
+            .fold(Self::new(), |provisioner, slot| {
+                let i = slot as usize * 2;
+                let slot_config = u16::from_le_bytes([slot_configs[i], slot_configs[i + 1]]);
+                let key_config = u16::from_le_bytes([key_configs[i], key_configs[i + 1]]);
+                let purpose = SlotPurpose::Custom(slot_config.into(), key_config.into());
+                provisioner.slot(slot, purpose)
+            })
+            .chip_options(u16::from_le_bytes([chip_options[2], chip_options[3]]))
+    }
+
+    /// Slot map of the TrustFLEX devices: permanent and regenerable ECC
+    /// keys, an I/O protection key, an AES key, certificate storage and a
+    /// secure boot public key.
+    pub fn trust_flex() -> Self {
+        use Slot::*;
+        let permanent = SlotPurpose::PrivateKey {
+            ecdh: true,
+            regenerable: false,
+        };
+        let regenerable = SlotPurpose::PrivateKey {
+            ecdh: true,
+            regenerable: true,
+        };
+        Self::new()
+            .slot(PrivateKey00, permanent)
+            .slot(PrivateKey01, SlotPurpose::InternalSignKey)
+            .slot(PrivateKey02, regenerable)
+            .slot(PrivateKey03, regenerable)
+            .slot(PrivateKey04, regenerable)
+            .slot(PrivateKey05, SlotPurpose::Secret { write_key: None })
+            .slot(PrivateKey06, SlotPurpose::IoProtectionKey)
+            .slot(PrivateKey07, SlotPurpose::ReadOnlyData)
+            .slot(Data08, SlotPurpose::Certificate)
+            .slot(Certificate09, SlotPurpose::AesKey { write_key: None })
+            .slot(Certificate0a, SlotPurpose::Certificate)
+            .slot(Certificate0b, SlotPurpose::Certificate)
+            .slot(Certificate0c, SlotPurpose::Certificate)
+            .slot(Certificate0d, SlotPurpose::PublicKey)
+            .slot(Certificate0e, SlotPurpose::PublicKey)
+            .slot(Certificate0f, SlotPurpose::PublicKey)
+    }
+
+    /// Apply the template on top of `zone`.
+    pub fn apply(&self, zone: &mut ConfigZone) -> Result<(), Error> {
+        for slot in Slot::keys() {
+            if let Some(purpose) = self.slots[slot as usize] {
+                let (slot_config, key_config) = purpose.words(slot)?;
+                zone.set_slot_config(slot, slot_config)
+                    .set_key_config(slot, key_config);
+            }
+        }
+        if let Some(options) = self.chip_options {
+            zone.set_chip_options(options);
+        }
+        Ok(())
+    }
+}
+
+/// SlotConfig and KeyConfig words of a slot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlotProfile {
+    pub slot_config: SlotConfig,
+    pub key_config: KeyConfig,
+}
+
+/// Configuration words set by provisioning, indexed by slot. Slots and
+/// chip options left as `None` keep whatever the device already holds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProvisioningProfile {
+    pub slots: [Option<SlotProfile>; 16],
+    pub chip_options: Option<u16>,
+}
+
+impl TryFrom<&Provisioner> for ProvisioningProfile {
+    type Error = Error;
+    fn try_from(provisioner: &Provisioner) -> Result<Self, Self::Error> {
+        let mut profile = Self {
+            chip_options: provisioner.chip_options,
+            ..Self::default()
+        };
+        for slot in Slot::keys() {
+            if let Some(purpose) = provisioner.slots[slot as usize] {
+                let (slot_config, key_config) = purpose.words(slot)?;
+                profile.slots[slot as usize].replace(SlotProfile {
+                    slot_config,
+                    key_config,
+                });
+            }
+        }
+        Ok(profile)
+    }
+}
+
+// Every slot and the chip options of a device's zone.
+impl From<&ConfigZone> for ProvisioningProfile {
+    fn from(zone: &ConfigZone) -> Self {
+        let mut profile = Self {
+            chip_options: Some(zone.chip_options()),
+            ..Self::default()
+        };
+        for slot in Slot::keys() {
+            profile.slots[slot as usize].replace(SlotProfile {
+                slot_config: zone.slot_config(slot),
+                key_config: zone.key_config(slot),
+            });
+        }
+        profile
+    }
+}
+
+impl From<&ProvisioningProfile> for Provisioner {
+    fn from(profile: &ProvisioningProfile) -> Self {
+        let provisioner = Slot::keys().fold(Self::new(), |provisioner, slot| {
+            match profile.slots[slot as usize] {
+                Some(words) => provisioner.slot(
+                    slot,
+                    SlotPurpose::Custom(words.slot_config, words.key_config),
+                ),
+                None => provisioner,
+            }
+        });
+        match profile.chip_options {
+            Some(options) => provisioner.chip_options(options),
+            None => provisioner,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::CONFIG_ZONE_SIZE;
+
+    #[test]
+    fn purposes() {
+        let mut zone = ConfigZone::from([0x00; CONFIG_ZONE_SIZE]);
+        Provisioner::new()
+            .slot(
+                Slot::PrivateKey00,
+                SlotPurpose::PrivateKey {
+                    ecdh: true,
+                    regenerable: false,
+                },
+            )
+            .slot(
+                Slot::PrivateKey05,
+                SlotPurpose::AesKey {
+                    write_key: Some(Slot::PrivateKey06),
+                },
+            )
+            .slot(Slot::Data08, SlotPurpose::Certificate)
+            .apply(&mut zone)
+            .unwrap();
+
+        // Same words as the primary key of TrustAndGo.
+        assert_eq!(u16::from(zone.slot_config(Slot::PrivateKey00)), 0x0085);
+        assert_eq!(u16::from(zone.key_config(Slot::PrivateKey00)), 0x0053);
+
+        let aes = zone.slot_config(Slot::PrivateKey05);
+        assert!(aes.is_secret());
+        assert_eq!(aes.write_key(), Slot::PrivateKey06 as u8);
+        assert_eq!(aes.write_config(), WRITE_ENCRYPT);
+        assert_eq!(
+            zone.key_config(Slot::PrivateKey05).key_type(),
+            Some(KeyType::Aes)
+        );
+
+        assert!(!zone.slot_config(Slot::Data08).is_secret());
+        assert!(zone.key_config(Slot::Data08).lockable());
+    }
+
+    #[test]
+    fn private_key_slot_range() {
+        let mut zone = ConfigZone::from([0x00; CONFIG_ZONE_SIZE]);
+        let result = Provisioner::new()
+            .slot(Slot::Data08, SlotPurpose::InternalSignKey)
+            .apply(&mut zone);
+        assert_eq!(result.unwrap_err().kind(), Some(ErrorKind::BadParam));
+    }
+
+    #[test]
+    fn trust_and_go() {
+        let mut zone = ConfigZone::from([0x00; CONFIG_ZONE_SIZE]);
+        Provisioner::trust_and_go().apply(&mut zone).unwrap();
+        assert_eq!(u16::from(zone.slot_config(Slot::PrivateKey02)), 0x2085);
+        assert_eq!(u16::from(zone.key_config(Slot::Certificate09)), 0x001a);
+        assert_eq!(zone.chip_options(), 0x0e60);
+        Provisioner::trust_flex().apply(&mut zone).unwrap();
+    }
+
+    #[test]
+    fn profile() {
+        let provisioner = Provisioner::trust_flex();
+        let profile = ProvisioningProfile::try_from(&provisioner).unwrap();
+        let mut expected = ConfigZone::from([0x00; CONFIG_ZONE_SIZE]);
+        provisioner.apply(&mut expected).unwrap();
+        let mut zone = ConfigZone::from([0x00; CONFIG_ZONE_SIZE]);
+        Provisioner::from(&profile).apply(&mut zone).unwrap();
+        assert_eq!(zone, expected);
+        assert_eq!(profile.chip_options, None);
+
+        let profile = ProvisioningProfile::from(&zone);
+        assert_eq!(profile.chip_options, Some(0x0000));
+        assert_eq!(
+            profile.slots[Slot::PrivateKey00 as usize]
+                .unwrap()
+                .slot_config,
+            zone.slot_config(Slot::PrivateKey00)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn profile_serde() {
+        let profile = ProvisioningProfile::try_from(&Provisioner::trust_and_go()).unwrap();
+        let json = serde_json::to_string(&profile).unwrap();
+        assert!(json.contains(r#""write_config":2"#));
+        assert_eq!(
+            serde_json::from_str::<ProvisioningProfile>(&json).unwrap(),
+            profile
+        );
+
+        let zone = ConfigZone::from([0xa5; CONFIG_ZONE_SIZE]);
+        let json = serde_json::to_string(&zone).unwrap();
+        assert_eq!(serde_json::from_str::<ConfigZone>(&json).unwrap(), zone);
+        assert!(serde_json::from_str::<ConfigZone>("[1,2,3]").is_err());
+
+        // The reserved KeyConfig bit survives a round trip.
+        let key_config = KeyConfig::from(0xffff);
+        let json = serde_json::to_string(&key_config).unwrap();
+        assert_eq!(
+            serde_json::from_str::<KeyConfig>(&json).unwrap(),
+            key_config
+        );
+        let slot_config = SlotConfig::from(0xffff);
+        let json = serde_json::to_string(&slot_config).unwrap();
+        assert_eq!(
+            serde_json::from_str::<SlotConfig>(&json).unwrap(),
+            slot_config
+        );
+    }
+}
@@ -0,0 +1,106 @@
+// Retrying transient failures in the execute path. Only errors raised while
+// waking the device or sending the command are retried, before the device
+// has accepted the command, so one with side effects such as Counter never
+// runs twice. A corrupted response is read again by the datalink instead.
+use super::error::{Error, ErrorKind, Phase};
+
+const DEFAULT_RETRYABLE: &[ErrorKind] = &[ErrorKind::WakeFailed, ErrorKind::TxFail];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: u8,
+    backoff_us: u32,
+    max_backoff_us: u32,
+    retryable: &'static [ErrorKind],
+}
+
+impl RetryPolicy {
+    /// Fail on the first error.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Total number of attempts, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u8) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry. It doubles on each subsequent retry up
+    /// to `max_backoff_us`.
+    pub fn backoff_us(mut self, backoff_us: u32, max_backoff_us: u32) -> Self {
+        self.backoff_us = backoff_us;
+        self.max_backoff_us = max_backoff_us.max(backoff_us);
+        self
+    }
+
+    /// Host side error kinds worth another attempt, when raised while waking
+    /// the device or sending the command.
+    pub fn retry_on(mut self, retryable: &'static [ErrorKind]) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Whether attempt number `attempt`, counted from 1, may be followed by
+    /// another one after failing with `error`.
+    pub(crate) fn should_retry(&self, attempt: u8, error: &Error) -> bool {
+        attempt < self.max_attempts
+            && matches!(error.phase(), Some(Phase::Wake) | Some(Phase::Send))
+            && error
+                .kind()
+                .is_some_and(|kind| self.retryable.contains(&kind))
+    }
+
+    /// Delay in us after attempt number `attempt` failed.
+    pub(crate) fn backoff(&self, attempt: u8) -> u32 {
+        let shift = u32::from(attempt.saturating_sub(1)).min(31);
+        self.backoff_us
+            .saturating_mul(1 << shift)
+            .min(self.max_backoff_us)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_us: 1000,
+            max_backoff_us: 10_000,
+            retryable: DEFAULT_RETRYABLE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Status;
+
+    #[test]
+    fn retry_policy() {
+        let tx_fail = |phase| Error::from(ErrorKind::TxFail).in_phase(phase);
+        let policy = RetryPolicy::default();
+        assert!(policy.should_retry(1, &tx_fail(Phase::Send)));
+        assert!(policy.should_retry(2, &Error::from(ErrorKind::WakeFailed).in_phase(Phase::Wake)));
+        assert!(!policy.should_retry(3, &tx_fail(Phase::Send)));
+        // The command may already have run.
+        assert!(!policy.should_retry(1, &tx_fail(Phase::Receive)));
+        assert!(!policy.should_retry(1, &ErrorKind::TxFail.into()));
+        assert!(!policy.should_retry(1, &Error::from(Status::Crc).in_phase(Phase::Parse)));
+        assert!(!policy.should_retry(1, &Status::Execution.into()));
+        assert!(!RetryPolicy::none().should_retry(1, &tx_fail(Phase::Send)));
+
+        let policy = policy.retry_on(&[ErrorKind::RxFail]);
+        assert!(policy.should_retry(1, &Error::from(ErrorKind::RxFail).in_phase(Phase::Wake)));
+        assert!(!policy.should_retry(1, &tx_fail(Phase::Send)));
+
+        let policy = policy.backoff_us(1000, 3000);
+        assert_eq!(policy.backoff(1), 1000);
+        assert_eq!(policy.backoff(2), 2000);
+        assert_eq!(policy.backoff(3), 3000);
+        assert_eq!(policy.backoff(40), 3000);
+    }
+}
@@ -0,0 +1,114 @@
+// Client certificate key for rustls, so a mutual TLS client on a Linux
+// gateway keeps its identity key in the device. The handshake message is
+// hashed on the device and signed with the slot key, and the signature is
+// DER encoded as ECDSA_NISTP256_SHA256 requires.
+//
+// rustls holds signing keys behind `Arc` and may sign from any thread, so
+// the client is shared through a `std::sync::Mutex`:
+//
+//     let shared = Arc::new(Mutex::new(atca));
+//     let key = Arc::new(SlotKey::new(shared, Slot::PrivateKey00));
+//     let certified = CertifiedKey::new(certificates, key);
+use super::client::{AtCaClient, BUFFER_SIZE};
+use super::datalink::Interface;
+use super::memory::Slot;
+use core::fmt;
+use embedded_hal::delay::DelayNs;
+use rustls::sign::{Signer, SigningKey};
+use rustls::{SignatureAlgorithm, SignatureScheme};
+use std::boxed::Box;
+use std::string::ToString;
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+/// P-256 private key in a slot, as a rustls `SigningKey` and `Signer`.
+pub struct SlotKey<PHY, D, const N: usize = BUFFER_SIZE> {
+    shared: Arc<Mutex<AtCaClient<PHY, D, N>>>,
+    key_id: Slot,
+}
+
+impl<PHY, D, const N: usize> SlotKey<PHY, D, N> {
+    pub fn new(shared: Arc<Mutex<AtCaClient<PHY, D, N>>>, key_id: Slot) -> Self {
+        Self { shared, key_id }
+    }
+}
+
+impl<PHY, D, const N: usize> Clone for SlotKey<PHY, D, N> {
+    fn clone(&self) -> Self {
+        Self::new(self.shared.clone(), self.key_id)
+    }
+}
+
+impl<PHY, D, const N: usize> fmt::Debug for SlotKey<PHY, D, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlotKey")
+            .field("key_id", &self.key_id)
+            .finish()
+    }
+}
+
+impl<PHY, D, const N: usize> SigningKey for SlotKey<PHY, D, N>
+where
+    PHY: Interface + Send + 'static,
+    D: DelayNs + Send + 'static,
+{
+    fn choose_scheme(&self, offered: &[SignatureScheme]) -> Option<Box<dyn Signer>> {
+        if offered.contains(&SignatureScheme::ECDSA_NISTP256_SHA256) {
+            Some(Box::new(self.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        SignatureAlgorithm::ECDSA
+    }
+}
+
+impl<PHY, D, const N: usize> Signer for SlotKey<PHY, D, N>
+where
+    PHY: Interface + Send + 'static,
+    D: DelayNs + Send + 'static,
+{
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, rustls::Error> {
+        // A panic while signing leaves the device in an unknown state, so a
+        // poisoned client is not used again.
+        let mut atca = self
+            .shared
+            .lock()
+            .map_err(|e| rustls::Error::General(e.to_string()))?;
+        let digest = atca.sha().digest(message);
+        digest
+            .and_then(|digest| atca.sign(self.key_id, &digest))
+            .map(|signature| signature.to_der().to_vec())
+            .map_err(|e| rustls::Error::General(e.to_string()))
+    }
+
+    fn scheme(&self) -> SignatureScheme {
+        SignatureScheme::ECDSA_NISTP256_SHA256
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+
+    #[test]
+    fn choose_scheme() {
+        let atca = AtCaClient::new(Device::new(0).respond(&[0x0f]), NoDelay);
+        let key = SlotKey::new(Arc::new(Mutex::new(atca)), Slot::PrivateKey00);
+        assert!(key.algorithm() == SignatureAlgorithm::ECDSA);
+        assert!(key.choose_scheme(&[SignatureScheme::ED25519]).is_none());
+
+        let offered = [
+            SignatureScheme::ED25519,
+            SignatureScheme::ECDSA_NISTP256_SHA256,
+        ];
+        let signer = key.choose_scheme(&offered).unwrap();
+        assert!(signer.scheme() == SignatureScheme::ECDSA_NISTP256_SHA256);
+        // The device answers with an execution error.
+        let error = signer.sign(b"handshake").unwrap_err();
+        assert!(matches!(error, rustls::Error::General(_)));
+    }
+}
@@ -0,0 +1,339 @@
+// Secure boot validates the firmware image digest against a signature made
+// with the secure boot public key. The recommended flow stores the digest on
+// the first boot (FullStore) and verifies the image on each subsequent boot
+// (Full). When the I/O protection key is set up, the digest is sent encrypted
+// and the device proves the result with a MAC, so that a tampered bus cannot
+// fake a successful response.
+//
+// On devices configured to keep the digest (SecureBootMode FullDig), the
+// fast path sends only the digest on subsequent boots: `store_for_fast_boot`
+// once, then `fast_verify` on every boot. The host keeps a `FastBootState`
+// between boots, along with the I/O protection key.
+use super::client::{verification, AtCaClient, BUFFER_SIZE};
+use super::command::{self, Block, Digest, OpCode, Signature};
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind, Status};
+use super::host::Sha256;
+use core::convert::TryFrom;
+use embedded_hal::delay::DelayNs;
+
+/// SecureBoot command modes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecureBootMode {
+    /// Validate the digest and signature.
+    Full = 0x05,
+    /// Validate, then store the digest in the secure boot digest slot.
+    FullStore = 0x06,
+    /// Validate, then copy the digest to the secure boot digest slot and set
+    /// the persistent latch.
+    FullCopy = 0x07,
+}
+
+/// Stage of the boot flow as observed through this handle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BootStage {
+    /// Nothing has been validated yet.
+    Unverified,
+    /// The digest has been validated and stored on the device.
+    DigestStored,
+    /// The image has been validated during this boot.
+    Verified,
+}
+
+// Hash the I/O protection key and TempKey into the key that encrypts the
+// digest and keys the response MAC.
+pub fn session_key(io_key: &Block, tempkey: &Block) -> Digest {
+    Sha256::new().update(io_key).update(tempkey).finalize()
+}
+
+// Encrypt the digest for transport to the device.
+pub fn encrypt_digest(session_key: &Digest, digest: &Digest) -> Digest {
+    let mut encrypted = *digest;
+    encrypted
+        .as_mut()
+        .iter_mut()
+        .zip(session_key.as_ref())
+        .for_each(|(d, k)| *d ^= k);
+    encrypted
+}
+
+// Expected response MAC over the plain digest. Whether the signature is
+// included depends on the secure boot configuration of the device.
+pub fn response_mac(
+    session_key: &Digest,
+    mode: SecureBootMode,
+    digest: &Digest,
+    signature: Option<&Signature>,
+) -> Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(session_key).update(digest);
+    if let Some(signature) = signature {
+        hasher.update(signature);
+    }
+    hasher
+        .update([OpCode::SecureBoot as u8, mode as u8 | 0x80, 0x00, 0x00])
+        .finalize()
+}
+
+/// What the host persists between boots for `fast_verify`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FastBootState {
+    digest_stored: bool,
+    boot_count: u32,
+}
+
+impl FastBootState {
+    pub const SIZE: usize = 5;
+
+    pub fn digest_stored(&self) -> bool {
+        self.digest_stored
+    }
+
+    pub fn boot_count(&self) -> u32 {
+        self.boot_count
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0x00; Self::SIZE];
+        bytes[0] = self.digest_stored as u8;
+        bytes[1..].copy_from_slice(&self.boot_count.to_le_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Self {
+        let mut count = [0x00; 4];
+        count.copy_from_slice(&bytes[1..]);
+        Self {
+            digest_stored: bytes[0] != 0x00,
+            boot_count: u32::from_le_bytes(count),
+        }
+    }
+
+    // NumIn for the next boot, unique as long as the state is persisted
+    // after each boot.
+    fn next_num_in(&mut self) -> [u8; 20] {
+        self.boot_count = self.boot_count.wrapping_add(1);
+        let mut num_in = [0x00; 20];
+        num_in[..4].copy_from_slice(&self.boot_count.to_le_bytes());
+        num_in
+    }
+}
+
+pub struct SecureBoot<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
+    stage: BootStage,
+}
+
+impl<'a, PHY, D, const N: usize> SecureBoot<'a, PHY, D, N> {
+    pub(crate) fn new(atca: &'a mut AtCaClient<PHY, D, N>) -> Self {
+        let stage = BootStage::Unverified;
+        Self { atca, stage }
+    }
+
+    pub fn stage(&self) -> BootStage {
+        self.stage
+    }
+}
+
+impl<'a, PHY, D, const N: usize> SecureBoot<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    // First boot: validate the image and store its digest.
+    pub fn store(&mut self, digest: &Digest, signature: &Signature) -> Result<bool, Error> {
+        let stored = self.execute(SecureBootMode::FullStore, digest, signature)?;
+        if stored {
+            self.stage = BootStage::DigestStored;
+        }
+        Ok(stored)
+    }
+
+    // Every boot: validate the image against the signature.
+    pub fn verify(&mut self, digest: &Digest, signature: &Signature) -> Result<bool, Error> {
+        let verified = self.execute(SecureBootMode::Full, digest, signature)?;
+        if verified {
+            self.stage = BootStage::Verified;
+        }
+        Ok(verified)
+    }
+
+    // Same as `verify`, with the digest encrypted by the I/O protection key
+    // and the result authenticated by a MAC. `num_in` should be unique per
+    // boot, e.g. drawn from a host side counter.
+    pub fn verify_mac(
+        &mut self,
+        mode: SecureBootMode,
+        digest: &Digest,
+        signature: &Signature,
+        io_key: &Block,
+        num_in: &[u8; 20],
+        mac_includes_signature: bool,
+    ) -> Result<bool, Error> {
+        let mac_signature = if mac_includes_signature {
+            Some(signature)
+        } else {
+            None
+        };
+        self.execute_mac(mode, digest, Some(signature), io_key, num_in, mac_signature)
+    }
+
+    // First boot of the fast path: validate the image, store its digest and
+    // record that in `state`. Needs the I/O protection key of the client.
+    pub fn store_for_fast_boot(
+        &mut self,
+        digest: &Digest,
+        signature: &Signature,
+        state: &mut FastBootState,
+    ) -> Result<bool, Error> {
+        let io_key = self.atca.io_key.ok_or(ErrorKind::FuncFail)?;
+        let num_in = state.next_num_in();
+        let stored = self.execute_mac(
+            SecureBootMode::FullStore,
+            digest,
+            Some(signature),
+            &io_key,
+            &num_in,
+            None,
+        )?;
+        if stored {
+            state.digest_stored = true;
+        }
+        Ok(stored)
+    }
+
+    // Every later boot: compare the image digest with the stored one in a
+    // single short command, authenticated by the response MAC. Persist
+    // `state` afterwards, whatever the result.
+    pub fn fast_verify(
+        &mut self,
+        image_digest: &Digest,
+        state: &mut FastBootState,
+    ) -> Result<bool, Error> {
+        if !state.digest_stored {
+            return Err(ErrorKind::FuncFail.into());
+        }
+        let io_key = self.atca.io_key.ok_or(ErrorKind::FuncFail)?;
+        let num_in = state.next_num_in();
+        self.execute_mac(
+            SecureBootMode::Full,
+            image_digest,
+            None,
+            &io_key,
+            &num_in,
+            None,
+        )
+    }
+
+    // Without a signature, the device compares the digest with the stored
+    // one.
+    fn execute_mac(
+        &mut self,
+        mode: SecureBootMode,
+        digest: &Digest,
+        signature: Option<&Signature>,
+        io_key: &Block,
+        num_in: &[u8; 20],
+        mac_signature: Option<&Signature>,
+    ) -> Result<bool, Error> {
+        let rand_out = self.atca.nonce_random(num_in)?;
+        let tempkey = super::host::nonce(&rand_out, num_in);
+        let session_key = session_key(io_key, &tempkey);
+        let encrypted = encrypt_digest(&session_key, digest);
+
+        let mut secure_boot = command::SecureBoot::new(self.atca.packet_builder());
+        let packet = match signature {
+            Some(signature) => secure_boot.full(mode as u8, &encrypted, signature, true)?,
+            None => secure_boot.stored_digest(mode as u8, &encrypted, true)?,
+        };
+        let response = self
+            .atca
+            .execute(packet)
+            .map(|r| Digest::try_from(r.as_ref()));
+        // The I/O protection session is used up either way.
+        self.atca.tempkey.invalidate();
+        let mac = match response {
+            Ok(response) => response?,
+            Err(e) if e.status() == Some(Status::CheckmacVerifyFailed) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let expected = response_mac(&session_key, mode, digest, mac_signature);
+        // Compare without an early exit.
+        let diff = mac
+            .as_ref()
+            .iter()
+            .zip(expected.as_ref())
+            .fold(0x00, |acc, (a, b)| acc | (a ^ b));
+        if diff != 0x00 {
+            return Err(ErrorKind::AssertFailure.into());
+        }
+
+        self.stage = match mode {
+            SecureBootMode::Full => BootStage::Verified,
+            _ => BootStage::DigestStored,
+        };
+        Ok(true)
+    }
+
+    fn execute(
+        &mut self,
+        mode: SecureBootMode,
+        digest: &Digest,
+        signature: &Signature,
+    ) -> Result<bool, Error> {
+        let packet = command::SecureBoot::new(self.atca.packet_builder())
+            .full(mode as u8, digest, signature, false)?;
+        verification(self.atca.execute(packet).map(drop))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+
+    #[test]
+    fn encrypt_digest_roundtrip() {
+        let mut io_key = Block::default();
+        io_key.as_mut().iter_mut().for_each(|v| *v = 0x5a);
+        let tempkey = Block::default();
+        let mut digest = Digest::default();
+        digest
+            .as_mut()
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, v)| *v = i as u8);
+
+        let key = session_key(&io_key, &tempkey);
+        let encrypted = encrypt_digest(&key, &digest);
+        assert_ne!(encrypted.as_ref(), digest.as_ref());
+        assert_eq!(encrypt_digest(&key, &encrypted).as_ref(), digest.as_ref());
+    }
+
+    #[test]
+    fn fast_boot_state() {
+        let mut state = FastBootState::default();
+        let mut device = Device::new(0);
+        let mut atca = AtCaClient::new(&mut device, NoDelay);
+        let error = atca
+            .secure_boot()
+            .fast_verify(&Digest::default(), &mut state)
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::FuncFail));
+        let error = atca
+            .secure_boot()
+            .store_for_fast_boot(&Digest::default(), &Signature::default(), &mut state)
+            .unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::FuncFail));
+        drop(atca);
+        assert_eq!(device.commands, 0);
+
+        state.digest_stored = true;
+        assert_eq!(state.next_num_in()[..4], [0x01, 0x00, 0x00, 0x00]);
+        let bytes = state.to_bytes();
+        assert_eq!(bytes, [0x01, 0x01, 0x00, 0x00, 0x00]);
+        assert_eq!(FastBootState::from_bytes(&bytes), state);
+    }
+}
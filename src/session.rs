@@ -0,0 +1,252 @@
+// Command sequences checked at compile time. A session holds the device
+// awake, like a transaction, and its type records what the commands so far
+// left in the device: a loaded TempKey, a GenDig or GenKey digest of it, or
+// a message digest. Commands that need one of these are only available in
+// the matching state, so sequences that would end in a bare execution error
+// do not compile.
+//
+//     let (_, session) = atca.session()?.nonce_random(&num_in)?;
+//     let session = session.gen_dig(key_id)?;
+//     let (signature, message, session) = session.sign_internal(signer, true)?;
+//     session.end()?;
+//
+// Ending a session puts the device to sleep, which clears TempKey and any
+// authorization. Dropping it only lets the device idle; the host then
+// forgets an authorization made in the session, as with `AuthSession`.
+use super::auth;
+use super::client::{verification, AtCaClient, BUFFER_SIZE};
+use super::command::{self, Block, Digest, OpCode, Signature};
+use super::datalink::Interface;
+use super::error::Error;
+use super::host::SignInternalMessage;
+use super::memory::Slot;
+use super::transaction::Transaction;
+use super::NonceTarget;
+use core::convert::TryInto;
+use core::marker::PhantomData;
+use embedded_hal::delay::DelayNs;
+
+/// Nothing loaded, or nothing the next command depends on.
+pub struct Ready;
+/// TempKey holds a nonce.
+pub struct TempKeyLoaded;
+/// GenDig combined a data slot into TempKey.
+pub struct GenDigested;
+/// GenKey digested a public key into TempKey.
+pub struct GenKeyDigested;
+/// The message digest buffer holds a digest to sign or verify.
+pub struct DigestLoaded;
+
+/// States Sign in Internal mode accepts.
+pub trait SignInternal {}
+impl SignInternal for GenDigested {}
+impl SignInternal for GenKeyDigested {}
+
+pub struct Session<'a, PHY, D, S, const N: usize = BUFFER_SIZE>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    transaction: Transaction<'a, PHY, D, N>,
+    state: PhantomData<S>,
+}
+
+impl<'a, PHY, D, const N: usize> Session<'a, PHY, D, Ready, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    pub(crate) fn new(atca: &'a mut AtCaClient<PHY, D, N>) -> Result<Self, Error> {
+        Ok(Self {
+            transaction: atca.transaction()?,
+            state: PhantomData,
+        })
+    }
+
+    // Authorize the use of slots whose AuthKey is `auth_key` for the rest
+    // of the session. Only at the start, so that its commands do not
+    // interleave with a sequence built on TempKey.
+    pub fn authorize(mut self, auth_key: Slot, key: &Block) -> Result<Self, Error> {
+        self.transaction
+            .begin_sequence(&[OpCode::Read, OpCode::Random, OpCode::CheckMac])?;
+        auth::authorize(&mut self.transaction, auth_key, key)?;
+        self.transaction.scope_authorization();
+        Ok(self)
+    }
+}
+
+impl<'a, PHY, D, S, const N: usize> Session<'a, PHY, D, S, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn advance<T>(self) -> Session<'a, PHY, D, T, N> {
+        Session {
+            transaction: self.transaction,
+            state: PhantomData,
+        }
+    }
+
+    // Load TempKey with a random nonce combined with `num_in`. Returns the
+    // random number; see `host::nonce` for the resulting TempKey.
+    pub fn nonce_random(
+        mut self,
+        num_in: &[u8; 20],
+    ) -> Result<(Block, Session<'a, PHY, D, TempKeyLoaded, N>), Error> {
+        self.transaction.begin_sequence(&[OpCode::Nonce])?;
+        let rand_out = self.transaction.nonce_random(num_in)?;
+        Ok((rand_out, self.advance()))
+    }
+
+    // Load a fixed value into TempKey.
+    pub fn nonce(mut self, value: &Block) -> Result<Session<'a, PHY, D, TempKeyLoaded, N>, Error> {
+        self.transaction.begin_sequence(&[OpCode::Nonce])?;
+        self.transaction
+            .nonce(NonceTarget::TempKey, value.as_ref())?;
+        Ok(self.advance())
+    }
+
+    // Load the digest to sign or verify, after a Random that updates the
+    // seed as Sign requires.
+    pub fn load_digest(
+        mut self,
+        digest: &Digest,
+    ) -> Result<Session<'a, PHY, D, DigestLoaded, N>, Error> {
+        self.transaction
+            .begin_sequence(&[OpCode::Random, OpCode::Nonce])?;
+        self.transaction.random()?;
+        self.transaction.write_message_digest_buffer(digest)?;
+        Ok(self.advance())
+    }
+
+    /// End the session, putting the device to sleep.
+    pub fn end(mut self) -> Result<(), Error> {
+        self.transaction.sleep()
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Session<'a, PHY, D, TempKeyLoaded, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    // Combine the key in `key_id` into TempKey. See `host::gen_dig`.
+    pub fn gen_dig(mut self, key_id: Slot) -> Result<Session<'a, PHY, D, GenDigested, N>, Error> {
+        self.transaction.begin_sequence(&[OpCode::GenDig])?;
+        self.transaction.gen_dig(key_id)?;
+        Ok(self.advance())
+    }
+
+    // Digest the public key in `key_id` into TempKey. See
+    // `host::gen_key_digest`.
+    pub fn gen_key_digest(
+        mut self,
+        key_id: Slot,
+        other_data: &[u8; 3],
+    ) -> Result<Session<'a, PHY, D, GenKeyDigested, N>, Error> {
+        self.transaction.begin_sequence(&[OpCode::GenKey])?;
+        self.transaction.gen_key_digest(key_id, other_data)?;
+        Ok(self.advance())
+    }
+}
+
+impl<'a, PHY, D, S, const N: usize> Session<'a, PHY, D, S, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+    S: SignInternal,
+{
+    // Sign the message built from TempKey. See `AtCaClient::sign_internal`.
+    // TempKey is left as it is.
+    pub fn sign_internal(
+        mut self,
+        key_id: Slot,
+        include_full_sn: bool,
+    ) -> Result<(Signature, SignInternalMessage, Self), Error> {
+        self.transaction.begin_sequence(&[
+            OpCode::Read,
+            OpCode::Read,
+            OpCode::Read,
+            OpCode::Read,
+            OpCode::Sign,
+        ])?;
+        let (signature, message) = self.transaction.sign_internal(key_id, include_full_sn)?;
+        Ok((signature, message, self))
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Session<'a, PHY, D, GenKeyDigested, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    // Mark the public key in `key_id`, digested into TempKey, valid. See
+    // `AtCaClient::validate_public_key`.
+    pub fn validate_public_key(
+        mut self,
+        key_id: Slot,
+        signature: &Signature,
+        other_data: &[u8; 19],
+    ) -> Result<(bool, Session<'a, PHY, D, Ready, N>), Error> {
+        self.transaction.begin_sequence(&[OpCode::Verify])?;
+        let valid = self
+            .transaction
+            .validate_public_key(key_id, signature, other_data)?;
+        Ok((valid, self.advance()))
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Session<'a, PHY, D, DigestLoaded, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    // Sign the loaded digest with the private key in `key_id`.
+    pub fn sign(
+        mut self,
+        key_id: Slot,
+    ) -> Result<(Signature, Session<'a, PHY, D, Ready, N>), Error> {
+        self.transaction.require_key_usable(key_id)?;
+        self.transaction.begin_sequence(&[OpCode::Sign])?;
+        let packet = command::Sign::new(self.transaction.packet_builder()).external(key_id)?;
+        let signature = self.transaction.execute(packet)?.as_ref().try_into()?;
+        Ok((signature, self.advance()))
+    }
+
+    // Verify a signature over the loaded digest against the public key
+    // stored in `key_id`.
+    pub fn verify_stored(
+        mut self,
+        key_id: Slot,
+        signature: &Signature,
+    ) -> Result<(bool, Session<'a, PHY, D, Ready, N>), Error> {
+        self.transaction.begin_sequence(&[OpCode::Verify])?;
+        let packet =
+            command::Verify::new(self.transaction.packet_builder()).stored(key_id, signature)?;
+        let valid = verification(self.transaction.execute(packet).map(drop))?;
+        Ok((valid, self.advance()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+    use crate::PowerState;
+
+    #[test]
+    fn typed_sequence() {
+        let mut atca = AtCaClient::new(Device::new(0), NoDelay);
+        let session = atca.session().unwrap();
+        let session = session.nonce(&Block::default()).unwrap();
+        let session = session.gen_dig(Slot::Data08).unwrap();
+        drop(session);
+        assert_eq!(atca.power_state(), PowerState::Idle);
+        assert_eq!(atca.tempkey().gen_dig_key(), Some(Slot::Data08));
+
+        let session = atca.session().unwrap();
+        session.nonce(&Block::default()).unwrap().end().unwrap();
+        assert_eq!(atca.power_state(), PowerState::Sleep);
+        assert!(!atca.tempkey().is_valid());
+    }
+}
@@ -0,0 +1,172 @@
+// Client shared between tasks running at different priorities, as in RTIC
+// applications. Each access locks the client for the whole command sequence,
+// so one task cannot slip a command between the Nonce and the Sign of
+// another.
+//
+// The lock is whatever `BusMutex` the application provides. A mutex over a
+// critical section is a few lines:
+//
+//     struct CsMutex<T>(critical_section::Mutex<RefCell<T>>);
+//
+//     impl<T> BusMutex<T> for CsMutex<T> {
+//         fn create(value: T) -> Self {
+//             Self(critical_section::Mutex::new(RefCell::new(value)))
+//         }
+//
+//         fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+//             critical_section::with(|cs| f(&mut self.0.borrow_ref_mut(cs)))
+//         }
+//
+//         fn into_inner(self) -> T {
+//             self.0.into_inner().into_inner()
+//         }
+//     }
+//
+// A sequence that does not run to completion poisons the client: either the
+// closure unwound, or it returned with a non-blocking command still in
+// flight, whose response another task would otherwise pick up. Every access
+// then fails with `Poisoned` until `recover` puts the device back to a known
+// state.
+use super::client::{AtCaClient, BUFFER_SIZE};
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind};
+use core::cell::RefCell;
+use embedded_hal::delay::DelayNs;
+
+/// Mutual exclusion around a value, shaped after `BusMutex` of shared-bus.
+pub trait BusMutex<T> {
+    fn create(value: T) -> Self;
+    /// Run `f` with exclusive access to the value.
+    fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R;
+    fn into_inner(self) -> T;
+}
+
+// For sharing within a single priority. Locking again from a task that
+// preempted the holder panics.
+impl<T> BusMutex<T> for RefCell<T> {
+    fn create(value: T) -> Self {
+        RefCell::new(value)
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.borrow_mut())
+    }
+
+    fn into_inner(self) -> T {
+        RefCell::into_inner(self)
+    }
+}
+
+/// Client together with its poisoning flag, as held by the mutex.
+pub struct Guarded<PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: AtCaClient<PHY, D, N>,
+    poisoned: bool,
+}
+
+pub struct SharedClient<M> {
+    mutex: M,
+}
+
+impl<M> SharedClient<M> {
+    pub fn new<PHY, D, const N: usize>(atca: AtCaClient<PHY, D, N>) -> Self
+    where
+        M: BusMutex<Guarded<PHY, D, N>>,
+    {
+        Self {
+            mutex: M::create(Guarded {
+                atca,
+                poisoned: false,
+            }),
+        }
+    }
+
+    pub fn release<PHY, D, const N: usize>(self) -> AtCaClient<PHY, D, N>
+    where
+        M: BusMutex<Guarded<PHY, D, N>>,
+    {
+        self.mutex.into_inner().atca
+    }
+
+    /// Run a command sequence with exclusive access to the client.
+    pub fn lock<PHY, D, R, const N: usize>(
+        &self,
+        f: impl FnOnce(&mut AtCaClient<PHY, D, N>) -> Result<R, Error>,
+    ) -> Result<R, Error>
+    where
+        M: BusMutex<Guarded<PHY, D, N>>,
+        PHY: Interface,
+        D: DelayNs,
+    {
+        self.mutex.lock(|guarded| {
+            if guarded.poisoned {
+                return Err(ErrorKind::Poisoned.into());
+            }
+            // Stays set if `f` does not return.
+            guarded.poisoned = true;
+            let result = f(&mut guarded.atca);
+            guarded.poisoned = guarded.atca.is_pending();
+            result
+        })
+    }
+
+    pub fn is_poisoned<PHY, D, const N: usize>(&self) -> bool
+    where
+        M: BusMutex<Guarded<PHY, D, N>>,
+    {
+        self.mutex.lock(|guarded| guarded.poisoned)
+    }
+
+    /// Abandon the interrupted sequence and put the device to sleep, which
+    /// clears TempKey and any authorization. The client stays poisoned if
+    /// the device does not go to sleep, as it does not while still busy with
+    /// an abandoned command; try again later.
+    pub fn recover<PHY, D, const N: usize>(&self) -> Result<(), Error>
+    where
+        M: BusMutex<Guarded<PHY, D, N>>,
+        PHY: Interface,
+        D: DelayNs,
+    {
+        self.mutex.lock(|guarded| {
+            if guarded.poisoned {
+                guarded.atca.abandon()?;
+                guarded.poisoned = false;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+    use crate::memory::Slot;
+    use crate::PublicKey;
+
+    type Shared<'a> = SharedClient<RefCell<Guarded<&'a mut Device, NoDelay>>>;
+
+    #[test]
+    fn sequence_left_in_flight() {
+        let mut device = Device::new(0).respond(&[0x04; 64]);
+        let shared = Shared::new(AtCaClient::new(&mut device, NoDelay));
+        let public_key = shared.lock(|atca| atca.generate_key(Slot::PrivateKey01));
+        assert_eq!(public_key.unwrap().as_ref(), [0x04; 64]);
+        assert!(!shared.is_poisoned());
+
+        shared
+            .lock(|atca| atca.start_generate_key(Slot::PrivateKey02))
+            .unwrap();
+        assert!(shared.is_poisoned());
+        let error = shared.lock(|atca| atca.info()).unwrap_err();
+        assert_eq!(error.kind(), Some(ErrorKind::Poisoned));
+
+        shared.recover().unwrap();
+        assert!(!shared.is_poisoned());
+        shared
+            .lock(|atca| atca.generate_key(Slot::PrivateKey02))
+            .unwrap();
+        let mut atca = shared.release();
+        assert!(!atca.is_pending());
+        assert!(atca.poll_response::<PublicKey>().is_err());
+    }
+}
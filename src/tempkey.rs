@@ -0,0 +1,130 @@
+// Mirror of the device's TempKey register. Most commands that take input
+// from TempKey fail with a bare execution error when it is invalid or was
+// loaded the wrong way; tracking it on the host turns those into errors
+// raised before anything is sent.
+//
+// The device clears TempKey when it goes to sleep. Idle, which the client
+// enters after every command, preserves it.
+use super::error::{Error, ErrorKind};
+use super::memory::Slot;
+
+/// How TempKey was last loaded, reflecting the device's SourceFlag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TempKeySource {
+    /// Random nonce.
+    Random,
+    /// Pass-through nonce or output of another command.
+    Input,
+}
+
+/// Host view of TempKey validity, its source and whether GenDig or GenKey
+/// has been applied to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TempKeyState {
+    source: Option<TempKeySource>,
+    gen_dig: Option<Slot>,
+    gen_key: Option<Slot>,
+}
+
+impl TempKeyState {
+    pub fn is_valid(&self) -> bool {
+        self.source.is_some()
+    }
+
+    pub fn source(&self) -> Option<TempKeySource> {
+        self.source
+    }
+
+    /// Slot whose key was combined into TempKey by the last GenDig.
+    pub fn gen_dig_key(&self) -> Option<Slot> {
+        self.gen_dig
+    }
+
+    /// Slot whose public key was digested into TempKey by the last GenKey.
+    pub fn gen_key_slot(&self) -> Option<Slot> {
+        self.gen_key
+    }
+
+    pub(crate) fn load(&mut self, source: TempKeySource) {
+        self.source.replace(source);
+        self.gen_dig.take();
+        self.gen_key.take();
+    }
+
+    pub(crate) fn gen_dig(&mut self, key_id: Slot) {
+        self.gen_dig.replace(key_id);
+        self.gen_key.take();
+    }
+
+    pub(crate) fn clear_gen_dig(&mut self) {
+        self.gen_dig.take();
+        self.gen_key.take();
+    }
+
+    pub(crate) fn gen_key(&mut self, key_id: Slot) {
+        self.gen_key.replace(key_id);
+        self.gen_dig.take();
+    }
+
+    pub(crate) fn invalidate(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(crate) fn require_valid(&self) -> Result<TempKeySource, Error> {
+        self.source.ok_or_else(|| ErrorKind::TempKeyInvalid.into())
+    }
+
+    pub(crate) fn require_source(&self, source: TempKeySource) -> Result<(), Error> {
+        if self.require_valid()? != source {
+            return Err(ErrorKind::TempKeySourceMismatch.into());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn require_gen_dig(&self, key_id: Slot) -> Result<(), Error> {
+        self.require_valid()?;
+        if self.gen_dig != Some(key_id) {
+            return Err(ErrorKind::TempKeySourceMismatch.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lifecycle() {
+        let mut state = TempKeyState::default();
+        assert_eq!(
+            state.require_valid().unwrap_err().kind(),
+            Some(ErrorKind::TempKeyInvalid)
+        );
+
+        state.load(TempKeySource::Random);
+        assert!(state.require_source(TempKeySource::Random).is_ok());
+        assert_eq!(
+            state
+                .require_source(TempKeySource::Input)
+                .unwrap_err()
+                .kind(),
+            Some(ErrorKind::TempKeySourceMismatch)
+        );
+
+        state.gen_dig(Slot::Data08);
+        assert!(state.require_gen_dig(Slot::Data08).is_ok());
+        assert!(state.require_gen_dig(Slot::PrivateKey00).is_err());
+
+        state.gen_key(Slot::PrivateKey01);
+        assert_eq!(state.gen_dig_key(), None);
+        assert_eq!(state.gen_key_slot(), Some(Slot::PrivateKey01));
+
+        state.load(TempKeySource::Input);
+        assert_eq!(state.gen_key_slot(), None);
+        state.invalidate();
+        assert!(!state.is_valid());
+    }
+}
@@ -0,0 +1,111 @@
+// TLS 1.2 key derivation on the device. The ECDH premaster secret and the
+// master secret stay in TempKey, and the key block leaves the device only
+// encrypted with the I/O protection key. The PRF runs through the KDF
+// command, which produces at most 64 bytes per call. That covers the 40-byte
+// key block of AES-128-GCM suites, but not the 72 bytes of AES-256-GCM, and
+// the device does not expose the PRF state to continue past the first call.
+use super::client::{AtCaClient, BUFFER_SIZE};
+use super::command::{Digest, KdfSource, KdfTarget, PublicKey};
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind};
+use super::memory::Slot;
+use embedded_hal::delay::DelayNs;
+use heapless::Vec;
+
+pub const RANDOM_SIZE: usize = 32;
+pub const VERIFY_DATA_SIZE: usize = 12;
+
+const MASTER_SECRET_SIZE: usize = 48;
+const MASTER_SECRET_LABEL: &[u8] = b"master secret";
+const KEY_EXPANSION_LABEL: &[u8] = b"key expansion";
+pub const CLIENT_FINISHED_LABEL: &[u8] = b"client finished";
+pub const SERVER_FINISHED_LABEL: &[u8] = b"server finished";
+
+pub struct Tls12<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
+}
+
+impl<'a, PHY, D, const N: usize> Tls12<'a, PHY, D, N> {
+    pub(crate) fn new(atca: &'a mut AtCaClient<PHY, D, N>) -> Self {
+        Self { atca }
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Tls12<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    // ECDHE with the server's ephemeral key, the premaster secret going to
+    // TempKey.
+    pub fn premaster_secret(&mut self, key_id: Slot, server_key: &PublicKey) -> Result<(), Error> {
+        self.atca.diffie_hellman_tempkey(key_id, server_key)
+    }
+
+    // master_secret = PRF(premaster, "master secret", client || server random),
+    // replacing the premaster secret in TempKey.
+    pub fn master_secret(
+        &mut self,
+        client_random: &[u8; RANDOM_SIZE],
+        server_random: &[u8; RANDOM_SIZE],
+    ) -> Result<(), Error> {
+        let seed = seed(MASTER_SECRET_LABEL, client_random, server_random)?;
+        self.atca
+            .kdf()
+            .prf(KdfSource::TempKey, KdfTarget::TempKey, 32, true, &seed)
+            .map(drop)
+    }
+
+    // key_block = PRF(master, "key expansion", server || client random),
+    // returned encrypted under the client's I/O protection key and
+    // decrypted on the host.
+    pub fn key_block(
+        &mut self,
+        client_random: &[u8; RANDOM_SIZE],
+        server_random: &[u8; RANDOM_SIZE],
+    ) -> Result<Vec<u8, 64>, Error> {
+        let seed = seed(KEY_EXPANSION_LABEL, server_random, client_random)?;
+        self.atca.kdf().prf(
+            KdfSource::TempKey,
+            KdfTarget::OutputEncrypted,
+            MASTER_SECRET_SIZE,
+            true,
+            &seed,
+        )
+    }
+
+    // verify_data of a Finished message, PRF(master, label, handshake hash).
+    pub fn verify_data(
+        &mut self,
+        label: &[u8],
+        handshake_hash: &Digest,
+    ) -> Result<[u8; VERIFY_DATA_SIZE], Error> {
+        let mut seed = Vec::<u8, 64>::new();
+        seed.extend_from_slice(label)
+            .and_then(|()| seed.extend_from_slice(handshake_hash.as_ref()))
+            .map_err(|()| Error::from(ErrorKind::InvalidSize))?;
+        let output = self.atca.kdf().prf(
+            KdfSource::TempKey,
+            KdfTarget::Output,
+            MASTER_SECRET_SIZE,
+            false,
+            &seed,
+        )?;
+        let mut verify_data = [0x00; VERIFY_DATA_SIZE];
+        verify_data.copy_from_slice(&output[..VERIFY_DATA_SIZE]);
+        Ok(verify_data)
+    }
+}
+
+fn seed(
+    label: &[u8],
+    first: &[u8; RANDOM_SIZE],
+    second: &[u8; RANDOM_SIZE],
+) -> Result<Vec<u8, 96>, Error> {
+    let mut seed = Vec::new();
+    seed.extend_from_slice(label)
+        .and_then(|()| seed.extend_from_slice(first))
+        .and_then(|()| seed.extend_from_slice(second))
+        .map_err(|()| Error::from(ErrorKind::InvalidSize))?;
+    Ok(seed)
+}
@@ -6,15 +6,20 @@
 // Signer public key from signer certificate. 6. ECDH/KDF key slot capable of
 // being used with AES keys and commands. 7. X.509 Compressed Certificate
 // Storage.
-use super::client::{AtCaClient, Memory, Sha};
+use super::cert::Certificate;
+use super::client::{AtCaClient, Memory, BUFFER_SIZE};
+use super::datalink::Interface;
 use super::error::Error;
-use super::memory::{Size, Slot, Zone};
+use super::memory::{LockToken, Size, Slot, Zone};
 use core::convert::TryFrom;
-use digest::{FixedOutputDirty, Reset, Update};
 use embedded_hal::delay::DelayNs;
-use embedded_hal::i2c;
-use generic_array::typenum::U32;
-use generic_array::GenericArray;
+
+mod cert_def;
+
+pub use cert_def::{DEVICE_CERT_DEF, SIGNER_CERT_DEF};
+
+#[cfg(feature = "digest")]
+pub use super::hasher::Hasher;
 
 pub const AUTH_PRIVATE_KEY: Slot = Slot::PrivateKey00;
 pub const SIGN_PRIVATE_KEY: Slot = Slot::PrivateKey01;
@@ -27,50 +32,13 @@ pub const DEVICE_CERTIFICATE: Slot = Slot::Certificate0a;
 pub const SIGNER_PUBLIC_KEY: Slot = Slot::Certificate0b;
 pub const SIGNER_CERTIFICATE: Slot = Slot::Certificate0c;
 
-pub struct Hasher<'a, PHY, D>(Sha<'a, PHY, D>);
-impl<'a, PHY, D> From<Sha<'a, PHY, D>> for Hasher<'a, PHY, D> {
-    fn from(sha: Sha<'a, PHY, D>) -> Self {
-        Self(sha)
-    }
-}
-
-impl<'a, PHY, D> Update for Hasher<'a, PHY, D>
-where
-    PHY: i2c::I2c,
-    D: DelayNs,
-{
-    fn update(&mut self, data: impl AsRef<[u8]>) {
-        self.0.update(data).expect("update operation failed");
-    }
+pub struct TrustAndGo<'a, PHY, D, const N: usize = BUFFER_SIZE> {
+    atca: &'a mut AtCaClient<PHY, D, N>,
 }
 
-impl<'a, PHY, D> FixedOutputDirty for Hasher<'a, PHY, D>
-where
-    PHY: i2c::I2c,
-    D: DelayNs,
-{
-    type OutputSize = U32;
-    fn finalize_into_dirty(&mut self, out: &mut GenericArray<u8, Self::OutputSize>) {
-        let digest = self.0.finalize().expect("finalize operation failed");
-        out.as_mut_slice().copy_from_slice(digest.as_ref());
-    }
-}
-
-impl<'a, PHY, D> Reset for Hasher<'a, PHY, D>
-where
-    PHY: i2c::I2c,
-    D: DelayNs,
-{
-    fn reset(&mut self) {}
-}
-
-pub struct TrustAndGo<'a, PHY, D> {
-    atca: &'a mut AtCaClient<PHY, D>,
-}
-
-impl<'a, PHY, D> TrustAndGo<'a, PHY, D> {
+impl<'a, PHY, D, const N: usize> TrustAndGo<'a, PHY, D, N> {
     // Miscellaneous device states.
-    const TNG_TLS_SLOT_CONFIG_DATA: [u8; Size::Block as usize] = [
+    pub(crate) const TNG_TLS_SLOT_CONFIG_DATA: [u8; Size::Block as usize] = [
         // Index 20..=51, block = 0, offset = 5
         0x85, 0x00, // Slot 0x00, Primary private key
         0x82, 0x00, // Slot 0x01, Internal sign private key
@@ -86,12 +54,12 @@ impl<'a, PHY, D> TrustAndGo<'a, PHY, D> {
         0x00, 0x00, 0x00, 0x00, 0xaf, 0x8f, // Slot 0x0d, 0x0e and 0x0f, reserved.
     ];
 
-    const TNG_TLS_CHIP_OPTIONS: [u8; Size::Word as usize] = [
+    pub(crate) const TNG_TLS_CHIP_OPTIONS: [u8; Size::Word as usize] = [
         // Index 88..=91, block = 2, offset = 6
         0xff, 0xff, 0x60, 0x0e,
     ];
 
-    const TNG_TLS_KEY_CONFIG_DATA: [u8; Size::Block as usize] = [
+    pub(crate) const TNG_TLS_KEY_CONFIG_DATA: [u8; Size::Block as usize] = [
         // Index 96..=127, block = 3, offset = 0
         0x53, 0x00, // 0x00
         0x53, 0x00, // 0x01
@@ -109,9 +77,9 @@ impl<'a, PHY, D> TrustAndGo<'a, PHY, D> {
 }
 
 // Methods for preparing device state. Configuraion, random nonce and key creation and so on.
-impl<'a, PHY, D> TrustAndGo<'a, PHY, D>
+impl<'a, PHY, D, const N: usize> TrustAndGo<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     // Slot config
@@ -120,7 +88,7 @@ where
             .chunks(Size::Word.len())
             .enumerate()
             .try_for_each(|(i, word)| {
-                let index = Memory::<PHY, D>::SLOT_CONFIG_INDEX + i * Size::Word.len();
+                let index = Memory::<PHY, D, N>::SLOT_CONFIG_INDEX + i * Size::Word.len();
                 let (block, offset, _) = Zone::locate_index(index);
                 self.atca
                     .memory()
@@ -131,7 +99,7 @@ where
 
     // Chip options
     pub fn configure_chip_options(&mut self) -> Result<(), Error> {
-        let (block, offset, _) = Zone::locate_index(Memory::<PHY, D>::CHIP_OPTIONS_INDEX);
+        let (block, offset, _) = Zone::locate_index(Memory::<PHY, D, N>::CHIP_OPTIONS_INDEX);
         self.atca
             .memory()
             .write_config(Size::Word, block, offset, &Self::TNG_TLS_CHIP_OPTIONS)
@@ -139,38 +107,75 @@ where
 
     // Key config
     pub fn configure_key_types(&mut self) -> Result<(), Error> {
-        let (block, offset, _) = Zone::locate_index(Memory::<PHY, D>::KEY_CONFIG_INDEX);
+        let (block, offset, _) = Zone::locate_index(Memory::<PHY, D, N>::KEY_CONFIG_INDEX);
         self.atca
             .memory()
             .write_config(Size::Block, block, offset, &Self::TNG_TLS_KEY_CONFIG_DATA)
     }
 }
 
-// On creation of TNG object, enforce stateful configuration.
-impl<'a, PHY, D> TryFrom<&'a mut AtCaClient<PHY, D>> for TrustAndGo<'a, PHY, D>
+// Certificates. The device keeps its own certificate in slot 10 and the
+// signer's in slot 12, both compressed, with the signer public key in slot 11.
+// The templates are not stored on the device; they come with
+// `DEVICE_CERT_DEF` and `SIGNER_CERT_DEF`.
+impl<'a, PHY, D, const N: usize> TrustAndGo<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    pub fn device_certificate(&mut self) -> Result<Certificate, Error> {
+        let compressed = self
+            .atca
+            .memory()
+            .compressed_certificate(DEVICE_CERTIFICATE)?;
+        let public_key = self.atca.public_key(AUTH_PRIVATE_KEY)?;
+        let signer_public_key = self.atca.memory().read_pubkey(SIGNER_PUBLIC_KEY)?;
+        let serial = self.atca.memory().serial_number()?;
+        DEVICE_CERT_DEF.build(
+            &compressed,
+            &public_key,
+            Some(&serial),
+            Some(&signer_public_key),
+        )
+    }
+
+    // The authority key identifier of the signer certificate refers to the
+    // root, which is part of the template.
+    pub fn signer_certificate(&mut self) -> Result<Certificate, Error> {
+        let compressed = self
+            .atca
+            .memory()
+            .compressed_certificate(SIGNER_CERTIFICATE)?;
+        let public_key = self.atca.memory().read_pubkey(SIGNER_PUBLIC_KEY)?;
+        let serial = self.atca.memory().serial_number()?;
+        SIGNER_CERT_DEF.build(&compressed, &public_key, Some(&serial), None)
+    }
+}
+
+// On creation of TNG object, enforce stateful configuration. The config zone
+// is locked only with a token. The data zone is left to the caller, to lock
+// once keys and certificates are written.
+impl<'a, PHY, D, const N: usize> TryFrom<(&'a mut AtCaClient<PHY, D, N>, Option<LockToken>)>
+    for TrustAndGo<'a, PHY, D, N>
 where
-    PHY: i2c::I2c,
+    PHY: Interface,
     D: DelayNs,
 {
     type Error = Error;
-    fn try_from(atca: &'a mut AtCaClient<PHY, D>) -> Result<Self, Self::Error> {
+    fn try_from(
+        (atca, lock): (&'a mut AtCaClient<PHY, D, N>, Option<LockToken>),
+    ) -> Result<Self, Self::Error> {
         let mut tng = Self { atca };
         // Check if configuration zone is locked.
-        if !tng.atca.memory().is_locked(Zone::Config)? {
+        if !tng.atca.memory().is_config_locked()? {
             tng.configure_permissions()?;
             tng.configure_chip_options()?;
             tng.configure_key_types()?;
             // Lock config zone
-            tng.atca.memory().lock(Zone::Config)?;
-        }
-
-        // Check if data zone is locked.
-        if !tng.atca.memory().is_locked(Zone::Data)? {
-            // Only lock the data zone for release build
-            #[cfg(not(debug_assertions))]
-            tng.atca.memory().lock(Zone::Data)?;
+            if let Some(token) = lock {
+                tng.atca.memory().lock(Zone::Config, token)?;
+            }
         }
-
         Ok(tng)
     }
 }
@@ -0,0 +1,144 @@
+// Commands that depend on each other through TempKey must run before the
+// watchdog puts the device to sleep. A transaction holds the device awake,
+// checks that the next sequence fits in what is left of the watchdog window
+// and restarts the window through idle, which keeps TempKey, when it does
+// not.
+use super::client::{AtCaClient, BUFFER_SIZE};
+use super::clock_divider::WATCHDOG_MS;
+use super::command::OpCode;
+use super::datalink::Interface;
+use super::error::{Error, ErrorKind};
+use super::PowerState;
+use core::ops::{Deref, DerefMut};
+use embedded_hal::delay::DelayNs;
+
+pub struct Transaction<'a, PHY, D, const N: usize = BUFFER_SIZE>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    atca: &'a mut AtCaClient<PHY, D, N>,
+    // Whether the transaction woke the device and has to put it back.
+    owned: bool,
+    // Whether an authorization made within ends with the transaction.
+    scoped_auth: bool,
+}
+
+impl<'a, PHY, D, const N: usize> Transaction<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    pub(crate) fn new(atca: &'a mut AtCaClient<PHY, D, N>) -> Result<Self, Error> {
+        let owned = atca.power_state() != PowerState::Awake;
+        atca.wake()?;
+        Ok(Self {
+            atca,
+            owned,
+            scoped_auth: false,
+        })
+    }
+
+    /// Time in ms since the device was last woken.
+    pub fn elapsed_ms(&self) -> u32 {
+        self.atca.awake_ms()
+    }
+
+    /// Time in ms left before the watchdog expires.
+    pub fn remaining_ms(&self) -> u32 {
+        WATCHDOG_MS.saturating_sub(self.elapsed_ms())
+    }
+
+    /// Start a sequence that takes up to `max_ms`. The watchdog window is
+    /// restarted if the sequence would not fit in the rest of it, and a
+    /// sequence longer than the whole window is refused.
+    pub fn begin(&mut self, max_ms: u32) -> Result<(), Error> {
+        if max_ms >= WATCHDOG_MS {
+            return Err(ErrorKind::WatchdogExceeded.into());
+        }
+        if max_ms >= self.remaining_ms() {
+            self.atca.restart_watchdog()?;
+        }
+        Ok(())
+    }
+
+    // Forget the authorization on the host when the transaction ends.
+    pub(crate) fn scope_authorization(&mut self) {
+        self.scoped_auth = true;
+    }
+
+    pub(crate) fn begin_sequence(&mut self, sequence: &[OpCode]) -> Result<(), Error> {
+        let max_ms = sequence
+            .iter()
+            .map(|opcode| self.atca.execution_time(opcode).max)
+            .sum();
+        self.begin(max_ms)
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Deref for Transaction<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    type Target = AtCaClient<PHY, D, N>;
+
+    fn deref(&self) -> &Self::Target {
+        self.atca
+    }
+}
+
+impl<'a, PHY, D, const N: usize> DerefMut for Transaction<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.atca
+    }
+}
+
+impl<'a, PHY, D, const N: usize> Drop for Transaction<'a, PHY, D, N>
+where
+    PHY: Interface,
+    D: DelayNs,
+{
+    fn drop(&mut self) {
+        if self.scoped_auth {
+            self.atca.authorized.take();
+        }
+        if self.owned {
+            self.atca.idle().ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalink::tests::{Device, NoDelay};
+
+    #[test]
+    fn watchdog_window() {
+        let mut atca = AtCaClient::new(Device::new(0), NoDelay);
+        let mut transaction = atca.transaction().unwrap();
+        assert_eq!(transaction.power_state(), PowerState::Awake);
+        assert_eq!(transaction.remaining_ms(), WATCHDOG_MS);
+
+        let result = transaction.begin(WATCHDOG_MS);
+        assert_eq!(
+            result.unwrap_err().kind(),
+            Some(ErrorKind::WatchdogExceeded)
+        );
+        transaction
+            .begin_sequence(&[OpCode::Nonce, OpCode::GenDig, OpCode::Read])
+            .unwrap();
+        drop(transaction);
+        assert_eq!(atca.power_state(), PowerState::Idle);
+
+        // A device held awake by the caller stays awake.
+        atca.wake().unwrap();
+        drop(atca.transaction().unwrap());
+        assert_eq!(atca.power_state(), PowerState::Awake);
+    }
+}